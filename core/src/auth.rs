@@ -258,6 +258,45 @@ impl EncryptionManager {
         self.encrypt_to_file(text.as_bytes(), path)
     }
 
+    /// Encrypt a plaintext file in place.
+    ///
+    /// The encrypted copy is written next to the original and decrypted again; the
+    /// plaintext is only replaced once that round trip matches it byte for byte.
+    pub fn encrypt_file_in_place(&self, path: &Path) -> Result<PlaintextMigration> {
+        if !path.exists() {
+            return Ok(PlaintextMigration::Missing);
+        }
+        let plaintext = fs::read(path)?;
+        if Self::is_encrypted(&plaintext) {
+            return Ok(PlaintextMigration::AlreadyEncrypted);
+        }
+
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".enc-tmp");
+        let tmp = path.with_file_name(tmp_name);
+        let verified = self
+            .encrypt_to_file(&plaintext, &tmp)
+            .and_then(|()| self.decrypt_from_file(&tmp));
+        match verified {
+            Ok(roundtrip) if roundtrip == plaintext => {}
+            Ok(_) => {
+                fs::remove_file(&tmp).ok();
+                return Err(Error::Auth(format!(
+                    "Encrypted copy of {} did not decrypt to the original; left it unencrypted",
+                    path.display()
+                )));
+            }
+            Err(e) => {
+                fs::remove_file(&tmp).ok();
+                return Err(e);
+            }
+        }
+
+        // rename replaces the plaintext in one step, so no partial file is left behind
+        fs::rename(&tmp, path)?;
+        Ok(PlaintextMigration::Encrypted)
+    }
+
     /// Read and decrypt text from file
     pub fn decrypt_text_from_file(&self, path: &Path) -> Result<String> {
         let data = self.decrypt_from_file(path)?;
@@ -265,6 +304,15 @@ impl EncryptionManager {
     }
 }
 
+/// What `EncryptionManager::encrypt_file_in_place` found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaintextMigration {
+    /// The plaintext file was replaced by a verified encrypted one
+    Encrypted,
+    AlreadyEncrypted,
+    Missing,
+}
+
 /// Check if a file is encrypted
 pub fn is_file_encrypted(path: &Path) -> Result<bool> {
     if !path.exists() {
@@ -313,6 +361,36 @@ mod tests {
         assert_eq!(original, decrypted);
     }
 
+    #[test]
+    fn test_encrypt_file_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let manager = EncryptionManager::new([7u8; 32]);
+
+        assert_eq!(
+            manager.encrypt_file_in_place(&path).unwrap(),
+            PlaintextMigration::Missing
+        );
+
+        fs::write(&path, "lan_net = \"lan-net\"\n").unwrap();
+        assert_eq!(
+            manager.encrypt_file_in_place(&path).unwrap(),
+            PlaintextMigration::Encrypted
+        );
+        assert!(is_file_encrypted(&path).unwrap());
+        assert_eq!(
+            manager.decrypt_text_from_file(&path).unwrap(),
+            "lan_net = \"lan-net\"\n"
+        );
+        // No plaintext or temporary copy is left next to it
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        assert_eq!(
+            manager.encrypt_file_in_place(&path).unwrap(),
+            PlaintextMigration::AlreadyEncrypted
+        );
+    }
+
     #[test]
     fn test_is_encrypted_check() {
         let password = "test_password_123";
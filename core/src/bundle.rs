@@ -0,0 +1,477 @@
+//! Export and import of the whole app configuration as one archive
+//!
+//! A bundle is a gzipped tarball (built with the `tar` CLI) laid out as:
+//!
+//! ```text
+//! manifest.toml
+//! config/   config.toml, templates.toml, auth.json (copied verbatim, even if encrypted)
+//! roles/    one directory per role
+//! images/   template base images, only when exported with images
+//! ```
+
+use crate::config::discover_roles;
+use crate::runner::{CommandRunner, SystemRunner, DEFAULT_MAX_OUTPUT_BYTES};
+use crate::{Error, GlobalConfig, Result, TemplateRegistry};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Bundle format version written to the manifest
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Files from the config directory that go into a bundle
+const CONFIG_FILES: &[&str] = &["config.toml", "templates.toml", "auth.json"];
+
+/// Describes what a bundle contains
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub version: u32,
+    pub created: DateTime<Utc>,
+    pub roles: Vec<String>,
+    /// File names under `images/`
+    #[serde(default)]
+    pub images: Vec<String>,
+    /// Images directory on the exporting machine, to relocate template paths after import
+    #[serde(default)]
+    pub images_dir: Option<PathBuf>,
+}
+
+/// Where the app keeps the files a bundle carries
+#[derive(Debug, Clone)]
+pub struct BundleLayout {
+    /// Directory holding config.toml, templates.toml and auth.json
+    pub config_dir: PathBuf,
+    /// Per-role configuration root
+    pub roles_root: PathBuf,
+    /// Where base images are restored on import
+    pub images_dir: PathBuf,
+}
+
+impl BundleLayout {
+    /// The locations used by this installation
+    pub fn current(config: &GlobalConfig) -> Self {
+        let config_path = GlobalConfig::default_path();
+        Self {
+            config_dir: config_path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            roles_root: config.cfg.root.clone(),
+            images_dir: config.libvirt.images_dir.clone(),
+        }
+    }
+}
+
+/// Write a bundle of the config files, all roles and optionally the template images to `dest`.
+///
+/// Image files can be many gigabytes, so they are only included when asked for.
+pub fn export_bundle(
+    layout: &BundleLayout,
+    templates: &TemplateRegistry,
+    dest: &Path,
+    include_images: bool,
+) -> Result<()> {
+    let staging = staging_dir("export")?;
+    let result = (|| {
+        // Symlinks into the live tree; tar -h archives what they point to,
+        // so nothing (images in particular) is copied twice
+        let config_out = staging.join("config");
+        fs::create_dir_all(&config_out)?;
+        for name in CONFIG_FILES {
+            let path = layout.config_dir.join(name);
+            if path.exists() {
+                symlink(&path, &config_out.join(name))?;
+            }
+        }
+
+        let roles = discover_roles(&layout.roles_root)?;
+        let roles_out = staging.join("roles");
+        fs::create_dir_all(&roles_out)?;
+        for role in &roles {
+            symlink(&layout.roles_root.join(role), &roles_out.join(role))?;
+        }
+
+        let mut images = Vec::new();
+        if include_images {
+            let images_out = staging.join("images");
+            fs::create_dir_all(&images_out)?;
+            for template in templates.list() {
+                if !template.exists() {
+                    continue;
+                }
+                let Some(name) = template.path.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                if images.iter().any(|existing| existing == name) {
+                    return Err(Error::bundle(format!(
+                        "Two templates use an image named '{}'",
+                        name
+                    )));
+                }
+                symlink(&template.path, &images_out.join(name))?;
+                images.push(name.to_string());
+            }
+        }
+
+        let manifest = BundleManifest {
+            version: BUNDLE_VERSION,
+            created: Utc::now(),
+            roles,
+            images,
+            images_dir: include_images.then(|| layout.images_dir.clone()),
+        };
+        fs::write(
+            staging.join("manifest.toml"),
+            toml::to_string_pretty(&manifest)?,
+        )?;
+
+        let staging_str = path_str(&staging)?;
+        tar(&["-czhf", path_str(dest)?, "-C", staging_str, "."]).map(|_| ())
+    })();
+    fs::remove_dir_all(&staging).ok();
+    result
+}
+
+/// Check that `src` is a bundle this version can import, without extracting anything
+pub fn validate_bundle(src: &Path) -> Result<()> {
+    bundle_entries(src).map(|_| ())
+}
+
+/// The entries of a valid bundle, relative to its root
+fn bundle_entries(src: &Path) -> Result<HashSet<PathBuf>> {
+    let listing = tar(&["-tzf", path_str(src)?])?;
+    let entries: HashSet<PathBuf> = listing
+        .lines()
+        .map(|line| normalize_entry(line.trim()))
+        .collect::<Result<_>>()?;
+
+    // Links would let extracted files point anywhere on the host; only plain
+    // files and directories are exported, so anything else is refused
+    let verbose = tar(&["-tvzf", path_str(src)?])?;
+    if let Some(line) = verbose
+        .lines()
+        .find(|line| !matches!(line.chars().next(), Some('-' | 'd')))
+    {
+        return Err(Error::bundle(format!(
+            "links and special files are not allowed: {}",
+            line.trim()
+        )));
+    }
+
+    for required in ["manifest.toml", "config/config.toml"] {
+        if !entries.contains(Path::new(required)) {
+            return Err(Error::bundle(format!("missing {}", required)));
+        }
+    }
+    for entry in &entries {
+        let top = entry.components().next();
+        let allowed = matches!(
+            top.and_then(|c| c.as_os_str().to_str()),
+            None | Some("manifest.toml" | "config" | "roles" | "images")
+        );
+        if !allowed {
+            return Err(Error::bundle(format!(
+                "unexpected entry {}",
+                entry.display()
+            )));
+        }
+    }
+    Ok(entries)
+}
+
+/// Restore a bundle into `layout`, returning its manifest.
+///
+/// Existing config files are kept as `<file>.pre-import` next to the restored
+/// ones. Role directories are merged file by file. Encrypted files are restored
+/// as they were, so the app must be unlocked with the exporting machine's password.
+pub fn import_bundle(layout: &BundleLayout, src: &Path) -> Result<BundleManifest> {
+    let entries = bundle_entries(src)?;
+
+    let staging = staging_dir("import")?;
+    let result = (|| {
+        tar(&[
+            "-xzf",
+            path_str(src)?,
+            "-C",
+            path_str(&staging)?,
+            "--no-same-owner",
+            "--no-same-permissions",
+        ])?;
+
+        let manifest: BundleManifest =
+            toml::from_str(&fs::read_to_string(staging.join("manifest.toml"))?)?;
+        if manifest.version > BUNDLE_VERSION {
+            return Err(Error::bundle(format!(
+                "bundle version {} is newer than supported ({})",
+                manifest.version, BUNDLE_VERSION
+            )));
+        }
+        for name in &manifest.images {
+            check_image_name(name, &entries)?;
+        }
+
+        fs::create_dir_all(&layout.config_dir)?;
+        for name in CONFIG_FILES {
+            let from = staging.join("config").join(name);
+            if !from.exists() {
+                continue;
+            }
+            let to = layout.config_dir.join(name);
+            if to.exists() {
+                fs::rename(&to, to.with_file_name(format!("{}.pre-import", name)))?;
+            }
+            fs::copy(&from, &to)?;
+        }
+
+        let roles_dir = staging.join("roles");
+        if roles_dir.exists() {
+            copy_dir(&roles_dir, &layout.roles_root)?;
+        }
+
+        for name in &manifest.images {
+            let from = staging.join("images").join(name);
+            fs::create_dir_all(&layout.images_dir)?;
+            fs::copy(&from, layout.images_dir.join(name)).map_err(|e| {
+                Error::bundle(format!(
+                    "could not restore image {} to {}: {}",
+                    name,
+                    layout.images_dir.display(),
+                    e
+                ))
+            })?;
+        }
+
+        Ok(manifest)
+    })();
+    fs::remove_dir_all(&staging).ok();
+    result
+}
+
+/// Turn a tar listing entry into a relative path, rejecting anything that escapes the bundle
+fn normalize_entry(entry: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(entry).components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(part) => path.push(part),
+            _ => return Err(Error::bundle(format!("unsafe path {}", entry))),
+        }
+    }
+    Ok(path)
+}
+
+/// Check that a manifest image name is a plain file name present under `images/`
+fn check_image_name(name: &str, entries: &HashSet<PathBuf>) -> Result<()> {
+    let mut components = Path::new(name).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return Err(Error::bundle(format!("unsafe image name {}", name)));
+    }
+    if !entries.contains(&Path::new("images").join(name)) {
+        return Err(Error::bundle(format!("image {} listed but missing", name)));
+    }
+    Ok(())
+}
+
+/// Copy a directory tree; symlinks and special files are skipped, never followed
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn staging_dir(purpose: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!(
+        "proxy-vm-bundle-{}-{}-{}",
+        purpose,
+        std::process::id(),
+        Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    ));
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+pub(crate) fn tar(args: &[&str]) -> Result<String> {
+    let output = SystemRunner.run("tar", args, DEFAULT_MAX_OUTPUT_BYTES)?;
+    if !output.success() {
+        return Err(Error::Command {
+            cmd: format!("tar {}", args.join(" ")),
+            message: output.stderr.trim().to_string(),
+        });
+    }
+    Ok(output.stdout)
+}
+
+pub(crate) fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| Error::validation(format!("Invalid path encoding: {}", path.display())))
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> Result<()> {
+    Ok(std::os::unix::fs::symlink(original, link)?)
+}
+
+#[cfg(not(unix))]
+fn symlink(original: &Path, link: &Path) -> Result<()> {
+    if original.is_dir() {
+        copy_dir(original, link)
+    } else {
+        fs::copy(original, link).map(|_| ()).map_err(Error::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RoleKind, Template};
+    use tempfile::tempdir;
+
+    fn layout(root: &Path) -> BundleLayout {
+        BundleLayout {
+            config_dir: root.join("config"),
+            roles_root: root.join("roles"),
+            images_dir: root.join("images"),
+        }
+    }
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let source = tempdir().unwrap();
+        let from = layout(source.path());
+        fs::create_dir_all(&from.config_dir).unwrap();
+        fs::write(from.config_dir.join("config.toml"), "encrypted-blob").unwrap();
+        fs::write(from.config_dir.join("auth.json"), "{}").unwrap();
+        let role_dir = from.roles_root.join("work");
+        fs::create_dir_all(&role_dir).unwrap();
+        fs::write(role_dir.join("proxy.conf"), "GATEWAY_MODE=PROXY_CHAIN\n").unwrap();
+        fs::write(role_dir.join("wg0.conf"), "[Interface]\n").unwrap();
+        fs::create_dir_all(&from.images_dir).unwrap();
+        let image = from.images_dir.join("debian-12.qcow2");
+        fs::write(&image, "qcow2").unwrap();
+
+        let mut templates = TemplateRegistry::default();
+        templates
+            .add(Template::new(
+                "debian",
+                "Debian",
+                image,
+                "debian12",
+                RoleKind::ProxyGateway,
+            ))
+            .unwrap();
+
+        let archive = source.path().join("bundle.tar.gz");
+        export_bundle(&from, &templates, &archive, true).unwrap();
+        validate_bundle(&archive).unwrap();
+
+        let target = tempdir().unwrap();
+        let to = layout(target.path());
+        fs::create_dir_all(&to.config_dir).unwrap();
+        fs::write(to.config_dir.join("config.toml"), "fresh").unwrap();
+
+        let manifest = import_bundle(&to, &archive).unwrap();
+        assert_eq!(manifest.roles, ["work"]);
+        assert_eq!(manifest.images, ["debian-12.qcow2"]);
+        assert_eq!(
+            manifest.images_dir.as_deref(),
+            Some(from.images_dir.as_path())
+        );
+
+        // Encrypted files are restored byte for byte; the old one is kept aside
+        assert_eq!(
+            fs::read_to_string(to.config_dir.join("config.toml")).unwrap(),
+            "encrypted-blob"
+        );
+        assert_eq!(
+            fs::read_to_string(to.config_dir.join("config.toml.pre-import")).unwrap(),
+            "fresh"
+        );
+        assert!(to.config_dir.join("auth.json").exists());
+        assert!(!to.config_dir.join("templates.toml").exists());
+        assert!(to.roles_root.join("work/wg0.conf").exists());
+        assert_eq!(
+            fs::read_to_string(to.images_dir.join("debian-12.qcow2")).unwrap(),
+            "qcow2"
+        );
+
+        // Without images the images/ directory stays empty
+        let small = source.path().join("small.tar.gz");
+        export_bundle(&from, &templates, &small, false).unwrap();
+        let listing = tar(&["-tzf", small.to_str().unwrap()]).unwrap();
+        assert!(!listing.contains("debian-12.qcow2"));
+    }
+
+    #[test]
+    fn test_validate_bundle_rejects_foreign_archives() {
+        let dir = tempdir().unwrap();
+        let content = dir.path().join("content");
+        fs::create_dir_all(content.join("config")).unwrap();
+        fs::write(content.join("config/config.toml"), "").unwrap();
+        let archive = dir.path().join("no-manifest.tar.gz");
+        tar(&[
+            "-czf",
+            archive.to_str().unwrap(),
+            "-C",
+            content.to_str().unwrap(),
+            ".",
+        ])
+        .unwrap();
+        assert!(matches!(validate_bundle(&archive), Err(Error::Bundle(_))));
+
+        fs::write(content.join("manifest.toml"), "").unwrap();
+        fs::write(content.join("notes.txt"), "").unwrap();
+        let archive = dir.path().join("extra.tar.gz");
+        tar(&[
+            "-czf",
+            archive.to_str().unwrap(),
+            "-C",
+            content.to_str().unwrap(),
+            ".",
+        ])
+        .unwrap();
+        assert!(matches!(validate_bundle(&archive), Err(Error::Bundle(_))));
+
+        // Links could point extracted role files at anything on the host
+        fs::remove_file(content.join("notes.txt")).unwrap();
+        fs::create_dir_all(content.join("roles/work")).unwrap();
+        std::os::unix::fs::symlink("/etc/passwd", content.join("roles/work/wg0.conf")).unwrap();
+        let archive = dir.path().join("symlink.tar.gz");
+        tar(&[
+            "-czf",
+            archive.to_str().unwrap(),
+            "-C",
+            content.to_str().unwrap(),
+            ".",
+        ])
+        .unwrap();
+        assert!(matches!(validate_bundle(&archive), Err(Error::Bundle(_))));
+
+        let entries: HashSet<PathBuf> = [PathBuf::from("images/debian.qcow2")].into();
+        assert!(check_image_name("debian.qcow2", &entries).is_ok());
+        assert!(check_image_name("fedora.qcow2", &entries).is_err());
+        assert!(check_image_name("../roles/x/payload", &entries).is_err());
+        assert!(check_image_name("/etc/passwd", &entries).is_err());
+        assert!(check_image_name("sub/debian.qcow2", &entries).is_err());
+
+        assert!(normalize_entry("../etc/passwd").is_err());
+        assert!(normalize_entry("/etc/passwd").is_err());
+        assert_eq!(
+            normalize_entry("./roles/work/proxy.conf").unwrap(),
+            PathBuf::from("roles/work/proxy.conf")
+        );
+    }
+}
@@ -1,6 +1,12 @@
 //! Configuration management for global settings, templates, and roles
 
-use crate::{auth, EncryptionManager, Error, GatewayMode, Result, RoleKind};
+use crate::{
+    auth, rotation::RotationPolicy, timefmt, validate_libvirt_uri, validate_role_name,
+    validate_vm_name, BootOptions, CrashPolicy, DiskTuning, EncryptionManager, Error, GatewayMode,
+    InstallSource, LogLevel, Result, RoleKind, RoleNetworkMode, ShareDriver, VmKind,
+    DEFAULT_INSTALL_DISK_GB,
+};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
@@ -9,6 +15,35 @@ use std::path::{Path, PathBuf};
 /// Current config version for migration support
 pub const CONFIG_VERSION: u32 = 1;
 
+/// Shortest dashboard auto-refresh interval; each refresh runs one virsh call per VM
+pub const MIN_AUTO_REFRESH_SECS: u64 = 2;
+
+/// libvirt connection used unless configured otherwise
+pub const DEFAULT_LIBVIRT_URI: &str = "qemu:///system";
+
+/// App VMs a role may have unless its metadata says otherwise
+pub const DEFAULT_MAX_APP_VMS: u32 = 20;
+
+fn default_max_app_vms() -> u32 {
+    DEFAULT_MAX_APP_VMS
+}
+
+fn default_libvirt_uri() -> String {
+    DEFAULT_LIBVIRT_URI.to_string()
+}
+
+fn default_virt_install_timeout_secs() -> u64 {
+    crate::libvirt::DEFAULT_VIRT_INSTALL_TIMEOUT_SECS
+}
+
+fn default_install_disk_gb() -> u32 {
+    DEFAULT_INSTALL_DISK_GB
+}
+
+fn default_role_subnet_pool() -> String {
+    crate::DEFAULT_ROLE_SUBNET_POOL.to_string()
+}
+
 /// Global configuration for the application
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalConfig {
@@ -16,6 +51,12 @@ pub struct GlobalConfig {
     pub cfg: CfgSection,
     pub libvirt: LibvirtSection,
     pub defaults: DefaultsSection,
+    #[serde(default)]
+    pub ui: UiSection,
+    #[serde(default)]
+    pub naming: NamingSection,
+    #[serde(default)]
+    pub logging: LoggingSection,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +71,18 @@ pub struct LibvirtSection {
     pub images_dir: PathBuf,
     /// Main LAN network for pfSense (gateway's first NIC)
     pub lan_net: String,
+    /// Filesystem driver for the gateway's /proxy share
+    #[serde(default)]
+    pub share_driver: ShareDriver,
+    /// Connection URI for virsh and virt-install, e.g. `qemu:///session` or `qemu+ssh://host/system`
+    #[serde(default = "default_libvirt_uri")]
+    pub uri: String,
+    /// Seconds before a hung virt-install is killed and the VM creation rolled back; 0 waits forever
+    #[serde(default = "default_virt_install_timeout_secs")]
+    pub virt_install_timeout_secs: u64,
+    /// Private IPv4 range NAT role networks get a free /24 from
+    #[serde(default = "default_role_subnet_pool")]
+    pub role_subnet_pool: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +99,249 @@ pub struct DefaultsSection {
     pub fedora_os_variant: String,
 }
 
+/// The shareable part of the settings, exported as a standalone TOML snippet.
+///
+/// Leaves out the config root, log directory and anything secret so a team can
+/// hand out a baseline without leaking one machine's layout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedDefaults {
+    pub version: u32,
+    pub defaults: DefaultsSection,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub libvirt: Option<LibvirtSection>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UiSection {
+    /// Seconds between automatic dashboard refreshes; 0 means manual only
+    pub auto_refresh_secs: u64,
+}
+
+impl Default for UiSection {
+    fn default() -> Self {
+        Self {
+            auto_refresh_secs: 5,
+        }
+    }
+}
+
+impl UiSection {
+    /// The auto-refresh interval, or `None` when refreshing is manual
+    pub fn auto_refresh_interval(&self) -> Option<std::time::Duration> {
+        (self.auto_refresh_secs > 0).then(|| std::time::Duration::from_secs(self.auto_refresh_secs))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingSection {
+    /// Directory holding the rotated log files
+    #[serde(default = "default_log_dir")]
+    pub dir: PathBuf,
+    /// Least severe entries written to the log file
+    #[serde(default)]
+    pub level: LogLevel,
+}
+
+impl Default for LoggingSection {
+    fn default() -> Self {
+        Self {
+            dir: default_log_dir(),
+            level: LogLevel::default(),
+        }
+    }
+}
+
+fn default_log_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("proxy-vm-wizard")
+        .join("logs")
+}
+
+/// Patterns for VM names.
+///
+/// `{role}` is the role name, `{n}` the app VM number and `{ts}` the creation
+/// timestamp of a disposable VM. Names are parsed back with the same patterns to
+/// tell which role and kind a domain belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamingSection {
+    pub gateway: String,
+    pub app: String,
+    pub disposable: String,
+}
+
+impl Default for NamingSection {
+    fn default() -> Self {
+        Self {
+            gateway: "{role}-gw".to_string(),
+            app: "{role}-app-{n}".to_string(),
+            disposable: "disp-{role}-{ts}".to_string(),
+        }
+    }
+}
+
+impl NamingSection {
+    pub fn gateway_name(&self, role: &str) -> String {
+        self.gateway.replace("{role}", role)
+    }
+
+    pub fn app_name(&self, role: &str, number: u32) -> String {
+        self.app
+            .replace("{role}", role)
+            .replace("{n}", &number.to_string())
+    }
+
+    pub fn disposable_name(&self, role: &str, timestamp: &str) -> String {
+        self.disposable
+            .replace("{role}", role)
+            .replace("{ts}", timestamp)
+    }
+
+    /// Kind and role of a VM named by one of the patterns, or `None` for foreign domains
+    pub fn classify(&self, name: &str) -> Option<(VmKind, String)> {
+        [
+            (VmKind::ProxyGateway, &self.gateway),
+            (VmKind::App, &self.app),
+            (VmKind::DisposableApp, &self.disposable),
+        ]
+        .into_iter()
+        .find_map(|(kind, pattern)| {
+            let re = pattern_regex(pattern).ok()?;
+            let role = re.captures(name)?.name("role")?.as_str().to_string();
+            Some((kind, role))
+        })
+    }
+
+    /// Check a role name and every libvirt name derived from it.
+    ///
+    /// [`validate_role_name`] keeps the role usable as a directory name; this also
+    /// rejects roles whose VM or network names libvirt would refuse, e.g. because a
+    /// long pattern pushes them past the length limit.
+    pub fn validate_role(&self, role: &str) -> std::result::Result<(), String> {
+        validate_role_name(role)?;
+        let derived = [
+            ("gateway VM", self.gateway_name(role)),
+            ("app VM", self.app_name(role, u32::MAX)),
+            (
+                "disposable VM",
+                self.disposable_name(role, "20240101-120000"),
+            ),
+            ("network", format!("{}-inet", role)),
+        ];
+        for (label, name) in derived {
+            validate_vm_name(&name).map_err(|e| {
+                format!(
+                    "Role '{}' gives the {} name '{}', which libvirt would reject: {}",
+                    role, label, name, e
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let patterns = [
+            ("Gateway", &self.gateway, &["role"][..]),
+            ("App", &self.app, &["role", "n"][..]),
+            ("Disposable", &self.disposable, &["role", "ts"][..]),
+        ];
+        for (label, pattern, required) in patterns {
+            let placeholders = pattern_placeholders(pattern)
+                .map_err(|e| format!("{} VM name pattern: {}", label, e))?;
+            for name in required {
+                if !placeholders.contains(name) {
+                    return Err(format!(
+                        "{} VM name pattern must contain {{{}}}",
+                        label, name
+                    ));
+                }
+            }
+            if let Some(extra) = placeholders.iter().find(|p| !required.contains(p)) {
+                return Err(format!(
+                    "{} VM name pattern cannot use {{{}}}",
+                    label, extra
+                ));
+            }
+        }
+
+        // Every kind must parse back as itself, or VMs would land on the wrong role card
+        for role in ["work", "my-role"] {
+            let samples = [
+                (VmKind::ProxyGateway, self.gateway_name(role)),
+                (VmKind::App, self.app_name(role, 1)),
+                (
+                    VmKind::DisposableApp,
+                    self.disposable_name(role, "20240101-120000"),
+                ),
+            ];
+            for (kind, name) in &samples {
+                if self.classify(name) != Some((*kind, role.to_string())) {
+                    return Err(format!(
+                        "VM name patterns are ambiguous: '{}' is not recognized as a {} of role '{}'",
+                        name,
+                        kind.display_name(),
+                        role
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Placeholders used in `pattern`; also rejects characters libvirt or virsh would choke on
+fn pattern_placeholders(pattern: &str) -> std::result::Result<Vec<&str>, String> {
+    let mut placeholders = Vec::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        check_literal(&rest[..start])?;
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| "unclosed '{'".to_string())?;
+        let name = &rest[start + 1..start + end];
+        if !matches!(name, "role" | "n" | "ts") {
+            return Err(format!("unknown placeholder {{{}}}", name));
+        }
+        if placeholders.contains(&name) {
+            return Err(format!("{{{}}} appears more than once", name));
+        }
+        placeholders.push(name);
+        rest = &rest[start + end + 1..];
+    }
+    check_literal(rest)?;
+    Ok(placeholders)
+}
+
+fn check_literal(literal: &str) -> std::result::Result<(), String> {
+    match literal
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+    {
+        Some(c) => Err(format!("'{}' is not allowed in VM names", c)),
+        None => Ok(()),
+    }
+}
+
+/// Anchored regex for a pattern; the role is matched as short as possible
+fn pattern_regex(pattern: &str) -> std::result::Result<regex::Regex, String> {
+    pattern_placeholders(pattern)?;
+    let mut re = String::from("^");
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        re.push_str(&regex::escape(&rest[..start]));
+        let end = start + rest[start..].find('}').unwrap_or(0);
+        re.push_str(match &rest[start + 1..end] {
+            "role" => "(?P<role>[a-z0-9_-]+?)",
+            "ts" => "[0-9-]+",
+            _ => ".+",
+        });
+        rest = &rest[end + 1..];
+    }
+    re.push_str(&regex::escape(rest));
+    re.push('$');
+    regex::Regex::new(&re).map_err(|e| e.to_string())
+}
+
 impl Default for GlobalConfig {
     fn default() -> Self {
         let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("/tmp"));
@@ -57,6 +353,10 @@ impl Default for GlobalConfig {
             libvirt: LibvirtSection {
                 images_dir: PathBuf::from("/var/lib/libvirt/images"),
                 lan_net: "lan-net".to_string(),
+                share_driver: ShareDriver::default(),
+                uri: default_libvirt_uri(),
+                virt_install_timeout_secs: default_virt_install_timeout_secs(),
+                role_subnet_pool: default_role_subnet_pool(),
             },
             defaults: DefaultsSection {
                 gateway_ram_mb: 1024, // Minimum recommended for Debian
@@ -65,6 +365,9 @@ impl Default for GlobalConfig {
                 debian_os_variant: "debian12".to_string(),
                 fedora_os_variant: "fedora40".to_string(),
             },
+            ui: UiSection::default(),
+            naming: NamingSection::default(),
+            logging: LoggingSection::default(),
         }
     }
 }
@@ -129,15 +432,52 @@ impl GlobalConfig {
         if self.libvirt.lan_net.is_empty() {
             return Err(Error::validation("LAN network name cannot be empty"));
         }
+        validate_libvirt_uri(&self.libvirt.uri).map_err(Error::validation)?;
+        crate::validate_role_subnet_pool(&self.libvirt.role_subnet_pool)
+            .map_err(Error::validation)?;
         if self.defaults.gateway_ram_mb < 128 {
             return Err(Error::validation("Gateway RAM must be at least 128 MB"));
         }
         if self.defaults.app_ram_mb < 256 {
             return Err(Error::validation("App RAM must be at least 256 MB"));
         }
+        if self.ui.auto_refresh_secs != 0 && self.ui.auto_refresh_secs < MIN_AUTO_REFRESH_SECS {
+            return Err(Error::validation(format!(
+                "Auto-refresh interval must be 0 (off) or at least {} seconds",
+                MIN_AUTO_REFRESH_SECS
+            )));
+        }
+        self.naming.validate().map_err(Error::validation)?;
         Ok(())
     }
 
+    /// Serialize the VM defaults, and the libvirt settings if asked, as a TOML snippet
+    pub fn export_defaults(&self, include_libvirt: bool) -> Result<String> {
+        let shared = SharedDefaults {
+            version: CONFIG_VERSION,
+            defaults: self.defaults.clone(),
+            libvirt: include_libvirt.then(|| self.libvirt.clone()),
+        };
+        Ok(toml::to_string_pretty(&shared)?)
+    }
+
+    /// Replace the sections carried by a snippet from [`Self::export_defaults`].
+    ///
+    /// The result is validated before anything changes. Returns whether the
+    /// snippet included libvirt settings.
+    pub fn apply_defaults(&mut self, snippet: &str) -> Result<bool> {
+        let shared: SharedDefaults = toml::from_str(snippet)?;
+        let mut updated = self.clone();
+        updated.defaults = shared.defaults;
+        let has_libvirt = shared.libvirt.is_some();
+        if let Some(libvirt) = shared.libvirt {
+            updated.libvirt = libvirt;
+        }
+        updated.validate()?;
+        *self = updated;
+        Ok(has_libvirt)
+    }
+
     /// Load encrypted config from file
     pub fn load_encrypted(encryption: &EncryptionManager) -> Result<Self> {
         let path = Self::default_path();
@@ -184,6 +524,21 @@ pub struct Template {
     pub default_ram_mb: u32,
     /// Optional notes about this template
     pub notes: Option<String>,
+    /// Disk cache/IO options for VMs created from this template
+    #[serde(default)]
+    pub disk_tuning: DiskTuning,
+    /// Kernel/console options for images that need them to boot
+    #[serde(default)]
+    pub boot: BootOptions,
+    /// Pinned templates can't be removed until they are unpinned
+    #[serde(default)]
+    pub pinned: bool,
+    /// Whether `path` is a disk image to import or an installer ISO
+    #[serde(default)]
+    pub install: InstallSource,
+    /// Size of the blank disk created for each VM when installing from an ISO
+    #[serde(default = "default_install_disk_gb")]
+    pub install_disk_gb: u32,
 }
 
 impl Template {
@@ -203,9 +558,26 @@ impl Template {
             role_kind,
             default_ram_mb: 1024, // Minimum recommended for most OS
             notes: None,
+            disk_tuning: DiskTuning::default(),
+            boot: BootOptions::default(),
+            pinned: false,
+            install: InstallSource::Import,
+            install_disk_gb: DEFAULT_INSTALL_DISK_GB,
         }
     }
 
+    /// Refuse a template that installs from an ISO where a VM needs a ready
+    /// disk; only gateways can be installed from scratch
+    pub fn require_disk_image(&self) -> Result<()> {
+        if self.install.is_fresh_install() {
+            return Err(Error::template(format!(
+                "Template '{}' installs from an ISO; app and disposable VMs need a disk image template",
+                self.label
+            )));
+        }
+        Ok(())
+    }
+
     /// Check if the template file exists and is readable
     pub fn validate(&self) -> Result<()> {
         if !self.path.exists() {
@@ -312,14 +684,62 @@ impl TemplateRegistry {
         Ok(())
     }
 
-    /// Remove a template by ID
-    pub fn remove(&mut self, id: &str) -> Result<()> {
+    /// Another template registered for the same image file as `template`.
+    /// Paths are compared with symlinks resolved when the file exists.
+    pub fn find_duplicate(&self, template: &Template) -> Option<&Template> {
+        let resolve = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let path = resolve(&template.path);
         self.templates
-            .remove(id)
+            .values()
+            .filter(|t| t.id != template.id && resolve(&t.path) == path)
+            .min_by(|a, b| a.label.cmp(&b.label))
+    }
+
+    /// Remove a template by ID; pinned templates are refused
+    pub fn remove(&mut self, id: &str) -> Result<()> {
+        let template = self
+            .templates
+            .get(id)
+            .ok_or_else(|| Error::NotFound(format!("Template with ID '{}' not found", id)))?;
+        if template.pinned {
+            return Err(Error::validation(format!(
+                "Template '{}' is pinned; unpin it before removing it",
+                template.label
+            )));
+        }
+        self.templates.remove(id);
+        Ok(())
+    }
+
+    /// Pin or unpin a template
+    pub fn set_pinned(&mut self, id: &str, pinned: bool) -> Result<()> {
+        let template = self
+            .templates
+            .get_mut(id)
             .ok_or_else(|| Error::NotFound(format!("Template with ID '{}' not found", id)))?;
+        template.pinned = pinned;
         Ok(())
     }
 
+    /// Rewrite the path prefix of every template under `old_base` to `new_base`.
+    ///
+    /// Returns the IDs of rebased templates whose file doesn't exist at the new location.
+    /// Templates outside `old_base` are left alone.
+    pub fn rebase_paths(&mut self, old_base: &Path, new_base: &Path) -> Vec<String> {
+        let mut missing = Vec::new();
+        for template in self.templates.values_mut() {
+            let Ok(relative) = template.path.strip_prefix(old_base) else {
+                continue;
+            };
+            template.path = new_base.join(relative);
+            if !template.path.exists() {
+                missing.push(template.id.clone());
+            }
+        }
+        missing.sort();
+        missing
+    }
+
     /// Get a template by ID
     pub fn get(&self, id: &str) -> Option<&Template> {
         self.templates.get(id)
@@ -350,6 +770,7 @@ impl TemplateRegistry {
                     || t.role_kind == RoleKind::DisposableApp
                     || t.role_kind == RoleKind::Generic
             })
+            .filter(|t| !t.install.is_fresh_install())
             .collect()
     }
 
@@ -415,6 +836,30 @@ pub struct RoleMeta {
     pub gateway_mode: GatewayMode,
     /// Count of app VMs created for this role
     pub app_vm_count: u32,
+    /// Most app VMs the role may have at once
+    #[serde(default = "default_max_app_vms")]
+    pub max_app_vms: u32,
+    /// Optional IPv6 subnet for the role network (e.g. `fd00:10::/64`); IPv4-only when unset
+    #[serde(default)]
+    pub ipv6_subnet: Option<String>,
+    /// How the role network connects to the host; isolated unless chosen otherwise
+    #[serde(default)]
+    pub network_mode: RoleNetworkMode,
+    /// Host CPUs the gateway's vCPUs are pinned to, e.g. `2-3`; unpinned when unset
+    #[serde(default)]
+    pub cpuset: Option<String>,
+    /// Networks attached to the gateway after its LAN and role NICs, e.g. for management
+    #[serde(default)]
+    pub extra_gateway_networks: Vec<String>,
+    /// What happens when the gateway's guest crashes
+    #[serde(default)]
+    pub on_crash: CrashPolicy,
+    /// Scheduled rotation of the exit proxy (proxy chain mode only)
+    #[serde(default)]
+    pub rotation: Option<RotationPolicy>,
+    /// When this metadata was last saved (file mtime for metas written before this existed)
+    #[serde(default)]
+    pub last_modified: Option<DateTime<Utc>>,
 }
 
 impl RoleMeta {
@@ -431,9 +876,24 @@ impl RoleMeta {
             gw_vcpus: None,
             gateway_mode: GatewayMode::ProxyChain,
             app_vm_count: 0,
+            max_app_vms: DEFAULT_MAX_APP_VMS,
+            ipv6_subnet: None,
+            network_mode: RoleNetworkMode::default(),
+            cpuset: None,
+            extra_gateway_networks: Vec::new(),
+            on_crash: CrashPolicy::default(),
+            rotation: None,
+            last_modified: None,
         }
     }
 
+    /// The gateway's networks in NIC order: LAN uplink, role network, then any extras
+    pub fn gateway_networks(&self, lan_net: &str, role_net: &str) -> Vec<String> {
+        let mut networks = vec![lan_net.to_string(), role_net.to_string()];
+        networks.extend(self.extra_gateway_networks.iter().cloned());
+        networks
+    }
+
     /// Get the path for this role's metadata file
     pub fn path_for_role(cfg_root: &Path, role: &str) -> PathBuf {
         cfg_root.join(role).join("role-meta.toml")
@@ -449,18 +909,63 @@ impl RoleMeta {
             )));
         }
         let content = fs::read_to_string(&path)?;
-        let meta: Self = toml::from_str(&content)?;
+        let mut meta: Self = toml::from_str(&content)?;
+        if meta.last_modified.is_none() {
+            meta.last_modified = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Utc>::from);
+        }
         Ok(meta)
     }
 
-    /// Save role metadata to file
-    pub fn save(&self, cfg_root: &Path) -> Result<()> {
+    /// Save role metadata to file, stamping `last_modified`
+    pub fn save(&mut self, cfg_root: &Path) -> Result<()> {
         let role_dir = cfg_root.join(&self.role_name);
         fs::create_dir_all(&role_dir)?;
 
+        self.last_modified = Some(Utc::now());
+
         let path = Self::path_for_role(cfg_root, &self.role_name);
         let content = toml::to_string_pretty(self)?;
         fs::write(&path, content)?;
+
+        // SECURITY: a rotation pool may hold proxy credentials, same as proxy.conf
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    /// Bring `gateway_mode` in line with the role's proxy.conf, which is what the
+    /// gateway actually runs. Saves the metadata and returns the mode it held
+    /// when the two disagreed; a missing proxy.conf or an unknown `GATEWAY_MODE`
+    /// leaves the metadata alone.
+    pub fn reconcile_gateway_mode(&mut self, cfg_root: &Path) -> Result<Option<GatewayMode>> {
+        let conf_path = cfg_root.join(&self.role_name).join("proxy.conf");
+        let Ok(content) = fs::read_to_string(conf_path) else {
+            return Ok(None);
+        };
+        match crate::ProxyConfigBuilder::conf_gateway_mode(&content) {
+            Some(mode) if mode != self.gateway_mode => {
+                let previous = std::mem::replace(&mut self.gateway_mode, mode);
+                self.save(cfg_root)?;
+                Ok(Some(previous))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Refuse another app VM when the role already has `existing` of `max_app_vms`
+    pub fn check_app_vm_limit(&self, existing: usize) -> Result<()> {
+        if existing >= self.max_app_vms as usize {
+            return Err(Error::validation(format!(
+                "Role '{}' already has {} app VM(s), its limit is {}; delete one or raise the limit",
+                self.role_name, existing, self.max_app_vms
+            )));
+        }
         Ok(())
     }
 
@@ -471,13 +976,13 @@ impl RoleMeta {
     }
 
     /// Get gateway VM name
-    pub fn gw_vm_name(&self) -> String {
-        format!("{}-gw", self.role_name)
+    pub fn gw_vm_name(&self, naming: &NamingSection) -> String {
+        naming.gateway_name(&self.role_name)
     }
 
     /// Get app VM name for given number
-    pub fn app_vm_name(&self, number: u32) -> String {
-        format!("{}-app-{}", self.role_name, number)
+    pub fn app_vm_name(&self, naming: &NamingSection, number: u32) -> String {
+        naming.app_name(&self.role_name, number)
     }
 
     /// Get role network name
@@ -486,6 +991,27 @@ impl RoleMeta {
     }
 }
 
+/// Short relative description of a past time, e.g. "2h ago"
+pub fn format_relative_time(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let secs = (now - then).num_seconds();
+    if secs < 60 {
+        return "just now".to_string();
+    }
+    let mins = secs / 60;
+    if mins < 60 {
+        return format!("{}m ago", mins);
+    }
+    let hours = mins / 60;
+    if hours < 24 {
+        return format!("{}h ago", hours);
+    }
+    let days = hours / 24;
+    if days < 30 {
+        return format!("{}d ago", days);
+    }
+    timefmt::date(&then)
+}
+
 /// Discover existing roles from the config root directory
 pub fn discover_roles(cfg_root: &Path) -> Result<Vec<String>> {
     if !cfg_root.exists() {
@@ -513,11 +1039,134 @@ pub fn discover_roles(cfg_root: &Path) -> Result<Vec<String>> {
     Ok(roles)
 }
 
+/// Roles whose metadata refers to a template, e.g. `Role 'work' (gateway, app VMs)`
+pub fn roles_using_template(cfg_root: &Path, template_id: &str) -> Result<Vec<String>> {
+    let mut users = Vec::new();
+    for role in discover_roles(cfg_root)? {
+        let Ok(meta) = RoleMeta::load(cfg_root, &role) else {
+            continue;
+        };
+        let uses: Vec<&str> = [
+            (&meta.gw_template_id, "gateway"),
+            (&meta.app_template_id, "app VMs"),
+            (&meta.disp_template_id, "disposables"),
+        ]
+        .into_iter()
+        .filter(|(id, _)| id.as_deref() == Some(template_id))
+        .map(|(_, kind)| kind)
+        .collect();
+        if !uses.is_empty() {
+            users.push(format!("Role '{}' ({})", role, uses.join(", ")));
+        }
+    }
+    Ok(users)
+}
+
+/// Move an unreadable config file aside so a fresh one can be written in its place.
+///
+/// Returns the backup path (`<file>.unreadable-<timestamp>`).
+pub fn backup_unreadable_file(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| Error::validation(format!("Invalid file path: {}", path.display())))?;
+    let backup = path.with_file_name(format!("{}.unreadable-{}", file_name, timefmt::now_stamp()));
+    fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_naming_patterns() {
+        let naming = NamingSection::default();
+        assert_eq!(naming.gateway_name("work"), "work-gw");
+        assert_eq!(naming.app_name("work", 3), "work-app-3");
+        assert_eq!(
+            naming.classify("my-role-gw"),
+            Some((VmKind::ProxyGateway, "my-role".to_string()))
+        );
+        assert_eq!(
+            naming.classify("work-app-browser"),
+            Some((VmKind::App, "work".to_string()))
+        );
+        assert_eq!(
+            naming.classify("disp-my-role-20240101-120000"),
+            Some((VmKind::DisposableApp, "my-role".to_string()))
+        );
+        assert_eq!(naming.classify("pfsense"), None);
+        assert!(naming.validate().is_ok());
+
+        let custom = NamingSection {
+            gateway: "pvmw-{role}-gw".to_string(),
+            app: "pvmw-{role}-app-{n}".to_string(),
+            disposable: "pvmw-{role}-tmp-{ts}".to_string(),
+        };
+        assert!(custom.validate().is_ok());
+        assert_eq!(
+            custom.classify("pvmw-work-app-2"),
+            Some((VmKind::App, "work".to_string()))
+        );
+        assert_eq!(custom.classify("work-gw"), None);
+
+        let invalid = |gateway: &str, app: &str| {
+            NamingSection {
+                gateway: gateway.to_string(),
+                app: app.to_string(),
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        };
+        assert!(invalid("gateway", "{role}-app-{n}"));
+        assert!(invalid("{role}-gw", "{role}-app"));
+        assert!(invalid("{role} gw", "{role}-app-{n}"));
+        assert!(invalid("{role}-{host}", "{role}-app-{n}"));
+        assert!(invalid("{role", "{role}-app-{n}"));
+        // A bare {role} gateway would also match every app VM name
+        assert!(invalid("{role}", "{role}-app-{n}"));
+        assert!(invalid("{role}-app-1", "{role}-app-{n}"));
+    }
+
+    #[test]
+    fn test_validate_role() {
+        let naming = NamingSection::default();
+        assert!(naming.validate_role("work").is_ok());
+        assert!(naming.validate_role("my_role-2").is_ok());
+        assert!(naming.validate_role(&"a".repeat(32)).is_ok());
+
+        for role in [
+            "",
+            "my role",
+            "café",
+            "работа",
+            "a/b",
+            "..",
+            "-work",
+            "Work",
+        ] {
+            assert!(naming.validate_role(role).is_err(), "{:?}", role);
+        }
+        assert!(naming.validate_role(&"a".repeat(33)).is_err());
+        // Spaces and case are normalized away before validation
+        assert!(naming
+            .validate_role(&crate::normalize_role_name("My Role"))
+            .is_ok());
+
+        // A long pattern can push a legal role name past libvirt's limit
+        let long = NamingSection {
+            disposable: "disposable-vm-of-role-{role}-started-{ts}".to_string(),
+            ..Default::default()
+        };
+        assert!(long.validate().is_ok());
+        assert!(long.validate_role("work").is_ok());
+        let err = long.validate_role(&"a".repeat(32)).unwrap_err();
+        assert!(err.contains("disposable VM name"));
+    }
+
     #[test]
     fn test_global_config_default() {
         let config = GlobalConfig::default();
@@ -539,6 +1188,65 @@ mod tests {
         assert_eq!(loaded.libvirt.lan_net, config.libvirt.lan_net);
     }
 
+    #[test]
+    fn test_auto_refresh_setting() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        // Configs written before the [ui] section existed keep the old 5s cadence
+        let mut config = GlobalConfig::default();
+        let content = toml::to_string_pretty(&config).unwrap();
+        let legacy: String = content.split("[ui]").next().unwrap().to_string();
+        fs::write(&path, legacy).unwrap();
+        let loaded = GlobalConfig::load(&path).unwrap();
+        assert_eq!(loaded.ui.auto_refresh_secs, 5);
+
+        config.ui.auto_refresh_secs = 0;
+        assert!(config.validate().is_ok());
+        assert!(config.ui.auto_refresh_interval().is_none());
+
+        config.ui.auto_refresh_secs = 1;
+        assert!(config.validate().is_err());
+
+        config.ui.auto_refresh_secs = 30;
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.ui.auto_refresh_interval(),
+            Some(std::time::Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_export_apply_defaults() {
+        let mut source = GlobalConfig::default();
+        source.defaults.app_ram_mb = 4096;
+        source.defaults.debian_os_variant = "debian13".to_string();
+        source.libvirt.lan_net = "office-lan".to_string();
+
+        let snippet = source.export_defaults(false).unwrap();
+        assert!(!snippet.contains("[cfg]"));
+        assert!(!snippet.contains("[libvirt]"));
+
+        let mut target = GlobalConfig::default();
+        target.cfg.root = PathBuf::from("/srv/roles");
+        assert!(!target.apply_defaults(&snippet).unwrap());
+        assert_eq!(target.defaults.app_ram_mb, 4096);
+        assert_eq!(target.defaults.debian_os_variant, "debian13");
+        assert_eq!(target.libvirt.lan_net, "lan-net");
+        assert_eq!(target.cfg.root, PathBuf::from("/srv/roles"));
+
+        let snippet = source.export_defaults(true).unwrap();
+        assert!(target.apply_defaults(&snippet).unwrap());
+        assert_eq!(target.libvirt.lan_net, "office-lan");
+
+        // Invalid values leave the config untouched
+        source.defaults.app_ram_mb = 64;
+        let snippet = source.export_defaults(false).unwrap();
+        assert!(target.apply_defaults(&snippet).is_err());
+        assert_eq!(target.defaults.app_ram_mb, 4096);
+        assert!(target.apply_defaults("not = [toml").is_err());
+    }
+
     #[test]
     fn test_template_registry() {
         let mut registry = TemplateRegistry::default();
@@ -557,11 +1265,152 @@ mod tests {
         // Can't add duplicate
         assert!(registry.add(template).is_err());
 
+        // Pinned templates can't be removed until unpinned
+        registry.set_pinned("test-1", true).unwrap();
+        assert!(registry.remove("test-1").is_err());
+        let reloaded: TemplateRegistry =
+            toml::from_str(&toml::to_string_pretty(&registry).unwrap()).unwrap();
+        assert!(reloaded.get("test-1").unwrap().pinned);
+        registry.set_pinned("test-1", false).unwrap();
+
         // Can remove
         registry.remove("test-1").unwrap();
         assert!(registry.get("test-1").is_none());
     }
 
+    #[test]
+    fn test_iso_template() {
+        let mut registry = TemplateRegistry::default();
+        let mut iso = Template::new(
+            "netinst",
+            "Debian netinst",
+            PathBuf::from("/tmp/debian-12-netinst.iso"),
+            "debian12",
+            RoleKind::Generic,
+        );
+        iso.install = InstallSource::Cdrom;
+        registry.add(iso.clone()).unwrap();
+
+        assert!(iso.require_disk_image().is_err());
+        assert_eq!(registry.get_gateway_templates().len(), 1);
+        assert!(registry.get_app_templates().is_empty());
+
+        // Registries written before install sources existed import their images
+        let mut old = toml::to_string_pretty(&registry).unwrap();
+        old = old
+            .lines()
+            .filter(|line| !line.starts_with("install"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let reloaded: TemplateRegistry = toml::from_str(&old).unwrap();
+        let template = reloaded.get("netinst").unwrap();
+        assert_eq!(template.install, InstallSource::Import);
+        assert_eq!(template.install_disk_gb, DEFAULT_INSTALL_DISK_GB);
+    }
+
+    #[test]
+    fn test_roles_using_template() {
+        let dir = tempdir().unwrap();
+        let mut work = RoleMeta::new("work".to_string());
+        work.gw_template_id = Some("debian".to_string());
+        work.app_template_id = Some("debian".to_string());
+        work.save(dir.path()).unwrap();
+        let mut bank = RoleMeta::new("bank".to_string());
+        bank.gw_template_id = Some("alpine".to_string());
+        bank.disp_template_id = Some("debian".to_string());
+        bank.save(dir.path()).unwrap();
+
+        assert_eq!(
+            roles_using_template(dir.path(), "debian").unwrap(),
+            vec![
+                "Role 'bank' (disposables)".to_string(),
+                "Role 'work' (gateway, app VMs)".to_string(),
+            ]
+        );
+        assert!(roles_using_template(dir.path(), "fedora")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rebase_paths() {
+        let dir = tempdir().unwrap();
+        let old_base = dir.path().join("old");
+        let new_base = dir.path().join("new");
+        fs::create_dir_all(&new_base).unwrap();
+        fs::write(new_base.join("debian.qcow2"), "").unwrap();
+
+        let mut registry = TemplateRegistry::default();
+        for (id, path) in [
+            ("present", old_base.join("debian.qcow2")),
+            ("missing", old_base.join("sub/fedora.qcow2")),
+            ("elsewhere", PathBuf::from("/srv/other.qcow2")),
+        ] {
+            registry
+                .add(Template::new(id, id, path, "debian12", RoleKind::Generic))
+                .unwrap();
+        }
+
+        let missing = registry.rebase_paths(&old_base, &new_base);
+        assert_eq!(missing, vec!["missing".to_string()]);
+        assert_eq!(
+            registry.get("present").unwrap().path,
+            new_base.join("debian.qcow2")
+        );
+        assert_eq!(
+            registry.get("missing").unwrap().path,
+            new_base.join("sub/fedora.qcow2")
+        );
+        assert_eq!(
+            registry.get("elsewhere").unwrap().path,
+            PathBuf::from("/srv/other.qcow2")
+        );
+    }
+
+    #[test]
+    fn test_find_duplicate_template() {
+        let dir = tempdir().unwrap();
+        let image = dir.path().join("debian.qcow2");
+        fs::write(&image, "").unwrap();
+        let link = dir.path().join("link.qcow2");
+        std::os::unix::fs::symlink(&image, &link).unwrap();
+
+        let mut registry = TemplateRegistry::default();
+        registry
+            .add(Template::new(
+                "gw",
+                "Debian GW",
+                image.clone(),
+                "debian12",
+                RoleKind::ProxyGateway,
+            ))
+            .unwrap();
+
+        let same = Template::new(
+            "new",
+            "Debian App",
+            image.clone(),
+            "debian12",
+            RoleKind::App,
+        );
+        assert_eq!(registry.find_duplicate(&same).unwrap().label, "Debian GW");
+        let via_link = Template::new("new", "Linked", link, "debian12", RoleKind::App);
+        assert_eq!(registry.find_duplicate(&via_link).unwrap().id, "gw");
+
+        // Editing a template doesn't flag itself
+        let edited = Template::new("gw", "Renamed", image, "debian12", RoleKind::ProxyGateway);
+        assert!(registry.find_duplicate(&edited).is_none());
+
+        let other = Template::new(
+            "new",
+            "Other",
+            dir.path().join("fedora.qcow2"),
+            "fedora40",
+            RoleKind::App,
+        );
+        assert!(registry.find_duplicate(&other).is_none());
+    }
+
     #[test]
     fn test_role_meta() {
         let dir = tempdir().unwrap();
@@ -575,5 +1424,87 @@ mod tests {
         let loaded = RoleMeta::load(cfg_root, "work").unwrap();
         assert_eq!(loaded.role_name, "work");
         assert_eq!(loaded.gw_template_id, Some("template-1".to_string()));
+        assert!(loaded.last_modified.is_some());
+        assert_eq!(loaded.last_modified, meta.last_modified);
+    }
+
+    #[test]
+    fn test_app_vm_limit() {
+        let mut meta = RoleMeta::new("work".to_string());
+        meta.max_app_vms = 2;
+        assert!(meta.check_app_vm_limit(1).is_ok());
+        let err = meta.check_app_vm_limit(2).unwrap_err();
+        assert!(err.to_string().contains("its limit is 2"));
+    }
+
+    #[test]
+    fn test_reconcile_gateway_mode() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let mut meta = RoleMeta::new("work".to_string());
+        meta.save(root).unwrap();
+
+        // No proxy.conf yet
+        assert_eq!(meta.reconcile_gateway_mode(root).unwrap(), None);
+
+        fs::write(root.join("work/proxy.conf"), "GATEWAY_MODE=WIREGUARD\n").unwrap();
+        assert_eq!(
+            meta.reconcile_gateway_mode(root).unwrap(),
+            Some(GatewayMode::ProxyChain)
+        );
+        assert_eq!(meta.gateway_mode, GatewayMode::WireGuard);
+        let reloaded = RoleMeta::load(root, "work").unwrap();
+        assert_eq!(reloaded.gateway_mode, GatewayMode::WireGuard);
+        assert_eq!(meta.reconcile_gateway_mode(root).unwrap(), None);
+
+        // An unknown mode is a broken proxy.conf, not a reason to rewrite the metadata
+        fs::write(root.join("work/proxy.conf"), "GATEWAY_MODE=TOR\n").unwrap();
+        assert_eq!(meta.reconcile_gateway_mode(root).unwrap(), None);
+        assert_eq!(meta.gateway_mode, GatewayMode::WireGuard);
+    }
+
+    #[test]
+    fn test_role_meta_without_last_modified_uses_mtime() {
+        let dir = tempdir().unwrap();
+        let cfg_root = dir.path();
+        fs::create_dir_all(cfg_root.join("legacy")).unwrap();
+        fs::write(
+            RoleMeta::path_for_role(cfg_root, "legacy"),
+            "version = 1\nrole_name = \"legacy\"\ngateway_mode = \"PROXY_CHAIN\"\napp_vm_count = 2\n",
+        )
+        .unwrap();
+
+        let loaded = RoleMeta::load(cfg_root, "legacy").unwrap();
+        assert_eq!(loaded.app_vm_count, 2);
+        assert_eq!(loaded.max_app_vms, DEFAULT_MAX_APP_VMS);
+        assert!(loaded.last_modified.is_some());
+    }
+
+    #[test]
+    fn test_format_relative_time() {
+        let now = Utc::now();
+        let ago = |secs| format_relative_time(now - chrono::Duration::seconds(secs), now);
+        assert_eq!(ago(5), "just now");
+        assert_eq!(ago(5 * 60), "5m ago");
+        assert_eq!(ago(2 * 3600 + 10), "2h ago");
+        assert_eq!(ago(3 * 86400), "3d ago");
+        assert_eq!(ago(-30), "just now");
+    }
+
+    #[test]
+    fn test_backup_unreadable_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "PVMW_ENC_V1 garbage").unwrap();
+
+        let backup = backup_unreadable_file(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "PVMW_ENC_V1 garbage");
+        assert!(backup
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("config.toml.unreadable-"));
     }
 }
@@ -0,0 +1,217 @@
+//! Diagnostics archive for bug reports
+//!
+//! Gathers what is needed to debug a setup into one gzipped tarball, built with
+//! the same `tar` CLI as configuration bundles:
+//!
+//! ```text
+//! summary.txt          version, libvirt URI and prerequisite check results
+//! virsh-list.txt       `virsh list --all`
+//! virsh-net-list.txt   `virsh net-list --all`
+//! config.toml          settings as loaded by the app
+//! templates.toml       template registry
+//! roles/<role>/        role-meta.toml, proxy.conf and a listing of the other files
+//! log.txt              recent log entries
+//! ```
+//!
+//! Proxy passwords and the OpenVPN auth file are replaced by [`REDACTED`]. VPN
+//! configs hold private keys, so they are only listed by name, never copied.
+
+use crate::bundle::{path_str, staging_dir, tar};
+use crate::config::discover_roles;
+use crate::proxy_config::REDACTED;
+use crate::{GlobalConfig, LibvirtAdapter, ProxyConfigBuilder, Result, RoleMeta, TemplateRegistry};
+use chrono::Local;
+use std::fs;
+use std::path::Path;
+
+/// Role files copied into the archive; everything else is only listed
+const ROLE_FILES: &[&str] = &["role-meta.toml", "proxy.conf"];
+
+/// Write a diagnostics archive to `dest`; `log` holds the recent log lines
+pub fn export_diagnostics(
+    libvirt: &LibvirtAdapter,
+    config: &GlobalConfig,
+    templates: &TemplateRegistry,
+    log: &[String],
+    dest: &Path,
+) -> Result<()> {
+    let staging = staging_dir("diagnostics")?;
+    let result = (|| {
+        fs::write(staging.join("summary.txt"), summary(libvirt))?;
+        fs::write(
+            staging.join("virsh-list.txt"),
+            command_output(libvirt, &["list", "--all"]),
+        )?;
+        fs::write(
+            staging.join("virsh-net-list.txt"),
+            command_output(libvirt, &["net-list", "--all"]),
+        )?;
+        fs::write(staging.join("config.toml"), toml::to_string_pretty(config)?)?;
+        fs::write(
+            staging.join("templates.toml"),
+            toml::to_string_pretty(templates)?,
+        )?;
+
+        let roles_out = staging.join("roles");
+        for role in discover_roles(&config.cfg.root)? {
+            let role_out = roles_out.join(&role);
+            fs::create_dir_all(&role_out)?;
+            write_role(&config.cfg.root, &role, &role_out)?;
+        }
+
+        let mut log_text = log.join("\n");
+        log_text.push('\n');
+        fs::write(staging.join("log.txt"), log_text)?;
+
+        tar(&["-czf", path_str(dest)?, "-C", path_str(&staging)?, "."]).map(|_| ())
+    })();
+    fs::remove_dir_all(&staging).ok();
+    result
+}
+
+fn summary(libvirt: &LibvirtAdapter) -> String {
+    let mut lines = vec![
+        format!("Proxy VM Wizard {}", env!("CARGO_PKG_VERSION")),
+        format!("Generated: {}", crate::timefmt::date_time(&Local::now())),
+        format!(
+            "Libvirt URI: {}",
+            libvirt.uri.as_deref().unwrap_or("(default)")
+        ),
+    ];
+    lines.push(match libvirt.check_prerequisites() {
+        Ok(_) => "Prerequisites: virsh, virt-install and qemu-img found".to_string(),
+        Err(e) => format!("Prerequisites: {}", e),
+    });
+    lines.push(match libvirt.check_libvirt_access() {
+        Ok(()) => "Libvirt access: ok".to_string(),
+        Err(e) => format!("Libvirt access: {}", e),
+    });
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// stdout of a virsh command, or why it failed; a broken libvirt is what is
+/// being diagnosed, so failures go into the file instead of stopping the export
+fn command_output(libvirt: &LibvirtAdapter, args: &[&str]) -> String {
+    match libvirt.run_cmd("virsh", args) {
+        Ok(output) if output.success() => output.stdout,
+        Ok(output) => format!(
+            "virsh {} failed: {}\n",
+            args.join(" "),
+            output.stderr.trim()
+        ),
+        Err(e) => format!("virsh {} failed: {}\n", args.join(" "), e),
+    }
+}
+
+fn write_role(cfg_root: &Path, role: &str, out: &Path) -> Result<()> {
+    let role_dir = cfg_root.join(role);
+
+    if let Ok(mut meta) = RoleMeta::load(cfg_root, role) {
+        if let Some(rotation) = &mut meta.rotation {
+            for hop in &mut rotation.pool {
+                if hop.password.as_deref().is_some_and(|p| !p.is_empty()) {
+                    hop.password = Some(REDACTED.to_string());
+                }
+            }
+        }
+        fs::write(out.join("role-meta.toml"), toml::to_string_pretty(&meta)?)?;
+    }
+    if let Ok(content) = fs::read_to_string(role_dir.join("proxy.conf")) {
+        let mut redacted = ProxyConfigBuilder::redact_conf_text(&content);
+        redacted.push('\n');
+        fs::write(out.join("proxy.conf"), redacted)?;
+    }
+
+    let mut listing: Vec<String> = fs::read_dir(&role_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if ROLE_FILES.contains(&name.as_str()) {
+                return None;
+            }
+            Some(match entry.metadata() {
+                Ok(meta) if meta.is_dir() => format!("{}/", name),
+                Ok(meta) => format!("{} ({} bytes)", name, meta.len()),
+                Err(_) => name,
+            })
+        })
+        .collect();
+    listing.sort();
+    listing.push(String::new());
+    fs::write(out.join("files.txt"), listing.join("\n"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::MockRunner;
+    use crate::{ProxyHop, ProxyType, RotationPolicy, RotationStrategy};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_export_diagnostics_redacts_secrets() {
+        let dir = tempdir().unwrap();
+        let mut config = GlobalConfig::default();
+        config.cfg.root = dir.path().join("roles");
+        let role_dir = config.cfg.root.join("work");
+        fs::create_dir_all(&role_dir).unwrap();
+        fs::write(
+            role_dir.join("proxy.conf"),
+            "PROXY_COUNT=1\nPROXY_1_HOST=proxy.example.com\nPROXY_1_PASS=hunter2\n",
+        )
+        .unwrap();
+        fs::write(
+            role_dir.join("wg0.conf"),
+            "[Interface]\nPrivateKey = secret\n",
+        )
+        .unwrap();
+
+        let mut meta = RoleMeta::new("work".to_string());
+        let mut hop = ProxyHop::new(1, ProxyType::Socks5, "pool.example.com".to_string(), 1080);
+        hop.password = Some("pool-secret".to_string());
+        meta.rotation = Some(RotationPolicy {
+            interval_secs: 3600,
+            strategy: RotationStrategy::RoundRobin,
+            pool: vec![hop],
+        });
+        meta.save(&config.cfg.root).unwrap();
+
+        let runner = MockRunner::new().on("virsh list --all", " Id   Name   State\n");
+        let libvirt = LibvirtAdapter::with_runner(Arc::new(runner));
+        let dest = dir.path().join("diagnostics.tar.gz");
+        export_diagnostics(
+            &libvirt,
+            &config,
+            &TemplateRegistry::default(),
+            &["12:00:00 [INFO] Refreshed".to_string()],
+            &dest,
+        )
+        .unwrap();
+
+        let out = dir.path().join("out");
+        fs::create_dir_all(&out).unwrap();
+        tar(&[
+            "-xzf",
+            path_str(&dest).unwrap(),
+            "-C",
+            path_str(&out).unwrap(),
+        ])
+        .unwrap();
+        let read = |name: &str| fs::read_to_string(out.join(name)).unwrap();
+
+        assert!(read("virsh-list.txt").contains("Name"));
+        assert!(read("virsh-net-list.txt").contains("failed"));
+        assert!(read("log.txt").contains("Refreshed"));
+        let conf = read("roles/work/proxy.conf");
+        assert!(conf.contains("PROXY_1_HOST=proxy.example.com"));
+        assert!(!conf.contains("hunter2"));
+        let meta = read("roles/work/role-meta.toml");
+        assert!(meta.contains("pool.example.com"));
+        assert!(!meta.contains("pool-secret"));
+        assert!(!out.join("roles/work/wg0.conf").exists());
+        assert!(read("roles/work/files.txt").contains("wg0.conf"));
+    }
+}
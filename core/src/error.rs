@@ -1,5 +1,7 @@
 //! Error types for the proxy-vm-core crate
 
+use crate::format_size;
+use std::path::PathBuf;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -19,9 +21,21 @@ pub enum Error {
     #[error("Command not found: {0}")]
     CommandNotFound(String),
 
+    #[error("Command timed out after {secs}s and was killed: {cmd}")]
+    Timeout { cmd: String, secs: u64 },
+
     #[error("Libvirt error: {0}")]
     Libvirt(String),
 
+    #[error("VM not found: {0}. It may have been removed outside the app; refresh the dashboard")]
+    DomainNotFound(String),
+
+    #[error("Network not found: {0}. Recreate it or choose another network")]
+    NetworkNotFound(String),
+
+    #[error("Cannot reach libvirtd: {0}. Check that it is running (systemctl status libvirtd) and the connection URI in Settings")]
+    LibvirtUnreachable(String),
+
     #[error("Network error: {0}")]
     Network(String),
 
@@ -52,9 +66,32 @@ pub enum Error {
     #[error("Resource already exists: {0}")]
     AlreadyExists(String),
 
+    #[error(
+        "VM '{vm}' already exists but uses disk {} instead of {}; repair the role or rebase that disk onto the template before recreating it",
+        .actual.display(),
+        .expected.display()
+    )]
+    VmDiskMismatch {
+        vm: String,
+        expected: PathBuf,
+        actual: PathBuf,
+    },
+
     #[error("Resource not found: {0}")]
     NotFound(String),
 
+    #[error(
+        "Insufficient space on {}: need ~{}, have {}",
+        .path.display(),
+        format_size(*.needed),
+        format_size(*.available)
+    )]
+    InsufficientSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+
     #[error("Connection test failed: {host}:{port} - {reason}")]
     ConnectionTest {
         host: String,
@@ -64,6 +101,9 @@ pub enum Error {
 
     #[error("Authentication error: {0}")]
     Auth(String),
+
+    #[error("Invalid bundle: {0}")]
+    Bundle(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -96,4 +136,8 @@ impl Error {
     pub fn template(msg: impl Into<String>) -> Self {
         Error::Template(msg.into())
     }
+
+    pub fn bundle(msg: impl Into<String>) -> Self {
+        Error::Bundle(msg.into())
+    }
 }
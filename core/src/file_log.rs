@@ -0,0 +1,193 @@
+//! Size-rotated log file
+//!
+//! Mirrors the app's log entries to disk so they are still there after a crash.
+//! Once the file reaches its size limit it becomes `<name>.1`, older files move
+//! up one number and the oldest is dropped.
+
+use crate::{timefmt, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Name of the current log file inside the log directory
+pub const LOG_FILE_NAME: &str = "proxy-vm-wizard.log";
+/// Size at which the log file is rotated
+pub const DEFAULT_MAX_LOG_BYTES: u64 = 1024 * 1024;
+/// Rotated files kept next to the current one
+pub const DEFAULT_KEPT_LOG_FILES: usize = 3;
+
+/// Least severe entries written to the log file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Error,
+    Warning,
+    #[default]
+    Info,
+    /// Everything, including each virsh/virt-install/qemu-img command line
+    Debug,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 4] = [
+        LogLevel::Error,
+        LogLevel::Warning,
+        LogLevel::Info,
+        LogLevel::Debug,
+    ];
+
+    /// Tag written in front of each line
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warning => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+
+    pub fn display_name(self) -> &'static str {
+        match self {
+            LogLevel::Error => "Errors only",
+            LogLevel::Warning => "Warnings",
+            LogLevel::Info => "Info",
+            LogLevel::Debug => "Debug (verbose, with commands)",
+        }
+    }
+}
+
+/// Appends timestamped lines to a log file, rotating it by size.
+///
+/// Shared behind an `Arc` so worker threads can write to it; write failures are
+/// ignored because logging must never take an operation down with it.
+#[derive(Debug)]
+pub struct FileLogger {
+    path: PathBuf,
+    level: LogLevel,
+    max_bytes: u64,
+    kept_files: usize,
+    file: Mutex<Option<File>>,
+}
+
+impl FileLogger {
+    /// Open (or create) `dir/LOG_FILE_NAME` for appending
+    pub fn open(dir: &Path, level: LogLevel) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOG_FILE_NAME);
+        let file = open_append(&path)?;
+        Ok(Self {
+            path,
+            level,
+            max_bytes: DEFAULT_MAX_LOG_BYTES,
+            kept_files: DEFAULT_KEPT_LOG_FILES,
+            file: Mutex::new(Some(file)),
+        })
+    }
+
+    /// Rotate at `max_bytes`, keeping `kept_files` old files
+    pub fn with_limits(mut self, max_bytes: u64, kept_files: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self.kept_files = kept_files;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    /// Whether entries at `level` are written
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level <= self.level
+    }
+
+    /// Write one entry; continuation lines of a multi-line message are indented
+    pub fn log(&self, level: LogLevel, message: &str) {
+        if !self.enabled(level) {
+            return;
+        }
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        self.rotate_if_full(&mut file);
+        if let Some(file) = file.as_mut() {
+            let line = format!(
+                "{} [{}] {}\n",
+                timefmt::date_time(&Local::now()),
+                level.as_str(),
+                message.trim_end().replace('\n', "\n    ")
+            );
+            file.write_all(line.as_bytes()).ok();
+        }
+    }
+
+    fn rotate_if_full(&self, file: &mut Option<File>) {
+        let full = file
+            .as_ref()
+            .and_then(|f| f.metadata().ok())
+            .is_some_and(|meta| meta.len() >= self.max_bytes);
+        if !full {
+            return;
+        }
+        *file = None;
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if self.kept_files == 0 {
+            fs::remove_file(&self.path).ok();
+        } else {
+            fs::remove_file(rotated(self.kept_files)).ok();
+            for n in (1..self.kept_files).rev() {
+                fs::rename(rotated(n), rotated(n + 1)).ok();
+            }
+            fs::rename(&self.path, rotated(1)).ok();
+        }
+        *file = open_append(&self.path).ok();
+    }
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_file_logger_levels() {
+        let dir = tempdir().unwrap();
+        let logger = FileLogger::open(dir.path(), LogLevel::Warning).unwrap();
+        logger.log(LogLevel::Error, "disk full");
+        logger.log(LogLevel::Info, "refreshed");
+        logger.log(LogLevel::Warning, "first\nsecond");
+
+        let content = fs::read_to_string(logger.path()).unwrap();
+        assert!(content.contains("[ERROR] disk full\n"));
+        assert!(!content.contains("refreshed"));
+        assert!(content.contains("[WARN] first\n    second\n"));
+        assert!(!logger.enabled(LogLevel::Debug));
+    }
+
+    #[test]
+    fn test_file_logger_rotation() {
+        let dir = tempdir().unwrap();
+        let logger = FileLogger::open(dir.path(), LogLevel::Info)
+            .unwrap()
+            .with_limits(64, 2);
+        for i in 0..10 {
+            logger.log(LogLevel::Info, &format!("entry number {}", i));
+        }
+
+        let rotated = |n: usize| dir.path().join(format!("{}.{}", LOG_FILE_NAME, n));
+        assert!(rotated(1).is_file());
+        assert!(rotated(2).is_file());
+        assert!(!rotated(3).exists());
+        let current = fs::read_to_string(logger.path()).unwrap();
+        assert!(current.contains("entry number 9"));
+        assert!(current.len() <= 64 + 64);
+    }
+}
@@ -0,0 +1,164 @@
+//! Single-instance lock in the config directory
+//!
+//! Atomic writes keep each config file intact, but two running instances still
+//! overwrite each other's changes. The lock file records which process owns the
+//! config directory; a lock whose process is gone is stale and can be taken over.
+
+use crate::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// The process recorded in a lock file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub started: DateTime<Utc>,
+}
+
+impl LockHolder {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            started: Utc::now(),
+        }
+    }
+
+    /// Whether the recorded process still exists
+    pub fn is_alive(&self) -> bool {
+        Path::new("/proc").join(self.pid.to_string()).exists()
+    }
+}
+
+/// Result of trying to take the lock
+#[derive(Debug)]
+pub enum LockAttempt {
+    Acquired(InstanceLock),
+    /// Another process holds the lock, or held it and did not clean up
+    Held(LockHolder),
+}
+
+/// Ownership of the config directory; the lock file is removed on drop
+#[derive(Debug)]
+pub struct InstanceLock {
+    path: PathBuf,
+    holder: LockHolder,
+}
+
+impl InstanceLock {
+    /// Get the default lock file path
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("~/.config"))
+            .join("proxy-vm-wizard")
+            .join("instance.lock")
+    }
+
+    /// Take the lock unless another holder is recorded.
+    ///
+    /// A lock file that can't be parsed is treated as abandoned and replaced.
+    pub fn acquire(path: &Path) -> Result<LockAttempt> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let holder = LockHolder::current();
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                file.write_all(serde_json::to_string(&holder)?.as_bytes())?;
+                Ok(LockAttempt::Acquired(Self {
+                    path: path.to_path_buf(),
+                    holder,
+                }))
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => match Self::read_holder(path) {
+                Some(other) => Ok(LockAttempt::Held(other)),
+                None => Self::take_over(path).map(LockAttempt::Acquired),
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace whatever lock is there with one held by this process
+    pub fn take_over(path: &Path) -> Result<Self> {
+        let holder = LockHolder::current();
+        let tmp = path.with_extension("lock.tmp");
+        fs::write(&tmp, serde_json::to_string(&holder)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            holder,
+        })
+    }
+
+    fn read_holder(path: &Path) -> Option<LockHolder> {
+        serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // Leave the file alone if another instance took the lock over from us
+        if Self::read_holder(&self.path).as_ref() == Some(&self.holder) {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_acquire_and_release() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("instance.lock");
+
+        let LockAttempt::Acquired(lock) = InstanceLock::acquire(&path).unwrap() else {
+            panic!("first acquire should succeed");
+        };
+        match InstanceLock::acquire(&path).unwrap() {
+            LockAttempt::Held(holder) => {
+                assert_eq!(holder.pid, std::process::id());
+                assert!(holder.is_alive());
+            }
+            LockAttempt::Acquired(_) => panic!("lock is already held"),
+        }
+
+        drop(lock);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_stale_and_takeover() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("instance.lock");
+        let stale = LockHolder {
+            pid: u32::MAX,
+            started: Utc::now(),
+        };
+        fs::write(&path, serde_json::to_string(&stale).unwrap()).unwrap();
+
+        match InstanceLock::acquire(&path).unwrap() {
+            LockAttempt::Held(holder) => assert!(!holder.is_alive()),
+            LockAttempt::Acquired(_) => panic!("stale lock should be reported"),
+        }
+
+        let first = InstanceLock::take_over(&path).unwrap();
+        let second = InstanceLock::take_over(&path).unwrap();
+        // The first owner was displaced, so dropping it keeps the new lock
+        drop(first);
+        assert!(path.exists());
+        drop(second);
+        assert!(!path.exists());
+
+        // Garbage is replaced without asking
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            InstanceLock::acquire(&path).unwrap(),
+            LockAttempt::Acquired(_)
+        ));
+    }
+}
@@ -4,19 +4,41 @@
 //! app VMs, and disposable VMs using libvirt/QEMU.
 
 pub mod auth;
+pub mod bundle;
 pub mod config;
+pub mod diagnostics;
 pub mod error;
+pub mod file_log;
+pub mod instance_lock;
 pub mod libvirt;
 pub mod model;
+pub mod progress;
 pub mod proxy_config;
+pub mod rotation;
+pub mod runner;
+pub mod timefmt;
+pub mod transaction;
 pub mod vpn_config;
 
-pub use auth::{AuthState, EncryptionManager};
-pub use config::{GlobalConfig, RoleMeta, Template, TemplateRegistry};
+pub use auth::{AuthState, EncryptionManager, PlaintextMigration};
+pub use bundle::{export_bundle, import_bundle, validate_bundle, BundleLayout, BundleManifest};
+pub use config::{
+    GlobalConfig, LoggingSection, NamingSection, RoleMeta, Template, TemplateRegistry,
+    DEFAULT_LIBVIRT_URI, MIN_AUTO_REFRESH_SECS,
+};
+pub use diagnostics::export_diagnostics;
 pub use error::{Error, Result};
-pub use libvirt::LibvirtAdapter;
+pub use file_log::{FileLogger, LogLevel};
+pub use instance_lock::{InstanceLock, LockAttempt, LockHolder};
+pub use libvirt::{needs_privilege, network_role, LibvirtAdapter, MAX_BACKING_CHAIN_DEPTH};
 pub use model::*;
-pub use proxy_config::ProxyConfigBuilder;
+pub use progress::{Step, StepReporter, StepState};
+pub use proxy_config::{
+    ConfBackup, ProxyConfigBuilder, APPLY_PROXY_SCRIPT_VERSION, CONF_BACKUP_LIMIT,
+};
+pub use rotation::{next_active_hop, rotate_role_proxy, RotationPolicy, RotationStrategy};
+pub use transaction::{create_role, CreatedResource, RoleProgress, RoleSpec, RoleTransaction};
 pub use vpn_config::{
-    list_openvpn_configs, list_wireguard_configs, OpenVpnParsedConfig, WireGuardParsedConfig,
+    add_openvpn_askpass, list_openvpn_configs, list_wireguard_configs, validate_auth_file,
+    OpenVpnParsedConfig, WireGuardParsedConfig,
 };
@@ -1,44 +1,112 @@
 //! Libvirt/QEMU integration via CLI tools (virsh, virt-install, qemu-img)
 
-use crate::{Error, NetworkInfo, NetworkState, Result, VmInfo, VmKind, VmState};
-use std::collections::HashMap;
+pub use crate::runner::{CommandOutput, CommandRunner, SystemRunner, DEFAULT_MAX_OUTPUT_BYTES};
+
+use crate::config::NamingSection;
+use crate::{
+    validate_role_name, validate_vm_name, BootOptions, BrokenBacking, CrashPolicy, DiskTuning,
+    Error, FileLogger, GatewayMode, ImageInfo, ImageScan, InstallSource, Ipv4Subnet, Ipv6Subnet,
+    LogLevel, NetworkInfo, NetworkState, PermIssue, PermIssueKind, ProxyConfig, ProxyHop,
+    ProxyMountStatus, ProxyType, Result, RoleDisk, RoleNetworkMode, ShareDriver, StartOutcome,
+    StopOutcome, UpstreamCheck, VmInfo, VmInterface, VmKind, VmState, WgPeerHandshake, WgStatus,
+    APP_VCPUS, DEFAULT_ROLE_SUBNET_POOL, GATEWAY_VCPUS, ROLE_SUBNET_PREFIX,
+};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Whether writing `path` needs root: it is under a system directory
+/// (`/var/lib`, `/usr` or `/etc`), so changes go through pkexec
+pub fn needs_privilege(path: &Path) -> bool {
+    ["/var/lib", "/usr", "/etc"]
+        .iter()
+        .any(|dir| path.starts_with(dir))
+}
 
 /// Helper to convert Path to &str with proper error handling
 fn path_to_str(path: &Path) -> Result<&str> {
-    path.to_str()
-        .ok_or_else(|| Error::validation(format!("Invalid path encoding: {}", path.display())))
+    path.to_str().ok_or_else(|| {
+        Error::validation(format!(
+            "Path is not valid UTF-8 and can't be passed to libvirt tools; rename it: {}",
+            path.display()
+        ))
+    })
 }
 
-/// Output from a command execution
-#[derive(Debug, Clone)]
-pub struct CommandOutput {
-    pub exit_code: i32,
-    pub stdout: String,
-    pub stderr: String,
-}
+/// Default for [`LibvirtAdapter::virt_install_timeout_secs`]
+pub const DEFAULT_VIRT_INSTALL_TIMEOUT_SECS: u64 = 600;
 
-impl CommandOutput {
-    pub fn success(&self) -> bool {
-        self.exit_code == 0
-    }
-}
+/// How long to wait for a freshly created gateway's guest agent before giving up on the /proxy check
+pub const PROXY_MOUNT_WAIT_SECS: u64 = 180;
+
+/// Backing files a VM disk may sit on before it is worth flattening.
+///
+/// An overlay on a template is one level; each clone or rebase on top adds another,
+/// and every read that misses the top layer walks down the chain.
+pub const MAX_BACKING_CHAIN_DEPTH: usize = 2;
+
+/// Oldest qemu-img the app works with. Overlays are created with `-F` (backing
+/// format), which older releases reject with a bare usage error.
+pub const MIN_QEMU_IMG_VERSION: (u32, u32) = (2, 0);
+
+/// Accounts the system libvirt daemon runs qemu as: Debian/Ubuntu, then Fedora/Arch
+const QEMU_USERS: &[&str] = &["libvirt-qemu", "qemu"];
+
+/// Troubleshooting hint shown when the gateway cannot see its `/proxy` share
+pub const PROXY_MOUNT_HELP: &str = "The gateway could not see /proxy/proxy.conf. \
+The role directory is shared with the guest over 9p (virtfs), which needs guest support: \
+1) load the modules (modprobe 9p 9pnet 9pnet_virtio) and add them to /etc/modules; \
+2) make sure /etc/fstab contains: proxy /proxy 9p trans=virtio,version=9p2000.L,_netdev 0 0; \
+3) run 'sudo mount -a' or reboot the gateway. \
+Cloud kernels (linux-image-cloud-amd64) often lack 9p, so install the standard kernel if modprobe fails. \
+Install qemu-guest-agent in the template so this check can run automatically.";
 
 /// Adapter for libvirt/QEMU operations via CLI
 #[derive(Debug, Clone)]
 pub struct LibvirtAdapter {
+    /// Executes every CLI call; shared so clones handed to worker threads use the same one
+    runner: Arc<dyn CommandRunner>,
     /// Timeout for connectivity tests in seconds
     pub connect_timeout_secs: u64,
+    /// How long to wait for a guest agent command to finish, in seconds
+    pub guest_agent_timeout_secs: u64,
+    /// Cap on captured stdout/stderr per command; the rest is read and discarded
+    pub max_output_bytes: usize,
+    /// Most virsh queries to run at once when gathering info for many VMs
+    pub max_parallel_queries: usize,
+    /// Seconds before a hung virt-install is killed; 0 waits forever
+    pub virt_install_timeout_secs: u64,
+    /// VM name patterns, used to tell which role and kind a domain belongs to
+    pub naming: NamingSection,
+    /// Connection URI for virsh and virt-install; their own default when unset
+    pub uri: Option<String>,
+    /// Private range NAT role networks get their /24 from
+    pub role_subnet_pool: Ipv4Subnet,
+    /// Receives every command line and its exit status when logging at debug level
+    pub command_log: Option<Arc<FileLogger>>,
 }
 
 impl Default for LibvirtAdapter {
     fn default() -> Self {
         Self {
+            runner: Arc::new(SystemRunner),
             connect_timeout_secs: 5,
+            guest_agent_timeout_secs: 10,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            max_parallel_queries: 8,
+            virt_install_timeout_secs: DEFAULT_VIRT_INSTALL_TIMEOUT_SECS,
+            naming: NamingSection::default(),
+            uri: None,
+            role_subnet_pool: Ipv4Subnet::parse(DEFAULT_ROLE_SUBNET_POOL)
+                .expect("default role subnet pool is valid"),
+            command_log: None,
         }
     }
 }
@@ -48,29 +116,59 @@ impl LibvirtAdapter {
         Self::default()
     }
 
+    /// Adapter that runs commands through `runner` instead of spawning processes
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
+        Self {
+            runner,
+            ..Self::default()
+        }
+    }
+
     // ==================== Command Execution ====================
 
-    /// Run a command and capture output
+    /// Run a command and capture output, keeping at most `max_output_bytes` of each stream.
+    ///
+    /// virsh and virt-install, also when run through pkexec, are pointed at `uri`.
+    /// virt-install is killed after `virt_install_timeout_secs`. At debug level the
+    /// full command line and its outcome go to `command_log`.
     pub fn run_cmd(&self, cmd: &str, args: &[&str]) -> Result<CommandOutput> {
-        let output = Command::new(cmd).args(args).output().map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                Error::CommandNotFound(cmd.to_string())
-            } else {
-                Error::Command {
-                    cmd: format!("{} {}", cmd, args.join(" ")),
-                    message: e.to_string(),
-                }
-            }
-        })?;
-
-        Ok(self.parse_output(output))
+        // Under pkexec the tool is the first argument, and its flags follow it
+        let (tool, flags_at) = match args.first() {
+            Some(tool) if cmd == "pkexec" => (*tool, 1),
+            _ => (cmd, 0),
+        };
+        let mut full_args = args.to_vec();
+        if let (Some(uri), Some(flag)) = (self.uri.as_deref(), connect_flag(tool)) {
+            full_args.splice(flags_at..flags_at, [flag, uri]);
+        }
+        let result = if tool == "virt-install" && self.virt_install_timeout_secs > 0 {
+            let timeout = Duration::from_secs(self.virt_install_timeout_secs);
+            self.runner
+                .run_with_timeout(cmd, &full_args, self.max_output_bytes, timeout)
+        } else {
+            self.runner.run(cmd, &full_args, self.max_output_bytes)
+        };
+        self.log_command(cmd, &full_args, &result);
+        result
     }
 
-    fn parse_output(&self, output: Output) -> CommandOutput {
-        CommandOutput {
-            exit_code: output.status.code().unwrap_or(-1),
-            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    /// At debug level, write a command line and its outcome to `command_log`
+    fn log_command(&self, cmd: &str, args: &[&str], result: &Result<CommandOutput>) {
+        if let Some(log) = self
+            .command_log
+            .as_deref()
+            .filter(|log| log.enabled(LogLevel::Debug))
+        {
+            let line = std::iter::once(cmd)
+                .chain(args.iter().copied())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let outcome = match result {
+                Ok(output) if output.success() => "exit 0".to_string(),
+                Ok(output) => format!("exit {}: {}", output.exit_code, output.stderr.trim()),
+                Err(e) => format!("failed: {}", e),
+            };
+            log.log(LogLevel::Debug, &format!("$ {} ({})", line, outcome));
         }
     }
 
@@ -82,10 +180,9 @@ impl LibvirtAdapter {
         let mut missing = Vec::new();
 
         for cmd in required {
-            if Command::new("which")
-                .arg(cmd)
-                .output()
-                .map(|o| !o.status.success())
+            if self
+                .run_cmd("which", &[cmd])
+                .map(|o| !o.success())
                 .unwrap_or(true)
             {
                 missing.push(cmd.to_string());
@@ -99,13 +196,44 @@ impl LibvirtAdapter {
             )));
         }
 
+        self.check_qemu_img_version()?;
+
         Ok(missing)
     }
 
+    /// Refuse a qemu-img older than [`MIN_QEMU_IMG_VERSION`]. A version line that
+    /// can't be read (e.g. a vendor build) is let through; `qemu-img` would fail later.
+    fn check_qemu_img_version(&self) -> Result<()> {
+        let output = self.run_cmd("qemu-img", &["--version"])?;
+        let Some(version) = parse_qemu_img_version(&output.stdout) else {
+            return Ok(());
+        };
+        let (major, minor) = MIN_QEMU_IMG_VERSION;
+        if (version.0, version.1) < MIN_QEMU_IMG_VERSION {
+            return Err(Error::Command {
+                cmd: "qemu-img".to_string(),
+                message: format!(
+                    "version {}.{}.{} is too old; at least {}.{} is needed for overlay disks (qemu-img create -F). Upgrade qemu-utils (qemu-img on Fedora)",
+                    version.0, version.1, version.2, major, minor
+                ),
+            });
+        }
+        Ok(())
+    }
+
     /// Check if the current user has libvirt access
     pub fn check_libvirt_access(&self) -> Result<()> {
         let output = self.run_cmd("virsh", &["list", "--all"])?;
         if !output.success() {
+            if let e @ Error::LibvirtUnreachable(_) = classify_virsh_error(&output.stderr) {
+                return Err(e);
+            }
+            if let Some(ref uri) = self.uri {
+                return Err(Error::PermissionDenied(format!(
+                    "Cannot connect to libvirt at '{}'. Check the connection URI in Settings and that you may use it. Error: {}",
+                    uri, output.stderr
+                )));
+            }
             return Err(Error::PermissionDenied(format!(
                 "Cannot access libvirt. Ensure you are in the 'libvirt' group or run with sudo. Error: {}",
                 output.stderr
@@ -114,11 +242,31 @@ impl LibvirtAdapter {
         Ok(())
     }
 
+    /// Number of CPUs on the libvirt host, from `virsh nodeinfo`
+    pub fn host_cpu_count(&self) -> Result<u32> {
+        let output = self.run_cmd("virsh", &["nodeinfo"])?;
+        if !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to read host info: {}",
+                output.stderr
+            )));
+        }
+        output
+            .stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("CPU(s):"))
+            .and_then(|value| value.trim().parse().ok())
+            .ok_or_else(|| Error::Parse("No CPU count in virsh nodeinfo output".to_string()))
+    }
+
     // ==================== Network Management ====================
 
     /// Check if a network exists
     pub fn network_exists(&self, name: &str) -> Result<bool> {
         let output = self.run_cmd("virsh", &["net-info", name])?;
+        if !output.success() {
+            check_reachable(&output.stderr)?;
+        }
         Ok(output.success())
     }
 
@@ -126,6 +274,7 @@ impl LibvirtAdapter {
     pub fn get_network_info(&self, name: &str) -> Result<Option<NetworkInfo>> {
         let output = self.run_cmd("virsh", &["net-info", name])?;
         if !output.success() {
+            check_reachable(&output.stderr)?;
             return Ok(None);
         }
 
@@ -174,21 +323,36 @@ impl LibvirtAdapter {
 
     /// Ensure the role-specific internal network exists, creating if necessary
     /// Returns true if the network was created, false if it already existed
-    pub fn ensure_role_network(&self, role: &str) -> Result<bool> {
+    pub fn ensure_role_network(
+        &self,
+        role: &str,
+        ipv6: Option<&Ipv6Subnet>,
+        mode: &RoleNetworkMode,
+    ) -> Result<bool> {
         let net_name = format!("{}-inet", role);
 
         if self.network_exists(&net_name)? {
             return Ok(false);
         }
 
+        if let RoleNetworkMode::Bridge(bridge) = mode {
+            mode.validate().map_err(Error::validation)?;
+            if !self.list_host_bridges()?.contains(bridge) {
+                return Err(Error::validation(format!(
+                    "Host bridge '{}' does not exist",
+                    bridge
+                )));
+            }
+        }
+
+        // NAT needs an IPv4 subnet on the host side; take one nothing else uses
+        let ipv4 = match mode {
+            RoleNetworkMode::Nat => Some(self.allocate_role_subnet()?),
+            _ => None,
+        };
+
         // Create temporary XML file for network definition
-        let xml = format!(
-            r#"<network>
-  <name>{}</name>
-  <bridge stp='on' delay='0'/>
-</network>"#,
-            net_name
-        );
+        let xml = role_network_xml(&net_name, ipv4.as_ref(), ipv6, mode);
 
         let tmp_path = std::env::temp_dir().join(format!("net-{}.xml", net_name));
         fs::write(&tmp_path, &xml)?;
@@ -230,15 +394,164 @@ impl LibvirtAdapter {
         Ok(true)
     }
 
+    /// The next /24 in `role_subnet_pool` that overlaps no libvirt network's
+    /// IPv4 subnet and no address on a host interface (which covers a LAN
+    /// reached through a host bridge)
+    pub fn allocate_role_subnet(&self) -> Result<Ipv4Subnet> {
+        let used = self.used_ipv4_subnets()?;
+        self.role_subnet_pool
+            .subnets(ROLE_SUBNET_PREFIX)
+            .find(|candidate| !used.iter().any(|subnet| subnet.overlaps(candidate)))
+            .ok_or_else(|| {
+                Error::validation(format!(
+                    "No free /{} left in the role subnet range {}; widen it in Settings",
+                    ROLE_SUBNET_PREFIX, self.role_subnet_pool
+                ))
+            })
+    }
+
+    /// IPv4 subnets of every libvirt network and host interface
+    fn used_ipv4_subnets(&self) -> Result<Vec<Ipv4Subnet>> {
+        let output = self.run_cmd("virsh", &["net-list", "--all", "--name"])?;
+        if !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to list networks: {}",
+                output.stderr
+            )));
+        }
+        let mut used = Vec::new();
+        for name in output
+            .stdout
+            .lines()
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+        {
+            let xml = self.run_cmd("virsh", &["net-dumpxml", name])?;
+            if xml.success() {
+                used.extend(parse_network_ipv4_subnets(&xml.stdout));
+            }
+        }
+        // Best effort: without `ip` only libvirt's own networks are avoided
+        if let Ok(output) = self.run_cmd("ip", &["-o", "-4", "addr", "show"]) {
+            if output.success() {
+                used.extend(parse_ip_addr_subnets(&output.stdout));
+            }
+        }
+        Ok(used)
+    }
+
+    /// Networks named `<role>-inet` for a valid role name, i.e. ones this app may have created
+    pub fn list_app_networks(&self) -> Result<Vec<NetworkInfo>> {
+        let output = self.run_cmd("virsh", &["net-list", "--all", "--name"])?;
+        if !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to list networks: {}",
+                output.stderr
+            )));
+        }
+
+        let mut networks = Vec::new();
+        for name in output.stdout.lines().map(str::trim) {
+            if network_role(name).is_none() {
+                continue;
+            }
+            if let Some(info) = self.get_network_info(name)? {
+                networks.push(info);
+            }
+        }
+        Ok(networks)
+    }
+
+    /// Start a defined but inactive network
+    pub fn start_network(&self, name: &str) -> Result<()> {
+        let output = self.run_cmd("virsh", &["net-start", name])?;
+        if !output.success() {
+            return Err(virsh_error(
+                &format!("Failed to start network '{}'", name),
+                &output.stderr,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Turn starting a network with libvirtd on or off
+    pub fn set_network_autostart(&self, name: &str, autostart: bool) -> Result<()> {
+        let mut args = vec!["net-autostart", name];
+        if !autostart {
+            args.push("--disable");
+        }
+        let output = self.run_cmd("virsh", &args)?;
+        if !output.success() {
+            return Err(virsh_error(
+                &format!(
+                    "Failed to {} autostart for network '{}'",
+                    if autostart { "enable" } else { "disable" },
+                    name
+                ),
+                &output.stderr,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Names of the bridge interfaces on the host
+    pub fn list_host_bridges(&self) -> Result<Vec<String>> {
+        let output = self.run_cmd("ip", &["-o", "link", "show", "type", "bridge"])?;
+        if !output.success() {
+            return Err(Error::Command {
+                cmd: "ip link show type bridge".to_string(),
+                message: output.stderr.trim().to_string(),
+            });
+        }
+        Ok(parse_ip_link_names(&output.stdout))
+    }
+
+    /// Host bridge device of a libvirt network, from `virsh net-dumpxml`
+    pub fn network_bridge(&self, name: &str) -> Result<Option<String>> {
+        let output = self.run_cmd("virsh", &["net-dumpxml", name])?;
+        if !output.success() {
+            return Ok(None);
+        }
+        Ok(parse_bridge_name(&output.stdout))
+    }
+
+    /// Role networks that share the LAN network's bridge device, one warning each.
+    ///
+    /// A role network in bridge mode onto the LAN's bridge puts the role's app VMs
+    /// straight on the LAN, so traffic never has to pass the gateway.
+    pub fn check_role_network_isolation(
+        &self,
+        lan_net: &str,
+        roles: &[String],
+    ) -> Result<Vec<String>> {
+        let Some(lan_bridge) = self.network_bridge(lan_net)? else {
+            return Ok(Vec::new());
+        };
+        let mut warnings = Vec::new();
+        for role in roles {
+            let role_net = format!("{}-inet", role);
+            if self.network_bridge(&role_net)?.as_deref() == Some(lan_bridge.as_str()) {
+                warnings.push(format!(
+                    "Role network '{}' is attached to bridge '{}', like the LAN network '{}': app VMs of role '{}' are on the LAN and bypass the gateway",
+                    role_net, lan_bridge, lan_net, role
+                ));
+            }
+        }
+        Ok(warnings)
+    }
+
     /// Destroy and undefine a network
     pub fn destroy_network(&self, name: &str) -> Result<()> {
         self.run_cmd("virsh", &["net-destroy", name]).ok();
         let output = self.run_cmd("virsh", &["net-undefine", name])?;
-        if !output.success() && !output.stderr.contains("not found") {
-            return Err(Error::libvirt(format!(
-                "Failed to undefine network '{}': {}",
-                name, output.stderr
-            )));
+        if !output.success() {
+            match virsh_error(
+                &format!("Failed to undefine network '{}'", name),
+                &output.stderr,
+            ) {
+                Error::NetworkNotFound(_) => {}
+                e => return Err(e),
+            }
         }
         Ok(())
     }
@@ -251,7 +564,19 @@ impl LibvirtAdapter {
     }
 
     /// Run a privileged command using pkexec (shows graphical password prompt)
+    ///
+    /// libvirt tools are refused for session and remote URIs: as root they would
+    /// reach root's own session daemon or use root's SSH identity, and change a
+    /// different libvirt instance than the one the app manages.
     fn run_privileged(&self, cmd: &str, args: &[&str]) -> Result<CommandOutput> {
+        if connect_flag(cmd).is_some() {
+            if let Some(uri) = self.uri.as_deref().filter(|uri| !is_local_system_uri(uri)) {
+                return Err(Error::PermissionDenied(format!(
+                    "Cannot run {} as root for {}; it only works on the local system instance",
+                    cmd, uri
+                )));
+            }
+        }
         // Build the full command as a single string for pkexec
         let mut full_args = vec![cmd];
         full_args.extend(args);
@@ -308,6 +633,57 @@ impl LibvirtAdapter {
         Ok(dest)
     }
 
+    /// Find qcow2 images in `images_dir`.
+    ///
+    /// Reads the directory directly, falling back to `ls` and then `pkexec ls` when
+    /// it isn't readable. `ls -b` escapes unusual bytes, so names that aren't UTF-8
+    /// come back intact and are reported in `invalid_names` rather than mangled.
+    pub fn discover_qcow2_files(&self, images_dir: &Path) -> Result<ImageScan> {
+        let mut names: Vec<OsString> = Vec::new();
+        if let Ok(entries) = fs::read_dir(images_dir) {
+            for entry in entries.flatten() {
+                if entry.path().is_file() && is_qcow2_name(&entry.file_name()) {
+                    names.push(entry.file_name());
+                }
+            }
+        }
+
+        if names.is_empty() {
+            let dir = path_to_str(images_dir)?;
+            for privileged in [false, true] {
+                let args = ["-1", "-b", dir];
+                let output = if privileged {
+                    self.run_privileged("ls", &args)
+                } else {
+                    self.run_cmd("ls", &args)
+                };
+                if let Ok(output) = output {
+                    if output.success() {
+                        names = output
+                            .stdout
+                            .lines()
+                            .map(unescape_ls_name)
+                            .filter(|name| is_qcow2_name(name))
+                            .collect();
+                        break;
+                    }
+                }
+            }
+        }
+
+        names.sort();
+        let mut scan = ImageScan::default();
+        for name in names {
+            let path = images_dir.join(&name);
+            if name.to_str().is_some() {
+                scan.files.push(path);
+            } else {
+                scan.invalid_names.push(path);
+            }
+        }
+        Ok(scan)
+    }
+
     /// Ensure the images directory exists and is writable (may need pkexec)
     pub fn ensure_images_dir(&self, images_dir: &Path) -> Result<()> {
         if !images_dir.exists() {
@@ -345,6 +721,9 @@ impl LibvirtAdapter {
         let template_str = path_to_str(template_path)?;
         let overlay_str = path_to_str(overlay_path)?;
 
+        // The overlay can grow to the template's virtual size; fail now rather than mid-write
+        self.check_overlay_space(template_path, overlay_path)?;
+
         // Ensure parent directory exists
         if let Some(parent) = overlay_path.parent() {
             if !parent.exists() {
@@ -355,11 +734,9 @@ impl LibvirtAdapter {
         }
 
         // Check if we need elevated privileges (writing to system directories)
-        let needs_privilege = overlay_path.starts_with("/var/lib")
-            || overlay_path.starts_with("/usr")
-            || overlay_path.starts_with("/etc");
+        let privileged = needs_privilege(overlay_path);
 
-        let output = if needs_privilege {
+        let output = if privileged {
             self.run_privileged(
                 "qemu-img",
                 &[
@@ -397,13 +774,206 @@ impl LibvirtAdapter {
         }
 
         // Set proper permissions if we used privilege
-        if needs_privilege {
+        if privileged {
             self.run_privileged("chmod", &["644", overlay_str]).ok();
         }
 
         Ok(())
     }
 
+    /// Create an empty qcow2 disk of `size_gb` for a VM that installs its OS from an ISO.
+    /// Uses pkexec if the disk goes into a system directory.
+    pub fn create_blank_disk(&self, disk_path: &Path, size_gb: u32) -> Result<()> {
+        if size_gb == 0 {
+            return Err(Error::validation("Disk size must be at least 1 GB"));
+        }
+        if disk_path.exists() {
+            return Err(Error::AlreadyExists(format!(
+                "Disk already exists: {}",
+                disk_path.display()
+            )));
+        }
+        let disk_str = path_to_str(disk_path)?;
+        let size = format!("{}G", size_gb);
+        let args = ["create", "-f", "qcow2", disk_str, size.as_str()];
+
+        let privileged = needs_privilege(disk_path);
+        let output = if privileged {
+            if let Some(parent) = disk_path.parent().filter(|p| !p.exists()) {
+                if let Ok(parent_str) = path_to_str(parent) {
+                    self.run_privileged("mkdir", &["-p", parent_str]).ok();
+                }
+            }
+            self.run_privileged("qemu-img", &args)?
+        } else {
+            self.run_cmd("qemu-img", &args)?
+        };
+        if !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to create disk: {}",
+                output.stderr
+            )));
+        }
+        if privileged {
+            self.run_privileged("chmod", &["644", disk_str]).ok();
+        }
+        Ok(())
+    }
+
+    /// Check that the qemu user can read `overlay` and every backing file under it,
+    /// and enter each directory above them.
+    ///
+    /// Only owner, group and mode bits are looked at; ACLs and SELinux labels are
+    /// not. Returns no issues on a session connection (qemu runs as the current
+    /// user there) or when no known qemu account exists on this host.
+    pub fn check_disk_permissions(&self, overlay: &Path) -> Result<Vec<PermIssue>> {
+        let Some((qemu_user, groups)) = self.qemu_user() else {
+            return Ok(Vec::new());
+        };
+
+        let mut files = vec![overlay.to_path_buf()];
+        files.extend(self.backing_chain(overlay)?);
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for file in &files {
+            for dir in file.ancestors().skip(1) {
+                if !dir.as_os_str().is_empty() && !dirs.iter().any(|d| d == dir) {
+                    dirs.push(dir.to_path_buf());
+                }
+            }
+        }
+
+        let mut args = vec!["-L", "-c", "%U:%G:%a:%n"];
+        for path in files.iter().chain(&dirs) {
+            args.push(path_to_str(path)?);
+        }
+        // stat exits non-zero if any path is missing but still prints the rest
+        let output = self.run_cmd("stat", &args)?;
+        if output.stdout.trim().is_empty() && !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to check permissions of {}: {}",
+                overlay.display(),
+                output.stderr.trim()
+            )));
+        }
+
+        let mut issues = Vec::new();
+        for line in output.stdout.lines() {
+            let mut fields = line.splitn(4, ':');
+            let (Some(owner), Some(group), Some(mode), Some(name)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Ok(mode) = u32::from_str_radix(mode, 8) else {
+                continue;
+            };
+            let path = PathBuf::from(name);
+            let (kind, bit) = if files.contains(&path) {
+                (PermIssueKind::Unreadable, 0o4)
+            } else {
+                (PermIssueKind::NotSearchable, 0o1)
+            };
+            let granted = if owner == qemu_user {
+                mode >> 6
+            } else if groups.iter().any(|g| g == group) {
+                mode >> 3
+            } else {
+                mode
+            };
+            if granted & bit == 0 {
+                issues.push(PermIssue {
+                    path,
+                    kind,
+                    qemu_user: qemu_user.clone(),
+                    owner: owner.to_string(),
+                    group: group.to_string(),
+                    mode,
+                });
+            }
+        }
+        Ok(issues)
+    }
+
+    /// The account qemu runs as and its groups, or `None` if it can't be told
+    fn qemu_user(&self) -> Option<(String, Vec<String>)> {
+        if self
+            .uri
+            .as_deref()
+            .is_some_and(|uri| uri.ends_with("/session"))
+        {
+            return None;
+        }
+        QEMU_USERS.iter().find_map(|user| {
+            let output = self.run_cmd("id", &["-nG", user]).ok()?;
+            output.success().then(|| {
+                let groups = output.stdout.split_whitespace().map(String::from).collect();
+                (user.to_string(), groups)
+            })
+        })
+    }
+
+    /// Virtual (guest-visible) size of a disk image in bytes
+    pub fn image_virtual_size(&self, path: &Path) -> Result<u64> {
+        Ok(self.image_info(path)?.virtual_size)
+    }
+
+    /// Virtual and allocated size of a disk image
+    pub fn image_info(&self, path: &Path) -> Result<ImageInfo> {
+        let output = self.run_cmd(
+            "qemu-img",
+            &["info", "-U", "--output=json", path_to_str(path)?],
+        )?;
+        if !output.success() {
+            return Err(Error::template(format!(
+                "Failed to inspect {}: {}",
+                path.display(),
+                output.stderr.trim()
+            )));
+        }
+        let info: serde_json::Value = serde_json::from_str(&output.stdout)?;
+        let virtual_size = info["virtual-size"]
+            .as_u64()
+            .ok_or_else(|| Error::Parse(format!("No virtual size for {}", path.display())))?;
+        Ok(ImageInfo {
+            virtual_size,
+            // Left out for some network-backed images, which take no local space
+            disk_size: info["actual-size"].as_u64().unwrap_or(0),
+            format: info["format"].as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    /// Bytes available to unprivileged users on the filesystem holding `path`
+    pub fn available_space(&self, path: &Path) -> Result<u64> {
+        // The overlay's directory may not exist yet; measure the nearest ancestor that does
+        let existing = path
+            .ancestors()
+            .find(|p| p.exists())
+            .unwrap_or_else(|| Path::new("/"));
+        let output = self.run_cmd("df", &["--output=avail", "-B1", path_to_str(existing)?])?;
+        if !output.success() {
+            return Err(Error::Command {
+                cmd: format!("df {}", existing.display()),
+                message: output.stderr.trim().to_string(),
+            });
+        }
+        parse_df_avail(&output.stdout)
+            .ok_or_else(|| Error::Parse(format!("Unexpected df output: {}", output.stdout)))
+    }
+
+    fn check_overlay_space(&self, template_path: &Path, overlay_path: &Path) -> Result<()> {
+        let needed = self.image_virtual_size(template_path)?;
+        let target = overlay_path.parent().unwrap_or(overlay_path);
+        let available = self.available_space(target)?;
+        if available < needed {
+            return Err(Error::InsufficientSpace {
+                path: target.to_path_buf(),
+                needed,
+                available,
+            });
+        }
+        Ok(())
+    }
+
     /// Delete an overlay disk (uses pkexec for system directories)
     pub fn delete_overlay_disk(&self, path: &Path) -> Result<()> {
         if !path.exists() {
@@ -411,12 +981,11 @@ impl LibvirtAdapter {
         }
 
         // Check if we need elevated privileges
-        let needs_privilege =
-            path.starts_with("/var/lib") || path.starts_with("/usr") || path.starts_with("/etc");
+        let privileged = needs_privilege(path);
 
         let path_str = path_to_str(path)?;
 
-        if needs_privilege {
+        if privileged {
             let output = self.run_privileged("rm", &["-f", path_str])?;
             if !output.success() && !output.stderr.contains("No such file") {
                 return Err(Error::libvirt(format!(
@@ -440,67 +1009,370 @@ impl LibvirtAdapter {
         images_dir.join(format!("{}-app-{}-overlay.qcow2", role, number))
     }
 
-    /// Get the overlay disk path for a disposable VM
-    pub fn disposable_overlay_path(&self, cfg_root: &Path, role: &str) -> PathBuf {
-        let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    /// App VM overlays of a role that exist: those numbered 1 to `app_vm_count`,
+    /// any other `<role>-app-<n>-overlay.qcow2` in `images_dir` (left behind when
+    /// the count in role.toml is off) and `vm_disks`, the disks of the role's app
+    /// VMs found in libvirt
+    pub fn role_app_overlays(
+        &self,
+        images_dir: &Path,
+        role: &str,
+        app_vm_count: u32,
+        vm_disks: &[PathBuf],
+    ) -> Vec<PathBuf> {
+        let mut numbered: Vec<(u32, PathBuf)> = (1..=app_vm_count)
+            .map(|n| (n, self.app_overlay_path(images_dir, role, n)))
+            .collect();
+        let prefix = format!("{}-app-", role);
+        if let Ok(entries) = fs::read_dir(images_dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let number = name
+                    .to_str()
+                    .and_then(|name| name.strip_prefix(&prefix))
+                    .and_then(|rest| rest.strip_suffix("-overlay.qcow2"))
+                    .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+                    .and_then(|n| n.parse::<u32>().ok());
+                if let Some(number) = number {
+                    numbered.push((number, entry.path()));
+                }
+            }
+        }
+        numbered.sort();
+        numbered.dedup();
+
+        let mut overlays: Vec<PathBuf> = numbered
+            .into_iter()
+            .map(|(_, path)| path)
+            .filter(|path| path.exists())
+            .collect();
+        for disk in vm_disks {
+            if disk.exists() && !overlays.contains(disk) {
+                overlays.push(disk.clone());
+            }
+        }
+        overlays
+    }
+
+    /// Get the overlay disk path for a disposable VM; `stamp` comes from `timefmt::now_stamp`
+    pub fn disposable_overlay_path(&self, cfg_root: &Path, role: &str, stamp: &str) -> PathBuf {
         let disp_dir = cfg_root.join(role).join("disposable");
         fs::create_dir_all(&disp_dir).ok();
-        disp_dir.join(format!("disp-{}.qcow2", timestamp))
+        disp_dir.join(format!("disp-{}.qcow2", stamp))
     }
 
-    // ==================== VM Management ====================
+    /// Disk files of a role that exist: the gateway overlay, its app VM overlays
+    /// (see [`Self::role_app_overlays`]), and any images in the role's disposable
+    /// directory
+    pub fn role_disks(
+        &self,
+        images_dir: &Path,
+        cfg_root: &Path,
+        role: &str,
+        app_vm_count: u32,
+    ) -> Vec<PathBuf> {
+        let mut disks = vec![self.gateway_overlay_path(images_dir, role)];
+        disks.retain(|disk| disk.exists());
+        disks.extend(self.role_app_overlays(images_dir, role, app_vm_count, &[]));
 
-    /// Check if a VM (domain) exists
-    pub fn vm_exists(&self, name: &str) -> Result<bool> {
-        let output = self.run_cmd("virsh", &["dominfo", name])?;
-        Ok(output.success())
+        if let Ok(entries) = fs::read_dir(cfg_root.join(role).join("disposable")) {
+            let mut disposables: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "qcow2"))
+                .collect();
+            disposables.sort();
+            disks.extend(disposables);
+        }
+        disks
     }
 
-    /// Get VM info
-    pub fn get_vm_info(&self, name: &str) -> Result<Option<VmInfo>> {
-        let output = self.run_cmd("virsh", &["dominfo", name])?;
-        if !output.success() {
-            return Ok(None);
+    /// Each disk with the base image its backing chain ends in, e.g. to show
+    /// which files deleting a role removes and which shared templates stay
+    pub fn role_disk_bases(&self, disks: &[PathBuf]) -> Vec<RoleDisk> {
+        disks
+            .iter()
+            .map(|disk| RoleDisk {
+                path: disk.clone(),
+                base: self
+                    .backing_chain(disk)
+                    .ok()
+                    .and_then(|chain| chain.last().cloned()),
+            })
+            .collect()
+    }
+
+    // ==================== Template Download ====================
+
+    /// Download a template image to `dest`, verifying its SHA-256 if given
+    pub fn download_template(
+        &self,
+        url: &str,
+        dest: &Path,
+        expected_sha256: Option<&str>,
+    ) -> Result<PathBuf> {
+        self.download_template_with_progress(url, dest, expected_sha256, |_, _| {})
+    }
+
+    /// Download a template image, reporting `(downloaded, total)` bytes while it runs.
+    ///
+    /// The download goes to a `.part` file in the user cache directory first, so an
+    /// interrupted download of the same URL resumes on the next attempt. It is only
+    /// moved to `dest` (via pkexec if needed) once complete and verified.
+    pub fn download_template_with_progress(
+        &self,
+        url: &str,
+        dest: &Path,
+        expected_sha256: Option<&str>,
+        mut on_progress: impl FnMut(u64, Option<u64>),
+    ) -> Result<PathBuf> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(Error::validation(
+                "Template URL must start with http:// or https://",
+            ));
+        }
+        let expected = expected_sha256.map(normalize_sha256).transpose()?;
+        if dest.exists() {
+            return Err(Error::AlreadyExists(format!(
+                "{} already exists",
+                dest.display()
+            )));
         }
 
-        let mut info = VmInfo {
-            name: name.to_string(),
-            state: VmState::Unknown,
-            kind: VmKind::ProxyGateway,
-            role: None,
-        };
+        let file_name = dest
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| Error::template("Invalid template filename"))?;
+        let download_dir = dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("proxy-vm-wizard")
+            .join("downloads");
+        fs::create_dir_all(&download_dir)?;
+        let part = download_dir.join(format!("{}.part", file_name));
+        let part_str = path_to_str(&part)?;
+        let part_url = claim_partial_download(&part, url)?;
 
-        for line in output.stdout.lines() {
-            let parts: Vec<&str> = line.splitn(2, ':').collect();
-            if parts.len() != 2 {
-                continue;
+        let total = self.remote_content_length(url);
+
+        // -C - resumes from whatever is already in the .part file. curl runs on
+        // a scoped thread so this one can report the growing .part size.
+        let args = [
+            "--fail",
+            "--location",
+            "--silent",
+            "--show-error",
+            "-C",
+            "-",
+            "-o",
+            part_str,
+            url,
+        ];
+        let output = std::thread::scope(|scope| {
+            let curl = scope.spawn(|| self.run_cmd("curl", &args));
+            while !curl.is_finished() {
+                on_progress(fs::metadata(&part).map(|m| m.len()).unwrap_or(0), total);
+                std::thread::sleep(Duration::from_millis(250));
             }
-            let key = parts[0].trim().to_lowercase();
-            let value = parts[1].trim();
+            curl.join()
+                .unwrap_or_else(|_| Err(Error::Network("curl thread panicked".to_string())))
+        })?;
 
-            if key == "state" {
-                info.state = VmState::from_virsh_state(value);
+        if !output.success() {
+            // 22 = HTTP error, 33 = server can't resume: the partial file is no use
+            if matches!(output.exit_code, 22 | 33) {
+                fs::remove_file(&part).ok();
+                fs::remove_file(&part_url).ok();
             }
+            return Err(Error::Network(format!(
+                "Download of {} failed: {}",
+                url,
+                output.stderr.trim()
+            )));
         }
 
-        // Determine role and kind from name
-        if name.ends_with("-gw") {
-            info.kind = VmKind::ProxyGateway;
-            info.role = Some(name.strip_suffix("-gw").unwrap_or(name).to_string());
-        } else if name.contains("-app-") {
-            info.kind = VmKind::App;
-            if let Some(role) = name.split("-app-").next() {
-                info.role = Some(role.to_string());
+        let downloaded = fs::metadata(&part)?.len();
+        on_progress(downloaded, total.or(Some(downloaded)));
+
+        if let Some(expected) = expected {
+            let actual = self.sha256_file(&part)?;
+            if actual != expected {
+                fs::remove_file(&part).ok();
+                fs::remove_file(&part_url).ok();
+                return Err(Error::validation(format!(
+                    "Checksum mismatch for {}: expected {}, got {}",
+                    file_name, expected, actual
+                )));
             }
-        } else if name.starts_with("disp-") {
-            info.kind = VmKind::DisposableApp;
         }
 
-        Ok(Some(info))
-    }
-
-    /// List all VMs matching a pattern
-    pub fn list_vms(&self, pattern: Option<&str>) -> Result<Vec<VmInfo>> {
+        // Move into place, escalating if images_dir is root-owned
+        if fs::copy(&part, dest).is_err() {
+            let output = self.run_privileged("cp", &[part_str, path_to_str(dest)?])?;
+            if !output.success() {
+                return Err(Error::libvirt(format!(
+                    "Failed to install downloaded template: {}",
+                    output.stderr
+                )));
+            }
+            self.run_privileged("chmod", &["644", path_to_str(dest)?])
+                .ok();
+        }
+        fs::remove_file(&part).ok();
+        fs::remove_file(&part_url).ok();
+
+        Ok(dest.to_path_buf())
+    }
+
+    /// Ask the server for the size of a download, if it reports one
+    fn remote_content_length(&self, url: &str) -> Option<u64> {
+        let output = self
+            .run_cmd("curl", &["--silent", "--head", "--location", url])
+            .ok()?;
+        if !output.success() {
+            return None;
+        }
+        parse_content_length(&output.stdout)
+    }
+
+    /// Like [`sha256_file`](Self::sha256_file), but feeds the file to `sha256sum`
+    /// through its stdin so `on_progress(hashed, total)` can follow along on
+    /// multi-GB images
+    pub fn sha256_file_with_progress(
+        &self,
+        path: &Path,
+        mut on_progress: impl FnMut(u64, u64),
+    ) -> Result<String> {
+        let mut file = fs::File::open(path)?;
+        let total = file.metadata()?.len();
+        let result = self.runner.run_with_input(
+            "sha256sum",
+            &["-"],
+            self.max_output_bytes,
+            &mut file,
+            &mut |hashed| on_progress(hashed, total),
+        );
+        self.log_command("sha256sum", &["-"], &result);
+        parse_sha256sum(result?)
+    }
+
+    /// Compute the SHA-256 of a file as lowercase hex
+    pub fn sha256_file(&self, path: &Path) -> Result<String> {
+        parse_sha256sum(self.run_cmd("sha256sum", &[path_to_str(path)?])?)
+    }
+
+    // ==================== VM Management ====================
+
+    /// Check if a VM (domain) exists
+    pub fn vm_exists(&self, name: &str) -> Result<bool> {
+        let output = self.run_cmd("virsh", &["dominfo", name])?;
+        if !output.success() {
+            check_reachable(&output.stderr)?;
+        }
+        Ok(output.success())
+    }
+
+    /// Get VM info
+    pub fn get_vm_info(&self, name: &str) -> Result<Option<VmInfo>> {
+        let output = self.run_cmd("virsh", &["dominfo", name])?;
+        if !output.success() {
+            check_reachable(&output.stderr)?;
+            return Ok(None);
+        }
+
+        let mut info = VmInfo {
+            name: name.to_string(),
+            state: VmState::Unknown,
+            kind: VmKind::ProxyGateway,
+            role: None,
+            // Filled in by `list_vms` from one listing of every title
+            title: None,
+        };
+
+        for line in output.stdout.lines() {
+            let parts: Vec<&str> = line.splitn(2, ':').collect();
+            if parts.len() != 2 {
+                continue;
+            }
+            let key = parts[0].trim().to_lowercase();
+            let value = parts[1].trim();
+
+            if key == "state" {
+                info.state = VmState::from_virsh_state(value);
+            }
+        }
+
+        // Determine role and kind from name
+        if let Some((kind, role)) = self.naming.classify(name) {
+            info.kind = kind;
+            info.role = Some(role);
+        }
+
+        Ok(Some(info))
+    }
+
+    /// Libvirt title of every VM that has one, by VM name; empty when the
+    /// titles can't be listed, since they are only for display
+    fn list_vm_titles(&self) -> HashMap<String, String> {
+        match self.run_cmd("virsh", &["list", "--all", "--title"]) {
+            Ok(output) if output.success() => parse_virsh_list_titles(&output.stdout),
+            _ => HashMap::new(),
+        }
+    }
+
+    /// Set the short title other libvirt tools show for a VM
+    pub fn set_vm_title(&self, vm: &str, title: &str) -> Result<()> {
+        if title.contains('\n') {
+            return Err(Error::validation("A VM title must be a single line"));
+        }
+        self.set_vm_desc(vm, title, true)
+    }
+
+    /// Set the free-form description other libvirt tools show for a VM
+    pub fn set_vm_description(&self, vm: &str, description: &str) -> Result<()> {
+        self.set_vm_desc(vm, description, false)
+    }
+
+    /// `virsh desc`, writing the persistent definition and, while the VM runs,
+    /// the live one too
+    fn set_vm_desc(&self, vm: &str, text: &str, title: bool) -> Result<()> {
+        let running = self
+            .get_vm_info(vm)?
+            .ok_or_else(|| Error::NotFound(format!("VM '{}' not found", vm)))?
+            .state
+            .is_running();
+        let mut args = vec!["desc", vm, "--config"];
+        if running {
+            args.push("--live");
+        }
+        if title {
+            args.push("--title");
+        }
+        args.extend(["--new-desc", text]);
+        let output = self.run_cmd("virsh", &args)?;
+        if !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to set the {} of '{}': {}",
+                if title { "title" } else { "description" },
+                vm,
+                output.stderr.trim()
+            )));
+        }
+        Ok(())
+    }
+
+    /// virt-install arguments giving a new VM its default title, when its name
+    /// matches a role pattern
+    fn title_args(&self, vm_name: &str) -> Vec<String> {
+        match self.naming.classify(vm_name) {
+            Some((kind, role)) => vec![
+                "--metadata".to_string(),
+                format!("title={}", kind.default_title(&role)),
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    /// List all VMs matching a pattern
+    pub fn list_vms(&self, pattern: Option<&str>) -> Result<Vec<VmInfo>> {
         let output = self.run_cmd("virsh", &["list", "--all", "--name"])?;
         if !output.success() {
             return Err(Error::libvirt(format!(
@@ -509,22 +1381,27 @@ impl LibvirtAdapter {
             )));
         }
 
+        let names: Vec<&str> = output
+            .stdout
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .filter(|name| pattern.is_none_or(|pattern| name.contains(pattern)))
+            .collect();
+
+        // One `virsh dominfo` per VM; run them side by side instead of back to back
+        let infos = parallel_map(&names, self.max_parallel_queries, |name| {
+            self.get_vm_info(name)
+        });
+
+        let mut titles = self.list_vm_titles();
         let mut vms = Vec::new();
-        for line in output.stdout.lines() {
-            let name = line.trim();
-            if name.is_empty() {
-                continue;
-            }
-            if let Some(pattern) = pattern {
-                if !name.contains(pattern) {
-                    continue;
-                }
-            }
-            if let Some(info) = self.get_vm_info(name)? {
+        for info in infos {
+            if let Some(mut info) = info? {
+                info.title = titles.remove(&info.name);
                 vms.push(info);
             }
         }
-
         Ok(vms)
     }
 
@@ -539,38 +1416,89 @@ impl LibvirtAdapter {
         &self,
         vm_name: &str,
         overlay_path: &Path,
-        lan_net: &str,
-        role_net: &str,
+        networks: &[String],
         role_dir: &Path,
         os_variant: &str,
         ram_mb: u32,
+        disk_tuning: &DiskTuning,
+        boot: &BootOptions,
+        share_driver: ShareDriver,
+        cpuset: Option<&str>,
+        on_crash: CrashPolicy,
+        install: InstallSource,
+        install_media: &Path,
     ) -> Vec<String> {
-        vec![
+        let vcpus = match cpuset {
+            Some(cpuset) => format!("{},cpuset={}", GATEWAY_VCPUS, cpuset),
+            None => GATEWAY_VCPUS.to_string(),
+        };
+        let mut args = vec![
             "--name".to_string(),
             vm_name.to_string(),
             "--memory".to_string(),
             ram_mb.to_string(),
             "--vcpus".to_string(),
-            "1".to_string(),
-            "--import".to_string(),
+            vcpus,
+        ];
+        args.extend(install.virt_install_args(install_media));
+        args.extend([
             "--disk".to_string(),
-            format!("path={},format=qcow2", overlay_path.display()),
-            "--network".to_string(),
-            format!("network={},model=virtio", lan_net),
-            "--network".to_string(),
-            format!("network={},model=virtio", role_net),
-            "--filesystem".to_string(),
             format!(
-                "source={},target=proxy,accessmode=mapped",
-                role_dir.display()
+                "path={},format=qcow2{}",
+                overlay_path.display(),
+                disk_tuning.disk_options()
             ),
+        ]);
+        args.extend(self.title_args(vm_name));
+        // NIC order is what the guest sees: the first network is the uplink
+        for network in networks {
+            args.push("--network".to_string());
+            args.push(format!("network={},model=virtio", network));
+        }
+
+        match share_driver {
+            ShareDriver::NineP => {
+                args.push("--filesystem".to_string());
+                args.push(format!(
+                    "source={},target=proxy,accessmode=mapped",
+                    role_dir.display()
+                ));
+            }
+            ShareDriver::Virtiofs => {
+                // virtiofs needs guest memory shared with virtiofsd
+                args.push("--memorybacking".to_string());
+                args.push("source.type=memfd,access.mode=shared".to_string());
+                args.push("--filesystem".to_string());
+                args.push(format!(
+                    "source={},target=proxy,driver.type=virtiofs",
+                    role_dir.display()
+                ));
+            }
+        }
+
+        // Leave keeps libvirt's own default rather than writing it out. A guest
+        // kernel panic only reaches libvirt as a crash through a panic device.
+        if on_crash == CrashPolicy::Restart {
+            args.push("--events".to_string());
+            args.push(format!("on_crash={}", on_crash.libvirt_action()));
+            args.push("--panic".to_string());
+            args.push("default".to_string());
+        }
+
+        args.extend([
+            "--channel".to_string(),
+            GUEST_AGENT_CHANNEL.to_string(),
             "--os-variant".to_string(),
             os_variant.to_string(),
             "--noautoconsole".to_string(),
-        ]
+        ]);
+        args.extend(boot.virt_install_args());
+
+        args
     }
 
     /// Build virt-install arguments for an app VM
+    #[allow(clippy::too_many_arguments)]
     pub fn build_app_virt_install_args(
         &self,
         vm_name: &str,
@@ -578,6 +1506,8 @@ impl LibvirtAdapter {
         role_net: &str,
         os_variant: &str,
         ram_mb: u32,
+        disk_tuning: &DiskTuning,
+        boot: &BootOptions,
         share_dir: Option<&Path>,
     ) -> Vec<String> {
         let mut args = vec![
@@ -586,16 +1516,21 @@ impl LibvirtAdapter {
             "--memory".to_string(),
             ram_mb.to_string(),
             "--vcpus".to_string(),
-            "2".to_string(),
+            APP_VCPUS.to_string(),
             "--import".to_string(),
             "--disk".to_string(),
-            format!("path={},format=qcow2", overlay_path.display()),
+            format!(
+                "path={},format=qcow2{}",
+                overlay_path.display(),
+                disk_tuning.disk_options()
+            ),
             "--network".to_string(),
             format!("network={},model=virtio", role_net),
             "--os-variant".to_string(),
             os_variant.to_string(),
             "--noautoconsole".to_string(),
         ];
+        args.extend(self.title_args(vm_name));
 
         if let Some(share) = share_dir {
             args.push("--filesystem".to_string());
@@ -604,11 +1539,13 @@ impl LibvirtAdapter {
                 share.display()
             ));
         }
+        args.extend(boot.virt_install_args());
 
         args
     }
 
     /// Build virt-install arguments for a disposable VM
+    #[allow(clippy::too_many_arguments)]
     pub fn build_disposable_virt_install_args(
         &self,
         vm_name: &str,
@@ -616,24 +1553,33 @@ impl LibvirtAdapter {
         role_net: &str,
         os_variant: &str,
         ram_mb: u32,
+        disk_tuning: &DiskTuning,
+        boot: &BootOptions,
     ) -> Vec<String> {
-        vec![
+        let mut args = vec![
             "--name".to_string(),
             vm_name.to_string(),
             "--memory".to_string(),
             ram_mb.to_string(),
             "--vcpus".to_string(),
-            "2".to_string(),
+            APP_VCPUS.to_string(),
             "--import".to_string(),
             "--transient".to_string(),
             "--disk".to_string(),
-            format!("path={},format=qcow2", overlay_path.display()),
+            format!(
+                "path={},format=qcow2{}",
+                overlay_path.display(),
+                disk_tuning.disk_options()
+            ),
             "--network".to_string(),
             format!("network={},model=virtio", role_net),
             "--os-variant".to_string(),
             os_variant.to_string(),
             "--noautoconsole".to_string(),
-        ]
+        ];
+        args.extend(self.title_args(vm_name));
+        args.extend(boot.virt_install_args());
+        args
     }
 
     /// Create a gateway VM
@@ -642,32 +1588,38 @@ impl LibvirtAdapter {
         &self,
         vm_name: &str,
         overlay_path: &Path,
-        lan_net: &str,
-        role_net: &str,
+        networks: &[String],
         role_dir: &Path,
         os_variant: &str,
         ram_mb: u32,
+        disk_tuning: &DiskTuning,
+        boot: &BootOptions,
+        share_driver: ShareDriver,
+        cpuset: Option<&str>,
+        on_crash: CrashPolicy,
+        install: InstallSource,
+        install_media: &Path,
     ) -> Result<()> {
         // Check VM doesn't already exist
-        if self.vm_exists(vm_name)? {
-            return Err(Error::AlreadyExists(format!(
-                "VM '{}' already exists",
-                vm_name
-            )));
-        }
+        self.ensure_vm_absent(vm_name, overlay_path)?;
 
         let args = self.build_gateway_virt_install_args(
             vm_name,
             overlay_path,
-            lan_net,
-            role_net,
+            networks,
             role_dir,
             os_variant,
             ram_mb,
+            disk_tuning,
+            boot,
+            share_driver,
+            cpuset,
+            on_crash,
+            install,
+            install_media,
         );
 
-        let args_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
-        let output = self.run_cmd("virt-install", &args_refs)?;
+        let output = self.run_virt_install(vm_name, &args)?;
 
         if !output.success() {
             return Err(Error::libvirt(format!(
@@ -680,6 +1632,7 @@ impl LibvirtAdapter {
     }
 
     /// Create an app VM
+    #[allow(clippy::too_many_arguments)]
     pub fn create_app_vm(
         &self,
         vm_name: &str,
@@ -687,14 +1640,11 @@ impl LibvirtAdapter {
         role_net: &str,
         os_variant: &str,
         ram_mb: u32,
+        disk_tuning: &DiskTuning,
+        boot: &BootOptions,
         share_dir: Option<&Path>,
     ) -> Result<()> {
-        if self.vm_exists(vm_name)? {
-            return Err(Error::AlreadyExists(format!(
-                "VM '{}' already exists",
-                vm_name
-            )));
-        }
+        self.ensure_vm_absent(vm_name, overlay_path)?;
 
         let args = self.build_app_virt_install_args(
             vm_name,
@@ -702,11 +1652,12 @@ impl LibvirtAdapter {
             role_net,
             os_variant,
             ram_mb,
+            disk_tuning,
+            boot,
             share_dir,
         );
 
-        let args_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
-        let output = self.run_cmd("virt-install", &args_refs)?;
+        let output = self.run_virt_install(vm_name, &args)?;
 
         if !output.success() {
             return Err(Error::libvirt(format!(
@@ -719,6 +1670,7 @@ impl LibvirtAdapter {
     }
 
     /// Create a disposable (transient) VM
+    #[allow(clippy::too_many_arguments)]
     pub fn create_disposable_vm(
         &self,
         vm_name: &str,
@@ -726,6 +1678,8 @@ impl LibvirtAdapter {
         role_net: &str,
         os_variant: &str,
         ram_mb: u32,
+        disk_tuning: &DiskTuning,
+        boot: &BootOptions,
     ) -> Result<()> {
         let args = self.build_disposable_virt_install_args(
             vm_name,
@@ -733,10 +1687,11 @@ impl LibvirtAdapter {
             role_net,
             os_variant,
             ram_mb,
+            disk_tuning,
+            boot,
         );
 
-        let args_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
-        let output = self.run_cmd("virt-install", &args_refs)?;
+        let output = self.run_virt_install(vm_name, &args)?;
 
         if !output.success() {
             return Err(Error::libvirt(format!(
@@ -748,38 +1703,81 @@ impl LibvirtAdapter {
         Ok(())
     }
 
+    /// Run virt-install for `vm_name`. If it times out, whatever it managed to
+    /// define is removed before the error is returned, so callers can roll back
+    /// the rest as for any other failure.
+    fn run_virt_install(&self, vm_name: &str, args: &[String]) -> Result<CommandOutput> {
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        match self.run_cmd("virt-install", &args) {
+            Err(e @ Error::Timeout { .. }) => {
+                self.undefine_vm(vm_name).ok();
+                Err(e)
+            }
+            result => result,
+        }
+    }
+
+    /// Fail if a VM with this name is already defined.
+    ///
+    /// When the existing domain points at a different disk than the overlay we are
+    /// about to attach, it was changed outside the app, so report that specifically
+    /// instead of a plain AlreadyExists.
+    fn ensure_vm_absent(&self, vm_name: &str, overlay_path: &Path) -> Result<()> {
+        if !self.vm_exists(vm_name)? {
+            return Ok(());
+        }
+
+        match self.get_vm_disk_path(vm_name)? {
+            Some(actual) if actual != overlay_path => Err(Error::VmDiskMismatch {
+                vm: vm_name.to_string(),
+                expected: overlay_path.to_path_buf(),
+                actual,
+            }),
+            _ => Err(Error::AlreadyExists(format!(
+                "VM '{}' already exists",
+                vm_name
+            ))),
+        }
+    }
+
     /// Start a VM
-    pub fn start_vm(&self, name: &str) -> Result<()> {
+    pub fn start_vm(&self, name: &str) -> Result<StartOutcome> {
         let output = self.run_cmd("virsh", &["start", name])?;
-        if !output.success() {
-            return Err(Error::libvirt(format!(
-                "Failed to start VM '{}': {}",
-                name, output.stderr
-            )));
+        if output.success() {
+            return Ok(StartOutcome::Started);
         }
-        Ok(())
+        if is_already_active(&output.stderr) {
+            return Ok(StartOutcome::AlreadyRunning);
+        }
+        Err(virsh_error(
+            &format!("Failed to start VM '{}'", name),
+            &output.stderr,
+        ))
     }
 
     /// Stop a VM (graceful shutdown)
-    pub fn stop_vm(&self, name: &str) -> Result<()> {
+    pub fn stop_vm(&self, name: &str) -> Result<StopOutcome> {
         let output = self.run_cmd("virsh", &["shutdown", name])?;
-        if !output.success() {
-            return Err(Error::libvirt(format!(
-                "Failed to stop VM '{}': {}",
-                name, output.stderr
-            )));
+        if output.success() {
+            return Ok(StopOutcome::ShuttingDown);
         }
-        Ok(())
+        if is_not_running(&output.stderr) {
+            return Ok(StopOutcome::AlreadyStopped);
+        }
+        Err(virsh_error(
+            &format!("Failed to stop VM '{}'", name),
+            &output.stderr,
+        ))
     }
 
     /// Force stop a VM
     pub fn destroy_vm(&self, name: &str) -> Result<()> {
         let output = self.run_cmd("virsh", &["destroy", name])?;
-        if !output.success() && !output.stderr.contains("not running") {
-            return Err(Error::libvirt(format!(
-                "Failed to destroy VM '{}': {}",
-                name, output.stderr
-            )));
+        if !output.success() && !is_not_running(&output.stderr) {
+            return Err(virsh_error(
+                &format!("Failed to destroy VM '{}'", name),
+                &output.stderr,
+            ));
         }
         Ok(())
     }
@@ -790,12 +1788,78 @@ impl LibvirtAdapter {
         self.destroy_vm(name).ok();
 
         let output = self.run_cmd("virsh", &["undefine", name])?;
-        if !output.success() && !output.stderr.contains("failed to get domain") {
+        if !output.success() {
+            match virsh_error(&format!("Failed to undefine VM '{}'", name), &output.stderr) {
+                Error::DomainNotFound(_) => {}
+                e => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy a running disposable's disk into a new overlay at `dest`.
+    ///
+    /// The copy stays backed by the same template, so only the disposable's own
+    /// writes are duplicated. The VM is paused for the copy so the result is
+    /// consistent, and resumed afterwards whether or not the copy worked.
+    pub fn snapshot_disposable_overlay(&self, vm_name: &str, dest: &Path) -> Result<()> {
+        let source = self
+            .get_vm_disk_path(vm_name)?
+            .ok_or_else(|| Error::libvirt(format!("No disk found for VM '{}'", vm_name)))?;
+        let backing = self.get_backing_file(&source)?.ok_or_else(|| {
+            Error::libvirt(format!("Disk of '{}' has no backing template", vm_name))
+        })?;
+
+        if dest.exists() {
+            return Err(Error::AlreadyExists(format!(
+                "Overlay disk already exists: {}",
+                dest.display()
+            )));
+        }
+        self.check_overlay_space(&source, dest)?;
+
+        let args = [
+            "convert",
+            "-U",
+            "-O",
+            "qcow2",
+            "-F",
+            "qcow2",
+            "-B",
+            path_to_str(&backing)?,
+            path_to_str(&source)?,
+            path_to_str(dest)?,
+        ];
+        let privileged = needs_privilege(dest);
+
+        let paused = self.run_cmd("virsh", &["suspend", vm_name])?;
+        if !paused.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to pause '{}': {}",
+                vm_name,
+                paused.stderr.trim()
+            )));
+        }
+        let copied = if privileged {
+            self.run_privileged("qemu-img", &args)
+        } else {
+            self.run_cmd("qemu-img", &args)
+        };
+        self.run_cmd("virsh", &["resume", vm_name]).ok();
+
+        let output = copied?;
+        if !output.success() {
+            self.delete_overlay_disk(dest).ok();
             return Err(Error::libvirt(format!(
-                "Failed to undefine VM '{}': {}",
-                name, output.stderr
+                "Failed to copy disk of '{}': {}",
+                vm_name,
+                output.stderr.trim()
             )));
         }
+        if privileged {
+            self.run_privileged("chmod", &["644", path_to_str(dest)?])
+                .ok();
+        }
         Ok(())
     }
 
@@ -809,69 +1873,565 @@ impl LibvirtAdapter {
         Ok(())
     }
 
-    // ==================== Connectivity Testing ====================
+    // ==================== Guest Agent ====================
 
-    /// Get the disk image path for a VM by parsing its XML definition
-    pub fn get_vm_disk_path(&self, vm_name: &str) -> Result<Option<PathBuf>> {
-        let output = self.run_cmd("virsh", &["dumpxml", vm_name])?;
+    /// Send a QEMU guest agent command to a VM and return the parsed JSON reply
+    fn guest_agent_command(
+        &self,
+        vm_name: &str,
+        command: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let payload = command.to_string();
+        let output = self.run_cmd("virsh", &["qemu-agent-command", vm_name, &payload])?;
         if !output.success() {
-            return Ok(None);
-        }
-
-        // Parse the XML to find the disk source
-        // Look for: <source file='/path/to/disk.qcow2'/>
-        for line in output.stdout.lines() {
-            let line = line.trim();
-            if line.contains("<source file=") {
-                // Extract path from: <source file='/path/to/file.qcow2'/>
-                if let Some(start) = line.find("file='") {
-                    let path_start = start + 6;
-                    if let Some(end) = line[path_start..].find('\'') {
-                        let path_str = &line[path_start..path_start + end];
-                        return Ok(Some(PathBuf::from(path_str)));
-                    }
-                }
-                // Also try double quotes
-                if let Some(start) = line.find("file=\"") {
-                    let path_start = start + 6;
-                    if let Some(end) = line[path_start..].find('"') {
-                        let path_str = &line[path_start..path_start + end];
-                        return Ok(Some(PathBuf::from(path_str)));
-                    }
-                }
-            }
+            return Err(Error::libvirt(format!(
+                "Guest agent command failed for '{}': {}",
+                vm_name,
+                output.stderr.trim()
+            )));
         }
-        Ok(None)
+        Ok(serde_json::from_str(output.stdout.trim())?)
     }
 
-    /// Get a map of disk paths to VM names for all VMs
-    pub fn get_disk_to_vm_map(&self) -> Result<HashMap<PathBuf, Vec<String>>> {
-        let mut map: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    /// Check whether the QEMU guest agent is responding inside a VM
+    pub fn guest_agent_available(&self, vm_name: &str) -> bool {
+        self.guest_agent_command(vm_name, &serde_json::json!({ "execute": "guest-ping" }))
+            .is_ok()
+    }
 
-        // Get list of all VMs
-        let output = self.run_cmd("virsh", &["list", "--all", "--name"])?;
+    /// Ask a running VM's guest agent to TRIM its filesystems (`virsh domfstrim`).
+    ///
+    /// Only returns space to the host when the disk was created with
+    /// `discard=unmap`; otherwise QEMU drops the discards.
+    pub fn trim_vm_disk(&self, vm_name: &str) -> Result<()> {
+        let output = self.run_cmd("virsh", &["domfstrim", vm_name])?;
         if !output.success() {
-            return Ok(map);
+            return Err(Error::libvirt(format!(
+                "Failed to trim disk of '{}' (needs qemu-guest-agent running in the VM): {}",
+                vm_name,
+                output.stderr.trim()
+            )));
         }
+        Ok(())
+    }
 
-        for line in output.stdout.lines() {
-            let vm_name = line.trim();
-            if vm_name.is_empty() {
-                continue;
-            }
-
-            if let Ok(Some(disk_path)) = self.get_vm_disk_path(vm_name) {
-                map.entry(disk_path).or_default().push(vm_name.to_string());
+    /// Rename a shut-off VM with `virsh domrename`, which refuses running domains.
+    ///
+    /// The new name must be free and must still match the naming pattern of the
+    /// VM's kind and role, or the VM would drop off its role card.
+    pub fn rename_vm(&self, old_name: &str, new_name: &str) -> Result<()> {
+        validate_vm_name(new_name).map_err(Error::validation)?;
+        if new_name == old_name {
+            return Ok(());
+        }
+        let info = self
+            .get_vm_info(old_name)?
+            .ok_or_else(|| Error::NotFound(format!("VM '{}' not found", old_name)))?;
+        if info.state != VmState::ShutOff {
+            return Err(Error::validation(format!(
+                "Shut '{}' down before renaming it",
+                old_name
+            )));
+        }
+        if let Some(old_class) = self.naming.classify(old_name) {
+            if self.naming.classify(new_name).as_ref() != Some(&old_class) {
+                return Err(Error::validation(format!(
+                    "'{}' doesn't match the {} name pattern of role '{}' (e.g. '{}')",
+                    new_name,
+                    old_class.0.display_name(),
+                    old_class.1,
+                    old_name
+                )));
             }
         }
+        if self.vm_exists(new_name)? {
+            return Err(Error::AlreadyExists(format!(
+                "A VM named '{}' already exists",
+                new_name
+            )));
+        }
 
-        Ok(map)
+        let output = self.run_cmd("virsh", &["domrename", old_name, new_name])?;
+        if !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to rename '{}' to '{}': {}",
+                old_name,
+                new_name,
+                output.stderr.trim()
+            )));
+        }
+        Ok(())
     }
 
-    /// Get all VMs that use a specific disk or its overlays (checks backing file chain)
-    pub fn get_vms_using_image(&self, image_path: &Path) -> Result<Vec<String>> {
-        let mut vms = Vec::new();
-        let disk_map = self.get_disk_to_vm_map()?;
+    /// Move a shut-off VM's disk to `<vm_name>-overlay.qcow2` next to it and point
+    /// the definition at the new file; returns the new path
+    pub fn rename_vm_overlay(&self, vm_name: &str) -> Result<PathBuf> {
+        let disk = self
+            .get_vm_disk_path(vm_name)?
+            .ok_or_else(|| Error::NotFound(format!("No disk found for VM '{}'", vm_name)))?;
+        let renamed = disk.with_file_name(format!("{}-overlay.qcow2", vm_name));
+        if renamed == disk {
+            return Ok(disk);
+        }
+        if renamed.exists() {
+            return Err(Error::AlreadyExists(format!(
+                "{} already exists",
+                renamed.display()
+            )));
+        }
+
+        let (from, to) = (path_to_str(&disk)?, path_to_str(&renamed)?);
+        let privileged = needs_privilege(&disk);
+        let moved = if privileged {
+            self.run_privileged("mv", &["-n", from, to])?
+        } else {
+            self.run_cmd("mv", &["-n", from, to])?
+        };
+        if !moved.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to move {}: {}",
+                disk.display(),
+                moved.stderr.trim()
+            )));
+        }
+
+        let disk_arg = format!("path={}", to);
+        let output = self.run_cmd("virt-xml", &[vm_name, "--edit", "--disk", &disk_arg])?;
+        if !output.success() {
+            // Put the file back so the definition still points at it
+            if privileged {
+                self.run_privileged("mv", &["-n", to, from]).ok();
+            } else {
+                self.run_cmd("mv", &["-n", to, from]).ok();
+            }
+            return Err(Error::libvirt(format!(
+                "Failed to point '{}' at its renamed disk: {}",
+                vm_name,
+                output.stderr.trim()
+            )));
+        }
+        Ok(renamed)
+    }
+
+    /// Run a program inside a VM via the guest agent and wait for it to exit
+    pub fn guest_exec(&self, vm_name: &str, path: &str, args: &[&str]) -> Result<CommandOutput> {
+        let reply = self.guest_agent_command(
+            vm_name,
+            &serde_json::json!({
+                "execute": "guest-exec",
+                "arguments": { "path": path, "arg": args, "capture-output": true }
+            }),
+        )?;
+        let pid = parse_guest_exec_pid(&reply)?;
+
+        let deadline = Instant::now() + Duration::from_secs(self.guest_agent_timeout_secs);
+        loop {
+            let status = self.guest_agent_command(
+                vm_name,
+                &serde_json::json!({
+                    "execute": "guest-exec-status",
+                    "arguments": { "pid": pid }
+                }),
+            )?;
+            if let Some(output) = parse_guest_exec_status(&status)? {
+                return Ok(output);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::vm(format!(
+                    "Timed out waiting for '{}' to finish in VM '{}'",
+                    path, vm_name
+                )));
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    }
+
+    /// Read the end of a file inside a VM through the guest agent, at most
+    /// `max_bytes` of it. When the file is longer, the cut-off first line is
+    /// dropped so the text starts at a line.
+    pub fn guest_read_file(&self, vm_name: &str, path: &str, max_bytes: usize) -> Result<String> {
+        if !self.guest_agent_available(vm_name) {
+            return Err(Error::vm(format!(
+                "Guest agent is not responding in '{}'",
+                vm_name
+            )));
+        }
+        let opened = self
+            .guest_agent_command(
+                vm_name,
+                &serde_json::json!({
+                    "execute": "guest-file-open",
+                    "arguments": { "path": path, "mode": "r" }
+                }),
+            )
+            .map_err(|e| Error::vm(format!("Cannot open {} in '{}': {}", path, vm_name, e)))?;
+        let handle = opened["return"]
+            .as_i64()
+            .ok_or_else(|| Error::Parse(format!("Unexpected guest-file-open reply: {}", opened)))?;
+
+        let read = self.guest_read_tail(vm_name, handle, max_bytes);
+        self.guest_agent_command(
+            vm_name,
+            &serde_json::json!({
+                "execute": "guest-file-close",
+                "arguments": { "handle": handle }
+            }),
+        )
+        .ok();
+        let (data, truncated) = read?;
+
+        let text = String::from_utf8_lossy(&data);
+        Ok(match text.split_once('\n') {
+            Some((_, rest)) if truncated => rest.to_string(),
+            _ => text.into_owned(),
+        })
+    }
+
+    /// Read the last `max_bytes` from an open guest file; also returns whether
+    /// anything before them was skipped
+    fn guest_read_tail(
+        &self,
+        vm_name: &str,
+        handle: i64,
+        max_bytes: usize,
+    ) -> Result<(Vec<u8>, bool)> {
+        let seek = |offset: u64, whence: &str| -> Result<u64> {
+            let reply = self.guest_agent_command(
+                vm_name,
+                &serde_json::json!({
+                    "execute": "guest-file-seek",
+                    "arguments": { "handle": handle, "offset": offset, "whence": whence }
+                }),
+            )?;
+            reply["return"]["position"]
+                .as_u64()
+                .ok_or_else(|| Error::Parse(format!("Unexpected guest-file-seek reply: {}", reply)))
+        };
+        let size = seek(0, "end")?;
+        let start = size.saturating_sub(max_bytes as u64);
+        seek(start, "set")?;
+
+        let mut data = Vec::new();
+        while data.len() < max_bytes {
+            let count = (max_bytes - data.len()).min(GUEST_READ_CHUNK);
+            let reply = self.guest_agent_command(
+                vm_name,
+                &serde_json::json!({
+                    "execute": "guest-file-read",
+                    "arguments": { "handle": handle, "count": count }
+                }),
+            )?;
+            let ret = &reply["return"];
+            if let Some(buf) = ret["buf-b64"].as_str() {
+                let chunk = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, buf)
+                    .map_err(|e| Error::Parse(format!("Invalid guest-file-read data: {}", e)))?;
+                data.extend(chunk);
+            }
+            if ret["eof"].as_bool().unwrap_or(true) || ret["count"].as_u64() == Some(0) {
+                break;
+            }
+        }
+        Ok((data, start > 0))
+    }
+
+    /// Last `max_lines` journal lines of the gateway service that runs
+    /// apply-proxy.sh at boot, read via the guest agent
+    pub fn gateway_journal(&self, vm_name: &str, max_lines: usize) -> Result<String> {
+        if !self.guest_agent_available(vm_name) {
+            return Err(Error::vm(format!(
+                "Guest agent is not responding in '{}'",
+                vm_name
+            )));
+        }
+        let lines = max_lines.to_string();
+        let mut args = vec!["--no-pager", "-o", "short-iso", "-n", &lines];
+        for unit in APPLY_PROXY_UNITS {
+            args.extend(["-u", unit]);
+        }
+        let output = self.guest_exec(vm_name, "journalctl", &args)?;
+        if !output.success() {
+            return Err(Error::vm(format!(
+                "'journalctl' failed in '{}': {}",
+                vm_name,
+                output.stderr.trim()
+            )));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Check that the role directory is mounted at /proxy inside a gateway VM
+    pub fn check_proxy_mount(&self, vm_name: &str) -> Result<ProxyMountStatus> {
+        if !self.guest_agent_available(vm_name) {
+            return Ok(ProxyMountStatus::AgentUnavailable);
+        }
+
+        let output = self.guest_exec(vm_name, "test", &["-f", "/proxy/proxy.conf"])?;
+        if output.success() {
+            Ok(ProxyMountStatus::Mounted)
+        } else {
+            Ok(ProxyMountStatus::Missing)
+        }
+    }
+
+    /// Like [`check_proxy_mount`](Self::check_proxy_mount), but for a VM that is
+    /// still booting: retries until the guest agent answers or `timeout` passes
+    pub fn wait_for_proxy_mount(
+        &self,
+        vm_name: &str,
+        timeout: Duration,
+    ) -> Result<ProxyMountStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = self.check_proxy_mount(vm_name)?;
+            if status != ProxyMountStatus::AgentUnavailable || Instant::now() >= deadline {
+                return Ok(status);
+            }
+            std::thread::sleep(Duration::from_secs(5));
+        }
+    }
+
+    /// Report WireGuard handshake ages inside a gateway via the guest agent
+    pub fn wireguard_status(&self, vm_name: &str, iface: &str) -> Result<WgStatus> {
+        if !self.guest_agent_available(vm_name) {
+            return Err(Error::vm(format!(
+                "Guest agent is not responding in '{}'",
+                vm_name
+            )));
+        }
+
+        // Use the guest clock so host/guest skew doesn't distort the age
+        let now = self.guest_exec(vm_name, "date", &["+%s"])?;
+        let now: u64 =
+            now.stdout.trim().parse().map_err(|_| {
+                Error::Parse(format!("Unexpected guest time: {}", now.stdout.trim()))
+            })?;
+
+        let output = self.guest_exec(vm_name, "wg", &["show", iface, "latest-handshakes"])?;
+        if !output.success() {
+            return Err(Error::vm(format!(
+                "'wg show {}' failed in '{}': {}",
+                iface,
+                vm_name,
+                output.stderr.trim()
+            )));
+        }
+
+        Ok(parse_wg_handshakes(iface, &output.stdout, now))
+    }
+
+    /// Check that a running gateway's upstream answers, the way its mode allows:
+    /// a TCP connection to the first proxy hop, or a recent WireGuard handshake
+    /// inside the gateway. OpenVPN gateways are skipped.
+    pub fn check_upstream(&self, gw_vm: &str, config: &ProxyConfig) -> UpstreamCheck {
+        match config.gateway_mode {
+            GatewayMode::ProxyChain => {
+                let Some(first) = config.hops.first() else {
+                    return UpstreamCheck::Skipped("No proxy hops configured".to_string());
+                };
+                match self.test_tcp_connection(&first.host, first.port) {
+                    Ok(()) => UpstreamCheck::Reachable(format!(
+                        "First hop {}:{} accepts connections",
+                        first.host, first.port
+                    )),
+                    Err(e) => UpstreamCheck::Unreachable(format!(
+                        "First hop {}:{}: {}",
+                        first.host, first.port, e
+                    )),
+                }
+            }
+            GatewayMode::WireGuard => {
+                let iface = config
+                    .wireguard
+                    .as_ref()
+                    .map(|wg| wg.interface_name.as_str())
+                    .unwrap_or("wg0");
+                match self.wireguard_status(gw_vm, iface) {
+                    Ok(status) if status.is_healthy() => UpstreamCheck::Reachable(format!(
+                        "{}: handshake {}s ago",
+                        iface,
+                        status.latest_handshake_age().unwrap_or(0)
+                    )),
+                    Ok(status) => UpstreamCheck::Unreachable(match status.latest_handshake_age() {
+                        Some(age) => format!("{}: last handshake {}s ago", iface, age),
+                        None => format!("{}: no handshake", iface),
+                    }),
+                    Err(e) => UpstreamCheck::Unreachable(e.to_string()),
+                }
+            }
+            GatewayMode::OpenVpn => {
+                UpstreamCheck::Skipped("OpenVPN gateways have no upstream check".to_string())
+            }
+        }
+    }
+
+    /// Try to open a TCP connection from an app VM straight to a LAN address.
+    ///
+    /// `lan_target` is `host` or `host:port` (port 80 if omitted). Returns `true`
+    /// when the LAN host answered, even by refusing the connection, meaning the VM
+    /// can reach the LAN directly and isolation is broken; `false` when the
+    /// connection timed out or had no route. Anything else is inconclusive and an
+    /// error. Needs the guest agent and bash inside the app VM.
+    pub fn probe_app_egress(&self, app_vm: &str, lan_target: &str) -> Result<bool> {
+        let (host, port) = split_probe_target(lan_target)?;
+        if !self.guest_agent_available(app_vm) {
+            return Err(Error::vm(format!(
+                "Guest agent is not responding in '{}'",
+                app_vm
+            )));
+        }
+
+        // Host and port go in as positional args, never spliced into the script.
+        // The C locale keeps bash's connect errors in English
+        let timeout = EGRESS_PROBE_TIMEOUT_SECS.to_string();
+        let port = port.to_string();
+        let output = self.guest_exec(
+            app_vm,
+            "env",
+            &[
+                "LC_ALL=C",
+                "timeout",
+                &timeout,
+                "bash",
+                "-c",
+                "exec 3<>\"/dev/tcp/$0/$1\"",
+                host,
+                &port,
+            ],
+        )?;
+        let stderr = output.stderr.to_lowercase();
+        match output.exit_code {
+            0 => Ok(true),
+            // timeout(1) gives up on a connect that hangs
+            124 => Ok(false),
+            126 | 127 => Err(Error::vm(format!(
+                "Could not run the egress probe in '{}': {}",
+                app_vm,
+                output.stderr.trim()
+            ))),
+            // A refusal comes from the LAN host itself, so it was reached
+            _ if stderr.contains("connection refused") => Ok(true),
+            _ if stderr.contains("no route to host")
+                || stderr.contains("network is unreachable") =>
+            {
+                Ok(false)
+            }
+            code => Err(Error::vm(format!(
+                "Egress probe in '{}' was inconclusive (exit {}): {}",
+                app_vm,
+                code,
+                output.stderr.trim()
+            ))),
+        }
+    }
+
+    /// The host's default gateway, used as a LAN-only address for isolation probes
+    pub fn host_default_gateway(&self) -> Result<Option<String>> {
+        let output = self.run_cmd("ip", &["route", "show", "default"])?;
+        if !output.success() {
+            return Ok(None);
+        }
+        Ok(parse_default_gateway(&output.stdout))
+    }
+
+    /// Network interfaces of a domain, in definition order
+    pub fn list_vm_interfaces(&self, vm_name: &str) -> Result<Vec<VmInterface>> {
+        let output = self.run_cmd("virsh", &["domiflist", vm_name])?;
+        if !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to list interfaces of '{}': {}",
+                vm_name,
+                output.stderr.trim()
+            )));
+        }
+        Ok(parse_domiflist(&output.stdout))
+    }
+
+    // ==================== Connectivity Testing ====================
+
+    /// libvirt networks any domain has a NIC on, whatever its name or owner
+    pub fn networks_in_use(&self) -> Result<HashSet<String>> {
+        let output = self.run_cmd("virsh", &["list", "--all", "--name"])?;
+        if !output.success() {
+            return Err(classify_virsh_error(&output.stderr));
+        }
+        let mut networks = HashSet::new();
+        for vm_name in output
+            .stdout
+            .lines()
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+        {
+            networks.extend(
+                self.list_vm_interfaces(vm_name)?
+                    .into_iter()
+                    .filter(|nic| nic.kind == "network")
+                    .map(|nic| nic.source),
+            );
+        }
+        Ok(networks)
+    }
+
+    /// Get the disk image path for a VM by parsing its XML definition
+    pub fn get_vm_disk_path(&self, vm_name: &str) -> Result<Option<PathBuf>> {
+        let output = self.run_cmd("virsh", &["dumpxml", vm_name])?;
+        if !output.success() {
+            return Ok(None);
+        }
+
+        // Parse the XML to find the disk source
+        // Look for: <source file='/path/to/disk.qcow2'/>
+        for line in output.stdout.lines() {
+            let line = line.trim();
+            if line.contains("<source file=") {
+                // Extract path from: <source file='/path/to/file.qcow2'/>
+                if let Some(start) = line.find("file='") {
+                    let path_start = start + 6;
+                    if let Some(end) = line[path_start..].find('\'') {
+                        let path_str = &line[path_start..path_start + end];
+                        return Ok(Some(PathBuf::from(path_str)));
+                    }
+                }
+                // Also try double quotes
+                if let Some(start) = line.find("file=\"") {
+                    let path_start = start + 6;
+                    if let Some(end) = line[path_start..].find('"') {
+                        let path_str = &line[path_start..path_start + end];
+                        return Ok(Some(PathBuf::from(path_str)));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Get a map of disk paths to VM names for all VMs
+    pub fn get_disk_to_vm_map(&self) -> Result<HashMap<PathBuf, Vec<String>>> {
+        let mut map: HashMap<PathBuf, Vec<String>> = HashMap::new();
+
+        // Get list of all VMs
+        let output = self.run_cmd("virsh", &["list", "--all", "--name"])?;
+        if !output.success() {
+            return Ok(map);
+        }
+
+        for line in output.stdout.lines() {
+            let vm_name = line.trim();
+            if vm_name.is_empty() {
+                continue;
+            }
+
+            if let Ok(Some(disk_path)) = self.get_vm_disk_path(vm_name) {
+                map.entry(disk_path).or_default().push(vm_name.to_string());
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Get all VMs that use a specific disk or its overlays (checks backing file chain)
+    pub fn get_vms_using_image(&self, image_path: &Path) -> Result<Vec<String>> {
+        let mut vms = Vec::new();
+        let disk_map = self.get_disk_to_vm_map()?;
 
         // Direct match - VM uses this image directly
         if let Some(vm_list) = disk_map.get(image_path) {
@@ -894,6 +2454,17 @@ impl LibvirtAdapter {
         Ok(vms)
     }
 
+    /// VMs using an image directly or through an overlay, with their current state
+    pub fn get_image_users(&self, image_path: &Path) -> Result<Vec<VmInfo>> {
+        let mut users = Vec::new();
+        for name in self.get_vms_using_image(image_path)? {
+            if let Some(info) = self.get_vm_info(&name)? {
+                users.push(info);
+            }
+        }
+        Ok(users)
+    }
+
     /// Get the backing file for a qcow2 image
     pub fn get_backing_file(&self, disk_path: &Path) -> Result<Option<PathBuf>> {
         let path_str = path_to_str(disk_path)?;
@@ -917,108 +2488,3158 @@ impl LibvirtAdapter {
         Ok(None)
     }
 
-    /// Test TCP connectivity to a host:port
-    pub fn test_tcp_connection(&self, host: &str, port: u16) -> Result<()> {
-        let addr_str = format!("{}:{}", host, port);
-        let addrs: Vec<SocketAddr> = addr_str
-            .to_socket_addrs()
-            .map_err(|e| Error::ConnectionTest {
-                host: host.to_string(),
-                port,
-                reason: format!("DNS resolution failed: {}", e),
-            })?
-            .collect();
-
-        if addrs.is_empty() {
-            return Err(Error::ConnectionTest {
-                host: host.to_string(),
-                port,
-                reason: "No addresses resolved".to_string(),
-            });
-        }
-
-        let timeout = Duration::from_secs(self.connect_timeout_secs);
-        for addr in addrs {
-            match TcpStream::connect_timeout(&addr, timeout) {
-                Ok(_) => return Ok(()),
-                Err(_) => continue,
+    /// Backing files under a disk, nearest first; the last one is the base image
+    pub fn backing_chain(&self, disk_path: &Path) -> Result<Vec<PathBuf>> {
+        let mut chain: Vec<PathBuf> = Vec::new();
+        let mut current = disk_path.to_path_buf();
+        while let Some(backing) = self.get_backing_file(&current)? {
+            if backing == disk_path || chain.contains(&backing) {
+                return Err(Error::libvirt(format!(
+                    "Backing chain of {} loops back to {}",
+                    disk_path.display(),
+                    backing.display()
+                )));
             }
+            chain.push(backing.clone());
+            current = backing;
         }
+        Ok(chain)
+    }
 
-        Err(Error::ConnectionTest {
-            host: host.to_string(),
-            port,
-            reason: "Connection timed out or refused".to_string(),
-        })
+    /// Number of backing files under a disk: 0 for a standalone image, 1 for an
+    /// overlay directly on its template
+    pub fn backing_chain_depth(&self, path: &Path) -> Result<usize> {
+        Ok(self.backing_chain(path)?.len())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Collapse the intermediate layers of a shut-off VM's disk into one overlay
+    /// directly on its base image, so the template stays shared.
+    ///
+    /// The new image is written next to the old one and only replaces it once
+    /// `qemu-img convert` succeeded. Returns the new chain depth.
+    pub fn flatten_vm_disk(&self, vm_name: &str) -> Result<usize> {
+        if self
+            .get_vm_info(vm_name)?
+            .is_some_and(|info| info.state.is_running())
+        {
+            return Err(Error::validation(format!(
+                "Shut down '{}' before flattening its disk",
+                vm_name
+            )));
+        }
+        let disk = self
+            .get_vm_disk_path(vm_name)?
+            .ok_or_else(|| Error::libvirt(format!("No disk found for '{}'", vm_name)))?;
+        let chain = self.backing_chain(&disk)?;
+        let Some(base) = chain.last().filter(|_| chain.len() > 1) else {
+            return Ok(chain.len());
+        };
 
-    #[test]
-    fn test_gateway_virt_install_args() {
-        let adapter = LibvirtAdapter::new();
-        let args = adapter.build_gateway_virt_install_args(
-            "work-gw",
-            Path::new("/var/lib/libvirt/images/work-gw.qcow2"),
-            "lan-net",
-            "work-inet",
-            Path::new("/home/user/VMS/VM-Proxy-configs/work"),
-            "debian12",
-            512,
-        );
+        let disk_str = path_to_str(&disk)?;
+        let tmp = PathBuf::from(format!("{}.flatten-tmp", disk_str));
+        self.check_overlay_space(&disk, &tmp)?;
 
-        assert!(args.contains(&"--name".to_string()));
-        assert!(args.contains(&"work-gw".to_string()));
-        assert!(args.contains(&"--import".to_string()));
-        assert!(args.iter().any(|a| a.contains("lan-net")));
-        assert!(args.iter().any(|a| a.contains("work-inet")));
-        assert!(args.iter().any(|a| a.contains("proxy,accessmode=mapped")));
-    }
+        let args = [
+            "convert",
+            "-O",
+            "qcow2",
+            "-F",
+            "qcow2",
+            "-B",
+            path_to_str(base)?,
+            disk_str,
+            path_to_str(&tmp)?,
+        ];
+        let privileged = needs_privilege(&disk);
+        let output = if privileged {
+            self.run_privileged("qemu-img", &args)?
+        } else {
+            self.run_cmd("qemu-img", &args)?
+        };
+        if !output.success() {
+            self.delete_overlay_disk(&tmp).ok();
+            return Err(Error::libvirt(format!(
+                "Failed to flatten disk of '{}': {}",
+                vm_name,
+                output.stderr.trim()
+            )));
+        }
 
-    #[test]
-    fn test_app_virt_install_args() {
-        let adapter = LibvirtAdapter::new();
-        let args = adapter.build_app_virt_install_args(
-            "work-app-1",
-            Path::new("/var/lib/libvirt/images/work-app-1.qcow2"),
-            "work-inet",
-            "fedora40",
-            2048,
-            None,
-        );
+        if privileged {
+            let moved = self.run_privileged("mv", &["-f", path_to_str(&tmp)?, disk_str])?;
+            if !moved.success() {
+                self.delete_overlay_disk(&tmp).ok();
+                return Err(Error::libvirt(format!(
+                    "Failed to replace {}: {}",
+                    disk.display(),
+                    moved.stderr.trim()
+                )));
+            }
+            self.run_privileged("chmod", &["644", disk_str]).ok();
+        } else if let Err(e) = std::fs::rename(&tmp, &disk) {
+            self.delete_overlay_disk(&tmp).ok();
+            return Err(e.into());
+        }
+        Ok(1)
+    }
+
+    /// Managed VMs whose disk chain points at a backing file that is gone
+    pub fn check_backing_chains(&self) -> Result<Vec<BrokenBacking>> {
+        let mut broken = Vec::new();
+        for vm in self.list_vms(None)? {
+            if vm.role.is_none() {
+                continue;
+            }
+            let Some(disk) = self.get_vm_disk_path(&vm.name)? else {
+                continue;
+            };
+            let mut seen = vec![disk.clone()];
+            let mut current = disk;
+            while let Some(backing) = self.get_backing_file(&current)? {
+                if !backing.exists() {
+                    broken.push(BrokenBacking {
+                        vm: vm.name.clone(),
+                        overlay: current,
+                        missing: backing,
+                    });
+                    break;
+                }
+                // A looping chain has no missing file; stop instead of walking it forever
+                if seen.contains(&backing) {
+                    break;
+                }
+                seen.push(backing.clone());
+                current = backing;
+            }
+        }
+        Ok(broken)
+    }
+
+    /// Point an overlay at a new backing file without touching its data
+    /// (`qemu-img rebase -u`), for when the old backing file was moved.
+    ///
+    /// The new backing file must be an existing qcow2 image that doesn't itself
+    /// sit on top of `overlay`. Unsafe rebase trusts that it holds the same data
+    /// as the old one; pointing it at a different image corrupts the overlay.
+    pub fn rebase_backing(&self, overlay: &Path, new_backing: &Path) -> Result<()> {
+        if !overlay.exists() {
+            return Err(Error::NotFound(overlay.display().to_string()));
+        }
+        if !new_backing.is_file() {
+            return Err(Error::NotFound(new_backing.display().to_string()));
+        }
+        let format = self.image_info(new_backing)?.format;
+        if format != "qcow2" {
+            return Err(Error::validation(format!(
+                "{} is a {} image; overlays need a qcow2 backing file",
+                new_backing.display(),
+                if format.is_empty() {
+                    "unknown"
+                } else {
+                    &format
+                }
+            )));
+        }
+        if new_backing == overlay
+            || self
+                .backing_chain(new_backing)?
+                .iter()
+                .any(|p| p == overlay)
+        {
+            return Err(Error::validation(format!(
+                "{} is built on {}, so it cannot back it",
+                new_backing.display(),
+                overlay.display()
+            )));
+        }
+
+        let args = [
+            "rebase",
+            "-u",
+            "-b",
+            path_to_str(new_backing)?,
+            "-F",
+            "qcow2",
+            path_to_str(overlay)?,
+        ];
+        let privileged = needs_privilege(overlay);
+        let output = if privileged {
+            self.run_privileged("qemu-img", &args)?
+        } else {
+            self.run_cmd("qemu-img", &args)?
+        };
+        if !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to rebase {}: {}",
+                overlay.display(),
+                output.stderr.trim()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Change the crash action in an existing VM's definition; a running VM
+    /// picks it up on its next start. Restart also gives the VM a panic device
+    /// if it has none, since without one a guest panic is never seen as a crash.
+    pub fn set_on_crash(&self, vm_name: &str, policy: CrashPolicy) -> Result<()> {
+        if policy == CrashPolicy::Restart {
+            let output = self.run_cmd("virsh", &["dumpxml", "--inactive", vm_name])?;
+            if !output.success() {
+                return Err(classify_virsh_error(&output.stderr));
+            }
+            if !output.stdout.contains("<panic") {
+                let output =
+                    self.run_cmd("virt-xml", &[vm_name, "--add-device", "--panic", "default"])?;
+                if !output.success() {
+                    return Err(Error::libvirt(format!(
+                        "Failed to add a panic device to '{}': {}",
+                        vm_name,
+                        output.stderr.trim()
+                    )));
+                }
+            }
+        }
+        let events = format!("on_crash={}", policy.libvirt_action());
+        let output = self.run_cmd("virt-xml", &[vm_name, "--edit", "--events", &events])?;
+        if !output.success() {
+            return Err(Error::libvirt(format!(
+                "Failed to set crash action of '{}': {}",
+                vm_name,
+                output.stderr.trim()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Test TCP connectivity to a host:port
+    pub fn test_tcp_connection(&self, host: &str, port: u16) -> Result<()> {
+        self.connect_tcp(host, port).map(|_| ())
+    }
+
+    /// Test TCP connectivity to several endpoints side by side, handing each
+    /// result to `on_result` with the endpoint's index as soon as it is known.
+    ///
+    /// Each connection still gets up to `connect_timeout_secs`, but the whole run
+    /// fits in `budget`: a connection never waits past it, and endpoints whose
+    /// turn comes after it fail without being tried.
+    pub fn test_tcp_connections<F>(
+        &self,
+        endpoints: &[(String, u16)],
+        budget: Duration,
+        on_result: F,
+    ) where
+        F: Fn(usize, Result<()>) + Sync,
+    {
+        let deadline = Instant::now() + budget;
+        let indexed: Vec<(usize, &(String, u16))> = endpoints.iter().enumerate().collect();
+        parallel_map(&indexed, PARALLEL_CONNECTION_TESTS, |(i, (host, port))| {
+            let result = self.connect_tcp_until(host, *port, Some(deadline));
+            on_result(*i, result.map(|_| ()));
+        });
+    }
+
+    /// Send one ICMP echo to `host` and return the round-trip time.
+    ///
+    /// Many networks drop ICMP, so a failure here only means no reply came back;
+    /// it says nothing about whether the proxy port is open.
+    pub fn ping_host(&self, host: &str) -> Result<Duration> {
+        let host = host.trim();
+        if host.is_empty() || host.starts_with('-') {
+            return Err(Error::validation(format!("Invalid host: '{}'", host)));
+        }
+        let wait = format!("-W{}", self.connect_timeout_secs.max(1));
+        let output = self.run_cmd("ping", &["-c1", &wait, host])?;
+        if !output.success() {
+            let detail = output.stderr.trim();
+            return Err(Error::Network(if detail.is_empty() {
+                format!("No ping reply from {}", host)
+            } else {
+                format!("No ping reply from {}: {}", host, detail)
+            }));
+        }
+        parse_ping_rtt(&output.stdout)
+            .ok_or_else(|| Error::Parse(format!("No round-trip time in ping output for {}", host)))
+    }
+
+    /// Guess whether a proxy endpoint speaks SOCKS5 or HTTP.
+    ///
+    /// Sends a SOCKS5 greeting first, then an HTTP CONNECT on a fresh connection.
+    /// Returns `Ok(None)` when the endpoint is reachable but neither probe gets a
+    /// recognizable reply.
+    pub fn detect_proxy_type(&self, host: &str, port: u16) -> Result<Option<ProxyType>> {
+        let timeout = Duration::from_secs(self.connect_timeout_secs);
+
+        let mut stream = self.connect_tcp(host, port)?;
+        if let Some(reply) = probe(&mut stream, &SOCKS5_GREETING, timeout) {
+            if is_socks5_reply(&reply) {
+                return Ok(Some(ProxyType::Socks5));
+            }
+            // Most HTTP proxies answer garbage with an HTTP error status
+            if is_http_reply(&reply) {
+                return Ok(Some(ProxyType::Http));
+            }
+        }
+
+        let mut stream = self.connect_tcp(host, port)?;
+        if let Some(reply) = probe(&mut stream, HTTP_CONNECT_PROBE.as_bytes(), timeout) {
+            if is_http_reply(&reply) {
+                return Ok(Some(ProxyType::Http));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check that a SOCKS5 proxy will relay UDP.
+    ///
+    /// Negotiates a UDP ASSOCIATE (with username/password auth when the hop has
+    /// credentials) and succeeds once the proxy answers with a usable relay address.
+    pub fn test_socks5_udp(&self, hop: &ProxyHop) -> Result<()> {
+        let fail = |reason: String| Error::ConnectionTest {
+            host: hop.host.clone(),
+            port: hop.port,
+            reason,
+        };
+        let io_fail = |e: std::io::Error| fail(format!("Proxy closed the connection: {}", e));
+        if hop.proxy_type != ProxyType::Socks5 {
+            return Err(fail("UDP relay needs a SOCKS5 proxy".to_string()));
+        }
+
+        let timeout = Duration::from_secs(self.connect_timeout_secs);
+        let mut stream = self.connect_tcp(&hop.host, hop.port)?;
+        stream.set_read_timeout(Some(timeout)).map_err(io_fail)?;
+        stream.set_write_timeout(Some(timeout)).map_err(io_fail)?;
+
+        let credentials = hop.username.as_deref().zip(hop.password.as_deref());
+        let greeting: &[u8] = if credentials.is_some() {
+            &[0x05, 0x02, 0x00, 0x02]
+        } else {
+            &SOCKS5_GREETING
+        };
+        stream.write_all(greeting).map_err(io_fail)?;
+        let mut method = [0u8; 2];
+        stream.read_exact(&mut method).map_err(io_fail)?;
+        if method[0] != 0x05 {
+            return Err(fail("Not a SOCKS5 proxy".to_string()));
+        }
+        match (method[1], credentials) {
+            (0x00, _) => {}
+            (0x02, Some((user, pass))) => {
+                stream
+                    .write_all(&socks5_auth_request(user, pass)?)
+                    .map_err(io_fail)?;
+                let mut status = [0u8; 2];
+                stream.read_exact(&mut status).map_err(io_fail)?;
+                if status[1] != 0x00 {
+                    return Err(fail("Proxy rejected the credentials".to_string()));
+                }
+            }
+            _ => return Err(fail("Proxy accepts none of our auth methods".to_string())),
+        }
+
+        // UDP ASSOCIATE from 0.0.0.0:0, i.e. "whatever address I send from"
+        stream
+            .write_all(&[0x05, 0x03, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+            .map_err(io_fail)?;
+        let mut reply = [0u8; 262];
+        let n = stream.read(&mut reply).map_err(io_fail)?;
+        parse_udp_associate_reply(&reply[..n]).map_err(fail)
+    }
+
+    /// Resolve host:port and connect to the first address that answers
+    fn connect_tcp(&self, host: &str, port: u16) -> Result<TcpStream> {
+        self.connect_tcp_until(host, port, None)
+    }
+
+    /// `connect_tcp`, giving up on every address once `deadline` has passed
+    fn connect_tcp_until(
+        &self,
+        host: &str,
+        port: u16,
+        deadline: Option<Instant>,
+    ) -> Result<TcpStream> {
+        let out_of_time = || Error::ConnectionTest {
+            host: host.to_string(),
+            port,
+            reason: "Ran out of time for the connection tests".to_string(),
+        };
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(out_of_time());
+        }
+
+        let addr_str = format!("{}:{}", host, port);
+        let addrs: Vec<SocketAddr> = addr_str
+            .to_socket_addrs()
+            .map_err(|e| Error::ConnectionTest {
+                host: host.to_string(),
+                port,
+                reason: format!("DNS resolution failed: {}", e),
+            })?
+            .collect();
+
+        if addrs.is_empty() {
+            return Err(Error::ConnectionTest {
+                host: host.to_string(),
+                port,
+                reason: "No addresses resolved".to_string(),
+            });
+        }
+
+        let timeout = Duration::from_secs(self.connect_timeout_secs);
+        for addr in addrs {
+            let timeout = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => timeout.min(left),
+                    _ => return Err(out_of_time()),
+                },
+                None => timeout,
+            };
+            if let Ok(stream) = TcpStream::connect_timeout(&addr, timeout) {
+                return Ok(stream);
+            }
+        }
+
+        Err(Error::ConnectionTest {
+            host: host.to_string(),
+            port,
+            reason: "Connection timed out or refused".to_string(),
+        })
+    }
+}
+
+/// Most connections `test_tcp_connections` opens at once; a full chain fits
+const PARALLEL_CONNECTION_TESTS: usize = crate::MAX_PROXY_HOPS;
+
+/// SOCKS5 greeting offering only "no authentication"
+const SOCKS5_GREETING: [u8; 3] = [0x05, 0x01, 0x00];
+
+/// RFC 1929 username/password sub-negotiation
+fn socks5_auth_request(user: &str, pass: &str) -> Result<Vec<u8>> {
+    if user.len() > 255 || pass.len() > 255 {
+        return Err(Error::validation(
+            "SOCKS5 username and password must be at most 255 bytes",
+        ));
+    }
+    let mut request = vec![0x01, user.len() as u8];
+    request.extend_from_slice(user.as_bytes());
+    request.push(pass.len() as u8);
+    request.extend_from_slice(pass.as_bytes());
+    Ok(request)
+}
+
+/// Round-trip time from a `ping` reply line such as `... time=12.3 ms`
+fn parse_ping_rtt(output: &str) -> Option<Duration> {
+    output.lines().find_map(|line| {
+        let (_, rest) = line
+            .split_once("time=")
+            .or_else(|| line.split_once("time<"))?;
+        let ms: f64 = rest.split_whitespace().next()?.parse().ok()?;
+        (ms.is_finite() && ms >= 0.0).then(|| Duration::from_secs_f64(ms / 1000.0))
+    })
+}
+
+/// Check a UDP ASSOCIATE reply: success code and a relay address with a real port
+fn parse_udp_associate_reply(reply: &[u8]) -> std::result::Result<(), String> {
+    if reply.len() < 4 || reply[0] != 0x05 {
+        return Err("Malformed SOCKS5 reply".to_string());
+    }
+    if reply[1] != 0x00 {
+        return Err(match reply[1] {
+            0x02 => "UDP relay not allowed by the proxy's rules".to_string(),
+            0x07 => "Proxy does not support UDP ASSOCIATE".to_string(),
+            code => format!("Proxy refused UDP ASSOCIATE (code {})", code),
+        });
+    }
+    let addr_len = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => 1 + *reply.get(4).ok_or("Malformed SOCKS5 reply")? as usize,
+        atyp => return Err(format!("Unknown address type {} in SOCKS5 reply", atyp)),
+    };
+    let port = reply
+        .get(4 + addr_len..6 + addr_len)
+        .map(|p| u16::from_be_bytes([p[0], p[1]]))
+        .ok_or("Truncated SOCKS5 reply")?;
+    if port == 0 {
+        return Err("Proxy returned no UDP relay port".to_string());
+    }
+    Ok(())
+}
+
+/// CONNECT request used to see whether an endpoint answers like an HTTP proxy
+const HTTP_CONNECT_PROBE: &str =
+    "CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n";
+
+/// Write a probe and return whatever the peer answers within the timeout
+fn probe(stream: &mut TcpStream, request: &[u8], timeout: Duration) -> Option<Vec<u8>> {
+    stream.set_read_timeout(Some(timeout)).ok()?;
+    stream.set_write_timeout(Some(timeout)).ok()?;
+    stream.write_all(request).ok()?;
+    let mut buf = [0u8; 64];
+    match stream.read(&mut buf) {
+        Ok(n) if n > 0 => Some(buf[..n].to_vec()),
+        _ => None,
+    }
+}
+
+/// A SOCKS5 method-selection reply: version 5, then the chosen method (0xFF = none acceptable)
+fn is_socks5_reply(reply: &[u8]) -> bool {
+    reply.len() >= 2 && reply[0] == 0x05
+}
+
+/// Any HTTP status line, including 400/407 from a proxy that wants credentials
+fn is_http_reply(reply: &[u8]) -> bool {
+    reply.starts_with(b"HTTP/")
+}
+
+/// Map `f` over `items` on up to `max_workers` threads, keeping the input order
+fn parallel_map<T, R, F>(items: &[T], max_workers: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = max_workers.clamp(1, items.len().max(1));
+    if workers == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            break;
+                        };
+                        done.push((i, f(item)));
+                    }
+                    done
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Version from `qemu-img --version`, e.g. `qemu-img version 8.2.2 (Debian 1:8.2.2+ds-0ubuntu1)`
+fn parse_qemu_img_version(stdout: &str) -> Option<(u32, u32, u32)> {
+    let line = stdout.lines().next()?;
+    let version = line.split_whitespace().nth(2)?.trim_end_matches(',');
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+    let major = parts.next()??;
+    let minor = parts.next()??;
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// Available bytes from `df --output=avail -B1` (header line, then the value)
+fn parse_df_avail(stdout: &str) -> Option<u64> {
+    stdout.lines().nth(1)?.trim().parse().ok()
+}
+
+/// Whether a file name ends in `.qcow2`, in any case
+fn is_qcow2_name(name: &OsStr) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() > 6 && bytes[bytes.len() - 6..].eq_ignore_ascii_case(b".qcow2")
+}
+
+/// Undo `ls -b` escaping: `\ooo` octal bytes and C escapes such as `\ ` or `\\`
+fn unescape_ls_name(line: &str) -> OsString {
+    let mut bytes = Vec::new();
+    let mut input = line.bytes().peekable();
+    while let Some(byte) = input.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match input.next() {
+            Some(digit @ b'0'..=b'7') => {
+                let mut value = u32::from(digit - b'0');
+                for _ in 0..2 {
+                    match input.peek() {
+                        Some(&next @ b'0'..=b'7') => {
+                            value = value * 8 + u32::from(next - b'0');
+                            input.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(b'a') => bytes.push(0x07),
+            Some(b'b') => bytes.push(0x08),
+            Some(b'f') => bytes.push(0x0c),
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'v') => bytes.push(0x0b),
+            Some(other) => bytes.push(other),
+            None => bytes.push(b'\\'),
+        }
+    }
+    OsString::from_vec(bytes)
+}
+
+/// The flag that selects a libvirt connection, for the tools that take one
+fn connect_flag(tool: &str) -> Option<&'static str> {
+    match tool {
+        "virsh" => Some("-c"),
+        "virt-install" | "virt-xml" => Some("--connect"),
+        _ => None,
+    }
+}
+
+/// Whether `uri` is a local system connection such as `qemu:///system`, the only
+/// kind that means the same libvirt instance for root and for the user
+fn is_local_system_uri(uri: &str) -> bool {
+    uri.split_once("://")
+        .is_some_and(|(scheme, rest)| !scheme.contains('+') && rest == "/system")
+}
+
+/// `name` of the `<bridge>` element in a network's XML
+fn parse_bridge_name(xml: &str) -> Option<String> {
+    let element = &xml[xml.find("<bridge ")?..];
+    let element = &element[..element.find('>')?];
+    let start = element.find("name=")? + "name=".len();
+    let quote = element[start..].chars().next()?;
+    let value = &element[start + 1..];
+    Some(value[..value.find(quote)?].to_string())
+}
+
+/// The role a `<role>-inet` network belongs to, or `None` for any other network
+pub fn network_role(net_name: &str) -> Option<&str> {
+    net_name
+        .strip_suffix("-inet")
+        .filter(|role| validate_role_name(role).is_ok())
+}
+
+/// IPv4 subnets of the `<ip>` elements in a network's XML
+fn parse_network_ipv4_subnets(xml: &str) -> Vec<Ipv4Subnet> {
+    xml.match_indices("<ip ")
+        .filter_map(|(start, _)| {
+            let element = &xml[start..];
+            let element = &element[..element.find('>')?];
+            if xml_attr(element, "family").is_some_and(|family| family != "ipv4") {
+                return None;
+            }
+            let address: Ipv4Addr = xml_attr(element, "address")?.parse().ok()?;
+            let prefix = match xml_attr(element, "prefix") {
+                Some(prefix) => prefix.parse().ok().filter(|p| *p <= 32)?,
+                None => {
+                    let netmask: Ipv4Addr = xml_attr(element, "netmask")?.parse().ok()?;
+                    u32::from(netmask).count_ones() as u8
+                }
+            };
+            Some(Ipv4Subnet::containing(address, prefix))
+        })
+        .collect()
+}
+
+/// Subnets of the addresses in `ip -o -4 addr show` output
+fn parse_ip_addr_subnets(stdout: &str) -> Vec<Ipv4Subnet> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            fields.find(|field| *field == "inet")?;
+            let (addr, prefix) = fields.next()?.split_once('/')?;
+            let prefix = prefix.parse().ok().filter(|p| *p <= 32)?;
+            Some(Ipv4Subnet::containing(addr.parse().ok()?, prefix))
+        })
+        .collect()
+}
+
+/// Value of attribute `name` in one XML start tag, with either quote style
+fn xml_attr<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!(" {}=", name);
+    let start = element.find(&key)? + key.len();
+    let quote = element[start..].chars().next()?;
+    let value = &element[start + 1..];
+    Some(&value[..value.find(quote)?])
+}
+
+/// Network XML for a role network: an isolated bridge, plus DHCPv6 when a subnet is given
+fn role_network_xml(
+    net_name: &str,
+    ipv4: Option<&Ipv4Subnet>,
+    ipv6: Option<&Ipv6Subnet>,
+    mode: &RoleNetworkMode,
+) -> String {
+    let mut xml = format!("<network>\n  <name>{}</name>\n", net_name);
+    match mode {
+        RoleNetworkMode::Isolated => xml.push_str("  <bridge stp='on' delay='0'/>\n"),
+        RoleNetworkMode::Nat => {
+            xml.push_str("  <forward mode='nat'/>\n  <bridge stp='on' delay='0'/>\n")
+        }
+        // libvirt manages no addressing on a host bridge, so there is nothing else to add
+        RoleNetworkMode::Bridge(bridge) => {
+            xml.push_str(&format!(
+                "  <forward mode='bridge'/>\n  <bridge name='{}'/>\n</network>",
+                bridge
+            ));
+            return xml;
+        }
+    }
+    if let Some(subnet) = ipv4 {
+        let (start, end) = subnet.dhcp_range();
+        xml.push_str(&format!(
+            "  <ip address='{}' netmask='{}'>\n    <dhcp>\n      <range start='{}' end='{}'/>\n    </dhcp>\n  </ip>\n",
+            subnet.gateway(),
+            subnet.netmask(),
+            start,
+            end
+        ));
+    }
+    if let Some(subnet) = ipv6 {
+        let (start, end) = subnet.dhcp_range();
+        xml.push_str(&format!(
+            "  <ip family='ipv6' address='{}' prefix='{}'>\n    <dhcp>\n      <range start='{}' end='{}'/>\n    </dhcp>\n  </ip>\n",
+            subnet.gateway(),
+            subnet.prefix,
+            start,
+            end
+        ));
+    }
+    xml.push_str("</network>");
+    xml
+}
+
+/// Derive a local filename from a download URL (last path segment, without query)
+pub fn filename_from_url(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let (_host, path) = rest.split(['?', '#']).next()?.split_once('/')?;
+    let name = path.rsplit('/').next()?;
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Normalize a user-supplied SHA-256 (optionally prefixed with `sha256:`) to lowercase hex
+fn normalize_sha256(value: &str) -> Result<String> {
+    let value = value.trim();
+    let hex = value
+        .strip_prefix("sha256:")
+        .unwrap_or(value)
+        .to_lowercase();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::validation(
+            "SHA-256 checksum must be 64 hexadecimal characters",
+        ));
+    }
+    Ok(hex)
+}
+
+/// Prepare `part` for resuming a download of `url`.
+///
+/// The URL each partial file came from is kept in a `.part.url` file next to it.
+/// A partial from another URL with the same file name (say, an older build of the
+/// same image) is deleted, so `curl -C -` doesn't splice two files together.
+/// Returns the path of the URL file.
+fn claim_partial_download(part: &Path, url: &str) -> Result<PathBuf> {
+    let part_url = part.with_extension("part.url");
+    if fs::read_to_string(&part_url).ok().as_deref() != Some(url) {
+        match fs::remove_file(part) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    fs::write(&part_url, url)?;
+    Ok(part_url)
+}
+
+/// Extract the final Content-Length from `curl --head --location` output
+fn parse_content_length(headers: &str) -> Option<u64> {
+    headers
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                value.trim().parse().ok()
+            } else {
+                None
+            }
+        })
+        .next_back()
+}
+
+/// Parse `wg show <iface> latest-handshakes` output (`<pubkey>\t<unix time>`, 0 = never)
+fn parse_wg_handshakes(iface: &str, output: &str, now: u64) -> WgStatus {
+    let peers = output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let public_key = parts.next()?.to_string();
+            let ts: u64 = parts.next()?.parse().ok()?;
+            Some(WgPeerHandshake {
+                public_key,
+                handshake_age_secs: (ts > 0).then(|| now.saturating_sub(ts)),
+            })
+        })
+        .collect();
+
+    WgStatus {
+        interface: iface.to_string(),
+        peers,
+    }
+}
+
+/// How long an egress probe waits for the TCP connection
+const EGRESS_PROBE_TIMEOUT_SECS: u64 = 3;
+
+/// Split `host[:port]` for an egress probe, rejecting anything that isn't a plain address
+fn split_probe_target(target: &str) -> Result<(&str, u16)> {
+    let target = target.trim();
+    let (host, port) = match target.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => {
+            let port = port
+                .parse()
+                .map_err(|_| Error::validation(format!("Invalid probe port: {}", port)))?;
+            (host, port)
+        }
+        _ => (target, 80),
+    };
+    let valid = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'));
+    if !valid {
+        return Err(Error::validation(format!(
+            "Invalid probe target: {}",
+            target
+        )));
+    }
+    Ok((host, port))
+}
+
+/// Sort a failed virsh command into the error classes callers act on, from the
+/// messages libvirt prints; anything else becomes [`Error::Libvirt`] with the
+/// stderr as is. Permission problems are checked before connection failures
+/// because a refused socket is reported as both.
+///
+/// The messages are matched in English; [`SystemRunner`](crate::runner::SystemRunner)
+/// runs virsh with `LC_ALL=C` so a translated session doesn't turn lookups that
+/// merely found nothing into errors.
+pub fn classify_virsh_error(stderr: &str) -> Error {
+    let detail = stderr
+        .lines()
+        .map(|line| line.trim().trim_start_matches("error:").trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("; ");
+    let lower = detail.to_lowercase();
+    let has = |needles: &[&str]| needles.iter().any(|needle| lower.contains(needle));
+
+    let denied = has(&["permission denied", "access denied"]);
+    if has(&["authentication failed", "authorization not available"])
+        || (denied && has(&["failed to connect", "libvirt-sock"]))
+    {
+        Error::PermissionDenied(format!(
+            "{}. Make sure you are in the 'libvirt' group, or use qemu:///session",
+            detail
+        ))
+    } else if denied {
+        // e.g. qemu unable to open a disk image; the fix is on that file
+        Error::PermissionDenied(detail)
+    } else if has(&[
+        "failed to connect socket",
+        "failed to connect to the hypervisor",
+        "unable to connect to server",
+        "connection refused",
+        "end of file while reading data",
+    ]) {
+        Error::LibvirtUnreachable(detail)
+    } else if has(&[
+        "failed to get domain",
+        "domain not found",
+        "no domain with matching",
+    ]) {
+        Error::DomainNotFound(detail)
+    } else if has(&[
+        "failed to get network",
+        "network not found",
+        "no network with matching",
+    ]) {
+        Error::NetworkNotFound(detail)
+    } else {
+        Error::Libvirt(detail)
+    }
+}
+
+/// [`classify_virsh_error`], with `action` (e.g. "Failed to start VM 'work-gw'")
+/// in front of stderr when it matches none of the known classes
+fn virsh_error(action: &str, stderr: &str) -> Error {
+    match classify_virsh_error(stderr) {
+        Error::Libvirt(detail) => Error::Libvirt(format!("{}: {}", action, detail)),
+        classified => classified,
+    }
+}
+
+/// For a failed lookup: an error if libvirt refused or could not be reached,
+/// so that is not mistaken for the domain or network being absent
+fn check_reachable(stderr: &str) -> Result<()> {
+    match classify_virsh_error(stderr) {
+        e @ (Error::PermissionDenied(_) | Error::LibvirtUnreachable(_)) => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// virsh's "Domain is already active" when starting a running domain; the
+/// runner forces the C locale, so the message is never translated
+fn is_already_active(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    stderr.contains("already active") || stderr.contains("already running")
+}
+
+/// virsh's "domain is not running" when stopping a stopped domain
+fn is_not_running(stderr: &str) -> bool {
+    stderr.to_lowercase().contains("not running")
+}
+
+/// The lowercase hex digest `sha256sum` printed, or why it failed
+fn parse_sha256sum(output: CommandOutput) -> Result<String> {
+    if !output.success() {
+        return Err(Error::Command {
+            cmd: "sha256sum".to_string(),
+            message: output.stderr.trim().to_string(),
+        });
+    }
+    output
+        .stdout
+        .split_whitespace()
+        .next()
+        .map(|s| s.to_lowercase())
+        .ok_or_else(|| Error::Parse("Empty sha256sum output".to_string()))
+}
+
+/// Rows of `virsh domiflist`: Interface, Type, Source, Model, MAC under a dashed rule
+fn parse_domiflist(output: &str) -> Vec<VmInterface> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [target, kind, source, model, mac] = fields[..] else {
+                return None;
+            };
+            Some(VmInterface {
+                target: (target != "-").then(|| target.to_string()),
+                kind: kind.to_string(),
+                source: source.to_string(),
+                model: model.to_string(),
+                mac: mac.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Titles from `virsh list --all --title`, by VM name. The columns are aligned
+/// under the header, so names and titles are cut at the header's column starts;
+/// rows without a title are left out.
+fn parse_virsh_list_titles(output: &str) -> HashMap<String, String> {
+    let mut lines = output.lines();
+    let Some(header) = lines.by_ref().find(|line| line.contains("Title")) else {
+        return HashMap::new();
+    };
+    let column = |name: &str| header.find(name).map(|byte| header[..byte].chars().count());
+    let (Some(name_col), Some(state_col), Some(title_col)) =
+        (column("Name"), column("State"), column("Title"))
+    else {
+        return HashMap::new();
+    };
+    lines
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            let cut = |from: usize, to: usize| -> String {
+                chars[from.min(chars.len())..to.min(chars.len())]
+                    .iter()
+                    .collect::<String>()
+                    .trim()
+                    .to_string()
+            };
+            let name = cut(name_col, state_col);
+            let title = cut(title_col, chars.len());
+            (!name.is_empty() && !title.is_empty()).then_some((name, title))
+        })
+        .collect()
+}
+
+/// Interface names from `ip -o link show`, e.g. "5: br0: <BROADCAST,...>"
+fn parse_ip_link_names(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let name = line.split(": ").nth(1)?;
+            // VLAN and similar links show as "name@parent"
+            Some(name.split('@').next().unwrap_or(name).to_string())
+        })
+        .collect()
+}
+
+/// Pull the gateway address out of `ip route show default`
+fn parse_default_gateway(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        words.find(|w| *w == "via")?;
+        words.next().map(str::to_string)
+    })
+}
+
+/// virt-install channel spec for the QEMU guest agent
+const GUEST_AGENT_CHANNEL: &str = "unix,target.type=virtio,target.name=org.qemu.guest_agent.0";
+
+/// Bytes asked for per `guest-file-read`; the agent base64-encodes each reply
+const GUEST_READ_CHUNK: usize = 64 * 1024;
+
+/// systemd units that run apply-proxy.sh at boot: `proxy-boot.service` from
+/// setup-proxy-template.sh, `apply-proxy.service` from the older script
+const APPLY_PROXY_UNITS: &[&str] = &["proxy-boot.service", "apply-proxy.service"];
+
+/// Extract the pid from a `guest-exec` reply
+fn parse_guest_exec_pid(reply: &serde_json::Value) -> Result<i64> {
+    reply["return"]["pid"]
+        .as_i64()
+        .ok_or_else(|| Error::Parse(format!("Unexpected guest-exec reply: {}", reply)))
+}
+
+/// Parse a `guest-exec-status` reply, returning `None` while the process is still running
+fn parse_guest_exec_status(reply: &serde_json::Value) -> Result<Option<CommandOutput>> {
+    let ret = &reply["return"];
+    let exited = ret["exited"]
+        .as_bool()
+        .ok_or_else(|| Error::Parse(format!("Unexpected guest-exec-status reply: {}", reply)))?;
+    if !exited {
+        return Ok(None);
+    }
+
+    let decode = |key: &str| -> String {
+        ret[key]
+            .as_str()
+            .and_then(|data| {
+                base64::Engine::decode(&base64::engine::general_purpose::STANDARD, data).ok()
+            })
+            .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+            .unwrap_or_default()
+    };
+
+    Ok(Some(CommandOutput {
+        exit_code: ret["exitcode"].as_i64().unwrap_or(-1) as i32,
+        stdout: decode("out-data"),
+        stderr: decode("err-data"),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandRunner, MockRunner};
+    use crate::{DiskCache, DiskIo, RoleMeta};
+
+    #[test]
+    fn test_gateway_virt_install_args() {
+        let adapter = LibvirtAdapter::new();
+        let args = adapter.build_gateway_virt_install_args(
+            "work-gw",
+            Path::new("/var/lib/libvirt/images/work-gw.qcow2"),
+            &["lan-net".to_string(), "work-inet".to_string()],
+            Path::new("/home/user/VMS/VM-Proxy-configs/work"),
+            "debian12",
+            512,
+            &DiskTuning::default(),
+            &BootOptions::default(),
+            ShareDriver::NineP,
+            None,
+            CrashPolicy::Leave,
+            InstallSource::Import,
+            Path::new("/var/lib/libvirt/images/debian-12.qcow2"),
+        );
+
+        assert!(args.contains(&"--name".to_string()));
+        assert!(!args.contains(&"--events".to_string()));
+        assert!(args.contains(&"work-gw".to_string()));
+        assert!(args.contains(&"--import".to_string()));
+        assert!(args.iter().any(|a| a.contains("lan-net")));
+        assert!(args.iter().any(|a| a.contains("work-inet")));
+        assert!(args.iter().any(|a| a.contains("proxy,accessmode=mapped")));
+        assert!(args.iter().any(|a| a.contains("org.qemu.guest_agent.0")));
+        let vcpus = args.iter().position(|a| a == "--vcpus").unwrap();
+        assert_eq!(args[vcpus + 1], "1");
+        let metadata = args.iter().position(|a| a == "--metadata").unwrap();
+        assert_eq!(args[metadata + 1], "title=Proxy gateway for role work");
+    }
+
+    #[test]
+    fn test_command_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let runner = MockRunner::new().on("virsh list --all", "");
+        let mut adapter = mock_adapter(runner);
+        let log = Arc::new(FileLogger::open(dir.path(), LogLevel::Debug).unwrap());
+        adapter.command_log = Some(log.clone());
+
+        adapter.run_cmd("virsh", &["list", "--all"]).unwrap();
+        adapter.run_cmd("virsh", &["start", "work-gw"]).unwrap();
+        let content = std::fs::read_to_string(log.path()).unwrap();
+        assert!(content.contains("[DEBUG] $ virsh list --all (exit 0)"));
+        assert!(content.contains("$ virsh start work-gw (exit 1: no canned output"));
+
+        // Below debug level nothing about commands is written
+        let quiet = Arc::new(FileLogger::open(&dir.path().join("quiet"), LogLevel::Info).unwrap());
+        adapter.command_log = Some(quiet.clone());
+        adapter.run_cmd("virsh", &["list", "--all"]).unwrap();
+        assert!(std::fs::read_to_string(quiet.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_connection_uri() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .on("virsh -c qemu:///session list --all", "")
+                .on("virt-install --connect qemu:///session --version", "4.1.0")
+                .on("qemu-img info x.qcow2", ""),
+        );
+        let mut adapter = LibvirtAdapter::with_runner(runner.clone());
+        adapter.uri = Some("qemu:///session".to_string());
+
+        adapter.check_libvirt_access().unwrap();
+        adapter.run_cmd("virt-install", &["--version"]).unwrap();
+        // Root would reach its own session daemon, so nothing is run
+        assert!(matches!(
+            adapter.run_privileged("virsh", &["net-start", "lan-net"]),
+            Err(Error::PermissionDenied(_))
+        ));
+        adapter.run_cmd("qemu-img", &["info", "x.qcow2"]).unwrap();
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "virsh -c qemu:///session list --all",
+                "virt-install --connect qemu:///session --version",
+                "qemu-img info x.qcow2",
+            ]
+        );
+
+        let runner =
+            Arc::new(MockRunner::new().on("pkexec virsh -c qemu:///system net-start lan-net", ""));
+        let mut system = LibvirtAdapter::with_runner(runner.clone());
+        system.uri = Some("qemu:///system".to_string());
+        system
+            .run_privileged("virsh", &["net-start", "lan-net"])
+            .unwrap();
+        system.uri = Some("qemu+ssh://host/system".to_string());
+        assert!(system
+            .run_privileged("virsh", &["net-start", "lan-net"])
+            .is_err());
+        assert_eq!(
+            runner.calls(),
+            ["pkexec virsh -c qemu:///system net-start lan-net"]
+        );
+
+        // A failed first call names the URI
+        let adapter = LibvirtAdapter {
+            uri: Some("qemu+ssh://nowhere/system".to_string()),
+            ..mock_adapter(MockRunner::new())
+        };
+        let err = adapter.check_libvirt_access().unwrap_err().to_string();
+        assert!(err.contains("qemu+ssh://nowhere/system"));
+    }
+
+    #[test]
+    fn test_gateway_virt_install_args_extra_nic() {
+        let adapter = LibvirtAdapter::new();
+        let mut meta = RoleMeta::new("work".to_string());
+        meta.extra_gateway_networks = vec!["mgmt-net".to_string()];
+        let args = adapter.build_gateway_virt_install_args(
+            "work-gw",
+            Path::new("/var/lib/libvirt/images/work-gw.qcow2"),
+            &meta.gateway_networks("lan-net", "work-inet"),
+            Path::new("/home/user/VMS/VM-Proxy-configs/work"),
+            "debian12",
+            512,
+            &DiskTuning::default(),
+            &BootOptions::default(),
+            ShareDriver::NineP,
+            None,
+            CrashPolicy::Leave,
+            InstallSource::Import,
+            Path::new("/var/lib/libvirt/images/debian-12.qcow2"),
+        );
+
+        let networks: Vec<&str> = args
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| *a == "--network")
+            .map(|(i, _)| args[i + 1].as_str())
+            .collect();
+        assert_eq!(
+            networks,
+            [
+                "network=lan-net,model=virtio",
+                "network=work-inet,model=virtio",
+                "network=mgmt-net,model=virtio",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sha256_file_with_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("abc");
+        fs::write(&small, "abc").unwrap();
+        let adapter = mock_adapter(MockRunner::new().on(
+            "sha256sum -",
+            "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD  -\n",
+        ));
+        assert_eq!(
+            adapter
+                .sha256_file_with_progress(&small, |_, _| {})
+                .unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let large = dir.path().join("zeros");
+        let size = 5 * 1024 * 1024 / 2;
+        fs::write(&large, vec![0u8; size]).unwrap();
+        let mut reports = Vec::new();
+        adapter
+            .sha256_file_with_progress(&large, |hashed, total| reports.push((hashed, total)))
+            .unwrap();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports.last(), Some(&(size as u64, size as u64)));
+    }
+
+    #[test]
+    fn test_download_helpers() {
+        assert_eq!(
+            filename_from_url("https://example.com/images/debian-12.qcow2?sig=abc"),
+            Some("debian-12.qcow2".to_string())
+        );
+        assert_eq!(filename_from_url("https://example.com/"), None);
+
+        let sum = "A".repeat(64);
+        assert_eq!(
+            normalize_sha256(&format!("sha256:{}", sum)).unwrap(),
+            "a".repeat(64)
+        );
+        assert!(normalize_sha256("abc123").is_err());
+
+        let headers = "HTTP/1.1 302 Found\r\nContent-Length: 0\r\n\r\n\
+                       HTTP/1.1 200 OK\r\ncontent-length: 4096\r\n";
+        assert_eq!(parse_content_length(headers), Some(4096));
+    }
+
+    #[test]
+    fn test_claim_partial_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("debian-12.qcow2.part");
+        let url = "https://example.com/20240101/debian-12.qcow2";
+
+        // A leftover without a recorded URL can't be trusted
+        fs::write(&part, "old").unwrap();
+        let part_url = claim_partial_download(&part, url).unwrap();
+        assert!(!part.exists());
+        assert_eq!(part_url, dir.path().join("debian-12.qcow2.part.url"));
+        assert_eq!(fs::read_to_string(&part_url).unwrap(), url);
+
+        // Same URL resumes
+        fs::write(&part, "partial").unwrap();
+        claim_partial_download(&part, url).unwrap();
+        assert_eq!(fs::read_to_string(&part).unwrap(), "partial");
+
+        // A newer build with the same file name starts over
+        let newer = "https://example.com/20240201/debian-12.qcow2";
+        claim_partial_download(&part, newer).unwrap();
+        assert!(!part.exists());
+        assert_eq!(fs::read_to_string(&part_url).unwrap(), newer);
+    }
+
+    #[test]
+    fn test_download_template_runs_curl_through_runner() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("pvw-runner-test-missing.qcow2");
+        let url = "https://example.com/pvw-runner-test-missing.qcow2";
+        let part = dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("proxy-vm-wizard")
+            .join("downloads")
+            .join("pvw-runner-test-missing.qcow2.part");
+        let runner = Arc::new(MockRunner::new().on_output(
+            &format!(
+                "curl --fail --location --silent --show-error -C - -o {} {}",
+                part.display(),
+                url
+            ),
+            CommandOutput {
+                exit_code: 22,
+                stdout: String::new(),
+                stderr: "The requested URL returned error: 404".to_string(),
+            },
+        ));
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        let err = adapter.download_template(url, &dest, None).unwrap_err();
+        assert!(err.to_string().contains("returned error: 404"));
+        // An HTTP error leaves nothing to resume
+        assert!(!part.exists());
+        assert!(!part.with_extension("part.url").exists());
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn test_parse_wg_handshakes() {
+        let output = "abc=\t1700000000\nxyz=\t0\n";
+        let status = parse_wg_handshakes("wg0", output, 1700000042);
+        assert_eq!(status.interface, "wg0");
+        assert_eq!(status.peers.len(), 2);
+        assert_eq!(status.peers[0].handshake_age_secs, Some(42));
+        assert_eq!(status.peers[1].handshake_age_secs, None);
+        assert_eq!(status.latest_handshake_age(), Some(42));
+        assert!(!status.is_healthy());
+
+        let status = parse_wg_handshakes("wg0", "abc=\t1700000000\n", 1700000042);
+        assert!(status.is_healthy());
+        assert!(!parse_wg_handshakes("wg0", "", 0).is_healthy());
+    }
+
+    #[test]
+    fn test_parse_guest_exec_replies() {
+        let reply = serde_json::json!({ "return": { "pid": 1234 } });
+        assert_eq!(parse_guest_exec_pid(&reply).unwrap(), 1234);
+        assert!(parse_guest_exec_pid(&serde_json::json!({ "return": {} })).is_err());
+
+        let running = serde_json::json!({ "return": { "exited": false } });
+        assert!(parse_guest_exec_status(&running).unwrap().is_none());
+
+        // "b2sK" is base64 for "ok\n"
+        let done = serde_json::json!({
+            "return": { "exited": true, "exitcode": 1, "out-data": "b2sK" }
+        });
+        let output = parse_guest_exec_status(&done).unwrap().unwrap();
+        assert_eq!(output.exit_code, 1);
+        assert_eq!(output.stdout, "ok\n");
+        assert!(output.stderr.is_empty());
+    }
+
+    #[test]
+    fn test_gateway_virt_install_args_virtiofs() {
+        let adapter = LibvirtAdapter::new();
+        let args = adapter.build_gateway_virt_install_args(
+            "work-gw",
+            Path::new("/var/lib/libvirt/images/work-gw.qcow2"),
+            &["lan-net".to_string(), "work-inet".to_string()],
+            Path::new("/home/user/VMS/VM-Proxy-configs/work"),
+            "debian12",
+            512,
+            &DiskTuning::default(),
+            &BootOptions::default(),
+            ShareDriver::Virtiofs,
+            Some("2-3"),
+            CrashPolicy::Restart,
+            InstallSource::Cdrom,
+            Path::new("/var/lib/libvirt/images/debian-12-netinst.iso"),
+        );
+
+        let pos = |flag: &str| args.iter().position(|a| a == flag).unwrap();
+        assert_eq!(
+            args[pos("--cdrom") + 1],
+            "/var/lib/libvirt/images/debian-12-netinst.iso"
+        );
+        assert!(!args.contains(&"--import".to_string()));
+        assert_eq!(
+            args[pos("--memorybacking") + 1],
+            "source.type=memfd,access.mode=shared"
+        );
+        assert_eq!(
+            args[pos("--filesystem") + 1],
+            "source=/home/user/VMS/VM-Proxy-configs/work,target=proxy,driver.type=virtiofs"
+        );
+        assert!(!args.iter().any(|a| a.contains("accessmode=mapped")));
+        assert_eq!(args[pos("--vcpus") + 1], "1,cpuset=2-3");
+        assert_eq!(args[pos("--events") + 1], "on_crash=restart");
+        assert_eq!(args[pos("--panic") + 1], "default");
+    }
+
+    #[test]
+    fn test_set_on_crash() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .on(
+                    "virsh -c qemu:///session dumpxml --inactive work-gw",
+                    "<domain type='kvm'>\n  <devices>\n  </devices>\n</domain>\n",
+                )
+                .on(
+                    "virt-xml --connect qemu:///session work-gw --add-device --panic default",
+                    "",
+                )
+                .on(
+                    "virt-xml --connect qemu:///session work-gw --edit --events on_crash=restart",
+                    "",
+                ),
+        );
+        let mut adapter = LibvirtAdapter::with_runner(runner.clone());
+        adapter.uri = Some("qemu:///session".to_string());
+
+        adapter
+            .set_on_crash("work-gw", CrashPolicy::Restart)
+            .unwrap();
+        assert_eq!(
+            runner.calls()[1],
+            "virt-xml --connect qemu:///session work-gw --add-device --panic default"
+        );
+        // No canned output for the destroy action, so the mock fails it
+        assert!(adapter.set_on_crash("work-gw", CrashPolicy::Leave).is_err());
+        assert_eq!(
+            runner.calls()[3],
+            "virt-xml --connect qemu:///session work-gw --edit --events on_crash=destroy"
+        );
+
+        // A gateway that already has a panic device doesn't get a second one
+        let runner = Arc::new(
+            MockRunner::new()
+                .on(
+                    "virsh -c qemu:///session dumpxml --inactive work-gw",
+                    "<domain type='kvm'>\n  <devices>\n    <panic model='isa'/>\n  </devices>\n</domain>\n",
+                )
+                .on(
+                    "virt-xml --connect qemu:///session work-gw --edit --events on_crash=restart",
+                    "",
+                ),
+        );
+        let mut adapter = LibvirtAdapter::with_runner(runner.clone());
+        adapter.uri = Some("qemu:///session".to_string());
+        adapter
+            .set_on_crash("work-gw", CrashPolicy::Restart)
+            .unwrap();
+        assert!(!runner.calls().iter().any(|c| c.contains("--add-device")));
+    }
+
+    #[test]
+    fn test_trim_vm_disk() {
+        let runner = Arc::new(MockRunner::new().on("virsh domfstrim work-app-1", ""));
+        let adapter = LibvirtAdapter::with_runner(runner);
+        adapter.trim_vm_disk("work-app-1").unwrap();
+        let err = adapter.trim_vm_disk("work-app-2").unwrap_err();
+        assert!(err.to_string().contains("qemu-guest-agent"));
+    }
+
+    #[test]
+    fn test_rename_vm() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .on("virsh dominfo work-app-1", "State: shut off\n")
+                .on("virsh dominfo work-app-2", DOMINFO_RUNNING)
+                .on("virsh dominfo work-app-3", "State: shut off\n")
+                .on("virsh domrename work-app-1 work-app-banking", ""),
+        );
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        adapter.rename_vm("work-app-1", "work-app-banking").unwrap();
+        assert_eq!(
+            runner.calls().last().unwrap(),
+            "virsh domrename work-app-1 work-app-banking"
+        );
+
+        // Running, taken, off-pattern and illegal names are refused before domrename
+        let calls = runner.calls().len();
+        assert!(adapter.rename_vm("work-app-2", "work-app-mail").is_err());
+        assert!(matches!(
+            adapter.rename_vm("work-app-1", "work-app-3"),
+            Err(Error::AlreadyExists(_))
+        ));
+        assert!(adapter.rename_vm("work-app-1", "banking").is_err());
+        assert!(adapter.rename_vm("work-app-1", "work-app-a b").is_err());
+        assert!(!runner.calls()[calls..]
+            .iter()
+            .any(|call| call.contains("domrename")));
+    }
+
+    #[test]
+    fn test_host_cpu_count_with_mock() {
+        let adapter = mock_adapter(MockRunner::new().on(
+            "virsh nodeinfo",
+            "CPU model:           x86_64\nCPU(s):              8\nCPU frequency:       3400 MHz\n",
+        ));
+        assert_eq!(adapter.host_cpu_count().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_app_virt_install_args() {
+        let adapter = LibvirtAdapter::new();
+        let args = adapter.build_app_virt_install_args(
+            "work-app-1",
+            Path::new("/var/lib/libvirt/images/work-app-1.qcow2"),
+            "work-inet",
+            "fedora40",
+            2048,
+            &DiskTuning {
+                cache: Some(DiskCache::None),
+                io: Some(DiskIo::Native),
+                discard_unmap: true,
+            },
+            &BootOptions::default(),
+            None,
+        );
 
         assert!(args.contains(&"--name".to_string()));
         assert!(args.contains(&"work-app-1".to_string()));
         assert!(args.contains(&"2048".to_string()));
         assert!(args.iter().any(|a| a.contains("work-inet")));
+        assert!(args.contains(
+            &"path=/var/lib/libvirt/images/work-app-1.qcow2,format=qcow2,cache=none,io=native,discard=unmap,detect_zeroes=unmap"
+                .to_string()
+        ));
         // Should not have lan-net
         assert!(!args.iter().any(|a| a.contains("lan-net")));
     }
 
     #[test]
-    fn test_disposable_virt_install_args() {
-        let adapter = LibvirtAdapter::new();
-        let args = adapter.build_disposable_virt_install_args(
-            "disp-work-20240101-120000",
-            Path::new("/tmp/disp.qcow2"),
-            "work-inet",
-            "debian12",
-            2048,
+    fn test_disposable_virt_install_args() {
+        let adapter = LibvirtAdapter::new();
+        let args = adapter.build_disposable_virt_install_args(
+            "disp-work-20240101-120000",
+            Path::new("/tmp/disp.qcow2"),
+            "work-inet",
+            "debian12",
+            2048,
+            &DiskTuning::default(),
+            &BootOptions::default(),
+        );
+
+        assert!(args.contains(&"--transient".to_string()));
+        assert!(args.contains(&"path=/tmp/disp.qcow2,format=qcow2".to_string()));
+        assert!(args.contains(&"--import".to_string()));
+        assert!(!args.contains(&"--boot".to_string()));
+        assert!(!args.contains(&"--console".to_string()));
+    }
+
+    /// Answers like its inner mock, except that every timed command hangs
+    #[derive(Debug)]
+    struct HangingRunner(MockRunner);
+
+    impl CommandRunner for HangingRunner {
+        fn run(&self, cmd: &str, args: &[&str], max: usize) -> Result<CommandOutput> {
+            self.0.run(cmd, args, max)
+        }
+
+        fn run_with_timeout(
+            &self,
+            cmd: &str,
+            args: &[&str],
+            max: usize,
+            timeout: Duration,
+        ) -> Result<CommandOutput> {
+            self.0.run(cmd, args, max)?;
+            Err(Error::Timeout {
+                cmd: cmd.to_string(),
+                secs: timeout.as_secs(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_virt_install_timeout_cleans_up() {
+        let runner = Arc::new(HangingRunner(
+            MockRunner::new()
+                .on("virsh destroy disp-work-1", "")
+                .on("virsh undefine disp-work-1", ""),
+        ));
+        let mut adapter = LibvirtAdapter::with_runner(runner.clone());
+        adapter.virt_install_timeout_secs = 30;
+
+        let err = adapter
+            .create_disposable_vm(
+                "disp-work-1",
+                Path::new("/tmp/disp.qcow2"),
+                "work-inet",
+                "debian12",
+                2048,
+                &DiskTuning::default(),
+                &BootOptions::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::Timeout { secs: 30, .. }));
+        let calls = runner.0.calls();
+        assert!(calls[0].starts_with("virt-install "));
+        assert_eq!(
+            calls[1..],
+            ["virsh destroy disp-work-1", "virsh undefine disp-work-1"]
+        );
+
+        // Other commands never get a timeout
+        assert!(adapter
+            .run_cmd("virsh", &["destroy", "disp-work-1"])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_boot_options_args() {
+        let boot = BootOptions {
+            kernel_args: "console=ttyS0 root=/dev/vda1".to_string(),
+            kernel: Some(PathBuf::from("/boot/vmlinuz-cloud")),
+            initrd: Some(PathBuf::from("/boot/initrd-cloud")),
+            serial_console: true,
+        };
+        let adapter = LibvirtAdapter::new();
+        let args = adapter.build_disposable_virt_install_args(
+            "disp-work-20240101-120000",
+            Path::new("/tmp/disp.qcow2"),
+            "work-inet",
+            "debian12",
+            2048,
+            &DiskTuning::default(),
+            &boot,
+        );
+
+        let pos = |flag: &str| args.iter().position(|a| a == flag).unwrap();
+        assert_eq!(
+            args[pos("--boot") + 1],
+            "kernel=/boot/vmlinuz-cloud,initrd=/boot/initrd-cloud,kernel_args=\"console=ttyS0 root=/dev/vda1\""
+        );
+        assert_eq!(args[pos("--console") + 1], "pty,target.type=serial");
+        assert!(boot.validate().is_ok());
+
+        let no_kernel = BootOptions {
+            kernel_args: "console=ttyS0".to_string(),
+            ..BootOptions::default()
+        };
+        assert!(no_kernel.validate().is_err());
+        assert!(no_kernel.virt_install_args().is_empty());
+        assert!(BootOptions::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_vm_state_parsing() {
+        assert_eq!(VmState::from_virsh_state("running"), VmState::Running);
+        assert_eq!(VmState::from_virsh_state("Running"), VmState::Running);
+        assert_eq!(VmState::from_virsh_state("shut off"), VmState::ShutOff);
+        assert_eq!(VmState::from_virsh_state("paused"), VmState::Paused);
+        assert_eq!(VmState::from_virsh_state("unknown"), VmState::Unknown);
+    }
+
+    /// Serve one canned reply per connection on a loopback port
+    fn fake_proxy(replies: Vec<&'static [u8]>) -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for reply in replies {
+                let (mut conn, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 256];
+                let _ = conn.read(&mut buf);
+                let _ = conn.write_all(reply);
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_check_upstream() {
+        let adapter = LibvirtAdapter::new();
+        let mut config = ProxyConfig::new("work".to_string(), GatewayMode::ProxyChain);
+        assert!(matches!(
+            adapter.check_upstream("work-gw", &config),
+            UpstreamCheck::Skipped(_)
+        ));
+
+        let port = fake_proxy(vec![b""]);
+        config.add_hop(ProxyHop::new(
+            1,
+            ProxyType::Socks5,
+            "127.0.0.1".to_string(),
+            port,
+        ));
+        assert!(matches!(
+            adapter.check_upstream("work-gw", &config),
+            UpstreamCheck::Reachable(_)
+        ));
+
+        // Nothing listens on a port we just released
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        config.hops[0].port = closed;
+        let check = adapter.check_upstream("work-gw", &config);
+        assert!(matches!(check, UpstreamCheck::Unreachable(_)));
+        assert!(check.detail().contains(&format!("127.0.0.1:{}", closed)));
+
+        // No guest agent in the gateway, so the handshake can't be read
+        let config = ProxyConfig::new("work".to_string(), GatewayMode::WireGuard);
+        let check = mock_adapter(MockRunner::new()).check_upstream("work-gw", &config);
+        assert!(matches!(check, UpstreamCheck::Unreachable(_)));
+
+        let config = ProxyConfig::new("work".to_string(), GatewayMode::OpenVpn);
+        assert!(matches!(
+            adapter.check_upstream("work-gw", &config),
+            UpstreamCheck::Skipped(_)
+        ));
+    }
+
+    #[test]
+    fn test_detect_proxy_type() {
+        assert!(is_socks5_reply(&[0x05, 0x00]));
+        assert!(is_socks5_reply(&[0x05, 0xFF]));
+        assert!(!is_socks5_reply(&[0x05]));
+        assert!(is_http_reply(
+            b"HTTP/1.1 407 Proxy Authentication Required\r\n"
+        ));
+        assert!(!is_http_reply(b"SSH-2.0-OpenSSH"));
+
+        let adapter = LibvirtAdapter::new();
+
+        let port = fake_proxy(vec![&[0x05, 0x00]]);
+        assert_eq!(
+            adapter.detect_proxy_type("127.0.0.1", port).unwrap(),
+            Some(ProxyType::Socks5)
+        );
+
+        // Silent on the SOCKS greeting, answers the CONNECT
+        let port = fake_proxy(vec![b"", b"HTTP/1.1 200 Connection established\r\n\r\n"]);
+        assert_eq!(
+            adapter.detect_proxy_type("127.0.0.1", port).unwrap(),
+            Some(ProxyType::Http)
+        );
+
+        let port = fake_proxy(vec![b"SSH-2.0-OpenSSH\r\n", b"SSH-2.0-OpenSSH\r\n"]);
+        assert_eq!(adapter.detect_proxy_type("127.0.0.1", port).unwrap(), None);
+    }
+
+    /// Minimal SOCKS5 server: accepts "no auth", answers one request with `reply`
+    fn fake_socks5(reply: &'static [u8]) -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 256];
+            let _ = conn.read(&mut buf);
+            let _ = conn.write_all(&[0x05, 0x00]);
+            let _ = conn.read(&mut buf);
+            let _ = conn.write_all(reply);
+        });
+        port
+    }
+
+    #[test]
+    fn test_ping_host() {
+        let reply = "PING 10.0.0.1 (10.0.0.1) 56(84) bytes of data.\n\
+                     64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time=12.5 ms\n\n\
+                     --- 10.0.0.1 ping statistics ---\n\
+                     1 packets transmitted, 1 received, 0% packet loss, time 0ms\n";
+        let runner = MockRunner::new().on("ping -c1 -W5 10.0.0.1", reply);
+        let adapter = mock_adapter(runner);
+        assert_eq!(
+            adapter.ping_host("10.0.0.1").unwrap(),
+            Duration::from_micros(12_500)
+        );
+
+        // Blocked ICMP or a down host is an error, not a panic
+        assert!(matches!(
+            adapter.ping_host("10.0.0.2"),
+            Err(Error::Network(_))
+        ));
+        // Never let a host be read as a ping option
+        assert!(adapter.ping_host("-f").is_err());
+
+        assert_eq!(
+            parse_ping_rtt("64 bytes from ::1: icmp_seq=1 ttl=64 time<1 ms"),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(parse_ping_rtt("1 packets transmitted, 0 received"), None);
+    }
+
+    #[test]
+    fn test_socks5_udp() {
+        let adapter = LibvirtAdapter::new();
+        let hop = |port| ProxyHop::new(1, ProxyType::Socks5, "127.0.0.1".to_string(), port);
+
+        let relay = fake_socks5(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x1F, 0x90]);
+        assert!(adapter.test_socks5_udp(&hop(relay)).is_ok());
+
+        let refused = fake_socks5(&[0x05, 0x07, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+        let err = adapter.test_socks5_udp(&hop(refused)).unwrap_err();
+        assert!(err.to_string().contains("does not support UDP"));
+
+        let no_port = fake_socks5(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+        assert!(adapter.test_socks5_udp(&hop(no_port)).is_err());
+
+        assert_eq!(
+            parse_udp_associate_reply(&[0x05, 0x00, 0x00, 0x03, 3, b'a', b'b', b'c', 0, 53]),
+            Ok(())
+        );
+        assert!(parse_udp_associate_reply(&[0x05, 0x00, 0x00, 0x04, 0, 0]).is_err());
+
+        let mut http = hop(relay);
+        http.proxy_type = ProxyType::Http;
+        assert!(adapter.test_socks5_udp(&http).is_err());
+    }
+
+    #[test]
+    fn test_role_network_xml() {
+        let v4_only = role_network_xml("work-inet", None, None, &RoleNetworkMode::Isolated);
+        assert_eq!(
+            v4_only,
+            "<network>\n  <name>work-inet</name>\n  <bridge stp='on' delay='0'/>\n</network>"
+        );
+
+        let subnet = Ipv6Subnet::parse("fd00:10::/64").unwrap();
+        let dual = role_network_xml("work-inet", None, Some(&subnet), &RoleNetworkMode::Isolated);
+        assert!(dual.contains("<ip family='ipv6' address='fd00:10::1' prefix='64'>"));
+        assert!(dual.contains("<range start='fd00:10::100' end='fd00:10::1ff'/>"));
+        assert!(dual.ends_with("</ip>\n</network>"));
+        assert!(!dual.contains("<forward"));
+
+        let v4 = Ipv4Subnet::parse("10.200.3.0/24").unwrap();
+        let nat = role_network_xml("work-inet", Some(&v4), Some(&subnet), &RoleNetworkMode::Nat);
+        assert!(nat.contains("<forward mode='nat'/>"));
+        assert!(nat.contains("<ip address='10.200.3.1' netmask='255.255.255.0'>"));
+        assert!(nat.contains("<range start='10.200.3.2' end='10.200.3.254'/>"));
+        assert!(nat.contains("<ip family='ipv6'"));
+
+        let bridged = role_network_xml(
+            "work-inet",
+            None,
+            Some(&subnet),
+            &RoleNetworkMode::Bridge("br0".to_string()),
+        );
+        assert_eq!(
+            bridged,
+            "<network>\n  <name>work-inet</name>\n  <forward mode='bridge'/>\n  <bridge name='br0'/>\n</network>"
+        );
+    }
+
+    #[test]
+    fn test_allocate_role_subnet() {
+        let networks = MockRunner::new()
+            .on("virsh net-list --all --name", "default\nlan-net\nwork-inet\n\n")
+            .on(
+                "virsh net-dumpxml default",
+                "<network>\n  <name>default</name>\n  <ip address='10.200.0.1' netmask='255.255.255.0'>\n  </ip>\n</network>\n",
+            )
+            .on(
+                "virsh net-dumpxml work-inet",
+                "<network>\n  <ip address=\"10.200.1.1\" prefix=\"24\"/>\n  <ip family='ipv6' address='fd00::1' prefix='64'/>\n</network>\n",
+            )
+            .on(
+                "virsh net-dumpxml lan-net",
+                "<network>\n  <forward mode='bridge'/>\n  <bridge name='br0'/>\n</network>\n",
+            );
+        // The LAN behind br0 only shows up as a host address
+        let runner = networks.on(
+            "ip -o -4 addr show",
+            "1: lo    inet 127.0.0.1/8 scope host lo\n\
+             3: br0    inet 10.200.2.20/23 brd 10.200.3.255 scope global br0\n",
+        );
+        let adapter = mock_adapter(runner);
+        assert_eq!(
+            adapter.allocate_role_subnet().unwrap().to_string(),
+            "10.200.4.0/24"
         );
 
-        assert!(args.contains(&"--transient".to_string()));
-        assert!(args.contains(&"--import".to_string()));
+        let mut full = mock_adapter(
+            MockRunner::new()
+                .on("virsh net-list --all --name", "default\n")
+                .on(
+                    "virsh net-dumpxml default",
+                    "<network><ip address='10.200.0.1' netmask='255.255.0.0'/></network>",
+                ),
+        );
+        let err = full.allocate_role_subnet().unwrap_err();
+        assert!(err.to_string().contains("No free /24"));
+        full.role_subnet_pool = Ipv4Subnet::parse("192.168.200.0/24").unwrap();
+        assert_eq!(
+            full.allocate_role_subnet().unwrap().to_string(),
+            "192.168.200.0/24"
+        );
     }
 
     #[test]
-    fn test_vm_state_parsing() {
-        assert_eq!(VmState::from_virsh_state("running"), VmState::Running);
-        assert_eq!(VmState::from_virsh_state("Running"), VmState::Running);
-        assert_eq!(VmState::from_virsh_state("shut off"), VmState::ShutOff);
-        assert_eq!(VmState::from_virsh_state("paused"), VmState::Paused);
-        assert_eq!(VmState::from_virsh_state("unknown"), VmState::Unknown);
+    fn test_check_role_network_isolation() {
+        let adapter = mock_adapter(
+            MockRunner::new()
+                .on(
+                    "virsh net-dumpxml lan-net",
+                    "<network>\n  <name>lan-net</name>\n  <forward mode='bridge'/>\n  <bridge name='br0'/>\n</network>\n",
+                )
+                .on(
+                    "virsh net-dumpxml work-inet",
+                    "<network>\n  <name>work-inet</name>\n  <bridge name='virbr3' stp='on' delay='0'/>\n</network>\n",
+                )
+                .on(
+                    "virsh net-dumpxml bank-inet",
+                    "<network>\n  <name>bank-inet</name>\n  <forward mode=\"bridge\"/>\n  <bridge name=\"br0\"/>\n</network>\n",
+                ),
+        );
+        assert_eq!(
+            adapter.network_bridge("work-inet").unwrap().as_deref(),
+            Some("virbr3")
+        );
+        let roles: Vec<String> = ["work", "bank", "gone"].map(String::from).to_vec();
+        let warnings = adapter
+            .check_role_network_isolation("lan-net", &roles)
+            .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Role network 'bank-inet' is attached to bridge 'br0'"));
+
+        // Without a LAN network there is nothing to compare against
+        assert!(adapter
+            .check_role_network_isolation("missing", &roles)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_ensure_bridged_network_checks_bridge() {
+        let adapter = mock_adapter(MockRunner::new().on(
+            "ip -o link show type bridge",
+            "3: virbr0: <BROADCAST,MULTICAST,UP> mtu 1500 qdisc noqueue state UP\n7: br0@enp3s0: <BROADCAST> mtu 1500\n",
+        ));
+        assert_eq!(adapter.list_host_bridges().unwrap(), ["virbr0", "br0"]);
+
+        let missing = RoleNetworkMode::Bridge("br9".to_string());
+        let err = adapter
+            .ensure_role_network("work", None, &missing)
+            .unwrap_err();
+        assert!(err.to_string().contains("br9"));
+    }
+
+    #[test]
+    fn test_check_prerequisites_qemu_img_version() {
+        let tools = || {
+            MockRunner::new()
+                .on("which virsh", "/usr/bin/virsh")
+                .on("which virt-install", "/usr/bin/virt-install")
+                .on("which qemu-img", "/usr/bin/qemu-img")
+        };
+        let adapter = mock_adapter(tools().on(
+            "qemu-img --version",
+            "qemu-img version 8.2.2 (Debian 1:8.2.2+ds-0ubuntu1)\nCopyright (c) 2003-2023 Fabrice Bellard\n",
+        ));
+        assert!(adapter.check_prerequisites().is_ok());
+
+        let adapter = mock_adapter(tools().on(
+            "qemu-img --version",
+            "qemu-img version 1.5.3, Copyright (c) 2004-2008 Fabrice Bellard\n",
+        ));
+        let err = adapter.check_prerequisites().unwrap_err().to_string();
+        assert!(err.contains("1.5.3 is too old"), "{}", err);
+
+        // An unreadable version line doesn't block anything
+        let adapter = mock_adapter(tools().on("qemu-img --version", "qemu-img (custom build)\n"));
+        assert!(adapter.check_prerequisites().is_ok());
+
+        assert_eq!(
+            parse_qemu_img_version("qemu-img version 2.0\n"),
+            Some((2, 0, 0))
+        );
+        assert_eq!(parse_qemu_img_version(""), None);
+    }
+
+    #[test]
+    fn test_needs_privilege() {
+        assert!(needs_privilege(Path::new(
+            "/var/lib/libvirt/images/work-gw.qcow2"
+        )));
+        assert!(needs_privilege(Path::new("/etc/libvirt/disk.qcow2")));
+        assert!(!needs_privilege(Path::new("/home/me/VMs/work-gw.qcow2")));
+        // Whole components only, not a name that merely starts the same
+        assert!(!needs_privilege(Path::new("/var/library/work-gw.qcow2")));
+    }
+
+    #[test]
+    fn test_parse_virsh_list_titles() {
+        let output = " Id   Name       State      Title\n\
+                      ----------------------------------------------\n \
+                      -    café-gw    shut off   Exit über Berlin\n \
+                      7    work-gw    running    \n";
+        let titles = parse_virsh_list_titles(output);
+        assert_eq!(titles.len(), 1);
+        assert_eq!(titles["café-gw"], "Exit über Berlin");
+        assert!(parse_virsh_list_titles("error: failed to connect\n").is_empty());
+    }
+
+    #[test]
+    fn test_parse_df_avail() {
+        assert_eq!(
+            parse_df_avail("    Avail\n52613349376\n"),
+            Some(52_613_349_376)
+        );
+        assert_eq!(parse_df_avail("Avail\n"), None);
+        assert_eq!(parse_df_avail("Avail\n-\n"), None);
+    }
+
+    #[test]
+    fn test_insufficient_space_message() {
+        let err = Error::InsufficientSpace {
+            path: PathBuf::from("/var/lib/libvirt/images"),
+            needed: 20 * 1024 * 1024 * 1024,
+            available: 3 * 1024 * 1024 * 1024 / 2,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Insufficient space on /var/lib/libvirt/images: need ~20.0 GiB, have 1.5 GiB"
+        );
+    }
+
+    #[test]
+    fn test_tcp_connections_report_each_endpoint() {
+        let open = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open_port = open.local_addr().unwrap().port();
+        let closed_port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let endpoints = vec![
+            ("127.0.0.1".to_string(), closed_port),
+            ("127.0.0.1".to_string(), open_port),
+        ];
+        let adapter = LibvirtAdapter::default();
+
+        let results = std::sync::Mutex::new(Vec::new());
+        adapter.test_tcp_connections(&endpoints, Duration::from_secs(5), |i, result| {
+            results.lock().unwrap().push((i, result.is_ok()));
+        });
+        let mut results = results.into_inner().unwrap();
+        results.sort();
+        assert_eq!(results, [(0, false), (1, true)]);
+
+        // Nothing is tried once the budget is spent
+        let errors = std::sync::Mutex::new(Vec::new());
+        adapter.test_tcp_connections(&endpoints, Duration::ZERO, |_, result| {
+            errors.lock().unwrap().push(result.unwrap_err().to_string());
+        });
+        let errors = errors.into_inner().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|e| e.contains("Ran out of time")));
+    }
+
+    #[test]
+    fn test_parallel_map_is_concurrent_and_ordered() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let items: Vec<u32> = (0..12).collect();
+
+        let start = Instant::now();
+        let results = parallel_map(&items, 4, |n| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            // Stand-in for a `virsh dominfo` fork
+            std::thread::sleep(Duration::from_millis(50));
+            running.fetch_sub(1, Ordering::SeqCst);
+            n * 10
+        });
+
+        assert_eq!(results, (0..12).map(|n| n * 10).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 4);
+        // 12 calls of 50ms in 3 waves, not 12 back to back
+        assert!(start.elapsed() < Duration::from_millis(450));
+
+        let serial = parallel_map(&items, 1, |n| *n);
+        assert_eq!(serial, items);
+        assert!(parallel_map(&Vec::<u32>::new(), 4, |n| *n).is_empty());
+    }
+
+    fn mock_adapter(runner: MockRunner) -> LibvirtAdapter {
+        LibvirtAdapter::with_runner(Arc::new(runner))
+    }
+
+    const DOMINFO_RUNNING: &str = "Id:             3\nName:           work-gw\nState:          running\nAutostart:      disable\n";
+
+    #[test]
+    fn test_get_vm_info_with_mock() {
+        let adapter = mock_adapter(
+            MockRunner::new()
+                .on("virsh dominfo work-gw", DOMINFO_RUNNING)
+                .on(
+                    "virsh dominfo work-app-browser",
+                    "State:          shut off\n",
+                ),
+        );
+
+        let gw = adapter.get_vm_info("work-gw").unwrap().unwrap();
+        assert_eq!(gw.state, VmState::Running);
+        assert_eq!(gw.kind, VmKind::ProxyGateway);
+        assert_eq!(gw.role.as_deref(), Some("work"));
+
+        let app = adapter.get_vm_info("work-app-browser").unwrap().unwrap();
+        assert_eq!(app.state, VmState::ShutOff);
+        assert_eq!(app.kind, VmKind::App);
+        assert_eq!(app.role.as_deref(), Some("work"));
+
+        // Unknown domain: virsh fails, so no info
+        assert!(adapter.get_vm_info("missing-gw").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_vm_title_with_mock() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .on("virsh list --all --name", "work-gw\nwork-app-1\n")
+                .on(
+                    "virsh list --all --title",
+                    " Id   Name         State      Title\n\
+                     --------------------------------------------------------------\n \
+                     3    work-gw      running    Proxy gateway for role work\n \
+                     -    work-app-1   shut off   \n",
+                )
+                .on("virsh dominfo work-gw", DOMINFO_RUNNING)
+                .on("virsh dominfo work-app-1", "State:          shut off\n")
+                .on(
+                    "virsh desc work-gw --config --live --title --new-desc Work exit",
+                    "Domain title updated successfully\n",
+                )
+                .on(
+                    "virsh desc work-app-1 --config --new-desc Banking only",
+                    "Domain description updated successfully\n",
+                ),
+        );
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        let vms = adapter.list_vms(None).unwrap();
+        assert_eq!(vms[0].title.as_deref(), Some("Proxy gateway for role work"));
+        assert_eq!(vms[1].title, None);
+        // Titles come from the one listing, not a `virsh desc` per VM
+        assert!(!runner
+            .calls()
+            .iter()
+            .any(|c| c.contains("desc work-app-1 --title")));
+
+        adapter.set_vm_title("work-gw", "Work exit").unwrap();
+        adapter
+            .set_vm_description("work-app-1", "Banking only")
+            .unwrap();
+        assert!(adapter.set_vm_title("work-gw", "two\nlines").is_err());
+        let err = adapter.set_vm_title("missing-gw", "x").unwrap_err();
+        assert!(matches!(err, Error::NotFound(_)));
+    }
+
+    #[test]
+    fn test_list_vms_with_mock() {
+        let adapter = mock_adapter(
+            MockRunner::new()
+                .on(
+                    "virsh list --all --name",
+                    "work-gw\nwork-app-browser\n\nbank-gw\ngone-gw\n",
+                )
+                .on("virsh dominfo work-gw", DOMINFO_RUNNING)
+                .on("virsh dominfo work-app-browser", "State: paused\n")
+                .on("virsh dominfo bank-gw", "State: shut off\n"),
+        );
+
+        // Domains that vanish between list and dominfo are dropped; order is kept
+        let vms = adapter.list_vms(None).unwrap();
+        let names: Vec<&str> = vms.iter().map(|vm| vm.name.as_str()).collect();
+        assert_eq!(names, ["work-gw", "work-app-browser", "bank-gw"]);
+        assert_eq!(vms[1].state, VmState::Paused);
+
+        let work = adapter.list_vms(Some("work")).unwrap();
+        assert_eq!(work.len(), 2);
+        assert!(work.iter().all(|vm| vm.role.as_deref() == Some("work")));
+
+        let failing = mock_adapter(MockRunner::new());
+        assert!(failing.list_vms(None).is_err());
+    }
+
+    #[test]
+    fn test_get_backing_file_with_mock() {
+        let adapter = mock_adapter(
+            MockRunner::new()
+                .on(
+                    "qemu-img info /images/work-gw.qcow2",
+                    "image: /images/work-gw.qcow2\nfile format: qcow2\nvirtual size: 20 GiB (21474836480 bytes)\nbacking file: /images/debian-12.qcow2 (actual path: /images/debian-12.qcow2)\nbacking file format: qcow2\n",
+                )
+                .on(
+                    "qemu-img info /images/base.qcow2",
+                    "image: /images/base.qcow2\nfile format: qcow2\n",
+                ),
+        );
+
+        assert_eq!(
+            adapter
+                .get_backing_file(Path::new("/images/work-gw.qcow2"))
+                .unwrap(),
+            Some(PathBuf::from("/images/debian-12.qcow2"))
+        );
+        assert!(adapter
+            .get_backing_file(Path::new("/images/base.qcow2"))
+            .unwrap()
+            .is_none());
+        assert!(adapter
+            .get_backing_file(Path::new("/images/missing.qcow2"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_check_disk_permissions() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .on("id -nG libvirt-qemu", "libvirt-qemu kvm\n")
+                .on(
+                    "qemu-img info /var/lib/libvirt/images/work-gw.qcow2",
+                    "backing file: /home/me/debian-12.qcow2\n",
+                )
+                .on(
+                    "qemu-img info /home/me/debian-12.qcow2",
+                    "file format: qcow2\n",
+                )
+                .on(
+                    "stat -L -c %U:%G:%a:%n /var/lib/libvirt/images/work-gw.qcow2 \
+                     /home/me/debian-12.qcow2 /var/lib/libvirt/images /var/lib/libvirt \
+                     /var/lib /var / /home/me /home",
+                    "root:root:644:/var/lib/libvirt/images/work-gw.qcow2\n\
+                     me:me:600:/home/me/debian-12.qcow2\n\
+                     root:root:711:/var/lib/libvirt/images\n\
+                     root:root:755:/var/lib/libvirt\n\
+                     root:root:755:/var/lib\n\
+                     root:root:755:/var\n\
+                     root:root:755:/\n\
+                     me:me:750:/home/me\n\
+                     root:root:755:/home\n",
+                ),
+        );
+        let adapter = LibvirtAdapter::with_runner(runner);
+        let issues = adapter
+            .check_disk_permissions(Path::new("/var/lib/libvirt/images/work-gw.qcow2"))
+            .unwrap();
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].path, PathBuf::from("/home/me/debian-12.qcow2"));
+        assert_eq!(issues[0].kind, PermIssueKind::Unreadable);
+        assert_eq!(issues[0].fix(), "sudo chmod o+r /home/me/debian-12.qcow2");
+        assert_eq!(issues[1].kind, PermIssueKind::NotSearchable);
+        assert_eq!(issues[1].fix(), "sudo chmod o+x /home/me");
+
+        // qemu runs as the current user on a session connection
+        let mut session = LibvirtAdapter::with_runner(Arc::new(MockRunner::new()));
+        session.uri = Some("qemu:///session".to_string());
+        assert!(session
+            .check_disk_permissions(Path::new("/home/me/work-gw.qcow2"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_check_and_rebase_backing() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = dir.path().join("work-gw.qcow2");
+        let base = dir.path().join("debian-12.qcow2");
+        let raw = dir.path().join("debian-12.img");
+        for file in [&disk, &base, &raw] {
+            fs::write(file, "").unwrap();
+        }
+        let disk_xml = |path: &Path| {
+            format!(
+                "<domain>\n  <devices>\n    <disk type='file' device='disk'>\n      <source file='{}'/>\n    </disk>\n  </devices>\n</domain>\n",
+                path.display()
+            )
+        };
+        let runner = Arc::new(
+            MockRunner::new()
+                .on("virsh list --all --name", "work-gw\nwork-app-1\nother\n")
+                .on("virsh dominfo work-gw", "State: shut off\n")
+                .on("virsh dominfo work-app-1", "State: shut off\n")
+                .on("virsh dominfo other", "State: shut off\n")
+                .on("virsh dumpxml work-gw", &disk_xml(&disk))
+                .on("virsh dumpxml work-app-1", &disk_xml(&base))
+                .on(
+                    &format!("qemu-img info {}", disk.display()),
+                    "backing file: /old/images/debian-12.qcow2\n",
+                )
+                .on(
+                    &format!("qemu-img info -U --output=json {}", base.display()),
+                    "{\"format\": \"qcow2\", \"virtual-size\": 21474836480}",
+                )
+                .on(
+                    &format!("qemu-img info -U --output=json {}", raw.display()),
+                    "{\"format\": \"raw\", \"virtual-size\": 21474836480}",
+                )
+                .on(
+                    &format!(
+                        "qemu-img rebase -u -b {} -F qcow2 {}",
+                        base.display(),
+                        disk.display()
+                    ),
+                    "",
+                ),
+        );
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        // `other` isn't managed, and work-app-1's disk has no backing file
+        assert_eq!(
+            adapter.check_backing_chains().unwrap(),
+            [BrokenBacking {
+                vm: "work-gw".to_string(),
+                overlay: disk.clone(),
+                missing: PathBuf::from("/old/images/debian-12.qcow2"),
+            }]
+        );
+
+        assert!(adapter.rebase_backing(&disk, &raw).is_err());
+        assert!(adapter
+            .rebase_backing(&disk, &dir.path().join("gone.qcow2"))
+            .is_err());
+        assert!(adapter.rebase_backing(&disk, &disk).is_err());
+        adapter.rebase_backing(&disk, &base).unwrap();
+        assert_eq!(
+            runner
+                .calls()
+                .iter()
+                .filter(|call| call.starts_with("qemu-img rebase"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_create_blank_disk_with_mock() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = dir.path().join("work-gw.qcow2");
+        let create = format!("qemu-img create -f qcow2 {} 30G", disk.display());
+        let runner = Arc::new(MockRunner::new().on(&create, ""));
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        adapter.create_blank_disk(&disk, 30).unwrap();
+        assert!(adapter.create_blank_disk(&disk, 0).is_err());
+        assert_eq!(runner.calls(), [create]);
+
+        std::fs::write(&disk, "").unwrap();
+        assert!(matches!(
+            adapter.create_blank_disk(&disk, 30),
+            Err(Error::AlreadyExists(_))
+        ));
+    }
+
+    #[test]
+    fn test_role_disk_bases_with_mock() {
+        let runner = MockRunner::new()
+            .on(
+                "qemu-img info /images/work-gw.qcow2",
+                "backing file: /images/debian-12.qcow2\n",
+            )
+            .on(
+                "qemu-img info /images/work-app-1-overlay.qcow2",
+                "backing file: /images/work-app-1-clone.qcow2\n",
+            )
+            .on(
+                "qemu-img info /images/work-app-1-clone.qcow2",
+                "backing file: /images/fedora-40.qcow2\n",
+            )
+            .on(
+                "qemu-img info /images/debian-12.qcow2",
+                "file format: qcow2\n",
+            )
+            .on(
+                "qemu-img info /images/fedora-40.qcow2",
+                "file format: qcow2\n",
+            )
+            .on(
+                "qemu-img info /images/standalone.qcow2",
+                "file format: qcow2\n",
+            );
+        let adapter = mock_adapter(runner);
+        let disks = adapter.role_disk_bases(&[
+            PathBuf::from("/images/work-gw.qcow2"),
+            PathBuf::from("/images/work-app-1-overlay.qcow2"),
+            PathBuf::from("/images/standalone.qcow2"),
+        ]);
+        let bases: Vec<Option<&Path>> = disks.iter().map(|d| d.base.as_deref()).collect();
+        assert_eq!(
+            bases,
+            vec![
+                Some(Path::new("/images/debian-12.qcow2")),
+                Some(Path::new("/images/fedora-40.qcow2")),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backing_chain_and_flatten_with_mock() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = dir.path().join("work-app-1.qcow2");
+        let tmp = dir.path().join("work-app-1.qcow2.flatten-tmp");
+        std::fs::write(&disk, "old").unwrap();
+        std::fs::write(&tmp, "new").unwrap();
+        let runner = Arc::new(
+            MockRunner::new()
+                .on("virsh dominfo work-app-1", "State: shut off\n")
+                .on(
+                    "virsh dumpxml work-app-1",
+                    &format!("<source file='{}'/>\n", disk.display()),
+                )
+                .on(
+                    &format!("qemu-img info {}", disk.display()),
+                    "backing file: /images/clone.qcow2\n",
+                )
+                .on(
+                    "qemu-img info /images/clone.qcow2",
+                    "backing file: /images/debian-12.qcow2\n",
+                )
+                .on(
+                    "qemu-img info /images/debian-12.qcow2",
+                    "file format: qcow2\n",
+                )
+                .on(
+                    &format!("qemu-img info -U --output=json {}", disk.display()),
+                    "{\"virtual-size\": 1000}",
+                )
+                .on(
+                    &format!("df --output=avail -B1 {}", dir.path().display()),
+                    "Avail\n5000\n",
+                )
+                .on(
+                    &format!(
+                        "qemu-img convert -O qcow2 -F qcow2 -B /images/debian-12.qcow2 {} {}",
+                        disk.display(),
+                        tmp.display()
+                    ),
+                    "",
+                )
+                .on(
+                    "qemu-img info /images/loop-a.qcow2",
+                    "backing file: /images/loop-b.qcow2\n",
+                )
+                .on(
+                    "qemu-img info /images/loop-b.qcow2",
+                    "backing file: /images/loop-a.qcow2\n",
+                ),
+        );
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        assert_eq!(adapter.backing_chain_depth(&disk).unwrap(), 2);
+        assert_eq!(
+            adapter
+                .backing_chain_depth(Path::new("/images/debian-12.qcow2"))
+                .unwrap(),
+            0
+        );
+        assert!(adapter
+            .backing_chain(Path::new("/images/loop-a.qcow2"))
+            .is_err());
+
+        assert_eq!(adapter.flatten_vm_disk("work-app-1").unwrap(), 1);
+        assert_eq!(std::fs::read_to_string(&disk).unwrap(), "new");
+        assert!(!tmp.exists());
+
+        let running = mock_adapter(MockRunner::new().on("virsh dominfo work-gw", DOMINFO_RUNNING));
+        assert!(running.flatten_vm_disk("work-gw").is_err());
+    }
+
+    #[test]
+    fn test_role_app_overlays_beyond_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let images = dir.path().join("images");
+        let elsewhere = dir.path().join("moved");
+        fs::create_dir_all(&images).unwrap();
+        fs::create_dir_all(&elsewhere).unwrap();
+        for n in 1..=25 {
+            fs::write(images.join(format!("work-app-{}-overlay.qcow2", n)), "").unwrap();
+        }
+        let moved = elsewhere.join("work-app-26.qcow2");
+        for file in [
+            moved.clone(),
+            images.join("work-app-x-overlay.qcow2"),
+            images.join("work-app-app-1-overlay.qcow2"),
+            images.join("workshop-app-1-overlay.qcow2"),
+            images.join("work-gw.qcow2"),
+        ] {
+            fs::write(file, "").unwrap();
+        }
+
+        let adapter = mock_adapter(MockRunner::new());
+        // role.toml only knows about 3 app VMs, but all 25 plus the moved disk go
+        let overlays = adapter.role_app_overlays(
+            &images,
+            "work",
+            3,
+            &[moved.clone(), images.join("work-app-1-overlay.qcow2")],
+        );
+        let mut expected: Vec<PathBuf> = (1..=25)
+            .map(|n| adapter.app_overlay_path(&images, "work", n))
+            .collect();
+        expected.push(moved);
+        assert_eq!(overlays, expected);
+    }
+
+    #[test]
+    fn test_role_disks_and_image_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let images = dir.path().join("images");
+        let cfg_root = dir.path().join("configs");
+        let disposable = cfg_root.join("work").join("disposable");
+        fs::create_dir_all(&images).unwrap();
+        fs::create_dir_all(&disposable).unwrap();
+        for file in [
+            images.join("work-gw.qcow2"),
+            images.join("work-app-2-overlay.qcow2"),
+            images.join("bank-gw.qcow2"),
+            disposable.join("disp-20240101-120000.qcow2"),
+            disposable.join("notes.txt"),
+        ] {
+            fs::write(file, "").unwrap();
+        }
+
+        let adapter = mock_adapter(MockRunner::new().on(
+            &format!(
+                "qemu-img info -U --output=json {}",
+                images.join("work-gw.qcow2").display()
+            ),
+            "{\"virtual-size\": 21474836480, \"actual-size\": 1310720}",
+        ));
+        // App VM 1 was deleted, so only the second overlay is left
+        assert_eq!(
+            adapter.role_disks(&images, &cfg_root, "work", 2),
+            [
+                images.join("work-gw.qcow2"),
+                images.join("work-app-2-overlay.qcow2"),
+                disposable.join("disp-20240101-120000.qcow2"),
+            ]
+        );
+        assert!(adapter.role_disks(&images, &cfg_root, "none", 3).is_empty());
+
+        let info = adapter.image_info(&images.join("work-gw.qcow2")).unwrap();
+        assert_eq!(info.virtual_size, 21474836480);
+        assert_eq!(info.disk_size, 1310720);
+        assert!(adapter
+            .image_info(&images.join("work-app-2-overlay.qcow2"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_start_stop_outcomes_with_mock() {
+        let failed = |stderr: &str| CommandOutput {
+            exit_code: 1,
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+        };
+        let adapter = mock_adapter(
+            MockRunner::new()
+                .on("virsh start work-gw", "Domain 'work-gw' started\n")
+                .on_output(
+                    "virsh start bank-gw",
+                    failed("error: Failed to start domain 'bank-gw'\nerror: Requested operation is not valid: domain is already active\n"),
+                )
+                .on("virsh shutdown work-gw", "Domain 'work-gw' is being shutdown\n")
+                .on_output(
+                    "virsh shutdown bank-gw",
+                    failed("error: Failed to shutdown domain 'bank-gw'\nerror: Requested operation is not valid: domain is not running\n"),
+                ),
+        );
+
+        assert_eq!(adapter.start_vm("work-gw").unwrap(), StartOutcome::Started);
+        assert_eq!(
+            adapter.start_vm("bank-gw").unwrap(),
+            StartOutcome::AlreadyRunning
+        );
+        assert!(adapter.start_vm("missing-gw").is_err());
+
+        assert_eq!(
+            adapter.stop_vm("work-gw").unwrap(),
+            StopOutcome::ShuttingDown
+        );
+        assert_eq!(
+            adapter.stop_vm("bank-gw").unwrap(),
+            StopOutcome::AlreadyStopped
+        );
+        assert!(adapter.stop_vm("missing-gw").is_err());
+    }
+
+    #[test]
+    fn test_snapshot_disposable_overlay_with_mock() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_str = dir.path().to_str().unwrap();
+        let source = dir.path().join("disp-20240101-120000.qcow2");
+        let dest = dir.path().join("work-app-3-overlay.qcow2");
+        let runner = |convert: bool| {
+            let runner = MockRunner::new()
+                .on(
+                    "virsh dumpxml disp-work-1",
+                    &format!("<disk>\n  <source file='{}'/>\n</disk>\n", source.display()),
+                )
+                .on(
+                    &format!("qemu-img info {}", source.display()),
+                    "backing file: /images/base.qcow2\n",
+                )
+                .on(
+                    &format!("qemu-img info -U --output=json {}", source.display()),
+                    "{\"virtual-size\": 1000}",
+                )
+                .on(
+                    &format!("df --output=avail -B1 {}", dir_str),
+                    "Avail\n5000\n",
+                )
+                .on("virsh suspend disp-work-1", "")
+                .on("virsh resume disp-work-1", "");
+            let runner = if convert {
+                runner.on(
+                    &format!(
+                        "qemu-img convert -U -O qcow2 -F qcow2 -B /images/base.qcow2 {} {}",
+                        source.display(),
+                        dest.display()
+                    ),
+                    "",
+                )
+            } else {
+                runner
+            };
+            Arc::new(runner)
+        };
+
+        let ok = runner(true);
+        let adapter = LibvirtAdapter::with_runner(ok.clone());
+        adapter
+            .snapshot_disposable_overlay("disp-work-1", &dest)
+            .unwrap();
+        let calls = ok.calls();
+        let position = |prefix: &str| calls.iter().position(|c| c.starts_with(prefix)).unwrap();
+        assert!(position("virsh suspend") < position("qemu-img convert"));
+        assert!(position("qemu-img convert") < position("virsh resume"));
+
+        // A failed copy still resumes the VM
+        let failing = runner(false);
+        let adapter = LibvirtAdapter::with_runner(failing.clone());
+        assert!(adapter
+            .snapshot_disposable_overlay("disp-work-1", &dest)
+            .is_err());
+        assert!(failing
+            .calls()
+            .iter()
+            .any(|c| c == "virsh resume disp-work-1"));
+    }
+
+    #[test]
+    fn test_discover_qcow2_files_non_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("debian.qcow2"), "").unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let odd = OsString::from_vec(b"caf\xe9.QCOW2".to_vec());
+        fs::write(dir.path().join(&odd), "").unwrap();
+
+        let adapter = mock_adapter(MockRunner::new());
+        let scan = adapter.discover_qcow2_files(dir.path()).unwrap();
+        assert_eq!(scan.files, vec![dir.path().join("debian.qcow2")]);
+        assert_eq!(scan.invalid_names, vec![dir.path().join(&odd)]);
+    }
+
+    #[test]
+    fn test_discover_qcow2_files_ls_fallback() {
+        // An unreadable directory goes through `ls -b`, whose escapes are decoded
+        let adapter = mock_adapter(MockRunner::new().on(
+            "ls -1 -b /restricted/images",
+            "debian\\ 12.qcow2\ncaf\\351.qcow2\nbase.img\n",
+        ));
+        let scan = adapter
+            .discover_qcow2_files(Path::new("/restricted/images"))
+            .unwrap();
+        assert_eq!(
+            scan.files,
+            vec![PathBuf::from("/restricted/images/debian 12.qcow2")]
+        );
+        assert_eq!(
+            scan.invalid_names,
+            vec![
+                Path::new("/restricted/images").join(OsString::from_vec(b"caf\xe9.qcow2".to_vec()))
+            ]
+        );
+
+        assert_eq!(unescape_ls_name("a\\\\b\\tc"), OsString::from("a\\b\tc"));
+    }
+
+    #[test]
+    fn test_list_app_networks_with_mock() {
+        let adapter = mock_adapter(
+            MockRunner::new()
+                .on(
+                    "virsh net-list --all --name",
+                    "default\nlan-net\nwork-inet\nold_role-inet\n-inet\nMy Net-inet\n\n",
+                )
+                .on(
+                    "virsh net-info work-inet",
+                    "Active:         yes\nAutostart:      yes\n",
+                )
+                .on(
+                    "virsh net-info old_role-inet",
+                    "Active:         no\nAutostart:      yes\n",
+                ),
+        );
+        let networks = adapter.list_app_networks().unwrap();
+        let names: Vec<&str> = networks.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, ["work-inet", "old_role-inet"]);
+        assert_eq!(networks[1].state, NetworkState::Inactive);
+
+        assert_eq!(network_role("work-inet"), Some("work"));
+        assert_eq!(network_role("default"), None);
+        assert_eq!(network_role("-inet"), None);
+    }
+
+    #[test]
+    fn test_classify_virsh_error() {
+        assert!(matches!(
+            classify_virsh_error("error: failed to get domain 'work-gw'\n"),
+            Error::DomainNotFound(detail) if detail == "failed to get domain 'work-gw'"
+        ));
+        assert!(matches!(
+            classify_virsh_error(
+                "error: Domain not found: no domain with matching name 'work-gw'\n"
+            ),
+            Error::DomainNotFound(_)
+        ));
+        assert!(matches!(
+            classify_virsh_error("error: failed to get network 'work-inet'\n"),
+            Error::NetworkNotFound(_)
+        ));
+        let denied = classify_virsh_error(
+            "error: failed to connect to the hypervisor\nerror: Failed to connect socket to \
+             '/var/run/libvirt/libvirt-sock': Permission denied\n",
+        );
+        assert!(matches!(&denied, Error::PermissionDenied(_)));
+        assert!(denied.to_string().contains("'libvirt' group"));
+        let disk = classify_virsh_error(
+            "error: Cannot access storage file '/images/work-gw.qcow2': Permission denied\n",
+        );
+        assert!(matches!(&disk, Error::PermissionDenied(_)));
+        assert!(!disk.to_string().contains("'libvirt' group"));
+        assert!(matches!(
+            classify_virsh_error(
+                "error: failed to connect to the hypervisor\nerror: Failed to connect socket to \
+                 '/var/run/libvirt/libvirt-sock': No such file or directory\n"
+            ),
+            Error::LibvirtUnreachable(_)
+        ));
+        assert!(matches!(
+            classify_virsh_error("error: unsupported configuration\n"),
+            Error::Libvirt(_)
+        ));
+        // Translated messages aren't recognized; the runner keeps virsh in English
+        assert!(matches!(
+            classify_virsh_error("Fehler: Domain 'work-gw' konnte nicht ermittelt werden\n"),
+            Error::Libvirt(_)
+        ));
+    }
+
+    #[test]
+    fn test_virsh_failures_with_mock() {
+        let failed = |stderr: &str| CommandOutput {
+            exit_code: 1,
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+        };
+        let runner = MockRunner::new()
+            .on_output(
+                "virsh dominfo work-gw",
+                failed("error: failed to get domain 'work-gw'\n"),
+            )
+            .on_output(
+                "virsh undefine work-gw",
+                failed("error: failed to get domain 'work-gw'\n"),
+            )
+            .on_output(
+                "virsh dominfo bank-gw",
+                failed("error: Failed to connect socket to '/var/run/libvirt/libvirt-sock': No such file or directory\n"),
+            )
+            .on_output(
+                "virsh start work-gw",
+                failed("error: failed to get domain 'work-gw'\n"),
+            )
+            .on_output(
+                "virsh net-info work-inet",
+                failed("error: failed to get network 'work-inet'\n"),
+            )
+            .on_output(
+                "virsh net-undefine work-inet",
+                failed("error: failed to get network 'work-inet'\n"),
+            );
+        let adapter = mock_adapter(runner);
+
+        assert!(!adapter.vm_exists("work-gw").unwrap());
+        assert!(adapter.get_vm_info("work-gw").unwrap().is_none());
+        adapter.undefine_vm("work-gw").unwrap();
+        assert!(matches!(
+            adapter.vm_exists("bank-gw"),
+            Err(Error::LibvirtUnreachable(_))
+        ));
+        assert!(matches!(
+            adapter.start_vm("work-gw"),
+            Err(Error::DomainNotFound(_))
+        ));
+        assert!(!adapter.network_exists("work-inet").unwrap());
+        adapter.destroy_network("work-inet").unwrap();
+        // Unrecognized failures keep the action in the message
+        let err = adapter.start_network("work-inet").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Failed to start network 'work-inet'"));
+    }
+
+    #[test]
+    fn test_start_network_and_autostart_with_mock() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .on("virsh net-start work-inet", "Network work-inet started\n")
+                .on("virsh net-autostart work-inet --disable", "")
+                .on("virsh net-autostart work-inet", ""),
+        );
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        adapter.start_network("work-inet").unwrap();
+        adapter.set_network_autostart("work-inet", false).unwrap();
+        adapter.set_network_autostart("work-inet", true).unwrap();
+        assert!(adapter.start_network("bank-inet").is_err());
+        let err = adapter
+            .set_network_autostart("bank-inet", false)
+            .unwrap_err();
+        assert!(err.to_string().contains("disable autostart"));
+        assert_eq!(
+            runner.calls(),
+            [
+                "virsh net-start work-inet",
+                "virsh net-autostart work-inet --disable",
+                "virsh net-autostart work-inet",
+                "virsh net-start bank-inet",
+                "virsh net-autostart bank-inet --disable",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_vm_interfaces_with_mock() {
+        let adapter = mock_adapter(MockRunner::new().on(
+            "virsh domiflist work-gw",
+            " Interface   Type      Source      Model    MAC\n\
+             -------------------------------------------------------------\n\
+             vnet3       network   lan-net     virtio   52:54:00:12:34:56\n\
+             -           network   work-inet   virtio   52:54:00:ab:cd:ef\n\n",
+        ));
+
+        let nics = adapter.list_vm_interfaces("work-gw").unwrap();
+        assert_eq!(nics.len(), 2);
+        assert_eq!(nics[0].target.as_deref(), Some("vnet3"));
+        assert_eq!(nics[0].source, "lan-net");
+        assert_eq!(nics[0].model, "virtio");
+        assert_eq!(nics[1].target, None);
+        assert_eq!(nics[1].source, "work-inet");
+        assert_eq!(nics[1].mac, "52:54:00:ab:cd:ef");
+
+        assert!(adapter.list_vm_interfaces("missing-gw").is_err());
+    }
+
+    #[test]
+    fn test_networks_in_use_with_mock() {
+        let adapter = mock_adapter(
+            MockRunner::new()
+                .on("virsh list --all --name", "work-gw\nrenamed-vm\n\n")
+                .on(
+                    "virsh domiflist work-gw",
+                    " Interface   Type      Source      Model    MAC\n\
+                     -------------------------------------------------------------\n\
+                     vnet3       network   lan-net     virtio   52:54:00:12:34:56\n",
+                )
+                .on(
+                    "virsh domiflist renamed-vm",
+                    " Interface   Type      Source      Model    MAC\n\
+                     -------------------------------------------------------------\n\
+                     -           network   old-inet    virtio   52:54:00:ab:cd:ef\n\
+                     -           bridge    br0         virtio   52:54:00:ab:cd:f0\n",
+                ),
+        );
+        let in_use = adapter.networks_in_use().unwrap();
+        assert_eq!(in_use, ["lan-net", "old-inet"].map(String::from).into());
+
+        // Not knowing is not the same as unused
+        let failing = mock_adapter(MockRunner::new().on("virsh list --all --name", "gone-vm\n"));
+        assert!(failing.networks_in_use().is_err());
+    }
+
+    #[test]
+    fn test_network_exists_with_mock() {
+        let runner = Arc::new(MockRunner::new().on(
+            "virsh net-info work-inet",
+            "Name:           work-inet\nActive:         yes\n",
+        ));
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        assert!(adapter.network_exists("work-inet").unwrap());
+        assert!(!adapter.network_exists("bank-inet").unwrap());
+        assert_eq!(
+            runner.calls(),
+            ["virsh net-info work-inet", "virsh net-info bank-inet"]
+        );
+    }
+
+    #[test]
+    fn test_parse_default_gateway() {
+        assert_eq!(
+            parse_default_gateway(
+                "default via 192.168.1.1 dev enp3s0 proto dhcp src 192.168.1.20 metric 100\n"
+            ),
+            Some("192.168.1.1".to_string())
+        );
+        assert_eq!(parse_default_gateway(""), None);
+
+        assert_eq!(
+            split_probe_target("192.168.1.1").unwrap(),
+            ("192.168.1.1", 80)
+        );
+        assert_eq!(split_probe_target("nas.lan:445").unwrap(), ("nas.lan", 445));
+        assert_eq!(split_probe_target("fe80::1").unwrap(), ("fe80::1", 80));
+        assert!(split_probe_target("10.0.0.1; reboot").is_err());
+        assert!(split_probe_target("10.0.0.1:http").is_err());
+    }
+
+    #[test]
+    fn test_probe_app_egress_with_mock() {
+        let agent = |vm: &str, command: serde_json::Value| {
+            format!("virsh qemu-agent-command {} {}", vm, command)
+        };
+        let exec = serde_json::json!({
+            "execute": "guest-exec",
+            "arguments": {
+                "path": "env",
+                "arg": [
+                    "LC_ALL=C",
+                    "timeout",
+                    "3",
+                    "bash",
+                    "-c",
+                    "exec 3<>\"/dev/tcp/$0/$1\"",
+                    "192.168.1.1",
+                    "80"
+                ],
+                "capture-output": true
+            }
+        });
+        let status = serde_json::json!({
+            "execute": "guest-exec-status",
+            "arguments": { "pid": 42 }
+        });
+        let ping = serde_json::json!({ "execute": "guest-ping" });
+        let runner_with_stderr = |exit_code: i32, stderr: &str| {
+            let err_data =
+                base64::Engine::encode(&base64::engine::general_purpose::STANDARD, stderr);
+            MockRunner::new()
+                .on(&agent("work-app-1", ping.clone()), r#"{"return":{}}"#)
+                .on(
+                    &agent("work-app-1", exec.clone()),
+                    r#"{"return":{"pid":42}}"#,
+                )
+                .on(
+                    &agent("work-app-1", status.clone()),
+                    &format!(
+                        r#"{{"return":{{"exited":true,"exitcode":{},"err-data":"{}"}}}}"#,
+                        exit_code, err_data
+                    ),
+                )
+        };
+        let runner = |exit_code: i32| runner_with_stderr(exit_code, "");
+
+        let leaking = mock_adapter(runner(0));
+        assert!(leaking
+            .probe_app_egress("work-app-1", "192.168.1.1")
+            .unwrap());
+
+        // timeout(1) exits 124 when the connect hangs
+        let isolated = mock_adapter(runner(124));
+        assert!(!isolated
+            .probe_app_egress("work-app-1", "192.168.1.1")
+            .unwrap());
+
+        // A closed port still answers, so the LAN host was reached
+        let refused = mock_adapter(runner_with_stderr(
+            1,
+            "bash: connect: Connection refused\nbash: line 1: /dev/tcp/192.168.1.1/80: Connection refused\n",
+        ));
+        assert!(refused
+            .probe_app_egress("work-app-1", "192.168.1.1")
+            .unwrap());
+
+        let no_route = mock_adapter(runner_with_stderr(1, "bash: connect: No route to host\n"));
+        assert!(!no_route
+            .probe_app_egress("work-app-1", "192.168.1.1")
+            .unwrap());
+
+        // Any other failure says nothing about isolation
+        let unknown = mock_adapter(runner_with_stderr(1, "bash: unexpected\n"));
+        assert!(unknown
+            .probe_app_egress("work-app-1", "192.168.1.1")
+            .is_err());
+
+        let no_bash = mock_adapter(runner(127));
+        assert!(no_bash
+            .probe_app_egress("work-app-1", "192.168.1.1")
+            .is_err());
+
+        // No guest agent in the VM
+        let no_agent = mock_adapter(MockRunner::new());
+        assert!(no_agent
+            .probe_app_egress("work-app-1", "192.168.1.1")
+            .is_err());
+    }
+
+    #[test]
+    fn test_wait_for_proxy_mount() {
+        let ping = "virsh qemu-agent-command work-gw {\"execute\":\"guest-ping\"}";
+        let exec = serde_json::json!({
+            "execute": "guest-exec",
+            "arguments": {
+                "path": "test",
+                "arg": ["-f", "/proxy/proxy.conf"],
+                "capture-output": true
+            }
+        });
+        let status = serde_json::json!({
+            "execute": "guest-exec-status",
+            "arguments": { "pid": 42 }
+        });
+        let adapter = mock_adapter(
+            MockRunner::new()
+                .on(ping, r#"{"return":{}}"#)
+                .on(
+                    &format!("virsh qemu-agent-command work-gw {}", exec),
+                    r#"{"return":{"pid":42}}"#,
+                )
+                .on(
+                    &format!("virsh qemu-agent-command work-gw {}", status),
+                    r#"{"return":{"exited":true,"exitcode":0}}"#,
+                ),
+        );
+        assert_eq!(
+            adapter
+                .wait_for_proxy_mount("work-gw", Duration::from_secs(60))
+                .unwrap(),
+            ProxyMountStatus::Mounted
+        );
+
+        // Gives up once the timeout passes without an agent
+        let no_agent = mock_adapter(MockRunner::new());
+        assert_eq!(
+            no_agent
+                .wait_for_proxy_mount("work-gw", Duration::ZERO)
+                .unwrap(),
+            ProxyMountStatus::AgentUnavailable
+        );
+    }
+
+    #[test]
+    fn test_guest_read_file_with_mock() {
+        let agent =
+            |command: serde_json::Value| format!("virsh qemu-agent-command work-gw {}", command);
+        let seek = |offset: u64, whence: &str| {
+            agent(serde_json::json!({
+                "execute": "guest-file-seek",
+                "arguments": { "handle": 7, "offset": offset, "whence": whence }
+            }))
+        };
+        let open = agent(serde_json::json!({
+            "execute": "guest-file-open",
+            "arguments": { "path": "/var/log/x.log", "mode": "r" }
+        }));
+        let read = agent(serde_json::json!({
+            "execute": "guest-file-read",
+            "arguments": { "handle": 7, "count": 10 }
+        }));
+        let close = agent(serde_json::json!({
+            "execute": "guest-file-close",
+            "arguments": { "handle": 7 }
+        }));
+        // "line1\nline2\nline3\n" is 18 bytes; the last 10 start inside line2
+        let tail =
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, "ne2\nline3\n");
+        let runner = Arc::new(
+            MockRunner::new()
+                .on(
+                    &agent(serde_json::json!({ "execute": "guest-ping" })),
+                    r#"{"return":{}}"#,
+                )
+                .on(&open, r#"{"return":7}"#)
+                .on(&seek(0, "end"), r#"{"return":{"position":18,"eof":true}}"#)
+                .on(&seek(8, "set"), r#"{"return":{"position":8,"eof":false}}"#)
+                .on(
+                    &read,
+                    &format!(
+                        r#"{{"return":{{"count":10,"buf-b64":"{}","eof":true}}}}"#,
+                        tail
+                    ),
+                )
+                .on(&close, r#"{"return":{}}"#),
+        );
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        assert_eq!(
+            adapter
+                .guest_read_file("work-gw", "/var/log/x.log", 10)
+                .unwrap(),
+            "line3\n"
+        );
+        assert!(runner.calls().contains(&close));
+
+        // A file the agent cannot open is an error
+        assert!(adapter
+            .guest_read_file("work-gw", "/etc/missing", 10)
+            .is_err());
+        let no_agent = mock_adapter(MockRunner::new());
+        let err = no_agent
+            .guest_read_file("work-gw", "/var/log/x.log", 10)
+            .unwrap_err();
+        assert!(err.to_string().contains("Guest agent is not responding"));
+    }
+
+    #[test]
+    fn test_get_image_users_with_mock() {
+        let disk_xml = |path: &str| {
+            format!(
+                "<domain>\n  <devices>\n    <disk type='file' device='disk'>\n      <source file='{}'/>\n    </disk>\n  </devices>\n</domain>\n",
+                path
+            )
+        };
+        let adapter = mock_adapter(
+            MockRunner::new()
+                .on("virsh list --all --name", "work-gw\nwork-app-1\nbank-gw\n")
+                .on("virsh dumpxml work-gw", &disk_xml("/images/work-gw.qcow2"))
+                .on(
+                    "virsh dumpxml work-app-1",
+                    &disk_xml("/images/work-app-1.qcow2"),
+                )
+                .on("virsh dumpxml bank-gw", &disk_xml("/images/other.qcow2"))
+                .on(
+                    "qemu-img info /images/work-gw.qcow2",
+                    "backing file: /images/debian-12.qcow2\n",
+                )
+                .on(
+                    "qemu-img info /images/work-app-1.qcow2",
+                    "backing file: /images/debian-12.qcow2\n",
+                )
+                .on("qemu-img info /images/other.qcow2", "file format: qcow2\n")
+                .on("virsh dominfo work-gw", DOMINFO_RUNNING)
+                .on("virsh dominfo work-app-1", "State: shut off\n"),
+        );
+
+        let users = adapter
+            .get_image_users(Path::new("/images/debian-12.qcow2"))
+            .unwrap();
+        let states: Vec<(&str, VmState)> = users
+            .iter()
+            .map(|vm| (vm.name.as_str(), vm.state))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                ("work-app-1", VmState::ShutOff),
+                ("work-gw", VmState::Running)
+            ]
+        );
+        assert!(adapter
+            .get_image_users(Path::new("/images/unused.qcow2"))
+            .unwrap()
+            .is_empty());
     }
 }
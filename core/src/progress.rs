@@ -0,0 +1,138 @@
+//! Step-by-step progress of a multi-step operation
+//!
+//! An operation calls [`StepReporter::begin`] as it starts each step and
+//! [`StepReporter::complete`] or [`StepReporter::fail`] when it ends; the UI only
+//! has to render [`StepReporter::steps`]. Starting a step completes the one
+//! before it, so a `progress` callback that reports one line per step can feed
+//! `begin` directly.
+
+use std::fmt;
+
+/// Where a step stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepState {
+    Running,
+    Done,
+    Failed,
+    /// A remark between steps, e.g. a warning from a check; never runs or fails
+    Note,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub message: String,
+    pub state: StepState,
+}
+
+/// Records the steps of one run of an operation
+#[derive(Debug, Clone, Default)]
+pub struct StepReporter {
+    steps: Vec<Step>,
+    error: Option<String>,
+}
+
+impl StepReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a step, completing the one still running
+    pub fn begin(&mut self, message: impl Into<String>) {
+        self.complete();
+        self.steps.push(Step {
+            message: message.into(),
+            state: StepState::Running,
+        });
+    }
+
+    /// Mark the running step done; does nothing if none is running
+    pub fn complete(&mut self) {
+        if let Some(step) = self.running_mut() {
+            step.state = StepState::Done;
+        }
+    }
+
+    /// Mark the running step failed and record why the operation stopped
+    pub fn fail(&mut self, error: impl fmt::Display) {
+        if let Some(step) = self.running_mut() {
+            step.state = StepState::Failed;
+        }
+        self.error = Some(error.to_string());
+    }
+
+    /// Add a line that isn't a step of its own; a running step keeps running
+    pub fn note(&mut self, message: impl Into<String>) {
+        self.steps.push(Step {
+            message: message.into(),
+            state: StepState::Note,
+        });
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Why the operation failed, once `fail` was called
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Forget all steps, to start a new run
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn running_mut(&mut self) -> Option<&mut Step> {
+        self.steps
+            .iter_mut()
+            .rev()
+            .find(|step| step.state == StepState::Running)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn states(reporter: &StepReporter) -> Vec<StepState> {
+        reporter.steps().iter().map(|step| step.state).collect()
+    }
+
+    #[test]
+    fn test_step_reporter_sequence() {
+        let mut reporter = StepReporter::new();
+        reporter.begin("Validating configuration...");
+        assert_eq!(states(&reporter), vec![StepState::Running]);
+
+        reporter.begin("Creating network...");
+        reporter.note("⚠ Template is world-unreadable");
+        reporter.begin("Creating gateway VM...");
+        reporter.complete();
+        reporter.complete();
+        assert_eq!(
+            states(&reporter),
+            vec![
+                StepState::Done,
+                StepState::Done,
+                StepState::Note,
+                StepState::Done
+            ]
+        );
+        assert!(!reporter.is_failed());
+
+        reporter.begin("Starting gateway...");
+        reporter.note("Rolled back network 'work-inet'");
+        reporter.fail("domain not found");
+        assert_eq!(reporter.steps()[4].state, StepState::Failed);
+        assert_eq!(reporter.steps()[5].state, StepState::Note);
+        assert_eq!(reporter.error(), Some("domain not found"));
+
+        reporter.clear();
+        assert!(reporter.steps().is_empty());
+        assert!(!reporter.is_failed());
+    }
+}
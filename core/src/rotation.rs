@@ -0,0 +1,301 @@
+//! Scheduled rotation of a role's upstream proxy
+//!
+//! A role can carry a pool of hops and an interval. Time is cut into slots of
+//! `interval_secs`; each slot maps to one hop from the pool, so the choice is a
+//! pure function of the clock and survives app restarts. The app owns the timer
+//! and calls into here when a slot boundary passes.
+
+use crate::{GatewayMode, ProxyConfig, ProxyConfigBuilder, ProxyHop, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Shortest interval accepted; reapplying the chain more often than this is just churn
+pub const MIN_ROTATION_INTERVAL_SECS: u64 = 60;
+
+/// How the next hop is picked from the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationStrategy {
+    #[default]
+    RoundRobin,
+    /// Shuffled order each pass through the pool, never the same hop twice in a row
+    Random,
+}
+
+/// Rotation settings stored in role metadata
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RotationPolicy {
+    pub interval_secs: u64,
+    pub strategy: RotationStrategy,
+    /// Candidate exit hops; their `index` is rewritten when applied
+    pub pool: Vec<ProxyHop>,
+}
+
+impl RotationPolicy {
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.interval_secs < MIN_ROTATION_INTERVAL_SECS {
+            return Err(format!(
+                "Rotation interval must be at least {} seconds",
+                MIN_ROTATION_INTERVAL_SECS
+            ));
+        }
+        if self.pool.is_empty() {
+            return Err("Rotation pool needs at least one proxy".to_string());
+        }
+        for hop in &self.pool {
+            hop.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Add imported hops to the pool, skipping any that reach a proxy already
+    /// in it (see [`ProxyHop::connection_key`]); returns how many were added
+    pub fn extend_pool(&mut self, hops: Vec<ProxyHop>) -> usize {
+        let before = self.pool.len();
+        for hop in hops {
+            let key = hop.connection_key();
+            if !self.pool.iter().any(|known| known.connection_key() == key) {
+                self.pool.push(hop);
+            }
+        }
+        self.pool.len() - before
+    }
+
+    /// The time slot `now` falls in; the active hop changes when this does
+    pub fn slot(&self, now: DateTime<Utc>) -> i64 {
+        now.timestamp().div_euclid(self.interval_secs.max(1) as i64)
+    }
+
+    fn pick(&self, slot: i64) -> usize {
+        let len = self.pool.len();
+        match self.strategy {
+            RotationStrategy::RoundRobin => slot.rem_euclid(len as i64) as usize,
+            // With two hops, "never the same twice" leaves only alternation
+            RotationStrategy::Random if len <= 2 => slot.rem_euclid(len as i64) as usize,
+            RotationStrategy::Random => {
+                let cycle = slot.div_euclid(len as i64);
+                let position = slot.rem_euclid(len as i64) as usize;
+                let mut order = shuffled(len, cycle);
+                // Don't repeat the previous cycle's last hop across the boundary.
+                // Swapping the first two never moves the last entry, since len > 2.
+                if order[0] == shuffled(len, cycle - 1)[len - 1] {
+                    order.swap(0, 1);
+                }
+                order[position]
+            }
+        }
+    }
+}
+
+/// A permutation of `0..len` derived from `seed` (Fisher-Yates)
+fn shuffled(len: usize, seed: i64) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..len).collect();
+    let mut state = seed as u64;
+    for i in (1..len).rev() {
+        state = splitmix64(state);
+        order.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+    order
+}
+
+/// The hop that should be active at `now`, or `None` for an empty pool
+pub fn next_active_hop(policy: &RotationPolicy, now: DateTime<Utc>) -> Option<ProxyHop> {
+    if policy.pool.is_empty() {
+        return None;
+    }
+    Some(policy.pool[policy.pick(policy.slot(now))].clone())
+}
+
+/// Make `hop` the exit of the chain, keeping any fixed hops in front of it
+pub fn apply_rotation(config: &mut ProxyConfig, mut hop: ProxyHop) {
+    config.hops.pop();
+    hop.index = config.hops.len() as u8 + 1;
+    config.hops.push(hop);
+}
+
+/// Rewrite a role's proxy.conf so the hop due at `now` is the exit.
+///
+/// Returns the hop written, or `None` when the pool is empty or the role isn't
+/// in proxy-chain mode.
+pub fn rotate_role_proxy(
+    role_dir: &Path,
+    role: &str,
+    policy: &RotationPolicy,
+    now: DateTime<Utc>,
+) -> Result<Option<ProxyHop>> {
+    let content = fs::read_to_string(role_dir.join("proxy.conf"))?;
+    let mut config = ProxyConfigBuilder::parse_proxy_conf(&content, role);
+    if config.gateway_mode != GatewayMode::ProxyChain {
+        return Ok(None);
+    }
+    let Some(hop) = next_active_hop(policy, now) else {
+        return Ok(None);
+    };
+    apply_rotation(&mut config, hop.clone());
+    ProxyConfigBuilder::write_proxy_conf(&config, role_dir)?;
+    Ok(Some(hop))
+}
+
+/// Stateless 64-bit mixer so a slot number maps to a well-spread index
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProxyType;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn policy(strategy: RotationStrategy, size: usize) -> RotationPolicy {
+        RotationPolicy {
+            interval_secs: 600,
+            strategy,
+            pool: (0..size)
+                .map(|i| ProxyHop::new(1, ProxyType::Socks5, format!("10.0.0.{}", i), 1080))
+                .collect(),
+        }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let policy = policy(RotationStrategy::RoundRobin, 3);
+        let hosts: Vec<String> = (0..6)
+            .map(|slot| next_active_hop(&policy, at(slot * 600 + 5)).unwrap().host)
+            .collect();
+        assert_eq!(
+            hosts,
+            ["10.0.0.0", "10.0.0.1", "10.0.0.2", "10.0.0.0", "10.0.0.1", "10.0.0.2"]
+        );
+
+        // Stable within a slot
+        assert_eq!(
+            next_active_hop(&policy, at(600)).unwrap().host,
+            next_active_hop(&policy, at(1199)).unwrap().host
+        );
+    }
+
+    #[test]
+    fn test_random() {
+        let policy = policy(RotationStrategy::Random, 4);
+        let picks: Vec<String> = (0..200)
+            .map(|slot| next_active_hop(&policy, at(slot * 600)).unwrap().host)
+            .collect();
+
+        assert!(picks.windows(2).all(|w| w[0] != w[1]));
+        for i in 0..4 {
+            assert!(picks.contains(&format!("10.0.0.{}", i)));
+        }
+        // Deterministic for a given time
+        assert_eq!(
+            next_active_hop(&policy, at(12_345)).unwrap().host,
+            next_active_hop(&policy, at(12_345)).unwrap().host
+        );
+
+        // Every hop is used once per pass
+        let mut first_pass = picks[..4].to_vec();
+        first_pass.sort();
+        assert_eq!(first_pass, ["10.0.0.0", "10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+
+        let pair = self::policy(RotationStrategy::Random, 2);
+        let a = next_active_hop(&pair, at(0)).unwrap().host;
+        let b = next_active_hop(&pair, at(600)).unwrap().host;
+        assert_ne!(a, b);
+
+        let single = self::policy(RotationStrategy::Random, 1);
+        assert_eq!(next_active_hop(&single, at(0)).unwrap().host, "10.0.0.0");
+        assert!(next_active_hop(&self::policy(RotationStrategy::Random, 0), at(0)).is_none());
+    }
+
+    #[test]
+    fn test_apply_rotation() {
+        let mut config = ProxyConfig::new("work".to_string(), GatewayMode::ProxyChain);
+        config.add_hop(ProxyHop::new(
+            1,
+            ProxyType::Socks5,
+            "entry".to_string(),
+            1080,
+        ));
+        config.add_hop(ProxyHop::new(
+            2,
+            ProxyType::Socks5,
+            "old-exit".to_string(),
+            1080,
+        ));
+
+        let exit = ProxyHop::new(1, ProxyType::Http, "new-exit".to_string(), 3128);
+        apply_rotation(&mut config, exit);
+        assert_eq!(config.hops.len(), 2);
+        assert_eq!(config.hops[0].host, "entry");
+        assert_eq!(config.hops[1].host, "new-exit");
+        assert_eq!(config.hops[1].index, 2);
+
+        let mut empty = ProxyConfig::new("work".to_string(), GatewayMode::ProxyChain);
+        apply_rotation(
+            &mut empty,
+            ProxyHop::new(5, ProxyType::Socks5, "only".to_string(), 1080),
+        );
+        assert_eq!(empty.hops.len(), 1);
+        assert_eq!(empty.hops[0].index, 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(policy(RotationStrategy::RoundRobin, 2).validate().is_ok());
+        assert!(policy(RotationStrategy::RoundRobin, 0).validate().is_err());
+        let mut short = policy(RotationStrategy::RoundRobin, 2);
+        short.interval_secs = 10;
+        assert!(short.validate().is_err());
+    }
+
+    #[test]
+    fn test_extend_pool() {
+        let mut policy = policy(RotationStrategy::RoundRobin, 2);
+        let csv = "host,port\n10.0.0.1,1080\n10.0.0.9,1080\n10.0.0.9,1080\n10.0.0.9,3128\n";
+        let added = policy.extend_pool(ProxyHop::parse_csv(csv).unwrap());
+        // A pool isn't a chain, so there is no hop limit, only duplicates are dropped
+        assert_eq!(added, 2);
+        assert_eq!(policy.pool.len(), 4);
+        assert_eq!(policy.pool[3].port, 3128);
+    }
+
+    #[test]
+    fn test_rotate_role_proxy() {
+        let dir = tempdir().unwrap();
+        let mut config = ProxyConfig::new("work".to_string(), GatewayMode::ProxyChain);
+        config.add_hop(ProxyHop::new(
+            1,
+            ProxyType::Socks5,
+            "entry".to_string(),
+            1080,
+        ));
+        ProxyConfigBuilder::write_proxy_conf(&config, dir.path()).unwrap();
+
+        let policy = policy(RotationStrategy::RoundRobin, 3);
+        let hop = rotate_role_proxy(dir.path(), "work", &policy, at(600))
+            .unwrap()
+            .unwrap();
+        assert_eq!(hop.host, "10.0.0.1");
+
+        let conf = fs::read_to_string(dir.path().join("proxy.conf")).unwrap();
+        assert!(conf.contains("PROXY_COUNT=1"));
+        assert!(conf.contains("PROXY_1_HOST=10.0.0.1"));
+        assert!(!conf.contains("entry"));
+
+        let vpn = ProxyConfig::new("vpn".to_string(), GatewayMode::WireGuard);
+        ProxyConfigBuilder::write_proxy_conf(&vpn, dir.path()).unwrap();
+        assert!(rotate_role_proxy(dir.path(), "vpn", &policy, at(0))
+            .unwrap()
+            .is_none());
+    }
+}
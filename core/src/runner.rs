@@ -0,0 +1,398 @@
+//! Command execution behind a trait, so adapter logic can run against canned output
+
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default cap on captured output per stream (4 MiB)
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+
+/// Output from a command execution
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+/// Runs a CLI command to completion and captures its output
+pub trait CommandRunner: Send + Sync + std::fmt::Debug {
+    /// Run `cmd` with `args`, keeping at most `max_output_bytes` of each stream
+    fn run(&self, cmd: &str, args: &[&str], max_output_bytes: usize) -> Result<CommandOutput>;
+
+    /// Like [`run`](Self::run), but kill the command and fail with
+    /// [`Error::Timeout`] once it has run for `timeout`.
+    ///
+    /// Runners that never block, like [`MockRunner`], can keep this default.
+    fn run_with_timeout(
+        &self,
+        cmd: &str,
+        args: &[&str],
+        max_output_bytes: usize,
+        _timeout: Duration,
+    ) -> Result<CommandOutput> {
+        self.run(cmd, args, max_output_bytes)
+    }
+
+    /// Like [`run`](Self::run), but write all of `input` to the command's stdin,
+    /// calling `on_fed` with the number of bytes written so far.
+    ///
+    /// The default reads `input` through and then runs the command without it,
+    /// which is enough for runners that answer from canned output.
+    fn run_with_input(
+        &self,
+        cmd: &str,
+        args: &[&str],
+        max_output_bytes: usize,
+        input: &mut dyn Read,
+        on_fed: &mut dyn FnMut(u64),
+    ) -> Result<CommandOutput> {
+        feed(input, &mut std::io::sink(), on_fed)?;
+        self.run(cmd, args, max_output_bytes)
+    }
+}
+
+/// Spawns real processes (no shell involved).
+///
+/// Children run with `LC_ALL=C`: callers recognize virsh and qemu-img failures by
+/// their English messages, which a translated desktop session would otherwise
+/// change. pkexec keeps `LC_ALL`, so privileged commands are covered too.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemRunner;
+
+/// How often a command with a timeout is polled for exit
+const WAIT_POLL: Duration = Duration::from_millis(50);
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, cmd: &str, args: &[&str], max_output_bytes: usize) -> Result<CommandOutput> {
+        self.spawn_and_wait(cmd, args, max_output_bytes, None, None)
+    }
+
+    fn run_with_timeout(
+        &self,
+        cmd: &str,
+        args: &[&str],
+        max_output_bytes: usize,
+        timeout: Duration,
+    ) -> Result<CommandOutput> {
+        self.spawn_and_wait(cmd, args, max_output_bytes, Some(timeout), None)
+    }
+
+    fn run_with_input(
+        &self,
+        cmd: &str,
+        args: &[&str],
+        max_output_bytes: usize,
+        input: &mut dyn Read,
+        on_fed: &mut dyn FnMut(u64),
+    ) -> Result<CommandOutput> {
+        self.spawn_and_wait(cmd, args, max_output_bytes, None, Some((input, on_fed)))
+    }
+}
+
+/// Stdin for a command, with a callback told how much of it was written so far
+type Input<'a> = (&'a mut dyn Read, &'a mut dyn FnMut(u64));
+
+impl SystemRunner {
+    fn spawn_and_wait(
+        &self,
+        cmd: &str,
+        args: &[&str],
+        max_output_bytes: usize,
+        timeout: Option<Duration>,
+        input: Option<Input>,
+    ) -> Result<CommandOutput> {
+        let cmd_error = |e: std::io::Error| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                Error::CommandNotFound(cmd.to_string())
+            } else {
+                Error::Command {
+                    cmd: format!("{} {}", cmd, args.join(" ")),
+                    message: e.to_string(),
+                }
+            }
+        };
+
+        let mut child = Command::new(cmd)
+            .args(args)
+            .env("LC_ALL", "C")
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(cmd_error)?;
+
+        // Drain both streams on their own threads so a chatty stream can't fill its
+        // pipe and stall the child, and so the wait below can give up on time
+        let stdout_reader = drain(child.stdout.take(), max_output_bytes);
+        let stderr_reader = drain(child.stderr.take(), max_output_bytes);
+
+        // Closing stdin once everything is written is what lets the child finish
+        let fed = match (input, child.stdin.take()) {
+            (Some((input, on_fed)), Some(mut stdin)) => feed(input, &mut stdin, on_fed),
+            _ => Ok(()),
+        };
+
+        let status = match timeout {
+            None => child.wait().map_err(cmd_error)?,
+            Some(timeout) => {
+                let deadline = Instant::now() + timeout;
+                loop {
+                    if let Some(status) = child.try_wait().map_err(cmd_error)? {
+                        break status;
+                    }
+                    if Instant::now() >= deadline {
+                        child.kill().ok();
+                        child.wait().ok();
+                        // Grandchildren may still hold the pipes, so the readers are left behind
+                        return Err(Error::Timeout {
+                            cmd: format!("{} {}", cmd, args.join(" ")),
+                            secs: timeout.as_secs(),
+                        });
+                    }
+                    std::thread::sleep(WAIT_POLL);
+                }
+            }
+        };
+        let stdout = stdout_reader.join().unwrap_or_default();
+        let stderr = stderr_reader.join().unwrap_or_default();
+        fed.map_err(cmd_error)?;
+
+        Ok(CommandOutput {
+            exit_code: status.code().unwrap_or(-1),
+            stdout: stdout.into_string(),
+            stderr: stderr.into_string(),
+        })
+    }
+}
+
+/// Test double that answers from canned output and records every call.
+///
+/// Commands are matched on the full command line (`"virsh dominfo work-gw"`).
+/// Anything without a canned answer exits 1 with an empty stdout.
+#[derive(Debug, Default)]
+pub struct MockRunner {
+    responses: Mutex<HashMap<String, CommandOutput>>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl MockRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `command_line` with exit code 0 and `stdout`
+    pub fn on(self, command_line: &str, stdout: &str) -> Self {
+        self.on_output(
+            command_line,
+            CommandOutput {
+                exit_code: 0,
+                stdout: stdout.to_string(),
+                stderr: String::new(),
+            },
+        )
+    }
+
+    /// Answer `command_line` with a full output, e.g. a failure
+    pub fn on_output(self, command_line: &str, output: CommandOutput) -> Self {
+        lock(&self.responses).insert(command_line.to_string(), output);
+        self
+    }
+
+    /// Command lines run so far, in call order
+    pub fn calls(&self) -> Vec<String> {
+        lock(&self.calls).clone()
+    }
+}
+
+impl CommandRunner for MockRunner {
+    fn run(&self, cmd: &str, args: &[&str], _max_output_bytes: usize) -> Result<CommandOutput> {
+        let line = std::iter::once(cmd)
+            .chain(args.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" ");
+        lock(&self.calls).push(line.clone());
+        Ok(lock(&self.responses)
+            .get(&line)
+            .cloned()
+            .unwrap_or(CommandOutput {
+                exit_code: 1,
+                stdout: String::new(),
+                stderr: format!("no canned output for: {}", line),
+            }))
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The first bytes of a stream, plus how many were dropped past the cap
+#[derive(Debug, Default)]
+struct BoundedCapture {
+    data: Vec<u8>,
+    omitted: u64,
+}
+
+impl BoundedCapture {
+    fn into_string(self) -> String {
+        let mut text = String::from_utf8_lossy(&self.data).to_string();
+        if self.omitted > 0 {
+            if !text.ends_with('\n') && !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&format!(
+                "[output truncated: {} bytes omitted]",
+                self.omitted
+            ));
+        }
+        text
+    }
+}
+
+/// Read a pipe to EOF on a new thread, keeping only the first `max` bytes
+fn drain<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    max: usize,
+) -> std::thread::JoinHandle<BoundedCapture> {
+    std::thread::spawn(move || pipe.map(|pipe| read_bounded(pipe, max)).unwrap_or_default())
+}
+
+/// Copy `input` to `output` in 1 MiB chunks, reporting the running total after each
+fn feed(
+    input: &mut dyn Read,
+    output: &mut dyn std::io::Write,
+    on_fed: &mut dyn FnMut(u64),
+) -> std::io::Result<()> {
+    let mut buf = vec![0u8; 1024 * 1024];
+    let mut fed = 0;
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        output.write_all(&buf[..n])?;
+        fed += n as u64;
+        on_fed(fed);
+    }
+}
+
+/// Read a stream to EOF, keeping only the first `max` bytes
+fn read_bounded(mut reader: impl Read, max: usize) -> BoundedCapture {
+    let mut capture = BoundedCapture::default();
+    let mut buf = [0u8; 8192];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                let keep = n.min(max.saturating_sub(capture.data.len()));
+                capture.data.extend_from_slice(&buf[..keep]);
+                capture.omitted += (n - keep) as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+    capture
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_bounded() {
+        let small = read_bounded(&b"hello"[..], 16);
+        assert_eq!(small.omitted, 0);
+        assert_eq!(small.into_string(), "hello");
+
+        let large = read_bounded(std::io::repeat(b'x').take(100_000), 1000);
+        assert_eq!(large.data.len(), 1000);
+        assert_eq!(large.omitted, 99_000);
+        assert!(large
+            .into_string()
+            .ends_with("\n[output truncated: 99000 bytes omitted]"));
+    }
+
+    #[test]
+    fn test_system_runner_bounds_large_output() {
+        // 8 MiB on stdout, more than a pipe buffer, must not block or be kept in full
+        let output = SystemRunner
+            .run("head", &["-c", "8388608", "/dev/zero"], 1024)
+            .unwrap();
+        assert!(output.success());
+        assert!(output.stdout.len() < 1100);
+        assert!(output
+            .stdout
+            .ends_with("[output truncated: 8387584 bytes omitted]"));
+    }
+
+    #[test]
+    fn test_system_runner_timeout() {
+        let started = Instant::now();
+        let err = SystemRunner
+            .run_with_timeout("sleep", &["30"], 1024, Duration::from_millis(200))
+            .unwrap_err();
+        assert!(matches!(err, Error::Timeout { ref cmd, .. } if cmd == "sleep 30"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let output = SystemRunner
+            .run_with_timeout("echo", &["done"], 1024, Duration::from_secs(10))
+            .unwrap();
+        assert!(output.success());
+        assert_eq!(output.stdout, "done\n");
+    }
+
+    #[test]
+    fn test_system_runner_untranslated_messages() {
+        let output = SystemRunner
+            .run("sh", &["-c", "echo $LC_ALL"], 1024)
+            .unwrap();
+        assert_eq!(output.stdout, "C\n");
+    }
+
+    #[test]
+    fn test_system_runner_input() {
+        let data = vec![7u8; 3 * 1024 * 1024 / 2];
+        let mut reports = Vec::new();
+        let output = SystemRunner
+            .run_with_input("wc", &["-c"], 1024, &mut &data[..], &mut |fed| {
+                reports.push(fed)
+            })
+            .unwrap();
+        assert_eq!(output.stdout.trim(), data.len().to_string());
+        assert_eq!(reports, [1024 * 1024, data.len() as u64]);
+    }
+
+    #[test]
+    fn test_mock_runner() {
+        let runner = MockRunner::new().on("virsh net-info work-inet", "Active: yes\n");
+        let hit = runner
+            .run("virsh", &["net-info", "work-inet"], 1024)
+            .unwrap();
+        assert!(hit.success());
+        assert_eq!(hit.stdout, "Active: yes\n");
+
+        let miss = runner.run("virsh", &["net-info", "other"], 1024).unwrap();
+        assert!(!miss.success());
+        assert_eq!(
+            runner.calls(),
+            ["virsh net-info work-inet", "virsh net-info other"]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Fixed timestamp formats
+//!
+//! Every format here is purely numeric, so the output never depends on the
+//! user's locale. Names, file paths and displayed times all go through this
+//! module so a stamp written in one place can be parsed back in another.
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use std::fmt::Display;
+
+/// Stamp used in VM names and file names, e.g. `20240101-120000`
+pub const STAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// Calendar date, e.g. `2024-01-01`
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+/// Date and time to the second, e.g. `2024-01-01 12:00:00`
+pub const DATE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Date and time to the minute, e.g. `2024-01-01 12:00`
+pub const DATE_TIME_MINUTES_FORMAT: &str = "%Y-%m-%d %H:%M";
+/// Time of day, e.g. `12:00:00`
+pub const CLOCK_FORMAT: &str = "%H:%M:%S";
+
+/// A name/file stamp for the current local time
+pub fn now_stamp() -> String {
+    stamp(&Local::now())
+}
+
+pub fn stamp<Tz: TimeZone>(time: &DateTime<Tz>) -> String
+where
+    Tz::Offset: Display,
+{
+    time.format(STAMP_FORMAT).to_string()
+}
+
+/// Read back a stamp made by [`stamp`]; anything else is `None`
+pub fn parse_stamp(s: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, STAMP_FORMAT).ok()
+}
+
+pub fn date<Tz: TimeZone>(time: &DateTime<Tz>) -> String
+where
+    Tz::Offset: Display,
+{
+    time.format(DATE_FORMAT).to_string()
+}
+
+pub fn date_time<Tz: TimeZone>(time: &DateTime<Tz>) -> String
+where
+    Tz::Offset: Display,
+{
+    time.format(DATE_TIME_FORMAT).to_string()
+}
+
+pub fn date_time_minutes<Tz: TimeZone>(time: &DateTime<Tz>) -> String
+where
+    Tz::Offset: Display,
+{
+    time.format(DATE_TIME_MINUTES_FORMAT).to_string()
+}
+
+pub fn clock<Tz: TimeZone>(time: &DateTime<Tz>) -> String
+where
+    Tz::Offset: Display,
+{
+    time.format(CLOCK_FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_format_strings() {
+        assert_eq!(STAMP_FORMAT, "%Y%m%d-%H%M%S");
+        assert_eq!(DATE_FORMAT, "%Y-%m-%d");
+        assert_eq!(DATE_TIME_FORMAT, "%Y-%m-%d %H:%M:%S");
+        assert_eq!(DATE_TIME_MINUTES_FORMAT, "%Y-%m-%d %H:%M");
+        assert_eq!(CLOCK_FORMAT, "%H:%M:%S");
+
+        let time = Utc.with_ymd_and_hms(2024, 3, 7, 9, 5, 2).unwrap();
+        assert_eq!(stamp(&time), "20240307-090502");
+        assert_eq!(date(&time), "2024-03-07");
+        assert_eq!(date_time(&time), "2024-03-07 09:05:02");
+        assert_eq!(date_time_minutes(&time), "2024-03-07 09:05");
+        assert_eq!(clock(&time), "09:05:02");
+    }
+
+    #[test]
+    fn test_parse_stamp() {
+        let time = Utc.with_ymd_and_hms(2024, 3, 7, 9, 5, 2).unwrap();
+        assert_eq!(parse_stamp(&stamp(&time)), Some(time.naive_utc()));
+        assert!(parse_stamp(&now_stamp()).is_some());
+        assert_eq!(parse_stamp("2024-03-07 09:05:02"), None);
+        assert_eq!(parse_stamp("20241307-090502"), None);
+    }
+}
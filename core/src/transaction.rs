@@ -0,0 +1,597 @@
+//! All-or-nothing role creation
+//!
+//! Creating a role touches libvirt networks, overlay disks, domains and the
+//! role's config directory. `RoleTransaction` records each resource as it is
+//! made; unless the transaction is committed, they are removed again in reverse
+//! order, so a failure at any step leaves the host as it was before.
+
+use crate::{
+    validate_cpuset, validate_gateway_networks, Error, GlobalConfig, Ipv6Subnet, LibvirtAdapter,
+    ProxyConfig, ProxyConfigBuilder, Result, RoleMeta, Template,
+};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Something role creation made that has to go if a later step fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CreatedResource {
+    Network(String),
+    RoleDir(PathBuf),
+    /// A file written into a role directory that already existed
+    File(PathBuf),
+    Overlay(PathBuf),
+    Vm(String),
+}
+
+impl fmt::Display for CreatedResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CreatedResource::Network(name) => write!(f, "network '{}'", name),
+            CreatedResource::RoleDir(path) => write!(f, "role directory '{}'", path.display()),
+            CreatedResource::File(path) => write!(f, "file '{}'", path.display()),
+            CreatedResource::Overlay(path) => write!(f, "overlay disk '{}'", path.display()),
+            CreatedResource::Vm(name) => write!(f, "VM '{}'", name),
+        }
+    }
+}
+
+/// A line of progress from [`create_role`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoleProgress {
+    /// A new step has started
+    Step(String),
+    /// A result, warning or rollback within the current step
+    Note(String),
+}
+
+impl fmt::Display for RoleProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoleProgress::Step(text) | RoleProgress::Note(text) => f.write_str(text),
+        }
+    }
+}
+
+/// Tracks created resources and unwinds them unless committed.
+///
+/// Dropping an uncommitted transaction rolls it back, so an early return or a
+/// panic can't leave half a role behind.
+pub struct RoleTransaction<'a> {
+    libvirt: &'a LibvirtAdapter,
+    created: Vec<CreatedResource>,
+}
+
+impl<'a> RoleTransaction<'a> {
+    pub fn new(libvirt: &'a LibvirtAdapter) -> Self {
+        Self {
+            libvirt,
+            created: Vec::new(),
+        }
+    }
+
+    /// Remember a resource so it is removed on rollback
+    pub fn record(&mut self, resource: CreatedResource) {
+        self.created.push(resource);
+    }
+
+    /// Resources recorded so far, oldest first
+    pub fn created(&self) -> &[CreatedResource] {
+        &self.created
+    }
+
+    /// Keep everything; returns what was created
+    pub fn commit(mut self) -> Vec<CreatedResource> {
+        std::mem::take(&mut self.created)
+    }
+
+    /// Remove everything recorded, newest first; returns what was removed
+    pub fn rollback(mut self) -> Vec<CreatedResource> {
+        self.unwind()
+    }
+
+    fn unwind(&mut self) -> Vec<CreatedResource> {
+        let mut undone = Vec::with_capacity(self.created.len());
+        while let Some(resource) = self.created.pop() {
+            match &resource {
+                CreatedResource::Vm(name) => {
+                    // undefine_vm destroys a running domain first
+                    self.libvirt.undefine_vm(name).ok();
+                }
+                CreatedResource::Overlay(path) => {
+                    self.libvirt.delete_overlay_disk(path).ok();
+                }
+                CreatedResource::RoleDir(path) => {
+                    // Only recorded when we made the directory, so everything in it is ours
+                    fs::remove_dir_all(path).ok();
+                }
+                CreatedResource::File(path) => {
+                    fs::remove_file(path).ok();
+                }
+                CreatedResource::Network(name) => {
+                    self.libvirt.destroy_network(name).ok();
+                }
+            }
+            undone.push(resource);
+        }
+        undone
+    }
+}
+
+impl Drop for RoleTransaction<'_> {
+    fn drop(&mut self) {
+        self.unwind();
+    }
+}
+
+/// Everything needed to create a role and its VMs
+pub struct RoleSpec<'a> {
+    /// Normalized role name
+    pub role: String,
+    pub gw_template: &'a Template,
+    /// Also create the role's first app VM from this template
+    pub app_template: Option<&'a Template>,
+    pub proxy_config: ProxyConfig,
+    pub ipv6_subnet: Option<Ipv6Subnet>,
+    /// Files copied into the role directory, e.g. VPN configs picked from elsewhere
+    pub extra_files: Vec<PathBuf>,
+    pub meta: RoleMeta,
+}
+
+/// Create a role's network, config directory, gateway and optional app VM.
+///
+/// Either every step succeeds, or everything created along the way is removed
+/// before the error is returned. `progress` receives a [`RoleProgress::Step`] as
+/// each step starts, and a [`RoleProgress::Note`] for results, warnings and
+/// each resource rolled back.
+pub fn create_role(
+    libvirt: &LibvirtAdapter,
+    config: &GlobalConfig,
+    spec: &RoleSpec,
+    progress: &mut dyn FnMut(RoleProgress),
+) -> Result<Vec<CreatedResource>> {
+    let mut tx = RoleTransaction::new(libvirt);
+    match create_role_steps(&mut tx, config, spec, progress) {
+        Ok(()) => Ok(tx.commit()),
+        Err(e) => {
+            for resource in tx.rollback() {
+                progress(RoleProgress::Note(format!("Rolled back {}", resource)));
+            }
+            Err(e)
+        }
+    }
+}
+
+fn create_role_steps(
+    tx: &mut RoleTransaction,
+    config: &GlobalConfig,
+    spec: &RoleSpec,
+    progress: &mut dyn FnMut(RoleProgress),
+) -> Result<()> {
+    let libvirt = tx.libvirt;
+    let role = &spec.role;
+    let role_dir = config.role_dir(role);
+    let role_net = format!("{}-inet", role);
+    let gw_name = config.naming.gateway_name(role);
+    let lan_net = &config.libvirt.lan_net;
+    let images_dir = &config.libvirt.images_dir;
+
+    if let Some(cpuset) = &spec.meta.cpuset {
+        validate_cpuset(cpuset, libvirt.host_cpu_count()?).map_err(Error::validation)?;
+    }
+    if let Some(app_template) = spec.app_template {
+        app_template.require_disk_image()?;
+    }
+
+    progress(RoleProgress::Step(format!(
+        "Checking LAN network '{}'...",
+        lan_net
+    )));
+    libvirt.ensure_lan_net_exists(lan_net)?;
+
+    let gw_networks = spec.meta.gateway_networks(lan_net, &role_net);
+    validate_gateway_networks(&gw_networks, lan_net, &role_net).map_err(Error::validation)?;
+    for network in &spec.meta.extra_gateway_networks {
+        if !libvirt.network_exists(network)? {
+            return Err(Error::libvirt(format!(
+                "Extra gateway network '{}' does not exist in libvirt",
+                network
+            )));
+        }
+    }
+
+    progress(RoleProgress::Step(format!(
+        "Creating role network '{}'...",
+        role_net
+    )));
+    let created_network =
+        libvirt.ensure_role_network(role, spec.ipv6_subnet.as_ref(), &spec.meta.network_mode)?;
+    if created_network {
+        tx.record(CreatedResource::Network(role_net.clone()));
+        progress(RoleProgress::Note(format!(
+            "Created network '{}'",
+            role_net
+        )));
+    } else {
+        progress(RoleProgress::Note(format!(
+            "Network '{}' already exists",
+            role_net
+        )));
+        if let Some(subnet) = &spec.ipv6_subnet {
+            progress(RoleProgress::Note(format!(
+                "⚠ IPv6 subnet {} was not added to the existing network '{}'",
+                subnet, role_net
+            )));
+        }
+    }
+
+    progress(RoleProgress::Step(
+        "Generating proxy configuration...".to_string(),
+    ));
+    // Files in a directory we made go with it; in an existing one, only the
+    // files that weren't there before are ours to remove
+    let dir_existed = role_dir.exists();
+    if !dir_existed {
+        fs::create_dir_all(&role_dir)?;
+        tx.record(CreatedResource::RoleDir(role_dir.clone()));
+    }
+    let record_new_file = |tx: &mut RoleTransaction, path: PathBuf, existed: bool| {
+        if dir_existed && !existed {
+            tx.record(CreatedResource::File(path));
+        }
+    };
+    for file in &spec.extra_files {
+        if let Some(name) = file.file_name() {
+            let dest = role_dir.join(name);
+            let existed = dest.exists();
+            fs::copy(file, &dest)?;
+            record_new_file(tx, dest.clone(), existed);
+            progress(RoleProgress::Note(format!(
+                "Copied {} to {}",
+                file.display(),
+                dest.display()
+            )));
+        }
+    }
+    let generated: Vec<(PathBuf, bool)> = ["proxy.conf", "apply-proxy.sh"]
+        .iter()
+        .map(|name| {
+            let path = role_dir.join(name);
+            let existed = path.exists();
+            (path, existed)
+        })
+        .collect();
+    let written = ProxyConfigBuilder::write_config_files(&spec.proxy_config, &role_dir);
+    // Record before bailing out, a failed write may have left one of them behind
+    for (path, existed) in generated {
+        if path.exists() {
+            record_new_file(tx, path, existed);
+        }
+    }
+    written?;
+
+    let overlay_path = libvirt.gateway_overlay_path(images_dir, role);
+    if spec.gw_template.install.is_fresh_install() {
+        progress(RoleProgress::Step(format!(
+            "Creating blank {} GB disk...",
+            spec.gw_template.install_disk_gb
+        )));
+        libvirt.create_blank_disk(&overlay_path, spec.gw_template.install_disk_gb)?;
+        tx.record(CreatedResource::Overlay(overlay_path.clone()));
+        // qemu reads the installer ISO too
+        report_disk_permissions(libvirt, &spec.gw_template.path, progress);
+    } else {
+        progress(RoleProgress::Step("Creating overlay disk...".to_string()));
+        libvirt.create_overlay_disk(&spec.gw_template.path, &overlay_path)?;
+        tx.record(CreatedResource::Overlay(overlay_path.clone()));
+        report_disk_permissions(libvirt, &overlay_path, progress);
+    }
+
+    progress(RoleProgress::Step(format!(
+        "Creating gateway VM '{}'...",
+        gw_name
+    )));
+    let ram_mb = spec
+        .gw_template
+        .default_ram_mb
+        .max(config.defaults.gateway_ram_mb);
+    libvirt.create_gateway_vm(
+        &gw_name,
+        &overlay_path,
+        &gw_networks,
+        &role_dir,
+        &spec.gw_template.os_variant,
+        ram_mb,
+        &spec.gw_template.disk_tuning,
+        &spec.gw_template.boot,
+        config.libvirt.share_driver,
+        spec.meta.cpuset.as_deref(),
+        spec.meta.on_crash,
+        spec.gw_template.install,
+        &spec.gw_template.path,
+    )?;
+    tx.record(CreatedResource::Vm(gw_name));
+
+    progress(RoleProgress::Step("Saving role metadata...".to_string()));
+    let mut meta = spec.meta.clone();
+    if !created_network {
+        // Only a network defined here has the requested IPv6 subnet
+        meta.ipv6_subnet = None;
+    }
+    meta.save(&config.cfg.root)?;
+
+    if let Some(app_template) = spec.app_template {
+        progress(RoleProgress::Step("Creating App VM...".to_string()));
+        let app_num = meta.next_app_number();
+        let app_vm_name = meta.app_vm_name(&config.naming, app_num);
+        let app_overlay = libvirt.app_overlay_path(images_dir, role, app_num);
+        libvirt.create_overlay_disk(&app_template.path, &app_overlay)?;
+        tx.record(CreatedResource::Overlay(app_overlay.clone()));
+        report_disk_permissions(libvirt, &app_overlay, progress);
+
+        let app_ram = app_template.default_ram_mb.max(config.defaults.app_ram_mb);
+        libvirt.create_app_vm(
+            &app_vm_name,
+            &app_overlay,
+            &role_net,
+            &app_template.os_variant,
+            app_ram,
+            &app_template.disk_tuning,
+            &app_template.boot,
+            None,
+        )?;
+        tx.record(CreatedResource::Vm(app_vm_name.clone()));
+        meta.save(&config.cfg.root)?;
+        progress(RoleProgress::Note(format!(
+            "✓ Created App VM '{}'",
+            app_vm_name
+        )));
+    }
+
+    Ok(())
+}
+
+/// Warn about images qemu won't be able to open; virt-install only fails on these
+/// once it boots the VM, with a much vaguer error
+fn report_disk_permissions(
+    libvirt: &LibvirtAdapter,
+    overlay: &Path,
+    progress: &mut dyn FnMut(RoleProgress),
+) {
+    match libvirt.check_disk_permissions(overlay) {
+        Ok(issues) => {
+            for issue in issues {
+                progress(RoleProgress::Note(format!("⚠ {}", issue)));
+            }
+        }
+        Err(e) => progress(RoleProgress::Note(format!(
+            "⚠ Could not check disk permissions: {}",
+            e
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::MockRunner;
+    use crate::Error;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rollback_on_failing_step() {
+        let dir = tempdir().unwrap();
+        let role_dir = dir.path().join("work");
+        fs::create_dir_all(&role_dir).unwrap();
+        fs::write(role_dir.join("proxy.conf"), "PROXY_COUNT=0\n").unwrap();
+        let overlay = dir.path().join("work-gw.qcow2");
+        fs::write(&overlay, b"qcow").unwrap();
+
+        let runner = Arc::new(MockRunner::new());
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        let steps: Vec<Box<dyn Fn() -> Result<CreatedResource>>> = vec![
+            Box::new(|| Ok(CreatedResource::Network("work-inet".to_string()))),
+            Box::new(|| Ok(CreatedResource::RoleDir(role_dir.clone()))),
+            Box::new(|| Ok(CreatedResource::Overlay(overlay.clone()))),
+            Box::new(|| Ok(CreatedResource::Vm("work-gw".to_string()))),
+            Box::new(|| Err(Error::libvirt("injected failure"))),
+        ];
+
+        let mut tx = RoleTransaction::new(&adapter);
+        let mut failed = false;
+        for step in &steps {
+            match step() {
+                Ok(resource) => tx.record(resource),
+                Err(_) => {
+                    failed = true;
+                    break;
+                }
+            }
+        }
+        assert!(failed);
+        assert_eq!(tx.created().len(), 4);
+
+        let undone = tx.rollback();
+        assert_eq!(
+            undone,
+            [
+                CreatedResource::Vm("work-gw".to_string()),
+                CreatedResource::Overlay(overlay.clone()),
+                CreatedResource::RoleDir(role_dir.clone()),
+                CreatedResource::Network("work-inet".to_string()),
+            ]
+        );
+        assert!(!overlay.exists());
+        assert!(!role_dir.exists());
+
+        // The VM goes before the network it is attached to
+        let calls = runner.calls();
+        let vm = calls.iter().position(|c| c == "virsh undefine work-gw");
+        let net = calls
+            .iter()
+            .position(|c| c == "virsh net-undefine work-inet");
+        assert!(vm.is_some() && net.is_some());
+        assert!(vm < net);
+    }
+
+    #[test]
+    fn test_commit_and_drop() {
+        let dir = tempdir().unwrap();
+        let kept = dir.path().join("kept");
+        let dropped = dir.path().join("dropped");
+        fs::create_dir_all(&kept).unwrap();
+        fs::create_dir_all(&dropped).unwrap();
+        let adapter = LibvirtAdapter::with_runner(Arc::new(MockRunner::new()));
+
+        let mut tx = RoleTransaction::new(&adapter);
+        tx.record(CreatedResource::RoleDir(kept.clone()));
+        assert_eq!(tx.commit(), [CreatedResource::RoleDir(kept.clone())]);
+        assert!(kept.exists());
+
+        {
+            let mut tx = RoleTransaction::new(&adapter);
+            tx.record(CreatedResource::RoleDir(dropped.clone()));
+        }
+        assert!(!dropped.exists());
+    }
+
+    #[test]
+    fn test_create_role_rolls_back_on_failure() {
+        let dir = tempdir().unwrap();
+        let mut config = GlobalConfig::default();
+        config.cfg.root = dir.path().to_path_buf();
+        config.libvirt.images_dir = dir.path().join("images");
+        // The base image is missing, so the overlay step fails after the network
+        // and role directory have been created
+        let template = Template::new(
+            "gw",
+            "Gateway",
+            dir.path().join("missing.qcow2"),
+            "debian12",
+            crate::RoleKind::ProxyGateway,
+        );
+        let spec = RoleSpec {
+            role: "work".to_string(),
+            gw_template: &template,
+            app_template: None,
+            proxy_config: ProxyConfig::new("work".to_string(), crate::GatewayMode::ProxyChain),
+            ipv6_subnet: None,
+            extra_files: Vec::new(),
+            meta: RoleMeta::new("work".to_string()),
+        };
+        let net_xml = std::env::temp_dir().join("net-work-inet.xml");
+        let runner = Arc::new(
+            MockRunner::new()
+                .on("virsh net-info lan-net", "Active: yes\n")
+                .on(&format!("virsh net-define {}", net_xml.display()), "")
+                .on("virsh net-autostart work-inet", "")
+                .on("virsh net-start work-inet", ""),
+        );
+        let adapter = LibvirtAdapter::with_runner(runner.clone());
+
+        let mut messages = Vec::new();
+        let result = create_role(&adapter, &config, &spec, &mut |m| {
+            messages.push(m.to_string())
+        });
+        assert!(result.is_err());
+        assert!(!dir.path().join("work").exists());
+        assert!(runner
+            .calls()
+            .contains(&"virsh net-undefine work-inet".to_string()));
+        assert_eq!(
+            messages[messages.len() - 2..],
+            [
+                format!(
+                    "Rolled back role directory '{}'",
+                    dir.path().join("work").display()
+                ),
+                "Rolled back network 'work-inet'".to_string(),
+            ]
+        );
+
+        // A reused network keeps its addressing, so the IPv6 subnet is reported as not applied
+        let existing = LibvirtAdapter::with_runner(Arc::new(
+            MockRunner::new()
+                .on("virsh net-info lan-net", "Active: yes\n")
+                .on("virsh net-info work-inet", "Active: yes\n"),
+        ));
+        let spec = RoleSpec {
+            ipv6_subnet: Some(crate::Ipv6Subnet::parse("fd00:1::/64").unwrap()),
+            ..spec
+        };
+        let mut messages = Vec::new();
+        assert!(create_role(&existing, &config, &spec, &mut |m| messages
+            .push(m.to_string()))
+        .is_err());
+        assert!(messages.contains(
+            &"⚠ IPv6 subnet fd00:1::/64 was not added to the existing network 'work-inet'"
+                .to_string()
+        ));
+
+        // Nothing is created when the very first check fails
+        let empty = LibvirtAdapter::with_runner(Arc::new(MockRunner::new()));
+        let mut messages = Vec::new();
+        assert!(create_role(&empty, &config, &spec, &mut |m| messages
+            .push(m.to_string()))
+        .is_err());
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn test_rollback_keeps_existing_role_dir() {
+        let dir = tempdir().unwrap();
+        let mut config = GlobalConfig::default();
+        config.cfg.root = dir.path().to_path_buf();
+        config.libvirt.images_dir = dir.path().join("images");
+        let role_dir = dir.path().join("work");
+        fs::create_dir_all(&role_dir).unwrap();
+        fs::write(role_dir.join("notes.txt"), "mine").unwrap();
+        let vpn = dir.path().join("work.ovpn");
+        fs::write(&vpn, "client\n").unwrap();
+
+        let template = Template::new(
+            "gw",
+            "Gateway",
+            dir.path().join("missing.qcow2"),
+            "debian12",
+            crate::RoleKind::ProxyGateway,
+        );
+        let mut proxy_config = ProxyConfig::new("work".to_string(), crate::GatewayMode::ProxyChain);
+        proxy_config.add_hop(crate::ProxyHop::new(
+            1,
+            crate::ProxyType::Socks5,
+            "10.0.0.1".to_string(),
+            1080,
+        ));
+        let spec = RoleSpec {
+            role: "work".to_string(),
+            gw_template: &template,
+            app_template: None,
+            proxy_config,
+            ipv6_subnet: None,
+            extra_files: vec![vpn],
+            meta: RoleMeta::new("work".to_string()),
+        };
+        let adapter = LibvirtAdapter::with_runner(Arc::new(
+            MockRunner::new()
+                .on("virsh net-info lan-net", "Active: yes\n")
+                .on("virsh net-info work-inet", "Active: yes\n"),
+        ));
+
+        let mut messages = Vec::new();
+        assert!(create_role(&adapter, &config, &spec, &mut |m| messages.push(m)).is_err());
+        assert!(role_dir.join("notes.txt").exists());
+        assert!(!role_dir.join("work.ovpn").exists());
+        assert!(!role_dir.join("proxy.conf").exists());
+        assert!(!role_dir.join("apply-proxy.sh").exists());
+        assert!(messages.contains(&RoleProgress::Step(
+            "Generating proxy configuration...".to_string()
+        )));
+        assert!(messages.contains(&RoleProgress::Note(format!(
+            "Rolled back file '{}'",
+            role_dir.join("proxy.conf").display()
+        ))));
+    }
+}
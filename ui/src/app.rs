@@ -1,15 +1,31 @@
 //! Main application state and logic
 
+use chrono::{DateTime, Utc};
 use eframe::egui;
 use proxy_vm_core::{
-    config::discover_roles, normalize_role_name, validate_role_name, AuthState, EncryptionManager,
-    GatewayMode, GlobalConfig, LibvirtAdapter, OpenVpnConfig, ProxyConfig, ProxyConfigBuilder,
-    ProxyHop, ProxyType, RoleKind, RoleMeta, TemplateRegistry, VmInfo, WireGuardConfig,
+    config::{backup_unreadable_file, discover_roles, roles_using_template},
+    create_role, export_bundle, export_diagnostics, import_bundle, network_role,
+    normalize_role_name, parse_port,
+    proxy_config::PROXYCHAINS_CONF,
+    rotate_role_proxy, running_gateways, timefmt, validate_auth_file, validate_bundle,
+    validate_cpuset, validate_extra_env_key, validate_gateway_networks, validate_lan_net,
+    validate_libvirt_uri, validate_role_subnet_pool, validate_share_dir, ApplyScriptStatus,
+    AuthState, BootOptions, BrokenBacking, BundleLayout, BundleManifest, ChecksumCache, ConfBackup,
+    CrashPolicy, DiskTuning, EncryptionManager, FileLogger, FileStamp, GatewayMode, GlobalConfig,
+    InstallSource, InstanceLock, Ipv6Subnet, LibvirtAdapter, LockAttempt, LockHolder, LogLevel,
+    NamingSection, NetworkInfo, OpenVpnConfig, OpenVpnProto, PlaintextMigration, ProxyConfig,
+    ProxyConfigBuilder, ProxyHop, ProxyMountStatus, ProxyType, RoleDisk, RoleKind, RoleMeta,
+    RoleNetworkMode, RoleProgress, RoleSpec, RotationPolicy, ShareDriver, StartOutcome,
+    StepReporter, StopOutcome, Template, TemplateRegistry, UpstreamCheck, VmInfo, VmInterface,
+    VmKind, WgStatus, WireGuardConfig, MIN_AUTO_REFRESH_SECS,
 };
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
 
+use crate::commands::{self, CommandPalette};
+use crate::ops::OperationQueue;
 use crate::views::{DashboardView, LogsView, SettingsView, TemplatesView, View, WizardView};
 
 /// Authentication screen state
@@ -33,21 +49,165 @@ pub struct AuthViewState {
     pub show_password: bool,
 }
 
-/// Message types for async operations (reserved for future background tasks)
+/// Ordering of role cards on the dashboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoleSort {
+    #[default]
+    Alphabetical,
+    RecentlyModified,
+}
+
+/// Message types for async operations
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum AsyncMessage {
-    VmListRefreshed(Vec<VmInfo>),
+    VmListRefreshed(Result<Vec<VmInfo>, String>),
     RolesDiscovered(Vec<String>),
     OperationSuccess(String),
     OperationError(String),
+    /// One wizard hop was tested; `endpoint` tells whether the hop at `index`
+    /// is still the one that was tested
     ConnectionTestResult {
         index: usize,
+        endpoint: (String, u16),
         success: bool,
         message: String,
     },
+    /// A hop's host answered a ping, or didn't; `host` tells whether the hop
+    /// at `index` still points at the host that was pinged
+    HopPinged {
+        list: HopList,
+        index: usize,
+        host: String,
+        result: Result<std::time::Duration, String>,
+    },
+    WireGuardStatus {
+        vm_name: String,
+        result: Result<WgStatus, String>,
+    },
+    /// A step or note from the role the wizard is creating
+    WizardProgress(RoleProgress),
+    /// The wizard's role was created, or everything it created was rolled back
+    WizardFinished {
+        result: Result<(), String>,
+    },
+    /// A new gateway's /proxy mount was checked once it booted
+    ProxyMountChecked {
+        vm_name: String,
+        result: Result<ProxyMountStatus, String>,
+    },
+    VmInterfaces {
+        vm_name: String,
+        result: Result<Vec<VmInterface>, String>,
+    },
+    DiskChain {
+        vm_name: String,
+        result: Result<usize, String>,
+    },
+    /// Host disk space taken by a role's VMs was measured
+    DiskUsage {
+        role: String,
+        result: Result<u64, String>,
+    },
+    /// Roles and VMs depending on a template were looked up
+    TemplateUsage {
+        id: String,
+        result: Result<Vec<String>, String>,
+    },
+    /// Result of probing a role's app VMs for direct LAN access
+    IsolationProbe {
+        role: String,
+        result: Result<IsolationReport, String>,
+    },
+    /// A queued libvirt mutation finished
+    OperationFinished {
+        level: StatusLevel,
+        message: String,
+    },
+    TemplateDownloadProgress {
+        downloaded: u64,
+        total: Option<u64>,
+    },
+    TemplateDownloaded(PathBuf),
+    TemplateDownloadFailed(String),
+    TemplateHashProgress {
+        path: PathBuf,
+        hashed: u64,
+        total: u64,
+    },
+    /// A template image finished hashing; `stamp` was taken before it started
+    TemplateHashed {
+        path: PathBuf,
+        stamp: FileStamp,
+        result: Result<String, String>,
+    },
+    /// A configuration bundle finished exporting
+    BundleExported(Result<PathBuf, String>),
+    /// A configuration bundle finished importing
+    BundleImported(Result<BundleManifest, String>),
+    /// Role networks left behind by roles that no longer exist
+    LeftoverNetworks(Result<Vec<NetworkInfo>, String>),
+    /// State of every `<role>-inet` network, for the role cards
+    RoleNetworks(Result<Vec<NetworkInfo>, String>),
+    /// One gateway of a "Test all gateways" run was checked
+    GatewayTested {
+        role: String,
+        gateway: String,
+        check: UpstreamCheck,
+    },
+    /// Every gateway of the run has been checked
+    GatewayTestsDone,
+    /// proxychains.conf and the apply-proxy journal, read from a gateway
+    GatewayLogs {
+        gateway: String,
+        proxychains: Result<String, String>,
+        journal: Result<String, String>,
+    },
+}
+
+/// Outcome of a runtime isolation probe for one role
+#[derive(Debug, Clone)]
+pub struct IsolationReport {
+    /// LAN address the app VMs tried to reach
+    pub target: String,
+    /// App VMs that were probed
+    pub checked: Vec<String>,
+    /// App VMs that reached `target` without going through the gateway
+    pub leaking: Vec<String>,
+    /// Running app VMs skipped because their guest agent didn't answer
+    pub skipped: Vec<String>,
 }
 
+/// Progress and results of "Test all gateways"
+#[derive(Debug, Clone, Default)]
+pub struct GatewayTestRun {
+    pub total: usize,
+    /// (role, gateway VM, outcome) in the order the checks finished
+    pub results: Vec<(String, String, UpstreamCheck)>,
+    pub finished: bool,
+}
+
+impl GatewayTestRun {
+    /// Passed, failed and skipped counts
+    pub fn tally(&self) -> (usize, usize, usize) {
+        self.results
+            .iter()
+            .fold((0, 0, 0), |(ok, bad, skip), (_, _, check)| match check {
+                UpstreamCheck::Reachable(_) => (ok + 1, bad, skip),
+                UpstreamCheck::Unreachable(_) => (ok, bad + 1, skip),
+                UpstreamCheck::Skipped(_) => (ok, bad, skip + 1),
+            })
+    }
+}
+
+/// Most of proxychains.conf shown in the gateway logs viewer
+const GATEWAY_LOG_MAX_BYTES: usize = 64 * 1024;
+/// Journal lines of the apply-proxy boot service shown in the gateway logs viewer
+const GATEWAY_JOURNAL_LINES: usize = 200;
+
+/// Dashboard VM states older than this are flagged when auto-refresh is off
+const STALE_AFTER: std::time::Duration = std::time::Duration::from_secs(30);
+
 /// Main application state
 pub struct ProxyVmWizardApp {
     // Authentication
@@ -67,6 +227,30 @@ pub struct ProxyVmWizardApp {
     pub discovered_roles: Vec<String>,
     pub role_vms: HashMap<String, Vec<VmInfo>>,
     pub last_refresh: Option<std::time::Instant>,
+    /// When VM states were last read successfully; `role_vms` is this old
+    pub last_good_refresh: Option<std::time::Instant>,
+    /// The latest refresh failed, so `role_vms` still holds older states
+    pub refresh_failed: bool,
+    pub refresh_in_flight: bool,
+    pub role_sort: RoleSort,
+    pub role_modified: HashMap<String, DateTime<Utc>>,
+    /// Roles whose apply-proxy.sh predates the current generated script
+    pub outdated_scripts: HashMap<String, Option<u32>>,
+    /// Each role's `<role>-inet` network by role; `None` until listed, and a
+    /// role missing from a listed map has no network
+    pub role_networks: Option<Result<HashMap<String, NetworkInfo>, String>>,
+    pub role_networks_in_flight: bool,
+
+    // Proxy rotation: policies from role metadata, and the slot last applied per role
+    pub role_rotations: HashMap<String, RotationPolicy>,
+    /// Gateway crash action per role, from role metadata
+    pub role_crash_policies: HashMap<String, CrashPolicy>,
+    /// Most app VMs per role, from role metadata
+    pub role_max_app_vms: HashMap<String, u32>,
+    /// Networks each role's gateway has beyond LAN and role network, from role metadata
+    pub role_extra_gateway_networks: HashMap<String, Vec<String>>,
+    pub rotation_slots: HashMap<String, i64>,
+    pub last_rotation_check: Option<std::time::Instant>,
 
     // Wizard state
     pub wizard: WizardState,
@@ -80,23 +264,150 @@ pub struct ProxyVmWizardApp {
     // Logs
     pub logs: Vec<LogEntry>,
     pub max_logs: usize,
+    /// On-disk copy of `logs`, also handed to the libvirt adapter for commands
+    pub file_log: Option<Arc<FileLogger>>,
 
-    // Async communication (reserved for future background tasks)
-    #[allow(dead_code)]
+    // Async communication with background tasks
     pub async_tx: Sender<AsyncMessage>,
-    #[allow(dead_code)]
     pub async_rx: Receiver<AsyncMessage>,
 
+    // Serialized queue for libvirt mutations
+    pub ops: OperationQueue,
+
+    // WireGuard handshake status per gateway VM, checked in the background
+    pub wg_status: HashMap<String, Result<WgStatus, String>>,
+    pub wg_checks_in_flight: HashSet<String>,
+
+    // NICs of each gateway VM, listed in the background after a refresh
+    pub gw_interfaces: HashMap<String, Result<Vec<VmInterface>, String>>,
+    pub iface_checks_in_flight: HashSet<String>,
+
+    // Backing chain depth of each gateway/app VM disk; cleared after every operation
+    pub disk_chains: HashMap<String, Result<usize, String>>,
+    pub chain_checks_in_flight: HashSet<String>,
+
+    // Host disk space per role, measured in the background when its card is first drawn;
+    // cleared like `disk_chains`
+    pub disk_usage: HashMap<String, Result<u64, String>>,
+    pub usage_checks_in_flight: HashSet<String>,
+
+    // Issues found by the last "Validate proxy.conf" per role; empty means valid
+    pub conf_validation: HashMap<String, Vec<String>>,
+
+    // Runtime isolation probe results per role
+    pub isolation_status: HashMap<String, Result<IsolationReport, String>>,
+    pub isolation_checks_in_flight: HashSet<String>,
+
+    // Batch upstream test across all running gateways; the modal shows while set
+    pub gateway_tests: Option<GatewayTestRun>,
+
+    // Ctrl+P command palette
+    pub palette: CommandPalette,
+
     // Status
     pub status_message: Option<(String, StatusLevel)>,
     pub prereq_error: Option<String>,
 
+    // Config files that exist but could not be read; never overwritten until backed up
+    pub unreadable_files: Vec<UnreadableFile>,
+    pub show_unreadable_dialog: bool,
+    /// Set after a bundle import; the in-memory config is stale until restart
+    pub restart_required: bool,
+
     // Pending confirmations
     pub pending_role_delete: Option<String>,
+    /// "Keep disks" in the role delete dialog
+    pub delete_keep_disks: bool,
+    /// Disks of the role awaiting delete confirmation, read when the dialog opened
+    pub delete_disks: RoleDeleteDisks,
 
     // Config editor state (for editing role configs from dashboard)
     pub editing_role_config: Option<String>,
     pub config_editor: ConfigEditorState,
+
+    // "Copy gateway config from…" dialog: target role, chosen source, restart after copy,
+    // and the gateway mode of each role with a proxy.conf, read when the dialog opens
+    pub copy_config_target: Option<String>,
+    pub copy_config_source: Option<String>,
+    pub copy_config_restart: bool,
+    pub copy_config_modes: HashMap<String, GatewayMode>,
+
+    /// Read-only view of a role's proxy.conf and apply-proxy.sh
+    pub generated_files: Option<GeneratedFilesView>,
+    /// Files and journal read from a running gateway through its guest agent
+    pub gateway_logs: Option<GatewayLogsView>,
+
+    /// App VM being renamed inline on the dashboard
+    pub renaming_vm: Option<VmRename>,
+    /// "New App VM" dialog, open for one role
+    pub new_app_vm: Option<NewAppVm>,
+
+    /// Held for the app's lifetime so a second instance can tell the config is in use
+    #[allow(dead_code)]
+    instance_lock: Option<InstanceLock>,
+    /// Lock holder found at startup, until the user decides whether to take over
+    lock_conflict: Option<LockHolder>,
+}
+
+/// What deleting a role does to its disks
+#[derive(Debug, Clone, Default)]
+pub struct RoleDeleteDisks {
+    /// The role's own overlays and disposable disks
+    pub disks: Vec<RoleDisk>,
+    /// Base images under those disks, which are kept, each with the other roles
+    /// whose templates point at it
+    pub shared_bases: Vec<(PathBuf, Vec<String>)>,
+}
+
+/// Options for the next app VM of a role
+#[derive(Debug, Clone, Default)]
+pub struct NewAppVm {
+    pub role: String,
+    /// Share a host folder with the VM, mounted in the guest by tag `shared`
+    pub share: bool,
+    pub share_dir: String,
+}
+
+/// New name typed for an app VM, and whether its disk file follows it
+#[derive(Debug, Clone)]
+pub struct VmRename {
+    pub vm: String,
+    pub new_name: String,
+    pub rename_disk: bool,
+}
+
+/// A role's proxy.conf and apply-proxy.sh as they are on disk
+#[derive(Debug, Clone)]
+pub struct GeneratedFilesView {
+    pub role: String,
+    pub tab: GeneratedFile,
+    /// Passwords masked; `Err` holds why it couldn't be read
+    pub proxy_conf: Result<String, String>,
+    /// What apply-proxy.sh will do with proxy.conf as parsed
+    pub apply_plan: Vec<String>,
+    pub apply_script: Result<String, String>,
+    /// apply-proxy.sh is exactly what this version generates for the role
+    pub script_is_generated: bool,
+}
+
+/// What a gateway reports about applying its proxy config; `None` while loading
+#[derive(Debug, Clone)]
+pub struct GatewayLogsView {
+    pub gateway: String,
+    pub proxychains: Option<Result<String, String>>,
+    pub journal: Option<Result<String, String>>,
+}
+
+impl GatewayLogsView {
+    pub fn is_loading(&self) -> bool {
+        self.proxychains.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratedFile {
+    ProxyConf,
+    ApplyScript,
 }
 
 /// State for editing a role's gateway configuration
@@ -108,6 +419,10 @@ pub struct ConfigEditorState {
     pub openvpn_config: OpenVpnConfigEntry,
     pub error: Option<String>,
     pub restart_after_save: bool,
+    /// Newest proxy.conf backup, offered as "last known good"
+    pub last_backup: Option<ConfBackup>,
+    /// Extra `X_*` proxy.conf lines as (key, value) rows; blank keys are dropped on save
+    pub extra_env: Vec<(String, String)>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -119,6 +434,17 @@ pub enum StatusLevel {
     Error,
 }
 
+impl StatusLevel {
+    /// Log file level for entries at this status
+    pub fn log_level(self) -> LogLevel {
+        match self {
+            StatusLevel::Info | StatusLevel::Success => LogLevel::Info,
+            StatusLevel::Warning => LogLevel::Warning,
+            StatusLevel::Error => LogLevel::Error,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LogEntry {
     pub timestamp: chrono::DateTime<chrono::Local>,
@@ -138,6 +464,18 @@ pub struct WizardState {
     pub selected_gw_template_id: Option<String>,
     pub selected_app_template_id: Option<String>,
     pub selected_disp_template_id: Option<String>,
+    pub ipv6_subnet: String,
+    pub ipv6_error: Option<String>,
+    pub network_mode: RoleNetworkMode,
+    pub network_mode_error: Option<String>,
+    /// Host CPUs to pin the gateway to; empty leaves it unpinned
+    pub cpuset: String,
+    pub cpuset_error: Option<String>,
+    /// Comma-separated networks attached to the gateway after its LAN and role NICs
+    pub extra_networks: String,
+    pub extra_networks_error: Option<String>,
+    /// Boot the gateway again when its guest crashes
+    pub restart_gw_on_crash: bool,
 
     // Step 2: Gateway mode
     pub gateway_mode: GatewayMode,
@@ -153,15 +491,7 @@ pub struct WizardState {
 
     // Execution state
     pub is_executing: bool,
-    pub execution_step: usize,
-    pub execution_messages: Vec<String>,
-    pub execution_error: Option<String>,
-
-    // Cleanup tracking - what was created during this wizard run
-    pub created_network: Option<String>,
-    pub created_overlay: Option<std::path::PathBuf>,
-    pub created_vm: Option<String>,
-    pub created_role_dir: Option<std::path::PathBuf>,
+    pub execution: StepReporter,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -181,6 +511,13 @@ pub enum WizardMode {
     Edit,
 }
 
+/// Which hop editor a background hop check reports back to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HopList {
+    Wizard,
+    ConfigEditor,
+}
+
 #[derive(Default, Clone)]
 pub struct ProxyHopEntry {
     pub proxy_type: ProxyType,
@@ -189,8 +526,166 @@ pub struct ProxyHopEntry {
     pub username: String,
     pub password: String,
     pub label: String,
+    pub notes: String,
+    pub country: String,
+    pub provider: String,
     pub test_status: Option<bool>,
     pub test_message: Option<String>,
+    /// A TCP test of this hop is running in the background
+    pub testing: bool,
+    /// Result of the SOCKS5 UDP ASSOCIATE probe, separate from the TCP test
+    pub udp_status: Option<bool>,
+    pub udp_message: Option<String>,
+    /// Ping round-trip time, or why no reply came back; informational only
+    pub ping_result: Option<Result<std::time::Duration, String>>,
+    /// A ping of this hop's host is running in the background
+    pub pinging: bool,
+}
+
+impl ProxyHopEntry {
+    /// Form fields for an existing hop
+    pub fn from_hop(hop: &ProxyHop) -> Self {
+        Self {
+            proxy_type: hop.proxy_type,
+            host: hop.host.clone(),
+            port: hop.port.to_string(),
+            username: hop.username.clone().unwrap_or_default(),
+            password: hop.password.clone().unwrap_or_default(),
+            label: hop.label.clone().unwrap_or_default(),
+            notes: hop.notes.clone().unwrap_or_default(),
+            country: hop.country.clone().unwrap_or_default(),
+            provider: hop.provider.clone().unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Copy the optional label and details onto `hop`, leaving blank ones unset
+    pub fn fill_metadata(&self, hop: &mut ProxyHop) {
+        let non_empty = |value: &str| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        hop.label = non_empty(&self.label);
+        hop.notes = non_empty(&self.notes);
+        hop.country = non_empty(&self.country);
+        hop.provider = non_empty(&self.provider);
+    }
+
+    /// Build the hop at position `index`, failing on an invalid port
+    pub fn to_hop(&self, index: u8) -> Result<ProxyHop, String> {
+        let port = parse_port(&self.port).map_err(|e| format!("Hop {}: {}", index, e))?;
+        let mut hop = ProxyHop::new(index, self.proxy_type, self.host.trim().to_string(), port);
+        if !self.username.is_empty() {
+            hop.username = Some(self.username.clone());
+        }
+        if !self.password.is_empty() {
+            hop.password = Some(self.password.clone());
+        }
+        self.fill_metadata(&mut hop);
+        Ok(hop)
+    }
+
+    /// Why the port field is invalid, or `None` while it is blank or valid
+    pub fn port_error(&self) -> Option<String> {
+        if self.port.trim().is_empty() {
+            return None;
+        }
+        parse_port(&self.port).err()
+    }
+
+    /// Host and port for a connection test, or the reason they can't be used
+    fn endpoint(&self) -> Result<(String, u16), String> {
+        if self.host.trim().is_empty() {
+            return Err("Host is required".to_string());
+        }
+        Ok((self.host.trim().to_string(), parse_port(&self.port)?))
+    }
+
+    /// Ask the proxy for a UDP relay and record whether it grants one
+    pub fn test_udp(&mut self, libvirt: &LibvirtAdapter) {
+        let (host, port) = match self.endpoint() {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                self.udp_status = Some(false);
+                self.udp_message = Some(e);
+                return;
+            }
+        };
+
+        let non_empty = |value: &str| Some(value.to_string()).filter(|v| !v.is_empty());
+        let mut hop = ProxyHop::new(1, self.proxy_type, host, port);
+        hop.username = non_empty(&self.username);
+        hop.password = non_empty(&self.password);
+        match libvirt.test_socks5_udp(&hop) {
+            Ok(()) => {
+                self.udp_status = Some(true);
+                self.udp_message = Some("UDP relay available".to_string());
+            }
+            Err(e) => {
+                self.udp_status = Some(false);
+                self.udp_message = Some(e.to_string());
+            }
+        }
+    }
+
+    /// Ping the host on a worker thread, to tell a down host from a closed
+    /// port; the result arrives as `HopPinged` for the hop at `index` of `list`
+    pub fn ping(
+        &mut self,
+        libvirt: &LibvirtAdapter,
+        tx: &Sender<AsyncMessage>,
+        list: HopList,
+        index: usize,
+    ) {
+        if self.pinging {
+            return;
+        }
+        let host = self.host.trim().to_string();
+        if host.is_empty() {
+            self.ping_result = Some(Err("Host is required".to_string()));
+            return;
+        }
+        self.pinging = true;
+        self.ping_result = None;
+        let libvirt = libvirt.clone();
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let result = libvirt.ping_host(&host).map_err(|e| e.to_string());
+            tx.send(AsyncMessage::HopPinged {
+                list,
+                index,
+                host,
+                result,
+            })
+            .ok();
+        });
+    }
+
+    /// Probe the endpoint and set `proxy_type` to whatever protocol it answers
+    pub fn auto_detect_type(&mut self, libvirt: &LibvirtAdapter) {
+        let (host, port) = match self.endpoint() {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                self.test_status = Some(false);
+                self.test_message = Some(e);
+                return;
+            }
+        };
+
+        match libvirt.detect_proxy_type(&host, port) {
+            Ok(Some(proxy_type)) => {
+                self.proxy_type = proxy_type;
+                self.test_status = Some(true);
+                self.test_message = Some(format!("Detected {}", proxy_type.as_str()));
+            }
+            Ok(None) => {
+                self.test_status = Some(false);
+                self.test_message =
+                    Some("Could not detect the proxy type; select it manually".to_string());
+            }
+            Err(e) => {
+                self.test_status = Some(false);
+                self.test_message = Some(e.to_string());
+            }
+        }
+    }
 }
 
 #[derive(Default, Clone)]
@@ -198,6 +693,7 @@ pub struct WireGuardConfigEntry {
     pub config_filename: String,
     pub interface_name: String,
     pub route_all_traffic: bool,
+    pub kill_switch: bool,
 }
 
 #[derive(Default, Clone)]
@@ -205,6 +701,38 @@ pub struct OpenVpnConfigEntry {
     pub config_filename: String,
     pub auth_filename: String,
     pub route_all_traffic: bool,
+    pub kill_switch: bool,
+    /// Problem found in the auth file, shown as a warning
+    pub auth_warning: Option<String>,
+    /// Passphrase typed in for an encrypted private key, until it is saved as an askpass file
+    pub askpass_passphrase: String,
+    /// Forced transport; `None` uses the config's own `proto`
+    pub proto: Option<OpenVpnProto>,
+    /// Local port to bind; empty uses the config's default
+    pub local_port: String,
+}
+
+impl OpenVpnConfigEntry {
+    /// The local port override, or why it isn't a valid port
+    pub fn local_port(&self) -> Result<Option<u16>, String> {
+        if self.local_port.trim().is_empty() {
+            return Ok(None);
+        }
+        parse_port(&self.local_port)
+            .map(Some)
+            .map_err(|e| format!("OpenVPN local port: {}", e))
+    }
+
+    /// Re-check the selected auth file's format
+    pub fn check_auth_file(&mut self) {
+        self.auth_warning = if self.auth_filename.is_empty() {
+            None
+        } else {
+            validate_auth_file(std::path::Path::new(&self.auth_filename))
+                .err()
+                .map(|e| e.to_string())
+        };
+    }
 }
 
 /// Templates view state
@@ -216,10 +744,15 @@ pub struct TemplatesViewState {
     // Selection mode - first ask to pick existing or browse new
     pub show_selection_dialog: bool,
     pub discovered_qcow2_files: Vec<std::path::PathBuf>,
+    /// Images skipped because their file names aren't UTF-8
+    pub discovered_invalid_names: Vec<std::path::PathBuf>,
     pub selected_existing_file: Option<std::path::PathBuf>,
 
     // Map of disk paths to VM names that use them
     pub disk_to_vm_map: HashMap<std::path::PathBuf, Vec<String>>,
+    /// Dependents per template ID, filled when a card's usage is checked
+    pub template_usage: HashMap<String, Result<Vec<String>, String>>,
+    pub usage_checks_in_flight: HashSet<String>,
 
     // Cached list of registered template paths (to avoid rebuilding every frame)
     pub registered_paths_cache: Vec<std::path::PathBuf>,
@@ -228,6 +761,9 @@ pub struct TemplatesViewState {
     pub pending_template_delete: Option<String>, // template ID to delete
     pub pending_template_delete_path: Option<std::path::PathBuf>, // path to delete
     pub delete_image_file: bool, // Whether to also delete the image file (default: true)
+    pub delete_image_users: Vec<VmInfo>, // VMs using the image, fetched when the dialog opens
+    pub delete_image_users_error: Option<String>, // Why the VMs using the image are unknown
+    pub force_delete_in_use: bool, // Delete the file even though running VMs use it
 
     // Form fields
     pub form_label: String,
@@ -236,7 +772,126 @@ pub struct TemplatesViewState {
     pub form_role_kind: RoleKind,
     pub form_ram_mb: String,
     pub form_notes: String,
+    pub form_disk_tuning: DiskTuning,
+    pub form_boot: BootForm,
+    pub form_install: InstallSource,
+    /// Blank disk size in GB when installing from an ISO; empty for the default
+    pub form_install_disk_gb: String,
     pub form_error: Option<String>,
+    /// ID of an existing template for the same image, pending the user's choice
+    pub form_duplicate_of: Option<String>,
+
+    // Download from URL
+    pub url_form_url: String,
+    pub url_form_sha256: String,
+    pub url_form_error: Option<String>,
+    /// `(downloaded, total)` bytes while a download is running
+    pub download_progress: Option<(u64, Option<u64>)>,
+
+    /// SHA-256 of template images hashed this session
+    pub checksums: ChecksumCache,
+    /// `(hashed, total)` bytes per image being hashed
+    pub hashing: HashMap<PathBuf, (u64, u64)>,
+
+    // Relocate images dir
+    pub show_relocate_dialog: bool,
+    pub relocate_old_base: String,
+    pub relocate_new_base: String,
+    pub relocate_error: Option<String>,
+
+    // Disk chain repair
+    pub show_backing_dialog: bool,
+    /// Layers found pointing at a missing backing file by the last check
+    pub broken_backings: Vec<BrokenBacking>,
+    /// New backing path typed per broken overlay
+    pub rebase_targets: HashMap<PathBuf, String>,
+}
+
+/// A VPN file named in the wizard, as it will end up in the role directory
+#[derive(Debug, Clone)]
+pub struct RoleFilePreview {
+    pub label: &'static str,
+    /// Picked from elsewhere and copied in; `None` when it must already be in the role directory
+    pub source: Option<PathBuf>,
+    pub dest: PathBuf,
+    /// How the gateway refers to it
+    pub guest_path: String,
+}
+
+/// A wizard file field holding a path to an existing file, which role creation
+/// copies into the role directory; returns the path and the bare file name
+fn picked_role_file(filename: &str) -> Option<(PathBuf, String)> {
+    let path = PathBuf::from(filename);
+    let name = path.file_name()?.to_string_lossy().to_string();
+    path.is_file().then_some((path, name))
+}
+
+/// Status for a VM created on `overlay`: a warning naming each image under it
+/// that qemu won't be able to open, otherwise `success`
+fn with_disk_permission_issues(
+    libvirt: &LibvirtAdapter,
+    overlay: &Path,
+    success: String,
+) -> (StatusLevel, String) {
+    let issues = match libvirt.check_disk_permissions(overlay) {
+        Ok(issues) => issues.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        Err(e) => vec![format!("Could not check disk permissions: {}", e)],
+    };
+    if issues.is_empty() {
+        (StatusLevel::Success, success)
+    } else {
+        (
+            StatusLevel::Warning,
+            format!("{}, but: {}", success, issues.join("; ")),
+        )
+    }
+}
+
+/// A config file that exists on disk but could not be loaded
+#[derive(Debug, Clone)]
+pub struct UnreadableFile {
+    pub label: &'static str,
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Template dialog fields for `BootOptions`, with paths kept as editable text
+#[derive(Default, Clone)]
+pub struct BootForm {
+    pub kernel_args: String,
+    pub kernel: String,
+    pub initrd: String,
+    pub serial_console: bool,
+}
+
+impl BootForm {
+    pub fn from_options(boot: &BootOptions) -> Self {
+        let path_text = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default()
+        };
+        Self {
+            kernel_args: boot.kernel_args.clone(),
+            kernel: path_text(&boot.kernel),
+            initrd: path_text(&boot.initrd),
+            serial_console: boot.serial_console,
+        }
+    }
+
+    pub fn to_options(&self) -> BootOptions {
+        let path = |text: &str| {
+            Some(text.trim())
+                .filter(|t| !t.is_empty())
+                .map(PathBuf::from)
+        };
+        BootOptions {
+            kernel_args: self.kernel_args.trim().to_string(),
+            kernel: path(&self.kernel),
+            initrd: path(&self.initrd),
+            serial_console: self.serial_console,
+        }
+    }
 }
 
 /// Settings view state
@@ -245,13 +900,34 @@ pub struct SettingsViewState {
     pub cfg_root: String,
     pub images_dir: String,
     pub lan_net: String,
+    pub share_driver: ShareDriver,
+    pub libvirt_uri: String,
+    pub virt_install_timeout: String,
+    pub role_subnet_pool: String,
     pub gateway_ram: String,
     pub app_ram: String,
     pub disp_ram: String,
     pub debian_variant: String,
     pub fedora_variant: String,
+    pub auto_refresh: String,
+    pub naming: NamingSection,
+    pub log_dir: String,
+    pub log_level: LogLevel,
     pub error: Option<String>,
     pub saved: bool,
+
+    // Configuration bundle export/import
+    pub bundle_include_images: bool,
+    pub bundle_busy: bool,
+    pub pending_bundle_import: Option<PathBuf>,
+
+    // Shared defaults snippet export/import
+    pub defaults_include_libvirt: bool,
+
+    // Leftover role network cleanup; `None` until scanned
+    pub leftover_networks: Option<Vec<NetworkInfo>>,
+    pub network_scan_busy: bool,
+    pub pending_network_removal: Option<String>,
 }
 
 impl ProxyVmWizardApp {
@@ -277,6 +953,7 @@ impl ProxyVmWizardApp {
 
         // Create async channel
         let (async_tx, async_rx) = channel();
+        let ops = OperationQueue::new(async_tx.clone());
 
         // Initialize libvirt adapter
         let libvirt = LibvirtAdapter::new();
@@ -288,8 +965,16 @@ impl ProxyVmWizardApp {
             AuthScreen::Setup
         };
 
+        // Claim the config directory before anything can write to it
+        let (instance_lock, lock_conflict, lock_error) =
+            match InstanceLock::acquire(&InstanceLock::default_path()) {
+                Ok(LockAttempt::Acquired(lock)) => (Some(lock), None, None),
+                Ok(LockAttempt::Held(holder)) => (None, Some(holder), None),
+                Err(e) => (None, None, Some(e)),
+            };
+
         // Create app with minimal state - actual config loading happens after authentication
-        Self {
+        let mut app = Self {
             auth_view: AuthViewState {
                 screen: auth_screen,
                 ..Default::default()
@@ -303,46 +988,163 @@ impl ProxyVmWizardApp {
             discovered_roles: Vec::new(),
             role_vms: HashMap::new(),
             last_refresh: None,
+            last_good_refresh: None,
+            refresh_failed: false,
+            refresh_in_flight: false,
+            role_sort: RoleSort::default(),
+            role_modified: HashMap::new(),
+            outdated_scripts: HashMap::new(),
+            role_networks: None,
+            role_networks_in_flight: false,
+            role_rotations: HashMap::new(),
+            role_crash_policies: HashMap::new(),
+            role_extra_gateway_networks: HashMap::new(),
+            role_max_app_vms: HashMap::new(),
+            rotation_slots: HashMap::new(),
+            last_rotation_check: None,
             wizard: WizardState::default(),
             templates_view: TemplatesViewState::default(),
             settings_view: SettingsViewState::default(),
             logs: Vec::new(),
             max_logs: 500,
+            file_log: None,
             async_tx,
             async_rx,
+            ops,
+            wg_status: HashMap::new(),
+            wg_checks_in_flight: HashSet::new(),
+            gw_interfaces: HashMap::new(),
+            iface_checks_in_flight: HashSet::new(),
+            disk_chains: HashMap::new(),
+            chain_checks_in_flight: HashSet::new(),
+            disk_usage: HashMap::new(),
+            usage_checks_in_flight: HashSet::new(),
+            conf_validation: HashMap::new(),
+            isolation_status: HashMap::new(),
+            isolation_checks_in_flight: HashSet::new(),
+            gateway_tests: None,
+            palette: CommandPalette::default(),
             status_message: None,
             prereq_error: None,
+            unreadable_files: Vec::new(),
+            show_unreadable_dialog: false,
+            restart_required: false,
             pending_role_delete: None,
+            delete_disks: RoleDeleteDisks::default(),
+            delete_keep_disks: false,
             editing_role_config: None,
             config_editor: ConfigEditorState::default(),
+            copy_config_target: None,
+            copy_config_source: None,
+            copy_config_restart: true,
+            copy_config_modes: HashMap::new(),
+            generated_files: None,
+            gateway_logs: None,
+            renaming_vm: None,
+            new_app_vm: None,
+            instance_lock,
+            lock_conflict,
+        };
+        if let Some(e) = lock_error {
+            app.log(
+                StatusLevel::Warning,
+                format!("Could not create the instance lock: {}", e),
+            );
         }
+        app
     }
 
-    /// Initialize the app after successful authentication
-    fn initialize_after_auth(&mut self) {
-        // Check prerequisites
-        self.prereq_error = match self.libvirt.check_prerequisites() {
-            Ok(_) => match self.libvirt.check_libvirt_access() {
-                Ok(_) => None,
-                Err(e) => Some(e.to_string()),
-            },
-            Err(e) => Some(e.to_string()),
+    /// Ask whether to take over the config directory from another instance
+    fn show_lock_conflict_screen(&mut self, ctx: &egui::Context) {
+        let Some(holder) = self.lock_conflict.clone() else {
+            return;
         };
+        let alive = holder.is_alive();
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(100.0);
+                ui.heading("⚠ Proxy VM Wizard is already open");
+                ui.add_space(10.0);
+                if alive {
+                    ui.label(format!(
+                        "Another instance (PID {}) has been using the configuration since {}.",
+                        holder.pid,
+                        timefmt::date_time_minutes(&holder.started.with_timezone(&chrono::Local))
+                    ));
+                    ui.label("Running both lets them overwrite each other's settings and templates.");
+                } else {
+                    ui.label(format!(
+                        "An earlier instance (PID {}) did not shut down cleanly and left its lock behind.",
+                        holder.pid
+                    ));
+                    ui.label("That process is no longer running, so it is safe to continue.");
+                }
+                ui.add_space(20.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Quit").clicked() {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                    let label = if alive { "Open anyway" } else { "Continue" };
+                    if ui.button(label).clicked() {
+                        match InstanceLock::take_over(&InstanceLock::default_path()) {
+                            Ok(lock) => self.instance_lock = Some(lock),
+                            Err(e) => self.log(
+                                StatusLevel::Warning,
+                                format!("Could not take over the instance lock: {}", e),
+                            ),
+                        }
+                        self.lock_conflict = None;
+                    }
+                });
+            });
+        });
+    }
+
+    /// This adapter pointed at another libvirt connection, e.g. to try a URI before saving it
+    pub fn libvirt_for_uri(&self, uri: &str) -> LibvirtAdapter {
+        let mut libvirt = self.libvirt.clone();
+        libvirt.uri = Some(uri.to_string());
+        libvirt
+    }
+
+    /// Why libvirt can't be used right now, if it can't
+    fn prerequisite_error(&self) -> Option<String> {
+        self.libvirt
+            .check_prerequisites()
+            .and_then(|_| self.libvirt.check_libvirt_access())
+            .err()
+            .map(|e| e.to_string())
+    }
 
-        // Collect any warnings to log after loading
-        let mut warnings: Vec<String> = Vec::new();
+    /// Re-run the libvirt checks, e.g. after libvirtd restarted; returns true once they pass
+    pub fn reconnect_libvirt(&mut self) -> bool {
+        self.prereq_error = self.prerequisite_error();
+        // Also paces the automatic retries
+        self.last_refresh = Some(std::time::Instant::now());
+        if self.prereq_error.is_some() {
+            return false;
+        }
+        self.log(StatusLevel::Success, "Reconnected to libvirt");
+        self.refresh_vms();
+        true
+    }
 
+    /// Initialize the app after successful authentication
+    fn initialize_after_auth(&mut self) {
         // Load config (encrypted or create new)
         if let Some(ref encryption) = self.encryption.clone() {
             // Try to load encrypted config
             match GlobalConfig::load_encrypted(encryption) {
                 Ok(config) => self.global_config = config,
+                Err(e) if GlobalConfig::is_encrypted().unwrap_or(false) => {
+                    // Encrypted but won't decrypt: wrong key or corruption, keep the file
+                    self.mark_unreadable("Settings", GlobalConfig::default_path(), e);
+                }
                 Err(_) => {
                     // Try plain config (might exist from before encryption)
-                    self.global_config = GlobalConfig::load_or_default().unwrap_or_default();
-                    // Save as encrypted
-                    if let Err(e) = self.global_config.save_encrypted(encryption) {
-                        warnings.push(format!("Failed to encrypt config: {}", e));
+                    match GlobalConfig::load_or_default() {
+                        Ok(config) => self.global_config = config,
+                        Err(e) => self.mark_unreadable("Settings", GlobalConfig::default_path(), e),
                     }
                 }
             }
@@ -350,48 +1152,140 @@ impl ProxyVmWizardApp {
             // Try to load encrypted template registry
             match TemplateRegistry::load_encrypted(encryption) {
                 Ok(registry) => self.template_registry = registry,
+                Err(e) if TemplateRegistry::is_encrypted().unwrap_or(false) => {
+                    self.mark_unreadable("Templates", TemplateRegistry::default_path(), e);
+                }
                 Err(_) => {
                     // Try plain registry
-                    self.template_registry =
-                        TemplateRegistry::load_or_default().unwrap_or_default();
-                    // Save as encrypted
-                    if let Err(e) = self.template_registry.save_encrypted(encryption) {
-                        warnings.push(format!("Failed to encrypt templates: {}", e));
+                    match TemplateRegistry::load_or_default() {
+                        Ok(registry) => self.template_registry = registry,
+                        Err(e) => {
+                            self.mark_unreadable("Templates", TemplateRegistry::default_path(), e)
+                        }
                     }
                 }
             }
+
+            // Anything loaded from plaintext above is still on disk unencrypted
+            self.encrypt_plaintext_config();
         } else {
             // No encryption - load normally
             self.global_config = GlobalConfig::load_or_default().unwrap_or_default();
             self.template_registry = TemplateRegistry::load_or_default().unwrap_or_default();
         }
 
-        // Log any warnings
-        for warning in warnings {
-            self.log(StatusLevel::Warning, warning);
+        // Domains are matched to roles by the configured name patterns
+        self.libvirt.naming = self.global_config.naming.clone();
+        self.libvirt.uri = Some(self.global_config.libvirt.uri.clone());
+        self.libvirt.virt_install_timeout_secs =
+            self.global_config.libvirt.virt_install_timeout_secs;
+        match validate_role_subnet_pool(&self.global_config.libvirt.role_subnet_pool) {
+            Ok(pool) => self.libvirt.role_subnet_pool = pool,
+            Err(e) => self.log(
+                StatusLevel::Warning,
+                format!(
+                    "Ignoring role subnet range: {}; using {}",
+                    e, self.libvirt.role_subnet_pool
+                ),
+            ),
+        }
+
+        // Entries from before the config was loaded go to the file too
+        self.open_file_log();
+        if let Some(file_log) = &self.file_log {
+            for entry in &self.logs {
+                file_log.log(entry.level.log_level(), &entry.message);
+            }
         }
 
+        // Check prerequisites, against the configured connection
+        self.prereq_error = self.prerequisite_error();
+
         // Discover roles
         self.discovered_roles = discover_roles(&self.global_config.cfg.root).unwrap_or_default();
+        if self.prereq_error.is_none() {
+            self.check_lan_net();
+        }
 
         // Initialize settings view state from config
         self.settings_view = SettingsViewState {
             cfg_root: self.global_config.cfg.root.display().to_string(),
             images_dir: self.global_config.libvirt.images_dir.display().to_string(),
             lan_net: self.global_config.libvirt.lan_net.clone(),
+            share_driver: self.global_config.libvirt.share_driver,
+            libvirt_uri: self.global_config.libvirt.uri.clone(),
+            virt_install_timeout: self
+                .global_config
+                .libvirt
+                .virt_install_timeout_secs
+                .to_string(),
+            role_subnet_pool: self.global_config.libvirt.role_subnet_pool.clone(),
             gateway_ram: self.global_config.defaults.gateway_ram_mb.to_string(),
             app_ram: self.global_config.defaults.app_ram_mb.to_string(),
             disp_ram: self.global_config.defaults.disp_ram_mb.to_string(),
             debian_variant: self.global_config.defaults.debian_os_variant.clone(),
             fedora_variant: self.global_config.defaults.fedora_os_variant.clone(),
+            auto_refresh: self.global_config.ui.auto_refresh_secs.to_string(),
+            naming: self.global_config.naming.clone(),
+            log_dir: self.global_config.logging.dir.display().to_string(),
+            log_level: self.global_config.logging.level,
             error: None,
             saved: false,
+            ..Default::default()
         };
 
         // Initial refresh
         self.refresh_vms();
     }
 
+    /// Warn when the LAN network is a role network or shares its bridge with one;
+    /// either puts app VMs on the LAN, past their gateway
+    fn check_lan_net(&mut self) {
+        let lan_net = self.global_config.libvirt.lan_net.clone();
+        if let Err(e) = validate_lan_net(&lan_net, &self.discovered_roles) {
+            self.set_status(StatusLevel::Error, e);
+        }
+        match self
+            .libvirt
+            .check_role_network_isolation(&lan_net, &self.discovered_roles)
+        {
+            Ok(warnings) => {
+                for warning in warnings {
+                    self.set_status(StatusLevel::Warning, warning);
+                }
+            }
+            Err(e) => self.log(
+                StatusLevel::Warning,
+                format!(
+                    "Could not compare role networks with the LAN network: {}",
+                    e
+                ),
+            ),
+        }
+    }
+
+    /// (Re)open the log file from the logging settings; the app keeps running
+    /// with in-memory logs only if it can't be opened
+    fn open_file_log(&mut self) {
+        let logging = &self.global_config.logging;
+        match FileLogger::open(&logging.dir, logging.level) {
+            Ok(file_log) => {
+                let file_log = Arc::new(file_log);
+                self.libvirt.command_log = Some(file_log.clone());
+                self.file_log = Some(file_log);
+            }
+            Err(e) => {
+                let dir = logging.dir.display().to_string();
+                self.file_log = None;
+                self.libvirt.command_log = None;
+                self.log(
+                    StatusLevel::Warning,
+                    format!("Not logging to disk: cannot open {}: {}", dir, e),
+                );
+            }
+        }
+    }
+
     /// Handle password setup
     fn setup_password(&mut self) -> bool {
         if self.auth_view.password.len() < 8 {
@@ -611,6 +1505,9 @@ impl ProxyVmWizardApp {
             level,
             message: message.into(),
         };
+        if let Some(file_log) = &self.file_log {
+            file_log.log(entry.level.log_level(), &entry.message);
+        }
         self.logs.push(entry);
         if self.logs.len() > self.max_logs {
             self.logs.remove(0);
@@ -633,98 +1530,613 @@ impl ProxyVmWizardApp {
     }
 
     pub fn refresh_vms(&mut self) {
-        self.role_vms.clear();
-
-        // Refresh roles
-        self.discovered_roles = discover_roles(&self.global_config.cfg.root).unwrap_or_default();
+        self.refresh_roles();
+        self.refresh_role_networks();
+        let result = self.libvirt.list_vms(None).map_err(|e| e.to_string());
+        self.apply_vm_list(result);
+    }
 
-        // Get all VMs
-        match self.libvirt.list_vms(None) {
-            Ok(vms) => {
-                for vm in vms {
-                    if let Some(role) = &vm.role {
-                        self.role_vms.entry(role.clone()).or_default().push(vm);
-                    }
-                }
-            }
-            Err(e) => {
-                self.log(StatusLevel::Error, format!("Failed to list VMs: {}", e));
-            }
+    /// List role networks on a worker thread; the result arrives as `RoleNetworks`
+    fn refresh_role_networks(&mut self) {
+        if self.role_networks_in_flight {
+            return;
         }
+        self.role_networks_in_flight = true;
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        std::thread::spawn(move || {
+            let result = libvirt.list_app_networks().map_err(|e| e.to_string());
+            tx.send(AsyncMessage::RoleNetworks(result)).ok();
+        });
+    }
 
-        self.last_refresh = Some(std::time::Instant::now());
+    /// Start a role's stopped `<role>-inet` network
+    pub fn start_role_network(&mut self, role: &str) {
+        let libvirt = self.libvirt.clone();
+        let net_name = format!("{}-inet", role);
+        self.ops.submit(
+            format!("Starting network {}", net_name),
+            move || match libvirt.start_network(&net_name) {
+                Ok(()) => (
+                    StatusLevel::Success,
+                    format!("Network '{}' started", net_name),
+                ),
+                Err(e) => (StatusLevel::Error, e.to_string()),
+            },
+        );
     }
 
-    pub fn start_vm(&mut self, name: &str) {
-        // First check current state
-        if let Ok(Some(info)) = self.libvirt.get_vm_info(name) {
-            if info.state.is_running() {
-                self.set_status(
-                    StatusLevel::Warning,
-                    format!("VM '{}' is already running", name),
-                );
-                self.refresh_vms();
-                return;
-            }
+    /// Turn autostart of a role's `<role>-inet` network on or off
+    pub fn set_role_network_autostart(&mut self, role: &str, autostart: bool) {
+        if let Some(info) = self
+            .role_networks
+            .as_mut()
+            .and_then(|networks| networks.as_mut().ok())
+            .and_then(|networks| networks.get_mut(role))
+        {
+            info.autostart = autostart;
         }
-
-        match self.libvirt.start_vm(name) {
-            Ok(_) => {
-                self.set_status(StatusLevel::Success, format!("Started VM: {}", name));
-                self.refresh_vms();
-            }
-            Err(e) => {
-                let msg = e.to_string();
-                if msg.contains("already running") || msg.contains("is running") {
-                    self.set_status(
-                        StatusLevel::Warning,
-                        format!("VM '{}' is already running", name),
-                    );
-                } else {
-                    self.set_status(StatusLevel::Error, format!("Failed to start VM: {}", e));
-                }
-                self.refresh_vms();
+        let libvirt = self.libvirt.clone();
+        let net_name = format!("{}-inet", role);
+        self.ops.submit(
+            format!("Setting autostart of {}", net_name),
+            move || match libvirt.set_network_autostart(&net_name, autostart) {
+                Ok(()) => (
+                    StatusLevel::Success,
+                    format!(
+                        "Network '{}' {} start with libvirt",
+                        net_name,
+                        if autostart { "will" } else { "won't" }
+                    ),
+                ),
+                Err(e) => (StatusLevel::Error, e.to_string()),
+            },
+        );
+    }
+
+    /// List VMs on a worker thread; the result arrives as `VmListRefreshed`
+    pub fn start_background_refresh(&mut self) {
+        if self.refresh_in_flight {
+            return;
+        }
+        self.refresh_in_flight = true;
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        std::thread::spawn(move || {
+            let result = libvirt.list_vms(None).map_err(|e| e.to_string());
+            tx.send(AsyncMessage::VmListRefreshed(result)).ok();
+        });
+        self.refresh_role_networks();
+    }
+
+    /// How old the shown VM states are, once they are old enough to mislead.
+    ///
+    /// States count as stale after two missed auto-refreshes, or after
+    /// `STALE_AFTER` when auto-refresh is off; a failed refresh makes them
+    /// stale at once.
+    pub fn stale_data_age(&self) -> Option<std::time::Duration> {
+        let age = self.last_good_refresh?.elapsed();
+        let threshold = match self.global_config.ui.auto_refresh_interval() {
+            Some(interval) => (interval * 2).max(STALE_AFTER),
+            None => STALE_AFTER,
+        };
+        (self.refresh_failed || age >= threshold).then_some(age)
+    }
+
+    /// Kick off a background refresh when the configured interval has passed
+    fn auto_refresh(&mut self) {
+        let Some(interval) = self.global_config.ui.auto_refresh_interval() else {
+            return;
+        };
+        let due = self
+            .last_refresh
+            .is_none_or(|last| last.elapsed() >= interval);
+        // The error modal covers every view, so retry libvirt from wherever we are
+        if self.prereq_error.is_some() {
+            if due {
+                self.reconnect_libvirt();
             }
+            return;
+        }
+        if self.current_view != View::Dashboard {
+            return;
+        }
+        if due {
+            self.start_background_refresh();
         }
     }
 
-    pub fn stop_vm(&mut self, name: &str) {
-        // First check current state
-        if let Ok(Some(info)) = self.libvirt.get_vm_info(name) {
-            if !info.state.is_running() {
-                self.set_status(
+    /// Re-read roles and their metadata from the config root
+    fn refresh_roles(&mut self) {
+        self.discovered_roles = discover_roles(&self.global_config.cfg.root).unwrap_or_default();
+        self.role_modified.clear();
+        self.role_rotations.clear();
+        self.role_crash_policies.clear();
+        self.role_max_app_vms.clear();
+        self.role_extra_gateway_networks.clear();
+        for role in self.discovered_roles.clone() {
+            let Ok(mut meta) = RoleMeta::load(&self.global_config.cfg.root, &role) else {
+                continue;
+            };
+            match meta.reconcile_gateway_mode(&self.global_config.cfg.root) {
+                Ok(Some(previous)) => self.log(
                     StatusLevel::Warning,
-                    format!("VM '{}' is not running", name),
-                );
-                self.refresh_vms();
-                return;
+                    format!(
+                        "Role '{}': role-meta.toml said {} but proxy.conf says {}; updated role-meta.toml to match",
+                        role,
+                        previous.display_name(),
+                        meta.gateway_mode.display_name()
+                    ),
+                ),
+                Ok(None) => {}
+                Err(e) => self.log(
+                    StatusLevel::Warning,
+                    format!(
+                        "Role '{}': gateway mode in role-meta.toml differs from proxy.conf but could not be updated: {}",
+                        role, e
+                    ),
+                ),
+            }
+            if let Some(modified) = meta.last_modified {
+                self.role_modified.insert(role.clone(), modified);
+            }
+            self.role_crash_policies.insert(role.clone(), meta.on_crash);
+            self.role_max_app_vms.insert(role.clone(), meta.max_app_vms);
+            self.role_extra_gateway_networks
+                .insert(role.clone(), meta.extra_gateway_networks.clone());
+            if let Some(policy) = meta.rotation {
+                match policy.validate() {
+                    Ok(()) => {
+                        self.role_rotations.insert(role.clone(), policy);
+                    }
+                    Err(e) => self.log(
+                        StatusLevel::Warning,
+                        format!("Ignoring rotation policy for '{}': {}", role, e),
+                    ),
+                }
             }
         }
+        self.rotation_slots
+            .retain(|role, _| self.role_rotations.contains_key(role));
+        self.outdated_scripts = self
+            .discovered_roles
+            .iter()
+            .filter_map(|role| {
+                match ProxyConfigBuilder::apply_script_status(&self.global_config.role_dir(role)) {
+                    ApplyScriptStatus::Outdated { installed } => Some((role.clone(), installed)),
+                    _ => None,
+                }
+            })
+            .collect();
+    }
 
-        match self.libvirt.stop_vm(name) {
-            Ok(_) => {
-                self.set_status(StatusLevel::Success, format!("Stopping VM: {}", name));
-                self.refresh_vms();
+    /// Group a fresh VM list by role and follow up on running gateways
+    fn apply_vm_list(&mut self, result: Result<Vec<VmInfo>, String>) {
+        self.refresh_failed = result.is_err();
+        match result {
+            Ok(vms) => {
+                self.role_vms.clear();
+                self.last_good_refresh = Some(std::time::Instant::now());
+                for vm in vms {
+                    if let Some(role) = &vm.role {
+                        self.role_vms.entry(role.clone()).or_default().push(vm);
+                    }
+                }
             }
+            // On failure the previous states stay up, marked stale
             Err(e) => {
-                let msg = e.to_string();
-                if msg.contains("not running") || msg.contains("domain is not running") {
-                    self.set_status(
-                        StatusLevel::Warning,
-                        format!("VM '{}' is already stopped", name),
+                self.log(StatusLevel::Error, format!("Failed to list VMs: {}", e));
+                // Most often libvirtd went away; show the reconnect modal until it's back
+                if let Err(access) = self.libvirt.check_libvirt_access() {
+                    self.prereq_error = Some(access.to_string());
+                }
+            }
+        }
+
+        self.last_refresh = Some(std::time::Instant::now());
+
+        // List gateway NICs so miswiring shows on the dashboard
+        let gateways: Vec<String> = self
+            .role_vms
+            .values()
+            .flatten()
+            .filter(|vm| vm.kind == VmKind::ProxyGateway)
+            .map(|vm| vm.name.clone())
+            .collect();
+        self.gw_interfaces.retain(|name, _| gateways.contains(name));
+        for gw_name in gateways {
+            self.fetch_vm_interfaces(&gw_name);
+        }
+
+        // Disk chains only change through our own operations, so fetch each once
+        let persistent: Vec<String> = self
+            .role_vms
+            .values()
+            .flatten()
+            .filter(|vm| vm.kind != VmKind::DisposableApp)
+            .map(|vm| vm.name.clone())
+            .collect();
+        self.disk_chains.retain(|name, _| persistent.contains(name));
+        for vm_name in persistent {
+            if !self.disk_chains.contains_key(&vm_name) {
+                self.fetch_disk_chain(&vm_name);
+            }
+        }
+
+        // Check tunnels of running WireGuard gateways; a stopped gateway's last
+        // status, or a check still in flight for it, would otherwise linger
+        let wg_gateways = running_gateways(&self.role_vms);
+        let is_running = |name: &String| wg_gateways.iter().any(|(_, gw)| gw == name);
+        self.wg_status.retain(|name, _| is_running(name));
+        self.wg_checks_in_flight.retain(is_running);
+        for (role, gw_name) in wg_gateways {
+            let is_wireguard = RoleMeta::load(&self.global_config.cfg.root, &role)
+                .map(|m| m.gateway_mode == GatewayMode::WireGuard)
+                .unwrap_or(false);
+            if is_wireguard {
+                self.check_wireguard(&role, &gw_name);
+            } else {
+                self.wg_status.remove(&gw_name);
+            }
+        }
+    }
+
+    /// List a VM's network interfaces in the background
+    fn fetch_vm_interfaces(&mut self, vm_name: &str) {
+        if !self.iface_checks_in_flight.insert(vm_name.to_string()) {
+            return;
+        }
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        let vm_name = vm_name.to_string();
+        std::thread::spawn(move || {
+            let result = libvirt
+                .list_vm_interfaces(&vm_name)
+                .map_err(|e| e.to_string());
+            tx.send(AsyncMessage::VmInterfaces { vm_name, result }).ok();
+        });
+    }
+
+    /// Measure a VM's disk backing chain in the background
+    fn fetch_disk_chain(&mut self, vm_name: &str) {
+        if !self.chain_checks_in_flight.insert(vm_name.to_string()) {
+            return;
+        }
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        let vm_name = vm_name.to_string();
+        std::thread::spawn(move || {
+            let result = libvirt
+                .get_vm_disk_path(&vm_name)
+                .and_then(|disk| match disk {
+                    Some(disk) => libvirt.backing_chain_depth(&disk),
+                    None => Ok(0),
+                })
+                .map_err(|e| e.to_string());
+            tx.send(AsyncMessage::DiskChain { vm_name, result }).ok();
+        });
+    }
+
+    /// Check the WireGuard handshake inside a gateway in the background
+    pub fn check_wireguard(&mut self, role: &str, gw_name: &str) {
+        if !self.wg_checks_in_flight.insert(gw_name.to_string()) {
+            return;
+        }
+
+        let conf_path = self.global_config.role_dir(role).join("proxy.conf");
+        let iface = std::fs::read_to_string(conf_path)
+            .ok()
+            .and_then(|content| ProxyConfigBuilder::parse_proxy_conf(&content, role).wireguard)
+            .map(|wg| wg.interface_name)
+            .unwrap_or_else(|| "wg0".to_string());
+
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        let vm_name = gw_name.to_string();
+        std::thread::spawn(move || {
+            let result = libvirt
+                .wireguard_status(&vm_name, &iface)
+                .map_err(|e| e.to_string());
+            tx.send(AsyncMessage::WireGuardStatus { vm_name, result })
+                .ok();
+        });
+    }
+
+    /// Check from each running app VM of a role that the LAN isn't reachable directly
+    pub fn verify_isolation(&mut self, role: &str) {
+        if !self.isolation_checks_in_flight.insert(role.to_string()) {
+            return;
+        }
+
+        let app_vms: Vec<String> = self
+            .role_vms
+            .get(role)
+            .into_iter()
+            .flatten()
+            .filter(|vm| vm.kind == VmKind::App && vm.state.is_running())
+            .map(|vm| vm.name.clone())
+            .collect();
+
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        let role = role.to_string();
+        std::thread::spawn(move || {
+            let result = (|| {
+                if app_vms.is_empty() {
+                    return Err("No running app VMs to probe".to_string());
+                }
+                let target = libvirt
+                    .host_default_gateway()
+                    .map_err(|e| e.to_string())?
+                    .ok_or_else(|| "Could not find the host's default gateway".to_string())?;
+
+                let mut report = IsolationReport {
+                    target: target.clone(),
+                    checked: Vec::new(),
+                    leaking: Vec::new(),
+                    skipped: Vec::new(),
+                };
+                for vm in app_vms {
+                    if !libvirt.guest_agent_available(&vm) {
+                        report.skipped.push(vm);
+                        continue;
+                    }
+                    if libvirt
+                        .probe_app_egress(&vm, &target)
+                        .map_err(|e| e.to_string())?
+                    {
+                        report.leaking.push(vm.clone());
+                    }
+                    report.checked.push(vm);
+                }
+                if report.checked.is_empty() {
+                    return Err(
+                        "No running app VM has a responding guest agent (install qemu-guest-agent)"
+                            .to_string(),
                     );
-                } else {
-                    self.set_status(StatusLevel::Error, format!("Failed to stop VM: {}", e));
                 }
-                self.refresh_vms();
+                Ok(report)
+            })();
+            tx.send(AsyncMessage::IsolationProbe { role, result }).ok();
+        });
+    }
+
+    /// Check the upstream of every running gateway in the background
+    pub fn test_all_gateways(&mut self) {
+        if self.gateway_tests.as_ref().is_some_and(|run| !run.finished) {
+            return;
+        }
+        let mut gateways: Vec<(String, String, PathBuf)> = self
+            .role_vms
+            .iter()
+            .flat_map(|(role, vms)| {
+                vms.iter()
+                    .filter(|vm| vm.kind == VmKind::ProxyGateway && vm.state.is_running())
+                    .map(move |vm| (role.clone(), vm.name.clone()))
+            })
+            .map(|(role, gateway)| {
+                let conf_path = self.global_config.role_dir(&role).join("proxy.conf");
+                (role, gateway, conf_path)
+            })
+            .collect();
+        if gateways.is_empty() {
+            self.set_status(StatusLevel::Warning, "No running gateways to test");
+            return;
+        }
+        gateways.sort();
+
+        self.gateway_tests = Some(GatewayTestRun {
+            total: gateways.len(),
+            ..Default::default()
+        });
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        std::thread::spawn(move || {
+            for (role, gateway, conf_path) in gateways {
+                let check = match std::fs::read_to_string(&conf_path) {
+                    Ok(content) => libvirt.check_upstream(
+                        &gateway,
+                        &ProxyConfigBuilder::parse_proxy_conf(&content, &role),
+                    ),
+                    Err(e) => {
+                        UpstreamCheck::Skipped(format!("Can't read {}: {}", conf_path.display(), e))
+                    }
+                };
+                tx.send(AsyncMessage::GatewayTested {
+                    role,
+                    gateway,
+                    check,
+                })
+                .ok();
+            }
+            tx.send(AsyncMessage::GatewayTestsDone).ok();
+        });
+    }
+
+    pub fn start_vm(&mut self, name: &str) {
+        let libvirt = self.libvirt.clone();
+        let name = name.to_string();
+        self.ops.submit(format!("Starting {}", name), move || {
+            // Checked first so a boot failure comes with the chmod that fixes it
+            let issues = libvirt
+                .get_vm_disk_path(&name)
+                .ok()
+                .flatten()
+                .and_then(|disk| libvirt.check_disk_permissions(&disk).ok())
+                .unwrap_or_default();
+            let (level, mut message) = match libvirt.start_vm(&name) {
+                Ok(StartOutcome::Started) => {
+                    (StatusLevel::Success, format!("Started VM: {}", name))
+                }
+                Ok(StartOutcome::AlreadyRunning) => (
+                    StatusLevel::Warning,
+                    format!("VM '{}' is already running", name),
+                ),
+                Err(e) => (StatusLevel::Error, format!("Failed to start VM: {}", e)),
+            };
+            if issues.is_empty() {
+                return (level, message);
+            }
+            for issue in &issues {
+                message.push_str(&format!("\n⚠ {}", issue));
+            }
+            let level = if level == StatusLevel::Error {
+                level
+            } else {
+                StatusLevel::Warning
+            };
+            (level, message)
+        });
+    }
+
+    /// TRIM a running VM's filesystems so space it freed goes back to the host
+    pub fn trim_vm_disk(&mut self, name: &str) {
+        let libvirt = self.libvirt.clone();
+        let name = name.to_string();
+        self.ops
+            .submit(
+                format!("Trimming disk of {}", name),
+                move || match libvirt.trim_vm_disk(&name) {
+                    Ok(()) => (StatusLevel::Success, format!("Trimmed disk of '{}'", name)),
+                    Err(e) => (StatusLevel::Error, e.to_string()),
+                },
+            );
+    }
+
+    /// Rename a shut-off VM, optionally moving its disk to `<new>-overlay.qcow2`
+    pub fn rename_vm(&mut self, old_name: &str, new_name: &str, rename_disk: bool) {
+        let libvirt = self.libvirt.clone();
+        let old_name = old_name.to_string();
+        let new_name = new_name.trim().to_string();
+        self.ops.submit(
+            format!("Renaming {} to {}", old_name, new_name),
+            move || {
+                if let Err(e) = libvirt.rename_vm(&old_name, &new_name) {
+                    return (StatusLevel::Error, e.to_string());
+                }
+                let renamed = format!("Renamed '{}' to '{}'", old_name, new_name);
+                if !rename_disk {
+                    return (StatusLevel::Success, renamed);
+                }
+                match libvirt.rename_vm_overlay(&new_name) {
+                    Ok(disk) => (
+                        StatusLevel::Success,
+                        format!("{}; disk is now {}", renamed, disk.display()),
+                    ),
+                    Err(e) => (
+                        StatusLevel::Warning,
+                        format!("{}, but its disk kept its old name: {}", renamed, e),
+                    ),
+                }
+            },
+        );
+    }
+
+    /// Check via the guest agent that the gateway has its role directory mounted at /proxy
+    pub fn verify_proxy_mount(&mut self, name: &str) {
+        match self.libvirt.check_proxy_mount(name) {
+            Ok(ProxyMountStatus::Mounted) => {
+                self.set_status(
+                    StatusLevel::Success,
+                    format!("/proxy is mounted in '{}'", name),
+                );
+            }
+            Ok(ProxyMountStatus::Missing) => {
+                self.set_status(
+                    StatusLevel::Error,
+                    format!("'{}' cannot see /proxy/proxy.conf - see Logs", name),
+                );
+                self.log(
+                    StatusLevel::Warning,
+                    proxy_vm_core::libvirt::PROXY_MOUNT_HELP,
+                );
+            }
+            Ok(ProxyMountStatus::AgentUnavailable) => {
+                self.set_status(
+                    StatusLevel::Warning,
+                    format!(
+                        "Guest agent is not responding in '{}'; install qemu-guest-agent to enable this check",
+                        name
+                    ),
+                );
+            }
+            Err(e) => {
+                self.set_status(StatusLevel::Error, format!("Mount check failed: {}", e));
             }
         }
     }
 
-    pub fn reset_wizard(&mut self) {
-        // Clean up any partial resources from previous wizard run
-        self.cleanup_wizard_resources();
+    /// Merge the intermediate layers of a shut-off VM's disk onto its base image
+    pub fn flatten_vm_disk(&mut self, name: &str) {
+        let libvirt = self.libvirt.clone();
+        let name = name.to_string();
+        self.ops.submit(
+            format!("Flattening disk of {}", name),
+            move || match libvirt.flatten_vm_disk(&name) {
+                Ok(depth) => (
+                    StatusLevel::Success,
+                    format!("Flattened disk of '{}' to {} backing level(s)", name, depth),
+                ),
+                Err(e) => (
+                    StatusLevel::Error,
+                    format!("Failed to flatten disk of '{}': {}", name, e),
+                ),
+            },
+        );
+    }
+
+    /// Change what libvirt does when the role's gateway crashes, in both the
+    /// domain definition and the role metadata
+    pub fn set_gateway_crash_policy(&mut self, role: &str, policy: CrashPolicy) {
+        let libvirt = self.libvirt.clone();
+        let cfg_root = self.global_config.cfg.root.clone();
+        let gw_name = self.global_config.naming.gateway_name(role);
+        let role = role.to_string();
+        self.ops
+            .submit(format!("Updating crash action of {}", gw_name), move || {
+                let result = libvirt.set_on_crash(&gw_name, policy).and_then(|()| {
+                    let mut meta = RoleMeta::load(&cfg_root, &role)?;
+                    meta.on_crash = policy;
+                    meta.save(&cfg_root)
+                });
+                match result {
+                    Ok(()) => (
+                        StatusLevel::Success,
+                        match policy {
+                            CrashPolicy::Restart => {
+                                format!("'{}' will restart if it crashes", gw_name)
+                            }
+                            CrashPolicy::Leave => {
+                                format!("'{}' will stay down if it crashes", gw_name)
+                            }
+                        },
+                    ),
+                    Err(e) => (
+                        StatusLevel::Error,
+                        format!("Failed to update crash action of '{}': {}", gw_name, e),
+                    ),
+                }
+            });
+    }
+
+    pub fn stop_vm(&mut self, name: &str) {
+        let libvirt = self.libvirt.clone();
+        let name = name.to_string();
+        self.ops.submit(format!("Stopping {}", name), move || {
+            match libvirt.stop_vm(&name) {
+                Ok(StopOutcome::ShuttingDown) => {
+                    (StatusLevel::Success, format!("Stopping VM: {}", name))
+                }
+                Ok(StopOutcome::AlreadyStopped) => (
+                    StatusLevel::Warning,
+                    format!("VM '{}' is already stopped", name),
+                ),
+                Err(e) => (StatusLevel::Error, format!("Failed to stop VM: {}", e)),
+            }
+        });
+    }
 
+    pub fn reset_wizard(&mut self) {
         self.wizard = WizardState::default();
         // Add initial proxy hop
         self.wizard.proxy_hops.push(ProxyHopEntry::default());
@@ -802,6 +2214,150 @@ impl ProxyVmWizardApp {
         self.wizard.pending_mode_change = None;
     }
 
+    /// Measure the space taken on the host by a role's gateway, app and
+    /// disposable disks in the background
+    pub fn fetch_disk_usage(&mut self, role: &str) {
+        if !self.usage_checks_in_flight.insert(role.to_string()) {
+            return;
+        }
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        let cfg_root = self.global_config.cfg.root.clone();
+        let images_dir = self.global_config.libvirt.images_dir.clone();
+        let role = role.to_string();
+        std::thread::spawn(move || {
+            let app_vm_count = RoleMeta::load(&cfg_root, &role)
+                .map(|meta| meta.app_vm_count)
+                .unwrap_or(0);
+            let result = libvirt
+                .role_disks(&images_dir, &cfg_root, &role, app_vm_count)
+                .iter()
+                .map(|disk| libvirt.image_info(disk).map(|info| info.disk_size))
+                .sum::<proxy_vm_core::Result<u64>>()
+                .map_err(|e| e.to_string());
+            tx.send(AsyncMessage::DiskUsage { role, result }).ok();
+        });
+    }
+
+    /// A role's proxy.conf with its secrets masked, for pasting into bug reports
+    pub fn redacted_role_config(&mut self, role: &str) -> Option<String> {
+        let conf_path = self.global_config.role_dir(role).join("proxy.conf");
+        match std::fs::read_to_string(&conf_path) {
+            Ok(content) => Some(format!(
+                "# Secrets redacted for sharing; this file will not work as is\n{}",
+                ProxyConfigBuilder::redact_conf_text(&content)
+            )),
+            Err(e) => {
+                self.set_status(
+                    StatusLevel::Error,
+                    format!("Failed to read {}: {}", conf_path.display(), e),
+                );
+                None
+            }
+        }
+    }
+
+    /// Open the read-only viewer on a role's generated files, or reload it
+    pub fn view_generated_files(&mut self, role: &str) {
+        let role_dir = self.global_config.role_dir(role);
+        let read = |name: &str| {
+            let path = role_dir.join(name);
+            std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        };
+        let conf = read("proxy.conf");
+        let apply_script = read("apply-proxy.sh");
+        let apply_plan = conf
+            .as_ref()
+            .map(|content| {
+                ProxyConfigBuilder::describe_apply_plan(&ProxyConfigBuilder::parse_proxy_conf(
+                    content, role,
+                ))
+            })
+            .unwrap_or_default();
+        let script_is_generated = apply_script
+            .as_ref()
+            .is_ok_and(|s| *s == ProxyConfigBuilder::generate_apply_proxy_script(role));
+        let tab = self
+            .generated_files
+            .as_ref()
+            .filter(|view| view.role == role)
+            .map_or(GeneratedFile::ProxyConf, |view| view.tab);
+
+        self.generated_files = Some(GeneratedFilesView {
+            role: role.to_string(),
+            tab,
+            proxy_conf: conf.map(|content| ProxyConfigBuilder::redact_conf_text(&content)),
+            apply_plan,
+            apply_script,
+            script_is_generated,
+        });
+    }
+
+    /// Open the gateway logs viewer and read proxychains.conf and the
+    /// apply-proxy journal through the guest agent in the background
+    pub fn view_gateway_logs(&mut self, gateway: &str) {
+        if self
+            .gateway_logs
+            .as_ref()
+            .is_some_and(|view| view.gateway == gateway && view.is_loading())
+        {
+            return;
+        }
+        self.gateway_logs = Some(GatewayLogsView {
+            gateway: gateway.to_string(),
+            proxychains: None,
+            journal: None,
+        });
+        let libvirt = self.libvirt.clone();
+        let gateway = gateway.to_string();
+        let tx = self.async_tx.clone();
+        std::thread::spawn(move || {
+            let proxychains = libvirt
+                .guest_read_file(&gateway, PROXYCHAINS_CONF, GATEWAY_LOG_MAX_BYTES)
+                .map(|text| ProxyConfigBuilder::redact_proxychains_text(&text))
+                .map_err(|e| e.to_string());
+            // Without an agent the journal read would fail the same way
+            let journal = match &proxychains {
+                Err(e) if !libvirt.guest_agent_available(&gateway) => Err(e.clone()),
+                _ => libvirt
+                    .gateway_journal(&gateway, GATEWAY_JOURNAL_LINES)
+                    .map_err(|e| e.to_string()),
+            };
+            tx.send(AsyncMessage::GatewayLogs {
+                gateway,
+                proxychains,
+                journal,
+            })
+            .ok();
+        });
+    }
+
+    /// Check the role's proxy.conf as the gateway script would read it
+    pub fn validate_role_config(&mut self, role: &str) {
+        let role_dir = self.global_config.role_dir(role);
+        let conf_path = role_dir.join("proxy.conf");
+        let issues = match std::fs::read_to_string(&conf_path) {
+            Ok(content) => ProxyConfigBuilder::validate_proxy_conf(&content, &role_dir),
+            Err(e) => vec![format!("Failed to read {}: {}", conf_path.display(), e)],
+        };
+        if issues.is_empty() {
+            self.set_status(
+                StatusLevel::Success,
+                format!("proxy.conf of '{}' is valid", role),
+            );
+        } else {
+            for issue in &issues {
+                self.log(StatusLevel::Warning, format!("{}: {}", role, issue));
+            }
+            self.set_status(
+                StatusLevel::Warning,
+                format!("proxy.conf of '{}' has {} issue(s)", role, issues.len()),
+            );
+        }
+        self.conf_validation.insert(role.to_string(), issues);
+    }
+
     /// Start editing a role's gateway configuration
     pub fn start_editing_role_config(&mut self, role: &str) {
         // Load current config from role metadata
@@ -819,6 +2375,9 @@ impl ProxyVmWizardApp {
         if let Ok(content) = std::fs::read_to_string(&conf_path) {
             self.parse_proxy_conf_into_editor(&content);
         }
+        self.config_editor.last_backup = ProxyConfigBuilder::list_conf_backups(&role_dir)
+            .into_iter()
+            .next();
 
         // Ensure at least one proxy hop exists
         if self.config_editor.proxy_hops.is_empty() {
@@ -830,82 +2389,35 @@ impl ProxyVmWizardApp {
 
     /// Parse proxy.conf content into the config editor state
     fn parse_proxy_conf_into_editor(&mut self, content: &str) {
-        let mut values: std::collections::HashMap<String, String> =
-            std::collections::HashMap::new();
-
-        for line in content.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
-            if let Some((key, value)) = line.split_once('=') {
-                values.insert(key.to_string(), value.to_string());
-            }
-        }
-
-        // Parse gateway mode
-        if let Some(mode) = values.get("GATEWAY_MODE") {
-            self.config_editor.gateway_mode = match mode.as_str() {
-                "PROXY_CHAIN" => GatewayMode::ProxyChain,
-                "WIREGUARD" => GatewayMode::WireGuard,
-                "OPENVPN" => GatewayMode::OpenVpn,
-                _ => GatewayMode::ProxyChain,
-            };
-        }
-
-        // Parse proxy chain
-        if let Some(count_str) = values.get("PROXY_COUNT") {
-            if let Ok(count) = count_str.parse::<usize>() {
-                self.config_editor.proxy_hops.clear();
-                for i in 1..=count {
-                    let mut hop = ProxyHopEntry::default();
-                    if let Some(t) = values.get(&format!("PROXY_{}_TYPE", i)) {
-                        hop.proxy_type = if t == "HTTP" {
-                            ProxyType::Http
-                        } else {
-                            ProxyType::Socks5
-                        };
-                    }
-                    if let Some(h) = values.get(&format!("PROXY_{}_HOST", i)) {
-                        hop.host = h.clone();
-                    }
-                    if let Some(p) = values.get(&format!("PROXY_{}_PORT", i)) {
-                        hop.port = p.clone();
-                    }
-                    if let Some(u) = values.get(&format!("PROXY_{}_USER", i)) {
-                        hop.username = u.clone();
-                    }
-                    if let Some(p) = values.get(&format!("PROXY_{}_PASS", i)) {
-                        hop.password = p.clone();
-                    }
-                    if let Some(l) = values.get(&format!("PROXY_{}_LABEL", i)) {
-                        hop.label = l.clone();
-                    }
-                    self.config_editor.proxy_hops.push(hop);
-                }
-            }
-        }
-
-        // Parse WireGuard config
-        if let Some(path) = values.get("WG_CONFIG_PATH") {
-            self.config_editor.wireguard_config.config_filename = path.replace("/proxy/", "");
-        }
-        if let Some(iface) = values.get("WG_INTERFACE_NAME") {
-            self.config_editor.wireguard_config.interface_name = iface.clone();
-        }
-        if let Some(route) = values.get("WG_ROUTE_ALL_TRAFFIC") {
-            self.config_editor.wireguard_config.route_all_traffic = route == "true";
+        let config = ProxyConfigBuilder::parse_proxy_conf(content, "");
+        self.config_editor.gateway_mode = config.gateway_mode;
+
+        self.config_editor.proxy_hops = config.hops.iter().map(ProxyHopEntry::from_hop).collect();
+        // What the gateway actually sources, in case proxy.conf was edited by hand
+        self.config_editor.extra_env = config.extra_env.into_iter().collect();
+
+        if let Some(wg) = config.wireguard {
+            self.config_editor.wireguard_config.config_filename =
+                wg.config_path.replace("/proxy/", "");
+            self.config_editor.wireguard_config.interface_name = wg.interface_name;
+            self.config_editor.wireguard_config.route_all_traffic = wg.route_all_traffic;
+            self.config_editor.wireguard_config.kill_switch = wg.kill_switch;
         }
 
-        // Parse OpenVPN config
-        if let Some(path) = values.get("OPENVPN_CONFIG_PATH") {
-            self.config_editor.openvpn_config.config_filename = path.replace("/proxy/", "");
-        }
-        if let Some(auth) = values.get("OPENVPN_AUTH_FILE") {
-            self.config_editor.openvpn_config.auth_filename = auth.replace("/proxy/", "");
-        }
-        if let Some(route) = values.get("OPENVPN_ROUTE_ALL_TRAFFIC") {
-            self.config_editor.openvpn_config.route_all_traffic = route == "true";
+        if let Some(ovpn) = config.openvpn {
+            self.config_editor.openvpn_config.config_filename =
+                ovpn.config_path.replace("/proxy/", "");
+            self.config_editor.openvpn_config.auth_filename = ovpn
+                .auth_file
+                .map(|auth| auth.replace("/proxy/", ""))
+                .unwrap_or_default();
+            self.config_editor.openvpn_config.route_all_traffic = ovpn.route_all_traffic;
+            self.config_editor.openvpn_config.kill_switch = ovpn.kill_switch;
+            self.config_editor.openvpn_config.proto = ovpn.proto;
+            self.config_editor.openvpn_config.local_port = ovpn
+                .local_port
+                .map(|port| port.to_string())
+                .unwrap_or_default();
         }
     }
 
@@ -917,30 +2429,25 @@ impl ProxyVmWizardApp {
         };
 
         let role_dir = self.global_config.role_dir(&role);
-        let gw_name = format!("{}-gw", role);
+        let gw_name = self.global_config.naming.gateway_name(&role);
 
         // Build proxy config from editor state
         let mut config = ProxyConfig::new(role.clone(), self.config_editor.gateway_mode);
 
         match self.config_editor.gateway_mode {
             GatewayMode::ProxyChain => {
-                for (i, hop) in self.config_editor.proxy_hops.iter().enumerate() {
+                for hop in &self.config_editor.proxy_hops {
                     if hop.host.is_empty() {
                         continue;
                     }
-                    let port = hop.port.parse().unwrap_or(1080);
-                    let mut proxy_hop =
-                        ProxyHop::new((i + 1) as u8, hop.proxy_type, hop.host.clone(), port);
-                    if !hop.username.is_empty() {
-                        proxy_hop.username = Some(hop.username.clone());
-                    }
-                    if !hop.password.is_empty() {
-                        proxy_hop.password = Some(hop.password.clone());
-                    }
-                    if !hop.label.is_empty() {
-                        proxy_hop.label = Some(hop.label.clone());
+                    // Number by kept hops, so skipped blank rows leave no gap
+                    match hop.to_hop(config.hops.len() as u8 + 1) {
+                        Ok(proxy_hop) => config.add_hop(proxy_hop),
+                        Err(e) => {
+                            self.set_status(StatusLevel::Error, e);
+                            return;
+                        }
                     }
-                    config.add_hop(proxy_hop);
                 }
             }
             GatewayMode::WireGuard => {
@@ -960,9 +2467,17 @@ impl ProxyVmWizardApp {
                         self.config_editor.wireguard_config.interface_name.clone()
                     },
                     route_all_traffic: self.config_editor.wireguard_config.route_all_traffic,
+                    kill_switch: self.config_editor.wireguard_config.kill_switch,
                 });
             }
             GatewayMode::OpenVpn => {
+                let local_port = match self.config_editor.openvpn_config.local_port() {
+                    Ok(port) => port,
+                    Err(e) => {
+                        self.config_editor.error = Some(e);
+                        return;
+                    }
+                };
                 config.openvpn = Some(OpenVpnConfig {
                     config_path: format!(
                         "/proxy/{}",
@@ -977,43 +2492,105 @@ impl ProxyVmWizardApp {
                         ))
                     },
                     route_all_traffic: self.config_editor.openvpn_config.route_all_traffic,
+                    kill_switch: self.config_editor.openvpn_config.kill_switch,
+                    proto: self.config_editor.openvpn_config.proto,
+                    local_port,
                 });
             }
         }
 
-        // Write config files
-        if let Err(e) = ProxyConfigBuilder::write_config_files(&config, &role_dir) {
-            self.config_editor.error = Some(format!("Failed to save config: {}", e));
-            return;
-        }
-
+        for (key, value) in &self.config_editor.extra_env {
+            let key = key.trim();
+            if key.is_empty() {
+                continue;
+            }
+            if let Err(e) = validate_extra_env_key(key) {
+                self.config_editor.error = Some(format!("Extra environment: {}", e));
+                return;
+            }
+            config.extra_env.insert(key.to_string(), value.clone());
+        }
+
+        // Nothing is written, and the gateway keeps running, until the VPN files are there
+        if let Err(e) = ProxyConfigBuilder::check_referenced_files(&config, &role_dir) {
+            self.config_editor.error = Some(format!("Not saved: {}", e));
+            return;
+        }
+
+        // Write config files
+        if let Err(e) = ProxyConfigBuilder::write_config_files(&config, &role_dir) {
+            self.config_editor.error = Some(format!("Failed to save config: {}", e));
+            return;
+        }
+        self.conf_validation.remove(&role);
+
         // Update role meta
         if let Ok(mut meta) = RoleMeta::load(&self.global_config.cfg.root, &role) {
             meta.gateway_mode = self.config_editor.gateway_mode;
             meta.save(&self.global_config.cfg.root).ok();
         }
 
-        // Restart VM if requested
-        if self.config_editor.restart_after_save {
-            // Stop the VM
-            self.libvirt.stop_vm(&gw_name).ok();
-            // Wait a moment then start
-            std::thread::sleep(std::time::Duration::from_millis(500));
-            if let Err(e) = self.libvirt.start_vm(&gw_name) {
-                self.log(
-                    StatusLevel::Warning,
-                    format!("Config saved but VM restart failed: {}", e),
-                );
-            } else {
-                self.log(
-                    StatusLevel::Success,
-                    format!("Config saved and VM '{}' restarting", gw_name),
-                );
+        self.finish_role_config_change(gw_name, "saved");
+    }
+
+    /// Restore the newest proxy.conf backup of the edited role ("last known
+    /// good") and optionally restart the gateway VM
+    pub fn revert_role_config(&mut self) {
+        let role = match &self.editing_role_config {
+            Some(r) => r.clone(),
+            None => return,
+        };
+        let role_dir = self.global_config.role_dir(&role);
+        let gw_name = self.global_config.naming.gateway_name(&role);
+
+        let config = match ProxyConfigBuilder::restore_last_conf_backup(&role_dir, &role) {
+            Ok(config) => config,
+            Err(e) => {
+                self.config_editor.error = Some(format!("Failed to revert config: {}", e));
+                return;
             }
+        };
+        self.conf_validation.remove(&role);
+        if let Ok(mut meta) = RoleMeta::load(&self.global_config.cfg.root, &role) {
+            meta.gateway_mode = config.gateway_mode;
+            meta.save(&self.global_config.cfg.root).ok();
+        }
+
+        self.finish_role_config_change(gw_name, "reverted");
+    }
+
+    /// Close the config editor after proxy.conf changed, restarting the
+    /// gateway if requested; `done` says what happened ("saved", "reverted")
+    fn finish_role_config_change(&mut self, gw_name: String, done: &'static str) {
+        if self.config_editor.restart_after_save {
+            let libvirt = self.libvirt.clone();
+            self.ops.submit(format!("Restarting {}", gw_name), move || {
+                // Stop the VM
+                libvirt.stop_vm(&gw_name).ok();
+                // Wait a moment then start
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                match libvirt.start_vm(&gw_name) {
+                    Err(e) => (
+                        StatusLevel::Warning,
+                        format!("Config {} but VM restart failed: {}", done, e),
+                    ),
+                    Ok(StartOutcome::Started) => (
+                        StatusLevel::Success,
+                        format!("Config {} and VM '{}' restarting", done, gw_name),
+                    ),
+                    Ok(StartOutcome::AlreadyRunning) => (
+                        StatusLevel::Warning,
+                        format!(
+                            "Config {} but VM '{}' is still shutting down; start it again once it stops",
+                            done, gw_name
+                        ),
+                    ),
+                }
+            });
         } else {
             self.set_status(
                 StatusLevel::Success,
-                "Configuration saved. Restart VM to apply changes.",
+                format!("Configuration {}. Restart VM to apply changes.", done),
             );
         }
 
@@ -1026,60 +2603,276 @@ impl ProxyVmWizardApp {
         self.editing_role_config = None;
     }
 
-    /// Clean up any resources created during a failed/cancelled wizard run
-    pub fn cleanup_wizard_resources(&mut self) {
-        // Clean up VM first
-        if let Some(ref vm_name) = self.wizard.created_vm.take() {
-            self.log(
-                StatusLevel::Warning,
-                format!("Cleaning up VM '{}'...", vm_name),
-            );
-            self.libvirt.destroy_vm(vm_name).ok();
-            self.libvirt.undefine_vm(vm_name).ok();
+    /// Add the proxies in a CSV file to a role's rotation pool
+    pub fn import_rotation_pool(&mut self, role: &str, path: PathBuf) {
+        let cfg_root = self.global_config.cfg.root.clone();
+        let role = role.to_string();
+        self.ops
+            .submit(format!("Importing rotation pool of {}", role), move || {
+                let result = std::fs::read_to_string(&path)
+                    .map_err(proxy_vm_core::Error::from)
+                    .and_then(|content| ProxyHop::parse_csv(&content))
+                    .and_then(|hops| {
+                        let mut meta = RoleMeta::load(&cfg_root, &role)?;
+                        let policy = meta.rotation.as_mut().ok_or_else(|| {
+                            proxy_vm_core::Error::validation("Role has no rotation policy")
+                        })?;
+                        let found = hops.len();
+                        let added = policy.extend_pool(hops);
+                        policy
+                            .validate()
+                            .map_err(proxy_vm_core::Error::validation)?;
+                        meta.save(&cfg_root)?;
+                        Ok((added, found))
+                    });
+                match result {
+                    Ok((added, found)) => (
+                        StatusLevel::Success,
+                        format!(
+                            "Added {} of {} proxies from {} to the rotation pool of '{}'",
+                            added,
+                            found,
+                            path.display(),
+                            role
+                        ),
+                    ),
+                    Err(e) => (
+                        StatusLevel::Error,
+                        format!("Failed to import {}: {}", path.display(), e),
+                    ),
+                }
+            });
+    }
+
+    /// Rotate any role whose rotation slot has rolled over since the last check
+    fn check_rotations(&mut self) {
+        if self
+            .last_rotation_check
+            .is_some_and(|t| t.elapsed() < std::time::Duration::from_secs(30))
+        {
+            return;
+        }
+        self.last_rotation_check = Some(std::time::Instant::now());
+
+        let now = Utc::now();
+        let due: Vec<String> = self
+            .role_rotations
+            .iter()
+            .filter_map(|(role, policy)| {
+                let slot = policy.slot(now);
+                // The first sighting only records the slot; rotation happens on boundaries
+                match self.rotation_slots.insert(role.clone(), slot) {
+                    Some(previous) if previous != slot => Some(role.clone()),
+                    _ => None,
+                }
+            })
+            .collect();
+        for role in due {
+            self.rotate_role(&role);
         }
+    }
 
-        // Clean up overlay disk
-        if let Some(ref overlay_path) = self.wizard.created_overlay.take() {
-            if overlay_path.exists() {
+    /// Write the currently due exit hop into a role's proxy.conf and reapply it in the gateway
+    pub fn rotate_role(&mut self, role: &str) {
+        let Some(policy) = self.role_rotations.get(role).cloned() else {
+            return;
+        };
+        let role_dir = self.global_config.role_dir(role);
+        let hop = match rotate_role_proxy(&role_dir, role, &policy, Utc::now()) {
+            Ok(Some(hop)) => hop,
+            Ok(None) => return,
+            Err(e) => {
                 self.log(
+                    StatusLevel::Error,
+                    format!("Failed to rotate proxy for '{}': {}", role, e),
+                );
+                return;
+            }
+        };
+        self.log(
+            StatusLevel::Info,
+            format!(
+                "Rotated exit proxy for '{}' to {}:{}",
+                role, hop.host, hop.port
+            ),
+        );
+
+        let gw_name = self.global_config.naming.gateway_name(role);
+        let gw_running = self
+            .role_vms
+            .get(role)
+            .and_then(|vms| vms.iter().find(|v| v.name == gw_name))
+            .is_some_and(|vm| vm.state.is_running());
+        if !gw_running {
+            return;
+        }
+
+        let libvirt = self.libvirt.clone();
+        self.ops.submit(
+            format!("Reapplying proxy in {}", gw_name),
+            move || match libvirt.guest_exec(&gw_name, "/bin/bash", &["/proxy/apply-proxy.sh"]) {
+                Ok(output) if output.success() => (
+                    StatusLevel::Success,
+                    format!("Rotated proxy applied in '{}'", gw_name),
+                ),
+                Ok(output) => (
                     StatusLevel::Warning,
-                    format!("Cleaning up overlay disk '{}'...", overlay_path.display()),
+                    format!(
+                        "apply-proxy.sh failed in '{}': {}",
+                        gw_name,
+                        output.stderr.trim()
+                    ),
+                ),
+                Err(e) => (
+                    StatusLevel::Warning,
+                    format!(
+                        "Proxy rotated but not reapplied in '{}' ({}); restart the gateway",
+                        gw_name, e
+                    ),
+                ),
+            },
+        );
+    }
+
+    /// Rewrite a role's apply-proxy.sh with the current generated version
+    pub fn update_apply_script(&mut self, role: &str) {
+        let role_dir = self.global_config.role_dir(role);
+        match ProxyConfigBuilder::write_apply_proxy_script(role, &role_dir) {
+            Ok(()) => {
+                self.outdated_scripts.remove(role);
+                self.set_status(
+                    StatusLevel::Success,
+                    format!(
+                        "Updated apply-proxy.sh for '{}'. Restart its gateway to apply.",
+                        role
+                    ),
                 );
-                self.libvirt.delete_overlay_disk(overlay_path).ok();
             }
+            Err(e) => self.set_status(
+                StatusLevel::Error,
+                format!("Failed to update apply-proxy.sh for '{}': {}", role, e),
+            ),
         }
+    }
 
-        // Clean up network (only if we created it)
-        if let Some(ref net_name) = self.wizard.created_network.take() {
-            self.log(
-                StatusLevel::Warning,
-                format!("Cleaning up network '{}'...", net_name),
-            );
-            self.libvirt.destroy_network(net_name).ok();
+    /// Open the "Copy gateway config from…" dialog for a role
+    pub fn start_copy_gateway_config(&mut self, target: &str) {
+        self.copy_config_target = Some(target.to_string());
+        self.copy_config_source = None;
+        self.copy_config_restart = true;
+        self.copy_config_modes = self
+            .discovered_roles
+            .iter()
+            .filter_map(|role| Some((role.clone(), self.role_gateway_mode(role)?)))
+            .collect();
+    }
+
+    /// Gateway mode a role's proxy.conf is currently set to, if it has one
+    fn role_gateway_mode(&self, role: &str) -> Option<GatewayMode> {
+        let conf_path = self.global_config.role_dir(role).join("proxy.conf");
+        let content = std::fs::read_to_string(conf_path).ok()?;
+        Some(ProxyConfigBuilder::parse_proxy_conf(&content, role).gateway_mode)
+    }
+
+    /// Copy the chosen source role's gateway config into the dialog's target role
+    pub fn copy_gateway_config(&mut self) {
+        let (Some(target), Some(source)) = (
+            self.copy_config_target.clone(),
+            self.copy_config_source.clone(),
+        ) else {
+            return;
+        };
+        self.copy_config_target = None;
+
+        let config = match ProxyConfigBuilder::copy_gateway_config(
+            &self.global_config.role_dir(&source),
+            &self.global_config.role_dir(&target),
+            &target,
+        ) {
+            Ok(config) => config,
+            Err(e) => {
+                self.set_status(
+                    StatusLevel::Error,
+                    format!("Failed to copy gateway config from '{}': {}", source, e),
+                );
+                return;
+            }
+        };
+
+        if let Ok(mut meta) = RoleMeta::load(&self.global_config.cfg.root, &target) {
+            meta.gateway_mode = config.gateway_mode;
+            if let Err(e) = meta.save(&self.global_config.cfg.root) {
+                self.log(
+                    StatusLevel::Warning,
+                    format!("Failed to update role metadata for '{}': {}", target, e),
+                );
+            }
         }
 
-        // Clean up role directory (only if it's empty or only has our config files)
-        if let Some(ref role_dir) = self.wizard.created_role_dir.take() {
-            if role_dir.exists() {
-                // Only delete if directory is relatively empty (our files only)
-                if let Ok(entries) = std::fs::read_dir(role_dir) {
-                    let count = entries.count();
-                    if count <= 3 {
-                        // proxy.conf, apply-proxy.sh, role-meta.toml
-                        self.log(
-                            StatusLevel::Warning,
-                            format!("Cleaning up role directory '{}'...", role_dir.display()),
-                        );
-                        std::fs::remove_dir_all(role_dir).ok();
-                    }
+        let gw_name = self.global_config.naming.gateway_name(&target);
+        let gw_running = self
+            .role_vms
+            .get(&target)
+            .and_then(|vms| vms.iter().find(|v| v.name == gw_name))
+            .is_some_and(|vm| vm.state.is_running());
+
+        if self.copy_config_restart && gw_running {
+            let libvirt = self.libvirt.clone();
+            self.ops.submit(format!("Restarting {}", gw_name), move || {
+                libvirt.stop_vm(&gw_name).ok();
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                match libvirt.start_vm(&gw_name) {
+                    Err(e) => (
+                        StatusLevel::Warning,
+                        format!("Config copied but VM restart failed: {}", e),
+                    ),
+                    Ok(StartOutcome::Started) => (
+                        StatusLevel::Success,
+                        format!("Config copied and VM '{}' restarting", gw_name),
+                    ),
+                    Ok(StartOutcome::AlreadyRunning) => (
+                        StatusLevel::Warning,
+                        format!(
+                            "Config copied but VM '{}' is still shutting down; start it again once it stops",
+                            gw_name
+                        ),
+                    ),
                 }
-            }
+            });
+        } else {
+            self.set_status(
+                StatusLevel::Success,
+                format!(
+                    "Copied gateway config from '{}' to '{}'. Restart its gateway to apply.",
+                    source, target
+                ),
+            );
         }
+
+        self.refresh_vms();
     }
 
-    pub fn start_create_role_wizard(&mut self) {
+    /// Open the wizard for a new role, optionally with `template` already picked
+    /// for every VM kind its `role_kind` suits
+    pub fn start_create_role_wizard(&mut self, template: Option<&Template>) {
         self.reset_wizard();
         self.wizard.mode = WizardMode::Create;
+        if let Some(template) = template {
+            let id = Some(template.id.clone());
+            match template.role_kind {
+                RoleKind::ProxyGateway => self.wizard.selected_gw_template_id = id,
+                RoleKind::App => {
+                    self.wizard.selected_app_template_id = id.clone();
+                    self.wizard.selected_disp_template_id = id;
+                }
+                RoleKind::DisposableApp => self.wizard.selected_disp_template_id = id,
+                RoleKind::Generic => {
+                    self.wizard.selected_gw_template_id = id.clone();
+                    self.wizard.selected_app_template_id = id.clone();
+                    self.wizard.selected_disp_template_id = id;
+                }
+            }
+        }
         self.navigate_to(View::Wizard);
     }
 
@@ -1095,6 +2888,10 @@ impl ProxyVmWizardApp {
             self.wizard.selected_app_template_id = meta.app_template_id;
             self.wizard.selected_disp_template_id = meta.disp_template_id;
             self.wizard.gateway_mode = meta.gateway_mode;
+            self.wizard.network_mode = meta.network_mode;
+            self.wizard.cpuset = meta.cpuset.unwrap_or_default();
+            self.wizard.extra_networks = meta.extra_gateway_networks.join(", ");
+            self.wizard.restart_gw_on_crash = meta.on_crash == CrashPolicy::Restart;
         }
 
         self.navigate_to(View::Wizard);
@@ -1104,7 +2901,7 @@ impl ProxyVmWizardApp {
         match self.wizard.step {
             WizardStep::RoleBasics => {
                 let name = normalize_role_name(&self.wizard.role_name);
-                if let Err(e) = validate_role_name(&name) {
+                if let Err(e) = self.global_config.naming.validate_role(&name) {
                     self.wizard.role_name_error = Some(e);
                     return false;
                 }
@@ -1118,6 +2915,16 @@ impl ProxyVmWizardApp {
                     }
                 }
 
+                // "<name>-inet" must not be the LAN network
+                let lan_net = &self.global_config.libvirt.lan_net;
+                if validate_lan_net(lan_net, std::slice::from_ref(&name)).is_err() {
+                    self.wizard.role_name_error = Some(format!(
+                        "Role '{}' would get '{}-inet' as its internal network, which is the LAN network",
+                        name, name
+                    ));
+                    return false;
+                }
+
                 // Must have gateway template selected
                 if self.wizard.selected_gw_template_id.is_none() {
                     self.wizard.role_name_error =
@@ -1125,6 +2932,30 @@ impl ProxyVmWizardApp {
                     return false;
                 }
 
+                if let Err(e) = self.wizard_ipv6_subnet() {
+                    self.wizard.ipv6_error = Some(e);
+                    return false;
+                }
+                self.wizard.ipv6_error = None;
+
+                if let Err(e) = self.check_wizard_network_mode() {
+                    self.wizard.network_mode_error = Some(e);
+                    return false;
+                }
+                self.wizard.network_mode_error = None;
+
+                if let Err(e) = self.check_wizard_cpuset() {
+                    self.wizard.cpuset_error = Some(e);
+                    return false;
+                }
+                self.wizard.cpuset_error = None;
+
+                if let Err(e) = self.check_wizard_extra_networks(&name) {
+                    self.wizard.extra_networks_error = Some(e);
+                    return false;
+                }
+                self.wizard.extra_networks_error = None;
+
                 self.wizard.role_name_error = None;
                 true
             }
@@ -1139,7 +2970,7 @@ impl ProxyVmWizardApp {
                             if hop.host.is_empty() {
                                 return false;
                             }
-                            if hop.port.parse::<u16>().is_err() {
+                            if parse_port(&hop.port).is_err() {
                                 return false;
                             }
                         }
@@ -1148,7 +2979,15 @@ impl ProxyVmWizardApp {
                     GatewayMode::WireGuard => {
                         !self.wizard.wireguard_config.config_filename.is_empty()
                     }
-                    GatewayMode::OpenVpn => !self.wizard.openvpn_config.config_filename.is_empty(),
+                    GatewayMode::OpenVpn => {
+                        // A bad auth file only warns; it may be fixed inside the VM later
+                        self.wizard.openvpn_config.check_auth_file();
+                        if let Some(warning) = self.wizard.openvpn_config.auth_warning.clone() {
+                            self.log(StatusLevel::Warning, warning);
+                        }
+                        !self.wizard.openvpn_config.config_filename.is_empty()
+                            && self.wizard.openvpn_config.local_port().is_ok()
+                    }
                 }
             }
             _ => true,
@@ -1180,371 +3019,309 @@ impl ProxyVmWizardApp {
         };
     }
 
+    /// The wizard's optional IPv6 subnet, parsed
+    pub fn wizard_ipv6_subnet(&self) -> Result<Option<Ipv6Subnet>, String> {
+        let value = self.wizard.ipv6_subnet.trim();
+        if value.is_empty() {
+            Ok(None)
+        } else {
+            Ipv6Subnet::parse(value).map(Some)
+        }
+    }
+
+    /// The gateway CPU list, if any, names CPUs that exist on the host
+    fn check_wizard_cpuset(&self) -> Result<(), String> {
+        let cpuset = self.wizard.cpuset.trim();
+        if cpuset.is_empty() {
+            return Ok(());
+        }
+        let host_cpus = self.libvirt.host_cpu_count().map_err(|e| e.to_string())?;
+        validate_cpuset(cpuset, host_cpus)
+    }
+
+    /// Extra gateway networks from the wizard, in the order they were entered
+    fn wizard_extra_networks(&self) -> Vec<String> {
+        self.wizard
+            .extra_networks
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Extra gateway networks exist and don't repeat the LAN or role network
+    fn check_wizard_extra_networks(&self, role: &str) -> Result<(), String> {
+        let extra = self.wizard_extra_networks();
+        if extra.is_empty() {
+            return Ok(());
+        }
+        let lan_net = &self.global_config.libvirt.lan_net;
+        let role_net = format!("{}-inet", role);
+        let mut networks = vec![lan_net.clone(), role_net.clone()];
+        networks.extend(extra.iter().cloned());
+        validate_gateway_networks(&networks, lan_net, &role_net)?;
+        for network in &extra {
+            if !self
+                .libvirt
+                .network_exists(network)
+                .map_err(|e| e.to_string())?
+            {
+                return Err(format!("Network '{}' does not exist in libvirt", network));
+            }
+        }
+        Ok(())
+    }
+
+    /// The chosen network mode is well formed and, for a bridge, present on the host
+    fn check_wizard_network_mode(&self) -> Result<(), String> {
+        let mode = &self.wizard.network_mode;
+        mode.validate()?;
+        if let RoleNetworkMode::Bridge(bridge) = mode {
+            let bridges = self
+                .libvirt
+                .list_host_bridges()
+                .map_err(|e| e.to_string())?;
+            if !bridges.contains(bridge) {
+                return Err(format!("Host bridge '{}' does not exist", bridge));
+            }
+        }
+        Ok(())
+    }
+
     pub fn execute_wizard(&mut self) {
         self.wizard.is_executing = true;
-        self.wizard.execution_step = 0;
-        self.wizard.execution_messages.clear();
-        self.wizard.execution_error = None;
-
-        // Reset cleanup tracking
-        self.wizard.created_network = None;
-        self.wizard.created_overlay = None;
-        self.wizard.created_vm = None;
-        self.wizard.created_role_dir = None;
+        self.wizard.execution.clear();
 
         let role = normalize_role_name(&self.wizard.role_name);
-        let role_dir = self.global_config.role_dir(&role);
-        let role_net = format!("{}-inet", role);
-        let gw_name = format!("{}-gw", role);
+        let gw_name = self.global_config.naming.gateway_name(&role);
 
         // Step 1: Validate global config
-        self.wizard
-            .execution_messages
-            .push("Validating configuration...".to_string());
+        self.wizard.execution.begin("Validating configuration...");
         if let Err(e) = self.global_config.validate() {
-            self.wizard.execution_error = Some(format!("Config validation failed: {}", e));
+            self.wizard
+                .execution
+                .fail(format!("Config validation failed: {}", e));
             self.wizard.is_executing = false;
             return;
         }
-        self.wizard.execution_step = 1;
 
         // Step 2: Validate template
-        self.wizard
-            .execution_messages
-            .push("Checking template...".to_string());
-        let template_id = match self.wizard.selected_gw_template_id.as_ref() {
-            Some(id) => id.clone(),
-            None => {
-                self.wizard.execution_error = Some("No gateway template selected".to_string());
-                self.wizard.is_executing = false;
-                return;
-            }
-        };
-        let template = match self.template_registry.get(&template_id) {
+        self.wizard.execution.begin("Checking template...");
+        let template = match self
+            .wizard
+            .selected_gw_template_id
+            .as_ref()
+            .and_then(|id| self.template_registry.get(id))
+        {
             Some(t) => t.clone(),
             None => {
-                self.wizard.execution_error = Some("Gateway template not found".to_string());
+                self.wizard.execution.fail("Gateway template not found");
                 self.wizard.is_executing = false;
                 return;
             }
         };
         if let Err(e) = template.validate() {
-            self.wizard.execution_error = Some(format!("Template error: {}", e));
+            self.wizard.execution.fail(format!("Template error: {}", e));
             self.wizard.is_executing = false;
             return;
         }
-        self.wizard.execution_step = 2;
+        let ipv6_subnet = match self.wizard_ipv6_subnet() {
+            Ok(subnet) => subnet,
+            Err(e) => {
+                self.wizard.execution.fail(e);
+                self.wizard.is_executing = false;
+                return;
+            }
+        };
+        let app_template = if self.wizard.create_app_vm {
+            let found = self
+                .wizard
+                .selected_app_template_id
+                .as_ref()
+                .and_then(|id| self.template_registry.get(id))
+                .cloned();
+            if found.is_none() {
+                self.log(
+                    StatusLevel::Warning,
+                    "App template not found, skipping App VM creation",
+                );
+            }
+            found
+        } else {
+            None
+        };
 
-        // Step 3: Ensure LAN network exists
-        self.wizard.execution_messages.push(format!(
-            "Checking LAN network '{}'...",
-            self.global_config.libvirt.lan_net
-        ));
-        if let Err(e) = self
-            .libvirt
-            .ensure_lan_net_exists(&self.global_config.libvirt.lan_net)
-        {
-            self.wizard.execution_error = Some(e.to_string());
-            self.wizard.is_executing = false;
-            return;
+        // VPN configs picked from elsewhere are copied into the role directory,
+        // and the config refers to them by file name
+        let mut extra_files = Vec::new();
+        let picked = match self.wizard.gateway_mode {
+            GatewayMode::WireGuard => vec![&mut self.wizard.wireguard_config.config_filename],
+            GatewayMode::OpenVpn => vec![
+                &mut self.wizard.openvpn_config.config_filename,
+                &mut self.wizard.openvpn_config.auth_filename,
+            ],
+            _ => Vec::new(),
+        };
+        for filename in picked {
+            if let Some((path, name)) = picked_role_file(filename) {
+                *filename = name;
+                extra_files.push(path);
+            }
         }
-        self.wizard.execution_step = 3;
 
-        // Step 4: Create role network
-        self.wizard
-            .execution_messages
-            .push(format!("Creating role network '{}'...", role_net));
-        match self.libvirt.ensure_role_network(&role) {
-            Ok(created) => {
-                if created {
-                    self.wizard
-                        .execution_messages
-                        .push(format!("Created network '{}'", role_net));
-                    // Track for cleanup
-                    self.wizard.created_network = Some(role_net.clone());
-                } else {
-                    self.wizard
-                        .execution_messages
-                        .push(format!("Network '{}' already exists", role_net));
-                }
-            }
+        let proxy_config = match self.build_proxy_config() {
+            Ok(config) => config,
             Err(e) => {
-                self.wizard.execution_error = Some(format!("Failed to create network: {}", e));
+                self.wizard.execution.fail(e);
                 self.wizard.is_executing = false;
-                self.cleanup_wizard_resources();
                 return;
             }
         };
-        self.wizard.execution_step = 4;
+        self.wizard.execution.complete();
 
-        // Step 5: Copy VPN config files if needed and generate proxy config
-        self.wizard
-            .execution_messages
-            .push("Generating proxy configuration...".to_string());
+        let mut meta = RoleMeta::new(role.clone());
+        meta.gw_template_id = self.wizard.selected_gw_template_id.clone();
+        meta.app_template_id = self.wizard.selected_app_template_id.clone();
+        meta.disp_template_id = self.wizard.selected_disp_template_id.clone();
+        meta.gateway_mode = self.wizard.gateway_mode;
+        meta.ipv6_subnet = ipv6_subnet.as_ref().map(|subnet| subnet.to_string());
+        meta.network_mode = self.wizard.network_mode.clone();
+        meta.cpuset = Some(self.wizard.cpuset.trim().to_string()).filter(|c| !c.is_empty());
+        meta.extra_gateway_networks = self.wizard_extra_networks();
+        meta.on_crash = if self.wizard.restart_gw_on_crash {
+            CrashPolicy::Restart
+        } else {
+            CrashPolicy::Leave
+        };
 
-        // Create role directory first
-        let role_dir_existed = role_dir.exists();
-        if let Err(e) = std::fs::create_dir_all(&role_dir) {
-            self.wizard.execution_error = Some(format!("Failed to create role directory: {}", e));
-            self.wizard.is_executing = false;
-            self.cleanup_wizard_resources();
-            return;
-        }
-        // Track for cleanup only if we created it
-        if !role_dir_existed {
-            self.wizard.created_role_dir = Some(role_dir.clone());
-        }
-
-        // Copy WireGuard config if it's a file path
-        if self.wizard.gateway_mode == GatewayMode::WireGuard {
-            let wg_path = std::path::Path::new(&self.wizard.wireguard_config.config_filename);
-            if wg_path.exists() && wg_path.is_file() {
-                if let Some(filename) = wg_path.file_name() {
-                    let dest = role_dir.join(filename);
-                    if let Err(e) = std::fs::copy(wg_path, &dest) {
-                        self.wizard.execution_error =
-                            Some(format!("Failed to copy WireGuard config: {}", e));
-                        self.wizard.is_executing = false;
-                        self.cleanup_wizard_resources();
-                        return;
-                    }
-                    self.wizard
-                        .execution_messages
-                        .push(format!("Copied WireGuard config to {}", dest.display()));
-                    // Update to just the filename for the config
-                    self.wizard.wireguard_config.config_filename =
-                        filename.to_string_lossy().to_string();
-                }
-            }
-        }
-
-        // Copy OpenVPN configs if they're file paths
-        if self.wizard.gateway_mode == GatewayMode::OpenVpn {
-            let ovpn_path = std::path::Path::new(&self.wizard.openvpn_config.config_filename);
-            if ovpn_path.exists() && ovpn_path.is_file() {
-                if let Some(filename) = ovpn_path.file_name() {
-                    let dest = role_dir.join(filename);
-                    if let Err(e) = std::fs::copy(ovpn_path, &dest) {
-                        self.wizard.execution_error =
-                            Some(format!("Failed to copy OpenVPN config: {}", e));
-                        self.wizard.is_executing = false;
-                        self.cleanup_wizard_resources();
-                        return;
-                    }
-                    self.wizard
-                        .execution_messages
-                        .push(format!("Copied OpenVPN config to {}", dest.display()));
-                    self.wizard.openvpn_config.config_filename =
-                        filename.to_string_lossy().to_string();
-                }
+        // Steps 3+: everything that creates resources, rolled back as a unit. It
+        // runs on the operation queue; progress comes back as `WizardProgress`
+        let libvirt = self.libvirt.clone();
+        let config = self.global_config.clone();
+        let tx = self.async_tx.clone();
+        self.ops.submit(format!("Creating role {}", role), move || {
+            let spec = RoleSpec {
+                role: role.clone(),
+                gw_template: &template,
+                app_template: app_template.as_ref(),
+                proxy_config,
+                ipv6_subnet,
+                extra_files,
+                meta,
+            };
+            let result = create_role(&libvirt, &config, &spec, &mut |progress| {
+                tx.send(AsyncMessage::WizardProgress(progress)).ok();
+            })
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+
+            // Post-create check: the gateway is useless if it can't see /proxy. It has
+            // only just started booting, so wait for its guest agent in the background
+            if result.is_ok() {
+                let libvirt = libvirt.clone();
+                let tx = tx.clone();
+                let vm_name = gw_name.clone();
+                std::thread::spawn(move || {
+                    let result = libvirt
+                        .wait_for_proxy_mount(
+                            &vm_name,
+                            std::time::Duration::from_secs(
+                                proxy_vm_core::libvirt::PROXY_MOUNT_WAIT_SECS,
+                            ),
+                        )
+                        .map_err(|e| e.to_string());
+                    tx.send(AsyncMessage::ProxyMountChecked { vm_name, result })
+                        .ok();
+                });
             }
+            let status = match &result {
+                Ok(()) => (
+                    StatusLevel::Success,
+                    format!("Created role '{}' with gateway VM '{}'", role, gw_name),
+                ),
+                Err(e) => (
+                    StatusLevel::Error,
+                    format!("Failed to create role '{}': {}", role, e),
+                ),
+            };
+            tx.send(AsyncMessage::WizardFinished { result }).ok();
+            status
+        });
+    }
 
-            // Copy auth file if provided
-            if !self.wizard.openvpn_config.auth_filename.is_empty() {
-                let auth_path = std::path::Path::new(&self.wizard.openvpn_config.auth_filename);
-                if auth_path.exists() && auth_path.is_file() {
-                    if let Some(filename) = auth_path.file_name() {
-                        let dest = role_dir.join(filename);
-                        if let Err(e) = std::fs::copy(auth_path, &dest) {
-                            self.log(
-                                StatusLevel::Warning,
-                                format!("Failed to copy auth file: {}", e),
-                            );
-                        } else {
-                            self.wizard
-                                .execution_messages
-                                .push(format!("Copied auth file to {}", dest.display()));
-                            self.wizard.openvpn_config.auth_filename =
-                                filename.to_string_lossy().to_string();
-                        }
-                    }
-                }
-            }
+    /// Record a line of wizard progress from the role being created
+    fn apply_wizard_progress(&mut self, progress: RoleProgress) {
+        match progress {
+            RoleProgress::Step(msg) => self.wizard.execution.begin(msg),
+            RoleProgress::Note(msg) => self.wizard.execution.note(msg),
         }
+    }
 
-        let proxy_config = self.build_proxy_config();
-        if let Err(e) = ProxyConfigBuilder::write_config_files(&proxy_config, &role_dir) {
-            self.wizard.execution_error = Some(format!("Failed to write config: {}", e));
-            self.wizard.is_executing = false;
-            self.cleanup_wizard_resources();
+    /// Finish the wizard's execution step once the role was created or rolled back
+    fn finish_wizard(&mut self, result: Result<(), String>) {
+        self.wizard.is_executing = false;
+        if let Err(e) = result {
+            self.wizard
+                .execution
+                .fail(format!("Failed to create role: {}", e));
             return;
         }
-        self.wizard.execution_step = 5;
-
-        // Step 6: Create overlay disk
+        self.wizard.execution.complete();
         self.wizard
-            .execution_messages
-            .push("Creating overlay disk...".to_string());
-        let overlay_path = self
-            .libvirt
-            .gateway_overlay_path(&self.global_config.libvirt.images_dir, &role);
-        if let Err(e) = self
-            .libvirt
-            .create_overlay_disk(&template.path, &overlay_path)
-        {
-            self.wizard.execution_error = Some(format!("Failed to create overlay: {}", e));
-            self.wizard.is_executing = false;
-            self.cleanup_wizard_resources();
-            return;
-        }
-        // Track overlay for cleanup
-        self.wizard.created_overlay = Some(overlay_path.clone());
-        self.wizard.execution_step = 6;
+            .execution
+            .note("The /proxy mount is checked once the gateway has booted; see Logs");
 
-        // Step 7: Create gateway VM
-        self.wizard
-            .execution_messages
-            .push(format!("Creating gateway VM '{}'...", gw_name));
-        let ram_mb = template
-            .default_ram_mb
-            .max(self.global_config.defaults.gateway_ram_mb);
-        if let Err(e) = self.libvirt.create_gateway_vm(
-            &gw_name,
-            &overlay_path,
-            &self.global_config.libvirt.lan_net,
-            &role_net,
-            &role_dir,
-            &template.os_variant,
-            ram_mb,
-        ) {
-            self.wizard.execution_error = Some(format!("Failed to create VM: {}", e));
-            self.wizard.is_executing = false;
-            self.cleanup_wizard_resources();
-            return;
-        }
-        // Track VM for cleanup (though at this point we're almost done)
-        self.wizard.created_vm = Some(gw_name.clone());
-        self.wizard.execution_step = 7;
+        self.wizard.execution.note("✓ Role created successfully!");
+    }
 
-        // Step 8: Save role metadata
-        self.wizard
-            .execution_messages
-            .push("Saving role metadata...".to_string());
-        let mut meta = RoleMeta::new(role.clone());
-        meta.gw_template_id = self.wizard.selected_gw_template_id.clone();
-        meta.app_template_id = self.wizard.selected_app_template_id.clone();
-        meta.disp_template_id = self.wizard.selected_disp_template_id.clone();
-        meta.gateway_mode = self.wizard.gateway_mode;
-        if let Err(e) = meta.save(&self.global_config.cfg.root) {
-            self.log(
-                StatusLevel::Warning,
-                format!("Failed to save role metadata: {}", e),
-            );
-        }
-        self.wizard.execution_step = 8;
-
-        // Step 9: Create App VM if requested
-        if self.wizard.create_app_vm {
-            if let Some(ref app_template_id) = self.wizard.selected_app_template_id {
-                if let Some(app_template) = self.template_registry.get(app_template_id).cloned() {
-                    self.wizard
-                        .execution_messages
-                        .push("Creating App VM...".to_string());
-
-                    // Load and update meta for app VM numbering
-                    let mut meta = RoleMeta::load(&self.global_config.cfg.root, &role)
-                        .unwrap_or_else(|_| RoleMeta::new(role.clone()));
-                    let app_num = meta.next_app_number();
-                    let app_vm_name = meta.app_vm_name(app_num);
-
-                    // Create app overlay
-                    let app_overlay = self.libvirt.app_overlay_path(
-                        &self.global_config.libvirt.images_dir,
-                        &role,
-                        app_num,
-                    );
-                    if let Err(e) = self
-                        .libvirt
-                        .create_overlay_disk(&app_template.path, &app_overlay)
-                    {
-                        self.log(
-                            StatusLevel::Warning,
-                            format!("Failed to create App VM overlay: {}", e),
-                        );
-                    } else {
-                        // Create app VM
-                        let app_ram = app_template
-                            .default_ram_mb
-                            .max(self.global_config.defaults.app_ram_mb);
-                        if let Err(e) = self.libvirt.create_app_vm(
-                            &app_vm_name,
-                            &app_overlay,
-                            &role_net,
-                            &app_template.os_variant,
-                            app_ram,
-                            None,
-                        ) {
-                            self.log(
-                                StatusLevel::Warning,
-                                format!("Failed to create App VM: {}", e),
-                            );
-                            self.libvirt.delete_overlay_disk(&app_overlay).ok();
-                        } else {
-                            self.wizard
-                                .execution_messages
-                                .push(format!("✓ Created App VM '{}'", app_vm_name));
-                            // Save updated meta
-                            meta.save(&self.global_config.cfg.root).ok();
-                        }
-                    }
-                } else {
-                    self.log(
-                        StatusLevel::Warning,
-                        "App template not found, skipping App VM creation",
-                    );
+    /// Where each VPN file named in the wizard goes, for the confirmation step
+    pub fn role_file_preview(&self) -> Vec<RoleFilePreview> {
+        let role = normalize_role_name(&self.wizard.role_name);
+        let role_dir = self.global_config.role_dir(&role);
+        let named = match self.wizard.gateway_mode {
+            GatewayMode::WireGuard => vec![(
+                "WireGuard config",
+                &self.wizard.wireguard_config.config_filename,
+            )],
+            GatewayMode::OpenVpn => vec![
+                (
+                    "OpenVPN config",
+                    &self.wizard.openvpn_config.config_filename,
+                ),
+                (
+                    "OpenVPN auth file",
+                    &self.wizard.openvpn_config.auth_filename,
+                ),
+            ],
+            GatewayMode::ProxyChain => Vec::new(),
+        };
+        named
+            .into_iter()
+            .filter(|(_, filename)| !filename.is_empty())
+            .map(|(label, filename)| {
+                let (source, name) = match picked_role_file(filename) {
+                    Some((path, name)) => (Some(path), name),
+                    None => (None, filename.clone()),
+                };
+                RoleFilePreview {
+                    label,
+                    source,
+                    dest: role_dir.join(&name),
+                    guest_path: format!("/proxy/{}", name),
                 }
-            } else {
-                self.log(
-                    StatusLevel::Warning,
-                    "No App template selected, skipping App VM creation",
-                );
-            }
-        }
-
-        self.wizard
-            .execution_messages
-            .push("✓ Role created successfully!".to_string());
-        self.wizard.is_executing = false;
-
-        // Clear cleanup tracking - everything succeeded!
-        self.wizard.created_network = None;
-        self.wizard.created_overlay = None;
-        self.wizard.created_vm = None;
-        self.wizard.created_role_dir = None;
-
-        self.log(
-            StatusLevel::Success,
-            format!("Created role '{}' with gateway VM '{}'", role, gw_name),
-        );
-
-        // Refresh VM list
-        self.refresh_vms();
+            })
+            .collect()
     }
 
-    fn build_proxy_config(&self) -> ProxyConfig {
+    pub fn build_proxy_config(&self) -> Result<ProxyConfig, String> {
         let role = normalize_role_name(&self.wizard.role_name);
         let mut config = ProxyConfig::new(role, self.wizard.gateway_mode);
 
         match self.wizard.gateway_mode {
             GatewayMode::ProxyChain => {
                 for (i, hop_entry) in self.wizard.proxy_hops.iter().enumerate() {
-                    let port = hop_entry.port.parse().unwrap_or(1080);
-                    let mut hop = ProxyHop::new(
-                        (i + 1) as u8,
-                        hop_entry.proxy_type,
-                        hop_entry.host.clone(),
-                        port,
-                    );
-                    if !hop_entry.username.is_empty() {
-                        hop.username = Some(hop_entry.username.clone());
-                    }
-                    if !hop_entry.password.is_empty() {
-                        hop.password = Some(hop_entry.password.clone());
-                    }
-                    if !hop_entry.label.is_empty() {
-                        hop.label = Some(hop_entry.label.clone());
-                    }
-                    config.add_hop(hop);
+                    config.add_hop(hop_entry.to_hop((i + 1) as u8)?);
                 }
             }
             GatewayMode::WireGuard => {
@@ -1556,6 +3333,7 @@ impl ProxyVmWizardApp {
                         self.wizard.wireguard_config.interface_name.clone()
                     },
                     route_all_traffic: self.wizard.wireguard_config.route_all_traffic,
+                    kill_switch: self.wizard.wireguard_config.kill_switch,
                 });
             }
             GatewayMode::OpenVpn => {
@@ -1570,51 +3348,125 @@ impl ProxyVmWizardApp {
                         ))
                     },
                     route_all_traffic: self.wizard.openvpn_config.route_all_traffic,
+                    kill_switch: self.wizard.openvpn_config.kill_switch,
+                    proto: self.wizard.openvpn_config.proto,
+                    local_port: self.wizard.openvpn_config.local_port()?,
                 });
             }
         }
 
-        config
+        Ok(config)
     }
 
     pub fn test_proxy_connection(&mut self, index: usize) {
-        if index >= self.wizard.proxy_hops.len() {
-            return;
-        }
-
-        let hop = &self.wizard.proxy_hops[index];
-        let host = hop.host.clone();
-        let port: u16 = hop.port.parse().unwrap_or(0);
+        self.test_proxy_hops(&[index]);
+    }
 
-        if host.is_empty() || port == 0 {
-            self.wizard.proxy_hops[index].test_status = Some(false);
-            self.wizard.proxy_hops[index].test_message = Some("Invalid host or port".to_string());
-            return;
-        }
+    /// Test every wizard hop at once
+    pub fn test_all_proxy_hops(&mut self) {
+        let indices: Vec<usize> = (0..self.wizard.proxy_hops.len()).collect();
+        self.test_proxy_hops(&indices);
+    }
 
-        match self.libvirt.test_tcp_connection(&host, port) {
-            Ok(_) => {
-                self.wizard.proxy_hops[index].test_status = Some(true);
-                self.wizard.proxy_hops[index].test_message =
-                    Some("Connection successful".to_string());
+    /// Test TCP connectivity of wizard hops on a worker thread; each result
+    /// arrives as `ConnectionTestResult` as soon as that hop is done. The run
+    /// gets twice the per-hop timeout, since all hops connect side by side.
+    fn test_proxy_hops(&mut self, indices: &[usize]) {
+        let mut slots = Vec::new();
+        let mut endpoints = Vec::new();
+        for &index in indices {
+            let Some(hop) = self.wizard.proxy_hops.get_mut(index) else {
+                continue;
+            };
+            if hop.testing {
+                continue;
             }
-            Err(e) => {
-                self.wizard.proxy_hops[index].test_status = Some(false);
-                self.wizard.proxy_hops[index].test_message = Some(e.to_string());
+            match hop.endpoint() {
+                Ok(endpoint) => {
+                    hop.testing = true;
+                    hop.test_status = None;
+                    hop.test_message = None;
+                    slots.push(index);
+                    endpoints.push(endpoint);
+                }
+                Err(e) => {
+                    hop.test_status = Some(false);
+                    hop.test_message = Some(e);
+                }
             }
         }
+        if endpoints.is_empty() {
+            return;
+        }
+
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        let budget = std::time::Duration::from_secs(libvirt.connect_timeout_secs.max(1) * 2);
+        std::thread::spawn(move || {
+            libvirt.test_tcp_connections(&endpoints, budget, |i, result| {
+                let (success, message) = match result {
+                    Ok(()) => (true, "Connection successful".to_string()),
+                    Err(e) => (false, e.to_string()),
+                };
+                tx.send(AsyncMessage::ConnectionTestResult {
+                    index: slots[i],
+                    endpoint: endpoints[i].clone(),
+                    success,
+                    message,
+                })
+                .ok();
+            });
+        });
     }
 
-    pub fn create_app_vm(&mut self, role: &str) {
-        let role_net = format!("{}-inet", role);
+    /// App VMs of a role as of the last refresh
+    pub fn app_vm_total(&self, role: &str) -> usize {
+        self.role_vms.get(role).map_or(0, |vms| {
+            vms.iter().filter(|vm| vm.kind == VmKind::App).count()
+        })
+    }
+
+    /// Change how many app VMs a role may have
+    pub fn set_max_app_vms(&mut self, role: &str, max_app_vms: u32) {
+        // The dashboard already shows the new limit; the refresh after the job
+        // puts the saved value back if the write fails
+        self.role_max_app_vms.insert(role.to_string(), max_app_vms);
+        let cfg_root = self.global_config.cfg.root.clone();
+        let role = role.to_string();
+        self.ops
+            .submit(format!("Updating app VM limit of {}", role), move || {
+                let result = RoleMeta::load(&cfg_root, &role).and_then(|mut meta| {
+                    meta.max_app_vms = max_app_vms;
+                    meta.save(&cfg_root)
+                });
+                match result {
+                    Ok(()) => (
+                        StatusLevel::Success,
+                        format!("'{}' may now have up to {} app VM(s)", role, max_app_vms),
+                    ),
+                    Err(e) => (
+                        StatusLevel::Error,
+                        format!("Failed to save app VM limit of '{}': {}", role, e),
+                    ),
+                }
+            });
+    }
 
+    /// Create the next app VM of `role`, attaching `share_dir` from the host if given
+    pub fn create_app_vm(&mut self, role: &str, share_dir: Option<&Path>) {
+        if let Some(dir) = share_dir {
+            if let Err(e) = validate_share_dir(dir) {
+                self.set_status(StatusLevel::Error, e);
+                return;
+            }
+        }
         // Get app template
         let template_id = match RoleMeta::load(&self.global_config.cfg.root, role) {
             Ok(meta) => meta.app_template_id,
             Err(_) => None,
         };
 
-        let template = match template_id.and_then(|id| self.template_registry.get(&id)) {
+        let template = match template_id.and_then(|id| self.template_registry.get(&id).cloned()) {
             Some(t) => t,
             None => {
                 self.set_status(
@@ -1624,147 +3476,220 @@ impl ProxyVmWizardApp {
                 return;
             }
         };
-
-        // Get next app number
-        let mut meta = RoleMeta::load(&self.global_config.cfg.root, role)
-            .unwrap_or_else(|_| RoleMeta::new(role.to_string()));
-        let app_num = meta.next_app_number();
-        let vm_name = meta.app_vm_name(app_num);
-
-        // Create overlay
-        let overlay_path =
-            self.libvirt
-                .app_overlay_path(&self.global_config.libvirt.images_dir, role, app_num);
-        if let Err(e) = self
-            .libvirt
-            .create_overlay_disk(&template.path, &overlay_path)
-        {
-            self.set_status(
-                StatusLevel::Error,
-                format!("Failed to create overlay: {}", e),
-            );
+        if let Err(e) = template.require_disk_image() {
+            self.set_status(StatusLevel::Error, e.to_string());
             return;
         }
 
-        // Create VM
+        let libvirt = self.libvirt.clone();
+        let cfg_root = self.global_config.cfg.root.clone();
+        let images_dir = self.global_config.libvirt.images_dir.clone();
+        let naming = self.global_config.naming.clone();
         let ram_mb = template
             .default_ram_mb
             .max(self.global_config.defaults.app_ram_mb);
-        if let Err(e) = self.libvirt.create_app_vm(
-            &vm_name,
-            &overlay_path,
-            &role_net,
-            &template.os_variant,
-            ram_mb,
-            None,
-        ) {
-            self.libvirt.delete_overlay_disk(&overlay_path).ok();
-            self.set_status(StatusLevel::Error, format!("Failed to create VM: {}", e));
-            return;
-        }
+        let existing_apps = self.app_vm_total(role);
+        let role = role.to_string();
+        let share_dir = share_dir.map(Path::to_path_buf);
+        self.ops
+            .submit(format!("Creating app VM for {}", role), move || {
+                // Get next app number
+                let mut meta = RoleMeta::load(&cfg_root, &role)
+                    .unwrap_or_else(|_| RoleMeta::new(role.clone()));
+                if let Err(e) = meta.check_app_vm_limit(existing_apps) {
+                    return (StatusLevel::Error, e.to_string());
+                }
+                let app_num = meta.next_app_number();
+                let vm_name = meta.app_vm_name(&naming, app_num);
+
+                // Create overlay
+                let overlay_path = libvirt.app_overlay_path(&images_dir, &role, app_num);
+                if let Err(e) = libvirt.create_overlay_disk(&template.path, &overlay_path) {
+                    return (
+                        StatusLevel::Error,
+                        format!("Failed to create overlay: {}", e),
+                    );
+                }
 
-        // Save updated meta
-        if let Err(e) = meta.save(&self.global_config.cfg.root) {
-            self.log(
-                StatusLevel::Warning,
-                format!("Failed to save role metadata: {}", e),
-            );
+                // Create VM
+                if let Err(e) = libvirt.create_app_vm(
+                    &vm_name,
+                    &overlay_path,
+                    &format!("{}-inet", role),
+                    &template.os_variant,
+                    ram_mb,
+                    &template.disk_tuning,
+                    &template.boot,
+                    share_dir.as_deref(),
+                ) {
+                    libvirt.delete_overlay_disk(&overlay_path).ok();
+                    return (StatusLevel::Error, format!("Failed to create VM: {}", e));
+                }
+
+                // Save updated meta
+                if let Err(e) = meta.save(&cfg_root) {
+                    return (
+                        StatusLevel::Warning,
+                        format!(
+                            "Created app VM '{}' but failed to save role metadata: {}",
+                            vm_name, e
+                        ),
+                    );
+                }
+                with_disk_permission_issues(
+                    &libvirt,
+                    &overlay_path,
+                    format!("Created app VM: {}", vm_name),
+                )
+            });
+    }
+
+    /// Open the delete confirmation for a role, reading which of its disks are
+    /// its own and which base images under them stay for other roles
+    pub fn request_role_delete(&mut self, role: &str) {
+        let cfg_root = &self.global_config.cfg.root;
+        let app_vm_count = RoleMeta::load(cfg_root, role)
+            .map(|meta| meta.app_vm_count)
+            .unwrap_or(0);
+        let disks = self.libvirt.role_disks(
+            &self.global_config.libvirt.images_dir,
+            cfg_root,
+            role,
+            app_vm_count,
+        );
+        let disks = self.libvirt.role_disk_bases(&disks);
+
+        let this_role = format!("Role '{}' ", role);
+        let mut shared_bases: Vec<(PathBuf, Vec<String>)> = Vec::new();
+        for base in disks.iter().filter_map(|disk| disk.base.as_ref()) {
+            if shared_bases.iter().any(|(path, _)| path == base) {
+                continue;
+            }
+            let users = self
+                .template_registry
+                .list()
+                .into_iter()
+                .filter(|template| template.path == *base)
+                .flat_map(|template| {
+                    roles_using_template(cfg_root, &template.id).unwrap_or_default()
+                })
+                .filter(|user| !user.starts_with(&this_role))
+                .collect();
+            shared_bases.push((base.clone(), users));
         }
 
-        self.set_status(StatusLevel::Success, format!("Created app VM: {}", vm_name));
-        self.refresh_vms();
+        self.delete_disks = RoleDeleteDisks {
+            disks,
+            shared_bases,
+        };
+        self.pending_role_delete = Some(role.to_string());
+        self.delete_keep_disks = false;
     }
 
-    pub fn delete_role(&mut self, role: &str) {
+    /// Remove a role's VMs and network. With `keep_disks`, the gateway and app
+    /// overlays and the role directory stay for their data; they are not reused,
+    /// so creating the role again fails until the overlays are moved away.
+    pub fn delete_role(&mut self, role: &str, keep_disks: bool) {
         self.log(
             StatusLevel::Warning,
-            format!("Deleting role '{}' and all associated resources...", role),
+            if keep_disks {
+                format!(
+                    "Deleting VMs and network of role '{}', keeping disks...",
+                    role
+                )
+            } else {
+                format!("Deleting role '{}' and all associated resources...", role)
+            },
         );
 
+        let libvirt = self.libvirt.clone();
+        let cfg_root = self.global_config.cfg.root.clone();
+        let images_dir = self.global_config.libvirt.images_dir.clone();
         let role_net = format!("{}-inet", role);
-        let gw_name = format!("{}-gw", role);
+        let gw_name = self.global_config.naming.gateway_name(role);
         let role_dir = self.global_config.role_dir(role);
 
         // Get all VMs for this role
         let vms = self.role_vms.get(role).cloned().unwrap_or_default();
+        let role = role.to_string();
+        self.ops.submit(format!("Deleting role {}", role), move || {
+            // App VM disks: every numbered overlay on disk, plus the disks of the app
+            // VMs found, read before they are undefined
+            let mut app_overlays = Vec::new();
+            if !keep_disks {
+                let app_vm_count = RoleMeta::load(&cfg_root, &role)
+                    .map(|meta| meta.app_vm_count)
+                    .unwrap_or(0);
+                let vm_disks: Vec<PathBuf> = vms
+                    .iter()
+                    .filter(|vm| vm.kind == VmKind::App)
+                    .filter_map(|vm| libvirt.get_vm_disk_path(&vm.name).ok().flatten())
+                    .collect();
+                app_overlays =
+                    libvirt.role_app_overlays(&images_dir, &role, app_vm_count, &vm_disks);
+            }
 
-        // Delete all VMs (gateway, app VMs, disposables)
-        for vm in &vms {
-            self.log(
-                StatusLevel::Warning,
-                format!("Removing VM '{}'...", vm.name),
-            );
-            // Destroy if running
-            self.libvirt.destroy_vm(&vm.name).ok();
-            // Undefine
-            self.libvirt.undefine_vm(&vm.name).ok();
-        }
+            // Delete all VMs (gateway, app VMs, disposables)
+            for vm in &vms {
+                // Destroy if running
+                libvirt.destroy_vm(&vm.name).ok();
+                // Undefine
+                libvirt.undefine_vm(&vm.name).ok();
+            }
 
-        // Also try to delete the gateway VM by name pattern in case it wasn't in the list
-        self.libvirt.destroy_vm(&gw_name).ok();
-        self.libvirt.undefine_vm(&gw_name).ok();
+            // Also try to delete the gateway VM by name pattern in case it wasn't in the list
+            libvirt.destroy_vm(&gw_name).ok();
+            libvirt.undefine_vm(&gw_name).ok();
 
-        // Delete overlay disks
-        let gw_overlay = self
-            .libvirt
-            .gateway_overlay_path(&self.global_config.libvirt.images_dir, role);
-        if gw_overlay.exists() {
-            self.log(
-                StatusLevel::Warning,
-                format!("Removing overlay disk '{}'...", gw_overlay.display()),
-            );
-            self.libvirt.delete_overlay_disk(&gw_overlay).ok();
-        }
+            // Delete overlay disks
+            let gw_overlay = libvirt.gateway_overlay_path(&images_dir, &role);
+            if !keep_disks && gw_overlay.exists() {
+                libvirt.delete_overlay_disk(&gw_overlay).ok();
+            }
 
-        // Delete app VM overlays (try a few numbers)
-        for i in 1..=20 {
-            let app_overlay =
-                self.libvirt
-                    .app_overlay_path(&self.global_config.libvirt.images_dir, role, i);
-            if app_overlay.exists() {
-                self.libvirt.delete_overlay_disk(&app_overlay).ok();
+            for app_overlay in &app_overlays {
+                libvirt.delete_overlay_disk(app_overlay).ok();
             }
-        }
 
-        // Delete disposable overlay directory
-        let disp_dir = role_dir.join("disposable");
-        if disp_dir.exists() {
-            std::fs::remove_dir_all(&disp_dir).ok();
-        }
+            // Disposable overlays go either way; their VMs were throwaway
+            let disp_dir = role_dir.join("disposable");
+            if disp_dir.exists() {
+                std::fs::remove_dir_all(&disp_dir).ok();
+            }
 
-        // Destroy and undefine the role network
-        self.log(
-            StatusLevel::Warning,
-            format!("Removing network '{}'...", role_net),
-        );
-        self.libvirt.destroy_network(&role_net).ok();
+            // Destroy and undefine the role network
+            libvirt.destroy_network(&role_net).ok();
 
-        // Delete role config directory
-        if role_dir.exists() {
-            self.log(
-                StatusLevel::Warning,
-                format!("Removing config directory '{}'...", role_dir.display()),
-            );
-            std::fs::remove_dir_all(&role_dir).ok();
-        }
+            // Delete role config directory
+            if keep_disks {
+                return (
+                    StatusLevel::Success,
+                    format!(
+                        "Deleted VMs and network of role '{}'; disks and {} were kept",
+                        role,
+                        role_dir.display()
+                    ),
+                );
+            }
+            if role_dir.exists() {
+                std::fs::remove_dir_all(&role_dir).ok();
+            }
 
-        self.set_status(
-            StatusLevel::Success,
-            format!("Deleted role '{}' and all associated resources", role),
-        );
-        self.refresh_vms();
+            (
+                StatusLevel::Success,
+                format!("Deleted role '{}' and all associated resources", role),
+            )
+        });
     }
 
     pub fn launch_disposable_vm(&mut self, role: &str) {
-        let role_net = format!("{}-inet", role);
-
         // Get disposable template (fallback to app template)
         let template_id = match RoleMeta::load(&self.global_config.cfg.root, role) {
             Ok(meta) => meta.disp_template_id.or(meta.app_template_id),
             Err(_) => None,
         };
 
-        let template = match template_id.and_then(|id| self.template_registry.get(&id)) {
+        let template = match template_id.and_then(|id| self.template_registry.get(&id).cloned()) {
             Some(t) => t,
             None => {
                 self.set_status(
@@ -1774,50 +3699,131 @@ impl ProxyVmWizardApp {
                 return;
             }
         };
-
-        // Generate name and overlay path
-        let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
-        let vm_name = format!("disp-{}-{}", role, timestamp);
-        let overlay_path = self
-            .libvirt
-            .disposable_overlay_path(&self.global_config.cfg.root, role);
-
-        // Create overlay
-        if let Err(e) = self
-            .libvirt
-            .create_overlay_disk(&template.path, &overlay_path)
-        {
-            self.set_status(
-                StatusLevel::Error,
-                format!("Failed to create overlay: {}", e),
-            );
+        if let Err(e) = template.require_disk_image() {
+            self.set_status(StatusLevel::Error, e.to_string());
             return;
         }
 
-        // Create transient VM
+        // Generate name and overlay path
+        // One stamp for both, so the VM and its overlay can be matched up
+        let timestamp = timefmt::now_stamp();
+        let vm_name = self.global_config.naming.disposable_name(role, &timestamp);
+        let overlay_path =
+            self.libvirt
+                .disposable_overlay_path(&self.global_config.cfg.root, role, &timestamp);
+        let role_net = format!("{}-inet", role);
         let ram_mb = template
             .default_ram_mb
             .max(self.global_config.defaults.disp_ram_mb);
-        if let Err(e) = self.libvirt.create_disposable_vm(
-            &vm_name,
-            &overlay_path,
-            &role_net,
-            &template.os_variant,
-            ram_mb,
-        ) {
-            self.libvirt.delete_overlay_disk(&overlay_path).ok();
+        let libvirt = self.libvirt.clone();
+        self.ops.submit(format!("Launching {}", vm_name), move || {
+            // Create overlay
+            if let Err(e) = libvirt.create_overlay_disk(&template.path, &overlay_path) {
+                return (
+                    StatusLevel::Error,
+                    format!("Failed to create overlay: {}", e),
+                );
+            }
+
+            // Create transient VM
+            if let Err(e) = libvirt.create_disposable_vm(
+                &vm_name,
+                &overlay_path,
+                &role_net,
+                &template.os_variant,
+                ram_mb,
+                &template.disk_tuning,
+                &template.boot,
+            ) {
+                libvirt.delete_overlay_disk(&overlay_path).ok();
+                return (
+                    StatusLevel::Error,
+                    format!("Failed to create disposable VM: {}", e),
+                );
+            }
+
+            with_disk_permission_issues(
+                &libvirt,
+                &overlay_path,
+                format!("Launched disposable VM: {}", vm_name),
+            )
+        });
+    }
+
+    /// Keep a running disposable by copying its disk into a new persistent app VM.
+    /// The disposable itself is left running; stopping it discards it as usual.
+    pub fn keep_disposable_vm(&mut self, role: &str, disp_name: &str) {
+        let template_id = match RoleMeta::load(&self.global_config.cfg.root, role) {
+            Ok(meta) => meta.disp_template_id.or(meta.app_template_id),
+            Err(_) => None,
+        };
+        let Some(template) = template_id
+            .and_then(|id| self.template_registry.get(&id))
+            .cloned()
+        else {
             self.set_status(
                 StatusLevel::Error,
-                format!("Failed to create disposable VM: {}", e),
+                "No disposable/app template configured for this role",
             );
             return;
-        }
+        };
 
-        self.set_status(
-            StatusLevel::Success,
-            format!("Launched disposable VM: {}", vm_name),
-        );
-        self.refresh_vms();
+        let libvirt = self.libvirt.clone();
+        let cfg_root = self.global_config.cfg.root.clone();
+        let images_dir = self.global_config.libvirt.images_dir.clone();
+        let naming = self.global_config.naming.clone();
+        let ram_mb = template
+            .default_ram_mb
+            .max(self.global_config.defaults.app_ram_mb);
+        let existing_apps = self.app_vm_total(role);
+        let role = role.to_string();
+        let disp_name = disp_name.to_string();
+        self.ops.submit(format!("Keeping {}", disp_name), move || {
+            let mut meta =
+                RoleMeta::load(&cfg_root, &role).unwrap_or_else(|_| RoleMeta::new(role.clone()));
+            if let Err(e) = meta.check_app_vm_limit(existing_apps) {
+                return (StatusLevel::Error, e.to_string());
+            }
+            let app_num = meta.next_app_number();
+            let vm_name = meta.app_vm_name(&naming, app_num);
+            let overlay_path = libvirt.app_overlay_path(&images_dir, &role, app_num);
+
+            if let Err(e) = libvirt.snapshot_disposable_overlay(&disp_name, &overlay_path) {
+                return (
+                    StatusLevel::Error,
+                    format!("Failed to copy '{}': {}", disp_name, e),
+                );
+            }
+            if let Err(e) = libvirt.create_app_vm(
+                &vm_name,
+                &overlay_path,
+                &format!("{}-inet", role),
+                &template.os_variant,
+                ram_mb,
+                &template.disk_tuning,
+                &template.boot,
+                None,
+            ) {
+                libvirt.delete_overlay_disk(&overlay_path).ok();
+                return (StatusLevel::Error, format!("Failed to create VM: {}", e));
+            }
+            if let Err(e) = meta.save(&cfg_root) {
+                return (
+                    StatusLevel::Warning,
+                    format!(
+                        "Created '{}' but failed to save role metadata: {}",
+                        vm_name, e
+                    ),
+                );
+            }
+            (
+                StatusLevel::Success,
+                format!(
+                    "Kept '{}' as app VM '{}'; stop the disposable to discard it",
+                    disp_name, vm_name
+                ),
+            )
+        });
     }
 
     pub fn save_settings(&mut self) {
@@ -1844,16 +3850,105 @@ impl ProxyVmWizardApp {
                 return;
             }
         };
+        let auto_refresh = match self.settings_view.auto_refresh.trim().parse::<u64>() {
+            Ok(v) if v == 0 || v >= MIN_AUTO_REFRESH_SECS => v,
+            _ => {
+                self.settings_view.error = Some(format!(
+                    "Auto-refresh must be 0 (off) or at least {} seconds",
+                    MIN_AUTO_REFRESH_SECS
+                ));
+                return;
+            }
+        };
+
+        let virt_install_timeout = match self
+            .settings_view
+            .virt_install_timeout
+            .trim()
+            .parse::<u64>()
+        {
+            Ok(v) => v,
+            Err(_) => {
+                self.settings_view.error = Some(
+                    "virt-install timeout must be a number of seconds (0 for none)".to_string(),
+                );
+                return;
+            }
+        };
+
+        if let Err(e) = self.settings_view.naming.validate() {
+            self.settings_view.error = Some(e);
+            return;
+        }
+
+        let log_dir = self.settings_view.log_dir.trim();
+        if log_dir.is_empty() {
+            self.settings_view.error = Some("Log directory is required".to_string());
+            return;
+        }
+        let log_dir = PathBuf::from(log_dir);
+
+        // A new URI must work before everything else is switched over to it
+        let uri = self.settings_view.libvirt_uri.trim().to_string();
+        if let Err(e) = validate_libvirt_uri(&uri) {
+            self.settings_view.error = Some(e);
+            return;
+        }
+        if uri != self.global_config.libvirt.uri {
+            if let Err(e) = self.libvirt_for_uri(&uri).check_libvirt_access() {
+                self.settings_view.error = Some(e.to_string());
+                return;
+            }
+        }
+
+        let roles = discover_roles(Path::new(&self.settings_view.cfg_root)).unwrap_or_default();
+        if let Err(e) = validate_lan_net(&self.settings_view.lan_net, &roles) {
+            self.settings_view.error = Some(e);
+            return;
+        }
+        let role_subnet_pool = match validate_role_subnet_pool(&self.settings_view.role_subnet_pool)
+        {
+            Ok(pool) => pool,
+            Err(e) => {
+                self.settings_view.error = Some(e);
+                return;
+            }
+        };
+
+        if self.is_unreadable(&GlobalConfig::default_path()) {
+            self.settings_view.error = Some(
+                "The settings file on disk could not be read; back it up before saving".to_string(),
+            );
+            self.show_unreadable_dialog = true;
+            return;
+        }
 
         // Update config
         self.global_config.cfg.root = PathBuf::from(&self.settings_view.cfg_root);
         self.global_config.libvirt.images_dir = PathBuf::from(&self.settings_view.images_dir);
         self.global_config.libvirt.lan_net = self.settings_view.lan_net.clone();
+        self.global_config.libvirt.share_driver = self.settings_view.share_driver;
+        self.global_config.libvirt.uri = uri;
+        self.libvirt.uri = Some(self.global_config.libvirt.uri.clone());
+        self.global_config.libvirt.virt_install_timeout_secs = virt_install_timeout;
+        self.libvirt.virt_install_timeout_secs = virt_install_timeout;
+        self.global_config.libvirt.role_subnet_pool = role_subnet_pool.to_string();
+        self.libvirt.role_subnet_pool = role_subnet_pool;
         self.global_config.defaults.gateway_ram_mb = gateway_ram;
         self.global_config.defaults.app_ram_mb = app_ram;
         self.global_config.defaults.disp_ram_mb = disp_ram;
         self.global_config.defaults.debian_os_variant = self.settings_view.debian_variant.clone();
         self.global_config.defaults.fedora_os_variant = self.settings_view.fedora_variant.clone();
+        self.global_config.ui.auto_refresh_secs = auto_refresh;
+        self.global_config.naming = self.settings_view.naming.clone();
+        self.libvirt.naming = self.global_config.naming.clone();
+        if log_dir != self.global_config.logging.dir
+            || self.settings_view.log_level != self.global_config.logging.level
+        {
+            self.global_config.logging.dir = log_dir;
+            self.global_config.logging.level = self.settings_view.log_level;
+            self.open_file_log();
+        }
 
         // Save (encrypted if encryption is available)
         let save_result = if let Some(ref encryption) = self.encryption {
@@ -1867,6 +3962,7 @@ impl ProxyVmWizardApp {
                 self.settings_view.error = None;
                 self.settings_view.saved = true;
                 self.set_status(StatusLevel::Success, "Settings saved");
+                self.check_lan_net();
             }
             Err(e) => {
                 self.settings_view.error = Some(format!("Failed to save: {}", e));
@@ -1874,26 +3970,654 @@ impl ProxyVmWizardApp {
         }
     }
 
-    /// Save template registry (encrypted if encryption is available)
-    pub fn save_template_registry(&mut self) -> proxy_vm_core::Result<()> {
-        if let Some(ref encryption) = self.encryption {
-            self.template_registry.save_encrypted(encryption)
-        } else {
-            self.template_registry.save()
+    /// Hash template images in the background, one thread per file; images
+    /// already hashed and unchanged since are skipped
+    pub fn compute_template_checksums(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            if self.templates_view.hashing.contains_key(&path)
+                || self.templates_view.checksums.get(&path).is_some()
+            {
+                continue;
+            }
+            let stamp = match FileStamp::of(&path) {
+                Ok(stamp) => stamp,
+                Err(e) => {
+                    self.set_status(
+                        StatusLevel::Error,
+                        format!("Cannot read {}: {}", path.display(), e),
+                    );
+                    continue;
+                }
+            };
+            self.templates_view
+                .hashing
+                .insert(path.clone(), (0, stamp.size));
+
+            let libvirt = self.libvirt.clone();
+            let tx = self.async_tx.clone();
+            std::thread::spawn(move || {
+                // Report in steps of 1% so huge images don't flood the channel
+                let step = (stamp.size / 100).max(1);
+                let mut reported = 0;
+                let result = libvirt
+                    .sha256_file_with_progress(&path, |hashed, total| {
+                        if hashed - reported >= step || hashed == total {
+                            reported = hashed;
+                            tx.send(AsyncMessage::TemplateHashProgress {
+                                path: path.clone(),
+                                hashed,
+                                total,
+                            })
+                            .ok();
+                        }
+                    })
+                    .map_err(|e| e.to_string());
+                tx.send(AsyncMessage::TemplateHashed {
+                    path,
+                    stamp,
+                    result,
+                })
+                .ok();
+            });
         }
     }
-}
 
-impl eframe::App for ProxyVmWizardApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Show authentication screen if needed
-        match self.auth_view.screen {
-            AuthScreen::Setup => {
-                self.show_setup_screen(ctx);
-                return;
-            }
-            AuthScreen::Login => {
-                self.show_login_screen(ctx);
+    /// Look up the roles and VMs that depend on a template in the background, from
+    /// role metadata and disk backing chains
+    pub fn check_template_usage(&mut self, id: &str) {
+        if !self
+            .templates_view
+            .usage_checks_in_flight
+            .insert(id.to_string())
+        {
+            return;
+        }
+        let libvirt = self.libvirt.clone();
+        let cfg_root = self.global_config.cfg.root.clone();
+        let path = self.template_registry.get(id).map(|t| t.path.clone());
+        let tx = self.async_tx.clone();
+        let id = id.to_string();
+        std::thread::spawn(move || {
+            let result = (|| -> proxy_vm_core::Result<Vec<String>> {
+                let mut usage = roles_using_template(&cfg_root, &id)?;
+                if let Some(path) = path {
+                    let vms = libvirt.get_vms_using_image(&path)?;
+                    usage.extend(vms.into_iter().map(|vm| format!("VM '{}'", vm)));
+                }
+                Ok(usage)
+            })()
+            .map_err(|e| e.to_string());
+            tx.send(AsyncMessage::TemplateUsage { id, result }).ok();
+        });
+    }
+
+    /// Encrypt settings and template files that are still stored as plaintext.
+    ///
+    /// Files that failed to load are left alone so they can still be recovered.
+    /// The work is queued like other mutations; returns false when there was
+    /// nothing to encrypt.
+    pub fn encrypt_plaintext_config(&mut self) -> bool {
+        let Some(encryption) = self.encryption.clone() else {
+            return false;
+        };
+
+        // Only queue files that are still plaintext; one that can't be read is
+        // left for the job to report
+        let targets: Vec<(&'static str, PathBuf)> = [
+            ("settings", GlobalConfig::default_path()),
+            ("templates", TemplateRegistry::default_path()),
+        ]
+        .into_iter()
+        .filter(|(_, path)| !self.is_unreadable(path))
+        .filter(|(_, path)| {
+            path.exists() && !matches!(proxy_vm_core::auth::is_file_encrypted(path), Ok(true))
+        })
+        .collect();
+        if targets.is_empty() {
+            return false;
+        }
+
+        self.ops.submit("Encrypting plaintext config", move || {
+            let mut encrypted = Vec::new();
+            let mut errors = Vec::new();
+            for (label, path) in targets {
+                match encryption.encrypt_file_in_place(&path) {
+                    Ok(PlaintextMigration::Encrypted) => encrypted.push(label),
+                    Ok(PlaintextMigration::AlreadyEncrypted | PlaintextMigration::Missing) => {}
+                    Err(e) => errors.push(format!(
+                        "Failed to encrypt {} ({}); the plaintext file was kept: {}",
+                        label,
+                        path.display(),
+                        e
+                    )),
+                }
+            }
+
+            if !errors.is_empty() {
+                (StatusLevel::Error, errors.join("\n"))
+            } else if !encrypted.is_empty() {
+                (
+                    StatusLevel::Success,
+                    format!(
+                        "Encrypted plaintext {} file(s); no unencrypted copies remain",
+                        encrypted.join(" and ")
+                    ),
+                )
+            } else {
+                (
+                    StatusLevel::Info,
+                    "Config files are already encrypted".to_string(),
+                )
+            }
+        });
+        true
+    }
+
+    /// Record a config file that exists but won't load, so it is never silently overwritten
+    fn mark_unreadable(&mut self, label: &'static str, path: PathBuf, error: proxy_vm_core::Error) {
+        self.log(
+            StatusLevel::Error,
+            format!(
+                "{} file {} could not be read: {}",
+                label,
+                path.display(),
+                error
+            ),
+        );
+        self.unreadable_files.push(UnreadableFile {
+            label,
+            path,
+            error: error.to_string(),
+        });
+        self.show_unreadable_dialog = true;
+    }
+
+    fn is_unreadable(&self, path: &std::path::Path) -> bool {
+        self.unreadable_files.iter().any(|f| f.path == path)
+    }
+
+    /// Move an unreadable file aside and write the in-memory defaults in its place
+    pub fn backup_unreadable(&mut self, index: usize) {
+        if index >= self.unreadable_files.len() {
+            return;
+        }
+        let file = self.unreadable_files[index].clone();
+        match backup_unreadable_file(&file.path) {
+            Ok(backup) => {
+                self.unreadable_files.remove(index);
+                self.log(
+                    StatusLevel::Warning,
+                    format!(
+                        "Backed up unreadable {} file to {}",
+                        file.label.to_lowercase(),
+                        backup.display()
+                    ),
+                );
+                let saved = if file.path == GlobalConfig::default_path() {
+                    match self.encryption {
+                        Some(ref encryption) => self.global_config.save_encrypted(encryption),
+                        None => self.global_config.save(),
+                    }
+                } else {
+                    self.save_template_registry()
+                };
+                match saved {
+                    Ok(_) => self.set_status(
+                        StatusLevel::Success,
+                        format!("Backed up to {}", backup.display()),
+                    ),
+                    Err(e) => self.set_status(
+                        StatusLevel::Error,
+                        format!("Backed up, but failed to write new file: {}", e),
+                    ),
+                }
+            }
+            Err(e) => {
+                self.set_status(StatusLevel::Error, format!("Backup failed: {}", e));
+            }
+        }
+        if self.unreadable_files.is_empty() {
+            self.show_unreadable_dialog = false;
+        }
+    }
+
+    /// Download the image from the URL form in the background
+    pub fn start_template_download(&mut self) {
+        let url = self.templates_view.url_form_url.trim().to_string();
+        let Some(file_name) = proxy_vm_core::libvirt::filename_from_url(&url) else {
+            self.templates_view.url_form_error =
+                Some("Could not determine a filename from the URL".to_string());
+            return;
+        };
+        let dest = self.global_config.libvirt.images_dir.join(&file_name);
+        if dest.exists() {
+            self.templates_view.url_form_error = Some(format!(
+                "{} already exists - select it from the existing images instead",
+                dest.display()
+            ));
+            return;
+        }
+        let sha256 =
+            Some(self.templates_view.url_form_sha256.trim().to_string()).filter(|s| !s.is_empty());
+
+        self.templates_view.url_form_error = None;
+        self.templates_view.download_progress = Some((0, None));
+        self.log(
+            StatusLevel::Info,
+            format!("Downloading template from {}", url),
+        );
+
+        let libvirt = self.libvirt.clone();
+        let tx = self.async_tx.clone();
+        std::thread::spawn(move || {
+            let progress_tx = tx.clone();
+            let result = libvirt.download_template_with_progress(
+                &url,
+                &dest,
+                sha256.as_deref(),
+                |downloaded, total| {
+                    progress_tx
+                        .send(AsyncMessage::TemplateDownloadProgress { downloaded, total })
+                        .ok();
+                },
+            );
+            let msg = match result {
+                Ok(path) => AsyncMessage::TemplateDownloaded(path),
+                Err(e) => AsyncMessage::TemplateDownloadFailed(e.to_string()),
+            };
+            tx.send(msg).ok();
+        });
+    }
+
+    /// Write a configuration bundle to `dest` in the background
+    pub fn export_bundle(&mut self, dest: PathBuf) {
+        let layout = BundleLayout::current(&self.global_config);
+        let templates = self.template_registry.clone();
+        let include_images = self.settings_view.bundle_include_images;
+        let tx = self.async_tx.clone();
+        self.settings_view.bundle_busy = true;
+        self.log(
+            StatusLevel::Info,
+            format!("Exporting configuration bundle to {}", dest.display()),
+        );
+        std::thread::spawn(move || {
+            let result = export_bundle(&layout, &templates, &dest, include_images)
+                .map(|()| dest)
+                .map_err(|e| e.to_string());
+            tx.send(AsyncMessage::BundleExported(result)).ok();
+        });
+    }
+
+    /// Write a diagnostics archive with secrets redacted to `dest` in the background
+    pub fn export_diagnostics(&mut self, dest: PathBuf) {
+        let libvirt = self.libvirt.clone();
+        let config = self.global_config.clone();
+        let templates = self.template_registry.clone();
+        let log: Vec<String> = self
+            .logs
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{} [{}] {}",
+                    timefmt::date_time(&entry.timestamp),
+                    entry.level.log_level().as_str(),
+                    entry.message
+                )
+            })
+            .collect();
+        self.ops.submit("Generating diagnostics", move || {
+            match export_diagnostics(&libvirt, &config, &templates, &log, &dest) {
+                Ok(()) => (
+                    StatusLevel::Success,
+                    format!("Diagnostics written to {}", dest.display()),
+                ),
+                Err(e) => (
+                    StatusLevel::Error,
+                    format!("Failed to generate diagnostics: {}", e),
+                ),
+            }
+        });
+    }
+
+    /// Restore the pending bundle in the background, replacing the current configuration
+    pub fn import_bundle(&mut self) {
+        let Some(src) = self.settings_view.pending_bundle_import.take() else {
+            return;
+        };
+        if let Err(e) = validate_bundle(&src) {
+            self.set_status(StatusLevel::Error, format!("Cannot import: {}", e));
+            return;
+        }
+
+        let layout = BundleLayout::current(&self.global_config);
+        let tx = self.async_tx.clone();
+        self.settings_view.bundle_busy = true;
+        self.log(
+            StatusLevel::Info,
+            format!("Importing configuration bundle from {}", src.display()),
+        );
+        std::thread::spawn(move || {
+            let result = import_bundle(&layout, &src).map_err(|e| e.to_string());
+            tx.send(AsyncMessage::BundleImported(result)).ok();
+        });
+    }
+
+    /// Write the saved VM defaults (and libvirt settings, if chosen) to `dest` as TOML
+    pub fn export_defaults(&mut self, dest: PathBuf) {
+        let result = self
+            .global_config
+            .export_defaults(self.settings_view.defaults_include_libvirt)
+            .and_then(|snippet| Ok(std::fs::write(&dest, snippet)?));
+        match result {
+            Ok(()) => self.set_status(
+                StatusLevel::Success,
+                format!("Defaults exported to {}", dest.display()),
+            ),
+            Err(e) => self.set_status(
+                StatusLevel::Error,
+                format!("Failed to export defaults: {}", e),
+            ),
+        }
+    }
+
+    /// Load a defaults snippet into the settings form; nothing is saved until Save
+    pub fn import_defaults(&mut self, src: PathBuf) {
+        let mut config = self.global_config.clone();
+        let result = std::fs::read_to_string(&src)
+            .map_err(proxy_vm_core::Error::from)
+            .and_then(|snippet| config.apply_defaults(&snippet));
+        let has_libvirt = match result {
+            Ok(has_libvirt) => has_libvirt,
+            Err(e) => {
+                self.set_status(
+                    StatusLevel::Error,
+                    format!("Cannot import {}: {}", src.display(), e),
+                );
+                return;
+            }
+        };
+
+        let view = &mut self.settings_view;
+        view.gateway_ram = config.defaults.gateway_ram_mb.to_string();
+        view.app_ram = config.defaults.app_ram_mb.to_string();
+        view.disp_ram = config.defaults.disp_ram_mb.to_string();
+        view.debian_variant = config.defaults.debian_os_variant;
+        view.fedora_variant = config.defaults.fedora_os_variant;
+        if has_libvirt {
+            view.images_dir = config.libvirt.images_dir.display().to_string();
+            view.lan_net = config.libvirt.lan_net;
+            view.share_driver = config.libvirt.share_driver;
+            view.libvirt_uri = config.libvirt.uri;
+            view.virt_install_timeout = config.libvirt.virt_install_timeout_secs.to_string();
+            view.role_subnet_pool = config.libvirt.role_subnet_pool;
+        }
+        view.saved = false;
+        self.set_status(
+            StatusLevel::Info,
+            format!(
+                "Imported defaults from {}; review them and click Save",
+                src.display()
+            ),
+        );
+    }
+
+    /// Look for `<role>-inet` networks whose role has neither a directory nor a VM
+    pub fn scan_leftover_networks(&mut self) {
+        let libvirt = self.libvirt.clone();
+        let cfg_root = self.global_config.cfg.root.clone();
+        let lan_net = self.global_config.libvirt.lan_net.clone();
+        let tx = self.async_tx.clone();
+        self.settings_view.network_scan_busy = true;
+        std::thread::spawn(move || {
+            let result = (|| -> proxy_vm_core::Result<Vec<NetworkInfo>> {
+                let vms = libvirt.list_vms(None)?;
+                // Renamed or unmanaged VMs can still sit on a role network
+                let in_use = libvirt.networks_in_use()?;
+                let networks = libvirt.list_app_networks()?;
+                Ok(networks
+                    .into_iter()
+                    .filter(|net| net.name != lan_net && net.name != "default")
+                    .filter(|net| !in_use.contains(&net.name))
+                    .filter(|net| {
+                        network_role(&net.name).is_some_and(|role| {
+                            !cfg_root.join(role).exists()
+                                && !vms.iter().any(|vm| vm.role.as_deref() == Some(role))
+                        })
+                    })
+                    .collect())
+            })()
+            .map_err(|e| e.to_string());
+            tx.send(AsyncMessage::LeftoverNetworks(result)).ok();
+        });
+    }
+
+    /// Destroy and undefine a network found by `scan_leftover_networks`
+    pub fn remove_leftover_network(&mut self, name: &str) {
+        if let Some(networks) = self.settings_view.leftover_networks.as_mut() {
+            networks.retain(|net| net.name != name);
+        }
+        let libvirt = self.libvirt.clone();
+        let name = name.to_string();
+        self.ops
+            .submit(format!("Removing network {}", name), move || match libvirt
+                .destroy_network(&name)
+            {
+                Ok(()) => (StatusLevel::Success, format!("Removed network '{}'", name)),
+                Err(e) => (
+                    StatusLevel::Error,
+                    format!("Failed to remove network '{}': {}", name, e),
+                ),
+            });
+    }
+
+    /// Look for VM disks whose backing file is gone and open the repair dialog,
+    /// suggesting a template or image with the missing file's name
+    pub fn check_backing_chains(&mut self) {
+        let broken = match self.libvirt.check_backing_chains() {
+            Ok(broken) => broken,
+            Err(e) => {
+                self.set_status(
+                    StatusLevel::Error,
+                    format!("Failed to check disk chains: {}", e),
+                );
+                return;
+            }
+        };
+        if broken.is_empty() {
+            self.set_status(StatusLevel::Success, "All VM disk chains are intact");
+        } else {
+            self.set_status(
+                StatusLevel::Warning,
+                format!(
+                    "{} VM disk(s) point at a missing backing file",
+                    broken.len()
+                ),
+            );
+        }
+
+        let images_dir = &self.global_config.libvirt.images_dir;
+        let mut targets = HashMap::new();
+        for entry in &broken {
+            let name = entry.missing.file_name();
+            let guess = self
+                .template_registry
+                .list()
+                .into_iter()
+                .map(|t| t.path.clone())
+                .chain(name.map(|name| images_dir.join(name)))
+                .find(|path| path.file_name() == name && path.is_file());
+            targets.insert(
+                entry.overlay.clone(),
+                guess.map(|p| p.display().to_string()).unwrap_or_default(),
+            );
+        }
+        self.templates_view.show_backing_dialog = !broken.is_empty();
+        self.templates_view.broken_backings = broken;
+        self.templates_view.rebase_targets = targets;
+    }
+
+    /// Rebase a broken overlay onto the path given for it in the repair dialog
+    pub fn rebase_backing(&mut self, overlay: &Path) {
+        let Some(entry) = self
+            .templates_view
+            .broken_backings
+            .iter()
+            .find(|b| b.overlay == overlay)
+            .cloned()
+        else {
+            return;
+        };
+        let new_backing = self
+            .templates_view
+            .rebase_targets
+            .get(overlay)
+            .map(|target| PathBuf::from(target.trim()))
+            .unwrap_or_default();
+        if new_backing.as_os_str().is_empty() {
+            self.set_status(StatusLevel::Error, "Choose the new backing file first");
+            return;
+        }
+
+        self.templates_view
+            .broken_backings
+            .retain(|b| b.overlay != overlay);
+        if self.templates_view.broken_backings.is_empty() {
+            self.templates_view.show_backing_dialog = false;
+        }
+        let libvirt = self.libvirt.clone();
+        self.ops.submit(
+            format!("Remapping disk of {}", entry.vm),
+            move || match libvirt.rebase_backing(&entry.overlay, &new_backing) {
+                Ok(()) => (
+                    StatusLevel::Success,
+                    format!(
+                        "{} now uses {} as its backing file",
+                        entry.overlay.display(),
+                        new_backing.display()
+                    ),
+                ),
+                Err(e) => (
+                    StatusLevel::Error,
+                    format!("Failed to remap disk of '{}': {}", entry.vm, e),
+                ),
+            },
+        );
+    }
+
+    /// Open the relocate dialog, guessing the old base from the first missing template
+    pub fn start_relocate_templates(&mut self) {
+        let old_base = self
+            .template_registry
+            .list()
+            .into_iter()
+            .find(|t| !t.exists())
+            .and_then(|t| t.path.parent().map(|p| p.display().to_string()))
+            .unwrap_or_default();
+        self.templates_view.relocate_old_base = old_base;
+        self.templates_view.relocate_new_base =
+            self.global_config.libvirt.images_dir.display().to_string();
+        self.templates_view.relocate_error = None;
+        self.templates_view.show_relocate_dialog = true;
+    }
+
+    /// Rewrite template paths from the old images dir to the new one and save
+    pub fn relocate_templates(&mut self) {
+        let old_base = PathBuf::from(self.templates_view.relocate_old_base.trim());
+        let new_base = PathBuf::from(self.templates_view.relocate_new_base.trim());
+        if old_base.as_os_str().is_empty() || new_base.as_os_str().is_empty() {
+            self.templates_view.relocate_error =
+                Some("Both the old and new directory are required".to_string());
+            return;
+        }
+        if old_base == new_base {
+            self.templates_view.relocate_error =
+                Some("Old and new directory are the same".to_string());
+            return;
+        }
+
+        let affected = self
+            .template_registry
+            .list()
+            .iter()
+            .filter(|t| t.path.starts_with(&old_base))
+            .count();
+        if affected == 0 {
+            self.templates_view.relocate_error = Some(format!(
+                "No templates are stored under {}",
+                old_base.display()
+            ));
+            return;
+        }
+
+        let missing = self.template_registry.rebase_paths(&old_base, &new_base);
+        if let Err(e) = self.save_template_registry() {
+            self.templates_view.relocate_error =
+                Some(format!("Failed to save template registry: {}", e));
+            return;
+        }
+
+        self.templates_view.show_relocate_dialog = false;
+        if missing.is_empty() {
+            self.set_status(
+                StatusLevel::Success,
+                format!(
+                    "Relocated {} template(s) to {}",
+                    affected,
+                    new_base.display()
+                ),
+            );
+        } else {
+            for id in &missing {
+                if let Some(t) = self.template_registry.get(id) {
+                    let message = format!(
+                        "Template '{}' not found at new location {}",
+                        t.label,
+                        t.path.display()
+                    );
+                    self.log(StatusLevel::Warning, message);
+                }
+            }
+            self.set_status(
+                StatusLevel::Warning,
+                format!(
+                    "Relocated {} template(s); {} not found at the new location (see logs)",
+                    affected,
+                    missing.len()
+                ),
+            );
+        }
+    }
+
+    /// Save template registry (encrypted if encryption is available)
+    pub fn save_template_registry(&mut self) -> proxy_vm_core::Result<()> {
+        if self.is_unreadable(&TemplateRegistry::default_path()) {
+            return Err(proxy_vm_core::Error::config(
+                "The template registry on disk could not be read; back it up before saving",
+            ));
+        }
+        if let Some(ref encryption) = self.encryption {
+            self.template_registry.save_encrypted(encryption)
+        } else {
+            self.template_registry.save()
+        }
+    }
+}
+
+impl eframe::App for ProxyVmWizardApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.lock_conflict.is_some() {
+            self.show_lock_conflict_screen(ctx);
+            return;
+        }
+
+        // Show authentication screen if needed
+        match self.auth_view.screen {
+            AuthScreen::Setup => {
+                self.show_setup_screen(ctx);
+                return;
+            }
+            AuthScreen::Login => {
+                self.show_login_screen(ctx);
                 return;
             }
             AuthScreen::None => {}
@@ -1908,35 +4632,425 @@ impl eframe::App for ProxyVmWizardApp {
                 AsyncMessage::OperationError(e) => {
                     self.set_status(StatusLevel::Error, e);
                 }
+                AsyncMessage::WireGuardStatus { vm_name, result }
+                    if self.wg_checks_in_flight.remove(&vm_name) =>
+                {
+                    self.wg_status.insert(vm_name, result);
+                }
+                // The gateway stopped while its check was running
+                AsyncMessage::WireGuardStatus { .. } => {}
+                AsyncMessage::ProxyMountChecked { vm_name, result } => match result {
+                    Ok(ProxyMountStatus::Mounted) => self.log(
+                        StatusLevel::Success,
+                        format!("'{}' can see /proxy/proxy.conf", vm_name),
+                    ),
+                    Ok(ProxyMountStatus::Missing) => self.set_status(
+                        StatusLevel::Warning,
+                        format!(
+                            "'{}' cannot see /proxy/proxy.conf: {}",
+                            vm_name,
+                            proxy_vm_core::libvirt::PROXY_MOUNT_HELP
+                        ),
+                    ),
+                    Ok(ProxyMountStatus::AgentUnavailable) | Err(_) => self.log(
+                        StatusLevel::Info,
+                        format!(
+                            "Guest agent in '{}' did not answer; verify the /proxy mount from the Dashboard",
+                            vm_name
+                        ),
+                    ),
+                },
+                AsyncMessage::VmInterfaces { vm_name, result } => {
+                    self.iface_checks_in_flight.remove(&vm_name);
+                    self.gw_interfaces.insert(vm_name, result);
+                }
+                AsyncMessage::DiskChain { vm_name, result } => {
+                    self.chain_checks_in_flight.remove(&vm_name);
+                    self.disk_chains.insert(vm_name, result);
+                }
+                AsyncMessage::DiskUsage { role, result } => {
+                    self.usage_checks_in_flight.remove(&role);
+                    self.disk_usage.insert(role, result);
+                }
+                AsyncMessage::TemplateUsage { id, result } => {
+                    self.templates_view.usage_checks_in_flight.remove(&id);
+                    self.templates_view.template_usage.insert(id, result);
+                }
+                AsyncMessage::VmListRefreshed(result) => {
+                    self.refresh_in_flight = false;
+                    self.refresh_roles();
+                    self.apply_vm_list(result);
+                }
+                AsyncMessage::GatewayLogs {
+                    gateway,
+                    proxychains,
+                    journal,
+                } => {
+                    if let Err(e) = &proxychains {
+                        self.log(
+                            StatusLevel::Warning,
+                            format!(
+                                "Could not read {} from '{}': {}",
+                                PROXYCHAINS_CONF, gateway, e
+                            ),
+                        );
+                    }
+                    if let Some(view) = self
+                        .gateway_logs
+                        .as_mut()
+                        .filter(|view| view.gateway == gateway)
+                    {
+                        view.proxychains = Some(proxychains);
+                        view.journal = Some(journal);
+                    }
+                }
+                AsyncMessage::IsolationProbe { role, result } => {
+                    self.isolation_checks_in_flight.remove(&role);
+                    match &result {
+                        Ok(report) if !report.leaking.is_empty() => self.log(
+                            StatusLevel::Error,
+                            format!(
+                                "Isolation broken for '{}': {} reached {} directly",
+                                role,
+                                report.leaking.join(", "),
+                                report.target
+                            ),
+                        ),
+                        Ok(report) => self.log(
+                            StatusLevel::Success,
+                            format!(
+                                "Isolation verified for '{}' ({} app VM(s) could not reach {})",
+                                role,
+                                report.checked.len(),
+                                report.target
+                            ),
+                        ),
+                        Err(e) => self.log(
+                            StatusLevel::Warning,
+                            format!("Isolation probe for '{}' failed: {}", role, e),
+                        ),
+                    }
+                    self.isolation_status.insert(role, result);
+                }
+                AsyncMessage::GatewayTested {
+                    role,
+                    gateway,
+                    check,
+                } => {
+                    let level = match check {
+                        UpstreamCheck::Reachable(_) => StatusLevel::Success,
+                        UpstreamCheck::Unreachable(_) => StatusLevel::Error,
+                        UpstreamCheck::Skipped(_) => StatusLevel::Warning,
+                    };
+                    self.log(
+                        level,
+                        format!("Gateway test '{}' ({}): {}", role, gateway, check.detail()),
+                    );
+                    if let Some(run) = &mut self.gateway_tests {
+                        run.results.push((role, gateway, check));
+                    }
+                }
+                AsyncMessage::GatewayTestsDone => {
+                    if let Some(run) = &mut self.gateway_tests {
+                        run.finished = true;
+                        let (passed, failed, skipped) = run.tally();
+                        let level = if failed > 0 {
+                            StatusLevel::Error
+                        } else {
+                            StatusLevel::Success
+                        };
+                        self.set_status(
+                            level,
+                            format!(
+                                "Gateway test: {} passed, {} failed, {} skipped",
+                                passed, failed, skipped
+                            ),
+                        );
+                    }
+                }
+                AsyncMessage::OperationFinished { level, message } => {
+                    self.set_status(level, message);
+                    self.disk_chains.clear();
+                    self.disk_usage.clear();
+                    self.refresh_vms();
+                }
                 AsyncMessage::ConnectionTestResult {
                     index,
+                    endpoint,
                     success,
                     message,
                 } => {
-                    if index < self.wizard.proxy_hops.len() {
-                        self.wizard.proxy_hops[index].test_status = Some(success);
-                        self.wizard.proxy_hops[index].test_message = Some(message);
+                    // Hops may have been removed or edited while the test ran
+                    if let Some(hop) = self.wizard.proxy_hops.get_mut(index) {
+                        hop.testing = false;
+                        if hop.endpoint().ok() == Some(endpoint) {
+                            hop.test_status = Some(success);
+                            hop.test_message = Some(message);
+                        }
+                    }
+                }
+                // The wizard may have been reset while the role was being created
+                AsyncMessage::WizardProgress(progress) if self.wizard.is_executing => {
+                    self.apply_wizard_progress(progress);
+                }
+                AsyncMessage::WizardFinished { result } if self.wizard.is_executing => {
+                    self.finish_wizard(result);
+                }
+                AsyncMessage::WizardProgress(_) | AsyncMessage::WizardFinished { .. } => {}
+                AsyncMessage::HopPinged {
+                    list,
+                    index,
+                    host,
+                    result,
+                } => {
+                    let hops = match list {
+                        HopList::Wizard => &mut self.wizard.proxy_hops,
+                        HopList::ConfigEditor => &mut self.config_editor.proxy_hops,
+                    };
+                    // Hops may have been removed or edited while the ping ran
+                    if let Some(hop) = hops.get_mut(index) {
+                        hop.pinging = false;
+                        if hop.host.trim() == host {
+                            hop.ping_result = Some(result);
+                        }
+                    }
+                }
+                AsyncMessage::TemplateDownloadProgress { downloaded, total }
+                    if self.templates_view.download_progress.is_some() =>
+                {
+                    self.templates_view.download_progress = Some((downloaded, total));
+                }
+                AsyncMessage::TemplateDownloaded(path) => {
+                    self.templates_view.download_progress = None;
+                    self.log(
+                        StatusLevel::Success,
+                        format!("Downloaded template to {}", path.display()),
+                    );
+
+                    // Hand over to the normal Add Template form to register it
+                    self.templates_view.show_selection_dialog = false;
+                    self.templates_view.show_add_dialog = true;
+                    self.templates_view.form_path = path.display().to_string();
+                    self.templates_view.form_label = path
+                        .file_stem()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    self.templates_view.form_os_variant =
+                        self.global_config.defaults.debian_os_variant.clone();
+                    self.templates_view.form_role_kind = RoleKind::ProxyGateway;
+                    self.templates_view.form_ram_mb =
+                        self.global_config.defaults.gateway_ram_mb.to_string();
+                    self.templates_view.form_disk_tuning = DiskTuning::default();
+                    self.templates_view.form_boot = BootForm::default();
+                    self.templates_view.form_install = InstallSource::default();
+                    self.templates_view.form_install_disk_gb = String::new();
+                    self.templates_view.form_notes = format!(
+                        "Downloaded from {}",
+                        self.templates_view.url_form_url.trim()
+                    );
+                    self.templates_view.form_error = None;
+                    self.templates_view.form_duplicate_of = None;
+                }
+                AsyncMessage::BundleExported(result) => {
+                    self.settings_view.bundle_busy = false;
+                    match result {
+                        Ok(dest) => self.set_status(
+                            StatusLevel::Success,
+                            format!("Configuration bundle written to {}", dest.display()),
+                        ),
+                        Err(e) => self
+                            .set_status(StatusLevel::Error, format!("Bundle export failed: {}", e)),
+                    }
+                }
+                AsyncMessage::RoleNetworks(result) => {
+                    self.role_networks_in_flight = false;
+                    let result = result.map(|networks| {
+                        networks
+                            .into_iter()
+                            .filter_map(|net| Some((network_role(&net.name)?.to_string(), net)))
+                            .collect()
+                    });
+                    // Logged once, not on every auto-refresh
+                    if let Err(e) = &result {
+                        if !matches!(self.role_networks, Some(Err(_))) {
+                            self.log(
+                                StatusLevel::Warning,
+                                format!("Failed to list role networks: {}", e),
+                            );
+                        }
+                    }
+                    self.role_networks = Some(result);
+                }
+                AsyncMessage::LeftoverNetworks(result) => {
+                    self.settings_view.network_scan_busy = false;
+                    match result {
+                        Ok(networks) => self.settings_view.leftover_networks = Some(networks),
+                        Err(e) => self
+                            .set_status(StatusLevel::Error, format!("Network scan failed: {}", e)),
+                    }
+                }
+                AsyncMessage::BundleImported(result) => {
+                    self.settings_view.bundle_busy = false;
+                    match result {
+                        Ok(manifest) => {
+                            self.log(
+                                StatusLevel::Success,
+                                format!(
+                                    "Imported bundle from {} ({} role(s), {} image(s))",
+                                    timefmt::date_time_minutes(&manifest.created),
+                                    manifest.roles.len(),
+                                    manifest.images.len()
+                                ),
+                            );
+                            self.restart_required = true;
+                        }
+                        Err(e) => self
+                            .set_status(StatusLevel::Error, format!("Bundle import failed: {}", e)),
+                    }
+                }
+                AsyncMessage::TemplateHashProgress {
+                    path,
+                    hashed,
+                    total,
+                } => {
+                    if let Some(progress) = self.templates_view.hashing.get_mut(&path) {
+                        *progress = (hashed, total);
+                    }
+                }
+                AsyncMessage::TemplateHashed {
+                    path,
+                    stamp,
+                    result,
+                } => {
+                    self.templates_view.hashing.remove(&path);
+                    match result {
+                        Ok(sha256) => {
+                            self.log(
+                                StatusLevel::Info,
+                                format!("SHA-256 of {}: {}", path.display(), sha256),
+                            );
+                            self.templates_view.checksums.insert(path, stamp, sha256);
+                        }
+                        Err(e) => self.set_status(
+                            StatusLevel::Error,
+                            format!("Failed to hash {}: {}", path.display(), e),
+                        ),
                     }
                 }
+                AsyncMessage::TemplateDownloadFailed(e) => {
+                    self.templates_view.download_progress = None;
+                    self.log(
+                        StatusLevel::Error,
+                        format!("Template download failed: {}", e),
+                    );
+                    self.templates_view.url_form_error = Some(e);
+                }
                 _ => {}
             }
         }
 
+        // After an import the files on disk no longer match what is loaded
+        if self.restart_required {
+            egui::Window::new("📦 Bundle Imported")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label("The configuration bundle was restored.");
+                    ui.label(
+                        "Restart the app and unlock it with the password from the machine \
+                         the bundle was exported on.",
+                    );
+                    ui.add_space(10.0);
+                    if ui.button("Quit").clicked() {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                });
+            return;
+        }
+
         // Prerequisite error modal
-        if let Some(ref error) = self.prereq_error {
+        if let Some(error) = self.prereq_error.clone() {
+            let retry_interval = self.global_config.ui.auto_refresh_interval();
+            let mut reconnect = false;
             egui::Window::new("⚠ Prerequisite Error")
                 .collapsible(false)
                 .resizable(false)
                 .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
                 .show(ctx, |ui| {
-                    ui.label(error);
+                    ui.label(&error);
                     ui.add_space(10.0);
                     ui.label("Please ensure libvirt is installed and you have access.");
                     ui.label("Try: sudo usermod -aG libvirt $USER");
+                    ui.label("If libvirtd was restarted, reconnect once it is running again.");
+                    ui.add_space(10.0);
+                    ui.horizontal(|ui| {
+                        reconnect = ui.button("🔄 Reconnect").clicked();
+                        if let Some(interval) = retry_interval {
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "Retrying every {}s",
+                                    interval.as_secs()
+                                ))
+                                .small()
+                                .color(egui::Color32::GRAY),
+                            );
+                        }
+                    });
                 });
+            if reconnect {
+                if self.reconnect_libvirt() {
+                    self.set_status(StatusLevel::Success, "Reconnected to libvirt");
+                }
+            } else {
+                self.auto_refresh();
+            }
+            if let Some(interval) = retry_interval {
+                ctx.request_repaint_after(interval);
+            }
             return;
         }
 
+        // Unreadable config modal
+        if self.show_unreadable_dialog && !self.unreadable_files.is_empty() {
+            let mut backup_index = None;
+            let mut dismiss = false;
+            egui::Window::new("⚠ Config Could Not Be Read")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label(
+                        "These files are encrypted with a different key or are corrupted. \
+                         Defaults are in use and the files will not be overwritten.",
+                    );
+                    ui.add_space(10.0);
+                    for (i, file) in self.unreadable_files.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}: {}", file.label, file.path.display()));
+                            if ui.button("💾 Back Up & Start Fresh").clicked() {
+                                backup_index = Some(i);
+                            }
+                        });
+                        ui.colored_label(egui::Color32::from_rgb(220, 20, 60), &file.error);
+                        ui.add_space(5.0);
+                    }
+                    ui.add_space(5.0);
+                    if ui.button("Later").clicked() {
+                        dismiss = true;
+                    }
+                });
+            if let Some(i) = backup_index {
+                self.backup_unreadable(i);
+            }
+            if dismiss {
+                self.show_unreadable_dialog = false;
+            }
+        }
+
+        // Shortcuts and palette, before any panel claims keyboard focus
+        commands::handle(self, ctx);
+
         // Top panel with navigation
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
@@ -1953,6 +5067,17 @@ impl eframe::App for ProxyVmWizardApp {
                     if ui.button("🔄 Refresh").clicked() {
                         self.refresh_vms();
                     }
+
+                    let pending = self.ops.pending();
+                    if pending > 0 {
+                        let current = self.ops.current().unwrap_or_else(|| "Working".to_string());
+                        if pending > 1 {
+                            ui.label(format!("{}... (+{} queued)", current, pending - 1));
+                        } else {
+                            ui.label(format!("{}...", current));
+                        }
+                        ui.spinner();
+                    }
                 });
             });
         });
@@ -1977,6 +5102,9 @@ impl eframe::App for ProxyVmWizardApp {
             });
         }
 
+        self.check_rotations();
+        self.auto_refresh();
+
         // Main content
         egui::CentralPanel::default().show(ctx, |ui| match self.current_view {
             View::Dashboard => DashboardView::show(self, ui),
@@ -1987,6 +5115,24 @@ impl eframe::App for ProxyVmWizardApp {
         });
 
         // Request repaint for real-time updates
-        ctx.request_repaint_after(std::time::Duration::from_secs(5));
+        if self.templates_view.download_progress.is_some()
+            || !self.templates_view.hashing.is_empty()
+            || self
+                .gateway_logs
+                .as_ref()
+                .is_some_and(GatewayLogsView::is_loading)
+            || self.wizard.proxy_hops.iter().any(|hop| hop.testing)
+            || self.ops.pending() > 0
+        {
+            ctx.request_repaint_after(std::time::Duration::from_millis(250));
+        } else {
+            // Still wake up now and then in manual mode so rotations are noticed
+            let interval = self
+                .global_config
+                .ui
+                .auto_refresh_interval()
+                .unwrap_or(std::time::Duration::from_secs(30));
+            ctx.request_repaint_after(interval);
+        }
     }
 }
@@ -0,0 +1,180 @@
+//! Keyboard shortcuts and the command palette
+//!
+//! Every action reachable from the palette is listed once in `COMMANDS`, with
+//! its optional shortcut. Shortcuts are ignored while a text field has focus so
+//! typing never triggers them.
+
+use crate::app::ProxyVmWizardApp;
+use crate::views::View;
+use eframe::egui::{self, Key, KeyboardShortcut, Modifiers};
+
+/// Opens and closes the palette; handled apart from `COMMANDS` so it works from
+/// inside the palette's own search field
+pub const PALETTE_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::P);
+
+/// An action the user can run by name or shortcut
+pub struct Command {
+    pub name: &'static str,
+    pub shortcut: Option<KeyboardShortcut>,
+    pub run: fn(&mut ProxyVmWizardApp),
+}
+
+const fn ctrl(key: Key) -> Option<KeyboardShortcut> {
+    Some(KeyboardShortcut::new(Modifiers::COMMAND, key))
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "Refresh VMs",
+        shortcut: ctrl(Key::R),
+        run: |app| app.refresh_vms(),
+    },
+    Command {
+        name: "New role",
+        shortcut: ctrl(Key::N),
+        run: |app| app.start_create_role_wizard(None),
+    },
+    Command {
+        name: "Test all gateways",
+        shortcut: None,
+        run: |app| app.test_all_gateways(),
+    },
+    Command {
+        name: "Go to Dashboard",
+        shortcut: ctrl(Key::Num1),
+        run: |app| app.navigate_to(View::Dashboard),
+    },
+    Command {
+        name: "Go to Wizard",
+        shortcut: ctrl(Key::Num2),
+        run: |app| app.navigate_to(View::Wizard),
+    },
+    Command {
+        name: "Go to Templates",
+        shortcut: ctrl(Key::Num3),
+        run: |app| app.navigate_to(View::Templates),
+    },
+    Command {
+        name: "Go to Settings",
+        shortcut: ctrl(Key::Num4),
+        run: |app| app.navigate_to(View::Settings),
+    },
+    Command {
+        name: "Go to Logs",
+        shortcut: ctrl(Key::L),
+        run: |app| app.navigate_to(View::Logs),
+    },
+];
+
+/// Search state of the open palette
+#[derive(Debug, Default)]
+pub struct CommandPalette {
+    pub open: bool,
+    pub query: String,
+    pub selected: usize,
+}
+
+impl CommandPalette {
+    fn matches(&self) -> Vec<&'static Command> {
+        let query = self.query.trim().to_lowercase();
+        COMMANDS
+            .iter()
+            .filter(|cmd| cmd.name.to_lowercase().contains(&query))
+            .collect()
+    }
+}
+
+/// Run whatever shortcut was pressed this frame, then draw the palette if open
+pub fn handle(app: &mut ProxyVmWizardApp, ctx: &egui::Context) {
+    if ctx.input_mut(|i| i.consume_shortcut(&PALETTE_SHORTCUT)) {
+        app.palette = CommandPalette {
+            open: !app.palette.open,
+            ..Default::default()
+        };
+    }
+
+    if !app.palette.open && !ctx.wants_keyboard_input() {
+        let pressed = COMMANDS.iter().find(|cmd| {
+            cmd.shortcut
+                .is_some_and(|shortcut| ctx.input_mut(|i| i.consume_shortcut(&shortcut)))
+        });
+        if let Some(cmd) = pressed {
+            (cmd.run)(app);
+        }
+    }
+
+    if app.palette.open {
+        show_palette(app, ctx);
+    }
+}
+
+fn show_palette(app: &mut ProxyVmWizardApp, ctx: &egui::Context) {
+    let matches = app.palette.matches();
+    let (up, down, enter, escape) = ctx.input(|i| {
+        (
+            i.key_pressed(Key::ArrowUp),
+            i.key_pressed(Key::ArrowDown),
+            i.key_pressed(Key::Enter),
+            i.key_pressed(Key::Escape),
+        )
+    });
+    if down && app.palette.selected + 1 < matches.len() {
+        app.palette.selected += 1;
+    }
+    if up {
+        app.palette.selected = app.palette.selected.saturating_sub(1);
+    }
+    app.palette.selected = app.palette.selected.min(matches.len().saturating_sub(1));
+
+    let mut chosen = enter
+        .then(|| matches.get(app.palette.selected).copied())
+        .flatten();
+    egui::Window::new("Command Palette")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .fixed_size([360.0, 0.0])
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+        .show(ctx, |ui| {
+            let search = ui.add(
+                egui::TextEdit::singleline(&mut app.palette.query)
+                    .hint_text("Type a command…")
+                    .desired_width(f32::INFINITY),
+            );
+            search.request_focus();
+            if search.changed() {
+                app.palette.selected = 0;
+            }
+            ui.separator();
+
+            if matches.is_empty() {
+                ui.label(egui::RichText::new("No matching command").color(egui::Color32::GRAY));
+            }
+            for (i, cmd) in matches.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .selectable_label(i == app.palette.selected, cmd.name)
+                        .clicked()
+                    {
+                        chosen = Some(*cmd);
+                    }
+                    if let Some(shortcut) = &cmd.shortcut {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.label(
+                                egui::RichText::new(ctx.format_shortcut(shortcut))
+                                    .small()
+                                    .color(egui::Color32::GRAY),
+                            );
+                        });
+                    }
+                });
+            }
+        });
+
+    if let Some(cmd) = chosen {
+        app.palette.open = false;
+        (cmd.run)(app);
+    } else if escape {
+        app.palette.open = false;
+    }
+}
@@ -4,6 +4,8 @@
 //! app VMs, and disposable VMs using libvirt/QEMU.
 
 mod app;
+mod commands;
+mod ops;
 mod views;
 
 use app::ProxyVmWizardApp;
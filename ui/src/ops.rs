@@ -0,0 +1,86 @@
+//! Serialized queue for libvirt mutations
+//!
+//! Mutating operations (create/delete/start/stop) run one at a time on a worker
+//! thread so rapid clicks can't interleave virsh calls on the same domain.
+//! Reads (VM list, state checks) don't go through here and stay concurrent.
+
+use crate::app::{AsyncMessage, StatusLevel};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A queued mutation, returning the status line to show when it finishes
+pub type Job = Box<dyn FnOnce() -> (StatusLevel, String) + Send>;
+
+pub struct OperationQueue {
+    tx: Sender<(String, Job)>,
+    pending: Arc<AtomicUsize>,
+    current: Arc<Mutex<Option<String>>>,
+}
+
+impl OperationQueue {
+    /// Start the worker thread. Results are reported as `AsyncMessage::OperationFinished`.
+    pub fn new(results: Sender<AsyncMessage>) -> Self {
+        let (tx, rx) = channel::<(String, Job)>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let current = Arc::new(Mutex::new(None));
+
+        let worker_pending = Arc::clone(&pending);
+        let worker_current = Arc::clone(&current);
+        std::thread::spawn(move || {
+            for (label, job) in rx {
+                *lock_ignoring_poison(&worker_current) = Some(label.clone());
+                // A panicking job must not take the only worker down with it
+                let (level, message) = std::panic::catch_unwind(AssertUnwindSafe(job))
+                    .unwrap_or_else(|_| {
+                        (
+                            StatusLevel::Error,
+                            format!("{} failed unexpectedly (internal error)", label),
+                        )
+                    });
+                *lock_ignoring_poison(&worker_current) = None;
+                worker_pending.fetch_sub(1, Ordering::SeqCst);
+                if results
+                    .send(AsyncMessage::OperationFinished { level, message })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            tx,
+            pending,
+            current,
+        }
+    }
+
+    /// Queue a mutation behind any that are already waiting
+    pub fn submit(
+        &self,
+        label: impl Into<String>,
+        job: impl FnOnce() -> (StatusLevel, String) + Send + 'static,
+    ) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if self.tx.send((label.into(), Box::new(job))).is_err() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Number of queued or running operations
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Label of the operation currently running, if any
+    pub fn current(&self) -> Option<String> {
+        lock_ignoring_poison(&self.current).clone()
+    }
+}
+
+/// Lock a mutex even if a thread panicked while holding it; the guarded data stays usable
+pub fn lock_ignoring_poison<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
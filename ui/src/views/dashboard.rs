@@ -1,13 +1,66 @@
 //! Dashboard view - overview of roles and VMs
 
-use crate::app::{ProxyHopEntry, ProxyVmWizardApp};
+use crate::app::{
+    GeneratedFile, HopList, NewAppVm, ProxyHopEntry, ProxyVmWizardApp, RoleSort, StatusLevel,
+    VmRename,
+};
+use chrono::Utc;
 use eframe::egui;
 use proxy_vm_core::{
-    GatewayMode, OpenVpnParsedConfig, ProxyType, VmKind, VmState, WireGuardParsedConfig,
+    add_openvpn_askpass,
+    config::{format_relative_time, DEFAULT_MAX_APP_VMS},
+    format_size,
+    proxy_config::PROXYCHAINS_CONF,
+    timefmt, validate_share_dir, validate_vm_name, CrashPolicy, GatewayMode, OpenVpnParsedConfig,
+    ProxyType, RotationStrategy, UpstreamCheck, VmInfo, VmKind, VmState, WireGuardParsedConfig,
+    APPLY_PROXY_SCRIPT_VERSION, EXTRA_ENV_PREFIX, MAX_BACKING_CHAIN_DEPTH, MAX_PROXY_HOPS,
 };
+use std::path::{Path, PathBuf};
 
 pub struct DashboardView;
 
+/// Icon and color for a VM state, dimmed when the state may be out of date
+fn state_style(state: VmState, stale: bool) -> (&'static str, egui::Color32) {
+    let (icon, color) = match state {
+        VmState::Running => ("🟢", egui::Color32::from_rgb(34, 139, 34)),
+        VmState::Paused => ("🟡", egui::Color32::from_rgb(255, 165, 0)),
+        VmState::ShutOff => ("🔴", egui::Color32::from_rgb(220, 20, 60)),
+        VmState::Unknown => ("⚪", egui::Color32::GRAY),
+    };
+    if stale {
+        (icon, color.gamma_multiply(0.45))
+    } else {
+        (icon, color)
+    }
+}
+
+/// Last component of a disk path, for lists where the full path is in the hover text
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// A VM's libvirt title after its name, unless it is the one given at creation
+fn vm_title_label(ui: &mut egui::Ui, vm: &VmInfo) {
+    let Some(title) = &vm.title else {
+        return;
+    };
+    if vm
+        .role
+        .as_deref()
+        .is_some_and(|role| vm.kind.default_title(role) == *title)
+    {
+        return;
+    }
+    ui.label(
+        egui::RichText::new(format!("“{}”", title))
+            .small()
+            .color(egui::Color32::GRAY),
+    )
+    .on_hover_text("Title set in libvirt, e.g. with virt-manager");
+}
+
 impl DashboardView {
     pub fn show(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
         // Handle delete confirmation dialog
@@ -20,11 +73,52 @@ impl DashboardView {
                     ui.label(format!("Are you sure you want to delete role '{}'?", role));
                     ui.add_space(5.0);
                     ui.label("This will permanently delete:");
-                    ui.label(format!("  • Gateway VM: {}-gw", role));
+                    ui.label(format!(
+                        "  • Gateway VM: {}",
+                        app.global_config.naming.gateway_name(&role)
+                    ));
                     ui.label("  • All App VMs for this role".to_string());
                     ui.label(format!("  • Network: {}-inet", role));
-                    ui.label("  • All overlay disks".to_string());
-                    ui.label("  • Config directory".to_string());
+                    if app.delete_keep_disks {
+                        ui.label("  • Disposable VM disks".to_string());
+                    } else {
+                        ui.label("  • Config directory".to_string());
+                        if !app.delete_disks.disks.is_empty() {
+                            ui.label("  • The role's own disks:");
+                            for disk in &app.delete_disks.disks {
+                                ui.label(
+                                    egui::RichText::new(format!("      {}", file_name(&disk.path)))
+                                        .monospace(),
+                                )
+                                .on_hover_text(disk.path.display().to_string());
+                            }
+                        }
+                    }
+                    if !app.delete_disks.shared_bases.is_empty() {
+                        ui.add_space(5.0);
+                        ui.label("Base images are kept, so other roles keep working:");
+                        for (base, users) in &app.delete_disks.shared_bases {
+                            let text = if users.is_empty() {
+                                format!("  • {}", file_name(base))
+                            } else {
+                                format!(
+                                    "  • {}, also used by {}",
+                                    file_name(base),
+                                    users.join(", ")
+                                )
+                            };
+                            ui.colored_label(egui::Color32::from_rgb(34, 139, 34), text)
+                                .on_hover_text(base.display().to_string());
+                        }
+                    }
+                    ui.add_space(5.0);
+                    ui.checkbox(&mut app.delete_keep_disks, "Keep disks")
+                        .on_hover_text(
+                            "Only undefine the VMs and remove the network. The gateway and app \
+                             overlays and the role directory stay, so their data can be copied \
+                             out. Creating the role again needs the kept overlays moved or \
+                             deleted first.",
+                        );
                     ui.add_space(10.0);
                     ui.colored_label(
                         egui::Color32::from_rgb(220, 20, 60),
@@ -35,10 +129,15 @@ impl DashboardView {
                         if ui.button("Cancel").clicked() {
                             app.pending_role_delete = None;
                         }
-                        if ui.button("🗑 Delete Everything").clicked() {
+                        let label = if app.delete_keep_disks {
+                            "🗑 Delete VMs and Network"
+                        } else {
+                            "🗑 Delete Everything"
+                        };
+                        if ui.button(label).clicked() {
                             let role_to_delete = role.clone();
                             app.pending_role_delete = None;
-                            app.delete_role(&role_to_delete);
+                            app.delete_role(&role_to_delete, app.delete_keep_disks);
                         }
                     });
                 });
@@ -49,21 +148,98 @@ impl DashboardView {
             Self::show_config_editor(app, ui, &role);
         }
 
+        if let Some(role) = app.copy_config_target.clone() {
+            Self::show_copy_config_dialog(app, ui, &role);
+        }
+
+        if app.new_app_vm.is_some() {
+            Self::show_new_app_vm_dialog(app, ui);
+        }
+
+        if app.gateway_tests.is_some() {
+            Self::show_gateway_tests(app, ui);
+        }
+
+        if app.generated_files.is_some() {
+            Self::show_generated_files(app, ui);
+        }
+
+        if app.gateway_logs.is_some() {
+            Self::show_gateway_logs(app, ui);
+        }
+
         ui.heading("📊 Dashboard");
         ui.add_space(10.0);
 
         // Quick actions
         ui.horizontal(|ui| {
             if ui.button("➕ Create New Role").clicked() {
-                app.start_create_role_wizard();
+                app.start_create_role_wizard(None);
+            }
+            let testing = app.gateway_tests.as_ref().is_some_and(|run| !run.finished);
+            if ui
+                .add_enabled(!testing, egui::Button::new("🩺 Test all gateways"))
+                .on_hover_text("Check the upstream of every running gateway")
+                .clicked()
+            {
+                app.test_all_gateways();
             }
             ui.separator();
             if let Some(instant) = app.last_refresh {
                 let elapsed = instant.elapsed().as_secs();
                 ui.label(format!("Last refresh: {}s ago", elapsed));
             }
+            if app.global_config.ui.auto_refresh_interval().is_none() {
+                ui.label(egui::RichText::new("⏸ Auto-refresh off").color(egui::Color32::GRAY))
+                    .on_hover_text("Change this under Settings → Dashboard");
+            }
+            ui.separator();
+            ui.label("Sort:");
+            ui.selectable_value(&mut app.role_sort, RoleSort::Alphabetical, "A–Z");
+            ui.selectable_value(
+                &mut app.role_sort,
+                RoleSort::RecentlyModified,
+                "Recently modified",
+            );
         });
 
+        let warning = match app.stale_data_age() {
+            Some(age) => {
+                let reason = if app.refresh_failed {
+                    "the last refresh failed"
+                } else if app.global_config.ui.auto_refresh_interval().is_none() {
+                    "auto-refresh is off"
+                } else {
+                    "refreshes are overdue"
+                };
+                Some(format!(
+                    "⚠ VM states may be stale ({}s old, {}). Check before acting on them.",
+                    age.as_secs(),
+                    reason
+                ))
+            }
+            // Nothing was ever listed, so there are no states to call stale
+            None if app.refresh_failed => Some(
+                "⚠ VMs could not be listed; the roles below show no VM states. See Logs for why."
+                    .to_string(),
+            ),
+            None => None,
+        };
+        if let Some(warning) = warning {
+            ui.add_space(10.0);
+            egui::Frame::group(ui.style())
+                .fill(egui::Color32::from_rgb(60, 45, 20))
+                .inner_margin(8.0)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(egui::Color32::from_rgb(255, 165, 0), warning);
+                        if ui.button("🔄 Refresh now").clicked() {
+                            app.refresh_vms();
+                        }
+                    });
+                });
+        }
+
         ui.add_space(20.0);
 
         if app.discovered_roles.is_empty() && app.role_vms.is_empty() {
@@ -84,6 +260,10 @@ impl DashboardView {
             }
         }
         all_roles.sort();
+        if app.role_sort == RoleSort::RecentlyModified {
+            // Stable sort keeps roles without a timestamp alphabetical at the end
+            all_roles.sort_by_key(|role| std::cmp::Reverse(app.role_modified.get(role).copied()));
+        }
 
         // Role cards
         egui::ScrollArea::vertical().show(ui, |ui| {
@@ -95,6 +275,7 @@ impl DashboardView {
     }
 
     fn show_role_card(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui, role: &str) {
+        let stale = app.stale_data_age().is_some();
         let vms = app.role_vms.get(role).cloned().unwrap_or_default();
         let gw_vm = vms.iter().find(|v| v.kind == VmKind::ProxyGateway);
         let app_vms: Vec<_> = vms.iter().filter(|v| v.kind == VmKind::App).collect();
@@ -110,6 +291,67 @@ impl DashboardView {
             .show(ui, |ui| {
                 ui.horizontal(|ui| {
                     ui.heading(format!("🏷 {}", role));
+                    if let Some(modified) = app.role_modified.get(role) {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "edited {}",
+                                format_relative_time(*modified, Utc::now())
+                            ))
+                            .small()
+                            .color(egui::Color32::GRAY),
+                        )
+                        .on_hover_text(timefmt::date_time(&modified.with_timezone(&chrono::Local)));
+                    }
+
+                    if !app.disk_usage.contains_key(role) {
+                        app.fetch_disk_usage(role);
+                    }
+                    match app.disk_usage.get(role) {
+                        None => {
+                            ui.spinner().on_hover_text("Measuring disk usage…");
+                        }
+                        Some(Ok(bytes)) => {
+                            ui.label(
+                                egui::RichText::new(format!("💾 {}", format_size(*bytes)))
+                                    .small()
+                                    .color(egui::Color32::GRAY),
+                            )
+                            .on_hover_text(
+                                "Host disk space used by this role's gateway, app and \
+                                 disposable disks, not counting shared templates",
+                            );
+                        }
+                        Some(Err(e)) => {
+                            ui.label(
+                                egui::RichText::new("💾 ?")
+                                    .small()
+                                    .color(egui::Color32::GRAY),
+                            )
+                            .on_hover_text(format!("Disk usage unknown: {}", e));
+                        }
+                    }
+
+                    match app.conf_validation.get(role) {
+                        Some(issues) if issues.is_empty() => {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(34, 139, 34),
+                                egui::RichText::new("✓ proxy.conf").small(),
+                            )
+                            .on_hover_text("No issues found by the last validation");
+                        }
+                        Some(issues) => {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(255, 165, 0),
+                                egui::RichText::new(format!(
+                                    "⚠ proxy.conf: {} issue(s)",
+                                    issues.len()
+                                ))
+                                .small(),
+                            )
+                            .on_hover_text(issues.join("\n"));
+                        }
+                        None => {}
+                    }
 
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         if ui
@@ -117,7 +359,7 @@ impl DashboardView {
                             .on_hover_text("Delete role and all VMs")
                             .clicked()
                         {
-                            app.pending_role_delete = Some(role.to_string());
+                            app.request_role_delete(role);
                         }
                         if ui
                             .button("🔧")
@@ -126,69 +368,390 @@ impl DashboardView {
                         {
                             app.start_editing_role_config(role);
                         }
+                        if ui
+                            .button("📄")
+                            .on_hover_text("View generated proxy.conf and apply-proxy.sh")
+                            .clicked()
+                        {
+                            app.view_generated_files(role);
+                        }
+                        if ui
+                            .button("🧪")
+                            .on_hover_text("Validate proxy.conf")
+                            .clicked()
+                        {
+                            app.validate_role_config(role);
+                        }
+                        if ui
+                            .button("📋")
+                            .on_hover_text("Copy gateway config from another role…")
+                            .clicked()
+                        {
+                            app.start_copy_gateway_config(role);
+                        }
+                        if ui
+                            .button("🕶")
+                            .on_hover_text("Copy proxy.conf with passwords masked, for sharing")
+                            .clicked()
+                        {
+                            if let Some(content) = app.redacted_role_config(role) {
+                                ui.ctx().copy_text(content);
+                                app.set_status(
+                                    StatusLevel::Success,
+                                    format!("Copied redacted proxy.conf of '{}'", role),
+                                );
+                            }
+                        }
                     });
                 });
 
+                if let Some(installed) = app.outdated_scripts.get(role).copied() {
+                    ui.horizontal(|ui| {
+                        let installed = installed
+                            .map(|v| format!("v{}", v))
+                            .unwrap_or_else(|| "unversioned".to_string());
+                        ui.colored_label(
+                            egui::Color32::from_rgb(255, 165, 0),
+                            format!(
+                                "⚠ apply-proxy.sh is outdated ({}, current v{})",
+                                installed, APPLY_PROXY_SCRIPT_VERSION
+                            ),
+                        );
+                        if ui.small_button("⬆ Update script").clicked() {
+                            app.update_apply_script(role);
+                        }
+                    });
+                }
+
+                if let Some(policy) = app.role_rotations.get(role) {
+                    let summary = format!(
+                        "🔀 Rotating exit proxy every {} min ({}, {} in pool)",
+                        policy.interval_secs / 60,
+                        match policy.strategy {
+                            RotationStrategy::RoundRobin => "round-robin",
+                            RotationStrategy::Random => "random",
+                        },
+                        policy.pool.len()
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(summary).small());
+                        if ui
+                            .small_button("Apply now")
+                            .on_hover_text("Write the proxy due for this interval and reapply it")
+                            .clicked()
+                        {
+                            app.rotate_role(role);
+                        }
+                        if ui
+                            .small_button("📄 Import pool CSV")
+                            .on_hover_text(
+                                "Add every proxy in a CSV file to the rotation pool; \
+                                 proxies already in it are skipped",
+                            )
+                            .clicked()
+                        {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("CSV", &["csv", "txt"])
+                                .pick_file()
+                            {
+                                app.import_rotation_pool(role, path);
+                            }
+                        }
+                    });
+                }
+
                 ui.add_space(8.0);
 
+                Self::show_role_network(app, ui, role);
+
                 // Gateway VM section
                 ui.horizontal(|ui| {
                     ui.label("Gateway:");
                     if let Some(gw) = gw_vm {
-                        let (status_icon, status_color) = match gw.state {
-                            VmState::Running => ("🟢", egui::Color32::from_rgb(34, 139, 34)),
-                            VmState::Paused => ("🟡", egui::Color32::from_rgb(255, 165, 0)),
-                            VmState::ShutOff => ("🔴", egui::Color32::from_rgb(220, 20, 60)),
-                            VmState::Unknown => ("⚪", egui::Color32::GRAY),
-                        };
+                        let (status_icon, status_color) = state_style(gw.state, stale);
                         ui.colored_label(status_color, format!("{} {}", status_icon, gw.name));
+                        vm_title_label(ui, gw);
 
                         if gw.state.is_running() {
                             if ui.small_button("⏹ Stop").clicked() {
                                 app.stop_vm(&gw.name);
                             }
+                            if ui
+                                .small_button("🔍")
+                                .on_hover_text("Verify /proxy mount inside the gateway")
+                                .clicked()
+                            {
+                                app.verify_proxy_mount(&gw.name);
+                            }
+                            if ui
+                                .small_button("📜")
+                                .on_hover_text(
+                                    "View proxychains.conf and the apply-proxy log in the gateway",
+                                )
+                                .clicked()
+                            {
+                                app.view_gateway_logs(&gw.name);
+                            }
                         } else if ui.small_button("▶ Start").clicked() {
                             app.start_vm(&gw.name);
                         }
+                        Self::show_disk_chain(app, ui, &gw.name, gw.state.is_running());
+
+                        let policy = app.role_crash_policies.get(role).copied();
+                        let mut restart = policy == Some(CrashPolicy::Restart);
+                        if policy.is_some()
+                            && ui
+                                .checkbox(&mut restart, "♻ Restart on crash")
+                                .on_hover_text(
+                                    "Boot the gateway again if its guest crashes, including a \
+                                     kernel panic (a panic device is added if it has none). \
+                                     A running gateway picks up the change on its next start.",
+                                )
+                                .changed()
+                        {
+                            let policy = if restart {
+                                CrashPolicy::Restart
+                            } else {
+                                CrashPolicy::Leave
+                            };
+                            app.set_gateway_crash_policy(role, policy);
+                        }
                     } else {
                         ui.label("Not created");
                     }
                 });
 
+                // Gateway NICs: one on the LAN, one on the role network, plus any
+                // extra networks the role asked for
+                if let Some(Ok(nics)) = gw_vm.and_then(|gw| app.gw_interfaces.get(&gw.name)) {
+                    let role_net = format!("{}-inet", role);
+                    let lan_net = &app.global_config.libvirt.lan_net;
+                    let extra_nets = app
+                        .role_extra_gateway_networks
+                        .get(role)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default();
+                    ui.indent("gw_nics", |ui| {
+                        for nic in nics {
+                            let text = format!(
+                                "🔌 {} → {} ({}{})",
+                                nic.mac,
+                                nic.source,
+                                nic.model,
+                                nic.target
+                                    .as_ref()
+                                    .map(|t| format!(", {}", t))
+                                    .unwrap_or_default()
+                            );
+                            if nic.source == *lan_net
+                                || nic.source == role_net
+                                || extra_nets.contains(&nic.source)
+                            {
+                                ui.label(egui::RichText::new(text).small());
+                            } else {
+                                let expected = std::iter::once(lan_net)
+                                    .chain(std::iter::once(&role_net))
+                                    .chain(extra_nets)
+                                    .map(|net| format!("'{}'", net))
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                ui.colored_label(
+                                    egui::Color32::from_rgb(255, 165, 0),
+                                    egui::RichText::new(format!("⚠ {}", text)).small(),
+                                )
+                                .on_hover_text(format!(
+                                    "Expected the gateway only on {}",
+                                    expected
+                                ));
+                            }
+                        }
+                    });
+                }
+
+                // WireGuard tunnel health (only tracked for running WireGuard gateways)
+                if let Some(gw) = gw_vm.filter(|gw| {
+                    app.wg_status.contains_key(&gw.name)
+                        || app.wg_checks_in_flight.contains(&gw.name)
+                }) {
+                    ui.horizontal(|ui| {
+                        ui.label("Tunnel:");
+                        match app.wg_status.get(&gw.name) {
+                            None => {
+                                ui.label("Checking handshake...");
+                            }
+                            Some(Ok(status)) if status.is_healthy() => {
+                                let age = status.latest_handshake_age().unwrap_or(0);
+                                ui.colored_label(
+                                    egui::Color32::from_rgb(34, 139, 34),
+                                    format!("🟢 {}: handshake {}s ago", status.interface, age),
+                                );
+                            }
+                            Some(Ok(status)) => {
+                                let text = match status.latest_handshake_age() {
+                                    Some(age) => format!(
+                                        "🔴 {}: last handshake {}s ago",
+                                        status.interface, age
+                                    ),
+                                    None => format!("🔴 {}: no handshake", status.interface),
+                                };
+                                ui.colored_label(egui::Color32::from_rgb(220, 20, 60), text)
+                                    .on_hover_text(
+                                        "The tunnel is not established. Check the gateway's \
+                                         WireGuard config and that the endpoint is reachable.",
+                                    );
+                            }
+                            Some(Err(e)) => {
+                                ui.colored_label(egui::Color32::GRAY, "⚪ Status unknown")
+                                    .on_hover_text(e.as_str());
+                            }
+                        }
+
+                        if app.wg_checks_in_flight.contains(&gw.name) {
+                            ui.spinner();
+                        } else if ui
+                            .small_button("🔄")
+                            .on_hover_text("Check WireGuard handshake")
+                            .clicked()
+                        {
+                            app.check_wireguard(role, &gw.name);
+                        }
+                    });
+                }
+
                 // App VMs section
                 ui.horizontal(|ui| {
-                    ui.label(format!("App VMs: {}", app_vms.len()));
-                    if ui.small_button("➕ New App VM").clicked() {
-                        app.create_app_vm(role);
+                    let saved_max = app
+                        .role_max_app_vms
+                        .get(role)
+                        .copied()
+                        .unwrap_or(DEFAULT_MAX_APP_VMS);
+                    let mut max_app_vms = saved_max;
+                    ui.label(format!("App VMs: {} /", app_vms.len()));
+                    let limit = ui
+                        .add(egui::DragValue::new(&mut max_app_vms).range(1..=999))
+                        .on_hover_text("Most app VMs this role may have");
+                    if max_app_vms != saved_max {
+                        app.role_max_app_vms.insert(role.to_string(), max_app_vms);
+                    }
+                    if limit.drag_stopped() || limit.lost_focus() {
+                        app.set_max_app_vms(role, max_app_vms);
+                    }
+                    let below_limit = app_vms.len() < max_app_vms as usize;
+                    if ui
+                        .add_enabled(below_limit, egui::Button::new("➕ New App VM").small())
+                        .on_disabled_hover_text("At the app VM limit; raise it to add more")
+                        .clicked()
+                    {
+                        app.new_app_vm = Some(NewAppVm {
+                            role: role.to_string(),
+                            ..Default::default()
+                        });
+                    }
+                    if app_vms.iter().any(|vm| vm.state.is_running()) {
+                        if app.isolation_checks_in_flight.contains(role) {
+                            ui.spinner();
+                        } else if ui
+                            .small_button("🛡 Verify isolation")
+                            .on_hover_text(
+                                "From each running app VM, try to reach the host's LAN \
+                                 gateway directly; it should fail",
+                            )
+                            .clicked()
+                        {
+                            app.verify_isolation(role);
+                        }
                     }
                 });
 
+                match app.isolation_status.get(role) {
+                    Some(Ok(report)) if report.leaking.is_empty() => {
+                        let mut label = ui.colored_label(
+                            egui::Color32::from_rgb(34, 139, 34),
+                            format!(
+                                "🛡 Isolated: {} app VM(s) cannot reach {}",
+                                report.checked.len(),
+                                report.target
+                            ),
+                        );
+                        if !report.skipped.is_empty() {
+                            label = label.on_hover_text(format!(
+                                "Not checked (no guest agent): {}",
+                                report.skipped.join(", ")
+                            ));
+                        }
+                        label.on_hover_text("Checked with a live connection attempt");
+                    }
+                    Some(Ok(report)) => {
+                        ui.colored_label(
+                            egui::Color32::from_rgb(220, 20, 60),
+                            format!(
+                                "⚠ Not isolated: {} reached {} directly",
+                                report.leaking.join(", "),
+                                report.target
+                            ),
+                        )
+                        .on_hover_text(
+                            "Traffic from these VMs bypasses the gateway. Check that they \
+                             are attached only to the role network.",
+                        );
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::GRAY, "⚪ Isolation not verified")
+                            .on_hover_text(e.as_str());
+                    }
+                    None => {}
+                }
+
                 if !app_vms.is_empty() {
                     ui.indent("app_vms", |ui| {
                         for vm in &app_vms {
                             ui.horizontal(|ui| {
-                                let (status_icon, status_color) = match vm.state {
-                                    VmState::Running => {
-                                        ("🟢", egui::Color32::from_rgb(34, 139, 34))
-                                    }
-                                    VmState::Paused => ("🟡", egui::Color32::from_rgb(255, 165, 0)),
-                                    VmState::ShutOff => {
-                                        ("🔴", egui::Color32::from_rgb(220, 20, 60))
-                                    }
-                                    VmState::Unknown => ("⚪", egui::Color32::GRAY),
-                                };
+                                let (status_icon, status_color) = state_style(vm.state, stale);
+                                if app.renaming_vm.as_ref().is_some_and(|r| r.vm == vm.name) {
+                                    ui.colored_label(status_color, status_icon);
+                                    Self::show_vm_rename(app, ui);
+                                    return;
+                                }
                                 ui.colored_label(
                                     status_color,
                                     format!("{} {}", status_icon, vm.name),
                                 );
+                                vm_title_label(ui, vm);
+                                if ui
+                                    .add_enabled(
+                                        !vm.state.is_running(),
+                                        egui::Button::new("✏").small(),
+                                    )
+                                    .on_hover_text("Rename")
+                                    .on_disabled_hover_text("Shut the VM down to rename it")
+                                    .clicked()
+                                {
+                                    app.renaming_vm = Some(VmRename {
+                                        vm: vm.name.clone(),
+                                        new_name: vm.name.clone(),
+                                        rename_disk: true,
+                                    });
+                                }
 
                                 if vm.state.is_running() {
                                     if ui.small_button("⏹").on_hover_text("Stop").clicked() {
                                         app.stop_vm(&vm.name);
                                     }
+                                    if ui
+                                        .small_button("✂")
+                                        .on_hover_text(
+                                            "Trim disk: return space freed in the guest to the \
+                                             host. Needs the guest agent and discard=unmap on \
+                                             the template.",
+                                        )
+                                        .clicked()
+                                    {
+                                        app.trim_vm_disk(&vm.name);
+                                    }
                                 } else if ui.small_button("▶").on_hover_text("Start").clicked() {
                                     app.start_vm(&vm.name);
                                 }
+                                Self::show_disk_chain(app, ui, &vm.name, vm.state.is_running());
                             });
                         }
                     });
@@ -210,6 +773,7 @@ impl DashboardView {
                                     egui::Color32::from_rgb(34, 139, 34),
                                     format!("🟢 {}", vm.name),
                                 );
+                                vm_title_label(ui, vm);
                                 if ui
                                     .small_button("⏹")
                                     .on_hover_text("Stop (will delete)")
@@ -217,6 +781,13 @@ impl DashboardView {
                                 {
                                     app.stop_vm(&vm.name);
                                 }
+                                if ui
+                                    .small_button("📌")
+                                    .on_hover_text("Keep as a persistent app VM")
+                                    .clicked()
+                                {
+                                    app.keep_disposable_vm(role, &vm.name);
+                                }
                             });
                         }
                     });
@@ -224,6 +795,521 @@ impl DashboardView {
             });
     }
 
+    /// Inline name field for the app VM in `app.renaming_vm`
+    fn show_vm_rename(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let Some(rename) = app.renaming_vm.as_mut() else {
+            return;
+        };
+        let response =
+            ui.add(egui::TextEdit::singleline(&mut rename.new_name).desired_width(160.0));
+        ui.checkbox(&mut rename.rename_disk, "Rename disk")
+            .on_hover_text("Also rename the overlay file to <name>-overlay.qcow2");
+
+        let invalid = validate_vm_name(rename.new_name.trim()).err();
+        let submit = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        let confirmed = ui
+            .add_enabled(invalid.is_none(), egui::Button::new("✔").small())
+            .on_hover_text("Rename")
+            .clicked();
+        let cancelled = ui.small_button("✖").on_hover_text("Cancel").clicked();
+        if let Some(error) = &invalid {
+            ui.colored_label(egui::Color32::from_rgb(220, 20, 60), error);
+        }
+
+        if cancelled {
+            app.renaming_vm = None;
+        } else if invalid.is_none() && (confirmed || submit) {
+            if let Some(rename) = app.renaming_vm.take() {
+                app.rename_vm(&rename.vm, &rename.new_name, rename.rename_disk);
+            }
+        }
+    }
+
+    /// State of the role's `<role>-inet` network: app VMs lose connectivity
+    /// when it is stopped, so it gets a start button and an autostart toggle
+    fn show_role_network(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui, role: &str) {
+        let net_name = format!("{}-inet", role);
+        let info = match &app.role_networks {
+            None => return,
+            Some(Err(e)) => {
+                ui.horizontal(|ui| {
+                    ui.label("Network:");
+                    ui.label(
+                        egui::RichText::new(format!("? {}", net_name)).color(egui::Color32::GRAY),
+                    )
+                    .on_hover_text(format!("Network state unknown: {}", e));
+                });
+                return;
+            }
+            Some(Ok(networks)) => networks.get(role).cloned(),
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("Network:");
+            let Some(info) = info else {
+                ui.colored_label(
+                    egui::Color32::from_rgb(220, 20, 60),
+                    format!("⚠ {} missing", net_name),
+                )
+                .on_hover_text("The role network is not defined in libvirt; the gateway and app VMs cannot start");
+                return;
+            };
+
+            if info.state.is_active() {
+                ui.colored_label(
+                    egui::Color32::from_rgb(34, 139, 34),
+                    format!("● {} active", net_name),
+                );
+            } else {
+                ui.colored_label(
+                    egui::Color32::from_rgb(255, 165, 0),
+                    format!("○ {} inactive", net_name),
+                )
+                .on_hover_text("App VMs on this network have no connectivity until it is started");
+                if ui.small_button("▶ Start").clicked() {
+                    app.start_role_network(role);
+                }
+            }
+
+            let mut autostart = info.autostart;
+            if ui
+                .checkbox(&mut autostart, "Autostart")
+                .on_hover_text("Start this network when libvirtd starts")
+                .changed()
+            {
+                app.set_role_network_autostart(role, autostart);
+            }
+        });
+    }
+
+    /// Backing chain depth of a VM's disk, with a flatten button once it gets deep
+    fn show_disk_chain(
+        app: &mut ProxyVmWizardApp,
+        ui: &mut egui::Ui,
+        vm_name: &str,
+        running: bool,
+    ) {
+        let Some(Ok(depth)) = app.disk_chains.get(vm_name).cloned() else {
+            return;
+        };
+        if depth <= MAX_BACKING_CHAIN_DEPTH {
+            ui.label(
+                egui::RichText::new(format!("💽 {}", depth))
+                    .small()
+                    .color(egui::Color32::GRAY),
+            )
+            .on_hover_text(format!("Disk backing chain depth: {}", depth));
+            return;
+        }
+
+        ui.colored_label(
+            egui::Color32::from_rgb(255, 165, 0),
+            egui::RichText::new(format!("⚠ 💽 {}", depth)).small(),
+        )
+        .on_hover_text(format!(
+            "The disk sits on {} backing files, and reads that miss the top layer walk \
+             down the whole chain. Flattening merges the intermediate layers into one \
+             overlay on the base image.",
+            depth
+        ));
+        if running {
+            ui.add_enabled(false, egui::Button::new("Flatten").small())
+                .on_disabled_hover_text("Shut the VM down to flatten its disk");
+        } else if ui
+            .small_button("Flatten")
+            .on_hover_text("Rewrite the disk as a single overlay on its base image")
+            .clicked()
+        {
+            app.flatten_vm_disk(vm_name);
+        }
+    }
+
+    fn show_gateway_tests(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let Some(run) = app.gateway_tests.clone() else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("🩺 Gateway Test")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .open(&mut open)
+            .show(ui.ctx(), |ui| {
+                if run.finished {
+                    let (passed, failed, skipped) = run.tally();
+                    ui.label(format!(
+                        "{} passed, {} failed, {} skipped",
+                        passed, failed, skipped
+                    ));
+                } else {
+                    ui.add(
+                        egui::ProgressBar::new(run.results.len() as f32 / run.total as f32)
+                            .text(format!("{} / {} gateways", run.results.len(), run.total))
+                            .animate(true),
+                    );
+                }
+                ui.add_space(5.0);
+
+                egui::Grid::new("gateway_test_results")
+                    .num_columns(3)
+                    .spacing([10.0, 4.0])
+                    .show(ui, |ui| {
+                        for (role, gateway, check) in &run.results {
+                            let (icon, color) = match check {
+                                UpstreamCheck::Reachable(_) => {
+                                    ("🟢", egui::Color32::from_rgb(34, 139, 34))
+                                }
+                                UpstreamCheck::Unreachable(_) => {
+                                    ("🔴", egui::Color32::from_rgb(220, 20, 60))
+                                }
+                                UpstreamCheck::Skipped(_) => ("⚪", egui::Color32::GRAY),
+                            };
+                            ui.colored_label(color, format!("{} {}", icon, role));
+                            ui.label(egui::RichText::new(gateway).small());
+                            ui.label(check.detail());
+                            ui.end_row();
+                        }
+                    });
+
+                ui.add_space(5.0);
+                ui.label(
+                    egui::RichText::new("Each result is also in the Logs view.")
+                        .small()
+                        .color(egui::Color32::GRAY),
+                );
+            });
+        if !open {
+            app.gateway_tests = None;
+        }
+    }
+
+    fn show_generated_files(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let Some(view) = app.generated_files.clone() else {
+            return;
+        };
+        let mut open = true;
+        let mut tab = view.tab;
+        let mut reload = false;
+        egui::Window::new(format!("📄 Generated Files: {}", view.role))
+            .collapsible(false)
+            .resizable(true)
+            .default_width(640.0)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .open(&mut open)
+            .show(ui.ctx(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut tab, GeneratedFile::ProxyConf, "proxy.conf");
+                    ui.selectable_value(&mut tab, GeneratedFile::ApplyScript, "apply-proxy.sh");
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("🔄 Reload").clicked() {
+                            reload = true;
+                        }
+                    });
+                });
+                ui.separator();
+
+                let (content, note) = match tab {
+                    GeneratedFile::ProxyConf => (
+                        &view.proxy_conf,
+                        "Passwords and the OpenVPN auth file are shown as <redacted>.",
+                    ),
+                    GeneratedFile::ApplyScript if view.script_is_generated => (
+                        &view.apply_script,
+                        "Matches the script this version generates.",
+                    ),
+                    GeneratedFile::ApplyScript => (
+                        &view.apply_script,
+                        "⚠ Differs from the script this version generates: it was \
+                         edited by hand or written by another version.",
+                    ),
+                };
+                match content {
+                    Ok(text) => {
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(note).small().color(egui::Color32::GRAY));
+                            if ui.small_button("📋 Copy").clicked() {
+                                ui.ctx().copy_text(text.clone());
+                            }
+                        });
+                        egui::ScrollArea::both().max_height(400.0).show(ui, |ui| {
+                            ui.add(
+                                egui::TextEdit::multiline(&mut text.as_str())
+                                    .code_editor()
+                                    .desired_width(f32::INFINITY),
+                            );
+                        });
+                    }
+                    Err(e) => {
+                        ui.colored_label(egui::Color32::from_rgb(220, 20, 60), e);
+                    }
+                }
+
+                if tab == GeneratedFile::ProxyConf && !view.apply_plan.is_empty() {
+                    ui.add_space(5.0);
+                    ui.label("What apply-proxy.sh does with this:");
+                    for line in &view.apply_plan {
+                        ui.label(format!("  • {}", line));
+                    }
+                }
+            });
+
+        if let Some(current) = &mut app.generated_files {
+            current.tab = tab;
+        }
+        if !open {
+            app.generated_files = None;
+        } else if reload {
+            app.view_generated_files(&view.role);
+        }
+    }
+
+    fn show_gateway_logs(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let Some(view) = app.gateway_logs.clone() else {
+            return;
+        };
+        let mut open = true;
+        let mut reload = false;
+        egui::Window::new(format!("📜 Gateway Logs: {}", view.gateway))
+            .collapsible(false)
+            .resizable(true)
+            .default_width(640.0)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .open(&mut open)
+            .show(ui.ctx(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        egui::RichText::new(
+                            "Read through the guest agent. Proxy passwords are shown as <redacted>.",
+                        )
+                        .small()
+                        .color(egui::Color32::GRAY),
+                    );
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui
+                            .add_enabled(!view.is_loading(), egui::Button::new("🔄 Reload"))
+                            .clicked()
+                        {
+                            reload = true;
+                        }
+                    });
+                });
+                ui.separator();
+
+                let sections = [
+                    (PROXYCHAINS_CONF, &view.proxychains),
+                    ("apply-proxy journal", &view.journal),
+                ];
+                for (title, content) in sections {
+                    ui.push_id(title, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(title).strong());
+                            if let Some(Ok(text)) = content {
+                                if ui.small_button("📋 Copy").clicked() {
+                                    ui.ctx().copy_text(text.clone());
+                                }
+                            }
+                        });
+                        match content {
+                            None => {
+                                ui.horizontal(|ui| {
+                                    ui.spinner();
+                                    ui.label("Reading from the gateway...");
+                                });
+                            }
+                            Some(Ok(text)) if text.trim().is_empty() => {
+                                ui.label(egui::RichText::new("(empty)").color(egui::Color32::GRAY));
+                            }
+                            Some(Ok(text)) => {
+                                egui::ScrollArea::both()
+                                    .max_height(220.0)
+                                    .stick_to_bottom(true)
+                                    .show(ui, |ui| {
+                                        ui.add(
+                                            egui::TextEdit::multiline(&mut text.as_str())
+                                                .code_editor()
+                                                .desired_width(f32::INFINITY),
+                                        );
+                                    });
+                            }
+                            Some(Err(e)) => {
+                                ui.colored_label(egui::Color32::from_rgb(220, 20, 60), e);
+                            }
+                        }
+                    });
+                    ui.add_space(8.0);
+                }
+            });
+
+        if !open {
+            app.gateway_logs = None;
+        } else if reload {
+            app.view_gateway_logs(&view.gateway);
+        }
+    }
+
+    fn show_new_app_vm_dialog(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let Some(dialog) = &mut app.new_app_vm else {
+            return;
+        };
+        let mut create = false;
+        let mut cancel = false;
+        egui::Window::new(format!("➕ New App VM for '{}'", dialog.role))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.checkbox(&mut dialog.share, "Share a host folder with this App VM");
+                let mut valid = true;
+                if dialog.share {
+                    ui.horizontal(|ui| {
+                        ui.label("Folder:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut dialog.share_dir)
+                                .hint_text("/home/user/shared")
+                                .desired_width(260.0),
+                        );
+                        if ui.button("📂 Browse").clicked() {
+                            if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                dialog.share_dir = path.to_string_lossy().into_owned();
+                            }
+                        }
+                    });
+                    if let Err(e) = validate_share_dir(Path::new(dialog.share_dir.trim())) {
+                        valid = false;
+                        ui.colored_label(egui::Color32::from_rgb(220, 20, 60), e);
+                    }
+                    ui.label(
+                        egui::RichText::new(
+                            "Mount it in the guest with: mount -t 9p -o trans=virtio shared /mnt",
+                        )
+                        .small()
+                        .monospace(),
+                    );
+                    ui.colored_label(
+                        egui::Color32::from_rgb(255, 165, 0),
+                        "⚠ The folder is shared with accessmode=mapped: QEMU reads and writes it \
+                         as the libvirt-qemu user, so that user needs access to it, and files the \
+                         guest creates are owned by libvirt-qemu on the host.",
+                    );
+                }
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                    if ui
+                        .add_enabled(valid, egui::Button::new("➕ Create"))
+                        .clicked()
+                    {
+                        create = true;
+                    }
+                });
+            });
+
+        if create {
+            if let Some(dialog) = app.new_app_vm.take() {
+                let share_dir = dialog.share.then(|| PathBuf::from(dialog.share_dir.trim()));
+                app.create_app_vm(&dialog.role, share_dir.as_deref());
+            }
+        } else if cancel {
+            app.new_app_vm = None;
+        }
+    }
+
+    fn show_copy_config_dialog(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui, role: &str) {
+        let sources: Vec<String> = app
+            .discovered_roles
+            .iter()
+            .filter(|r| r.as_str() != role)
+            .cloned()
+            .collect();
+        let target_mode = app.copy_config_modes.get(role).copied();
+        let source_mode = app
+            .copy_config_source
+            .as_ref()
+            .and_then(|source| app.copy_config_modes.get(source).copied());
+
+        egui::Window::new(format!("📋 Copy Gateway Config to '{}'", role))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.label("Replace this role's proxy.conf with another role's gateway settings.");
+                ui.label(
+                    egui::RichText::new(
+                        "VPN config, auth, key and askpass files are copied into this role's directory.",
+                    )
+                    .small()
+                    .color(egui::Color32::GRAY),
+                );
+                ui.add_space(10.0);
+
+                if sources.is_empty() {
+                    ui.label("No other roles to copy from.");
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.label("Copy from:");
+                        egui::ComboBox::from_id_salt("copy_config_source")
+                            .selected_text(app.copy_config_source.as_deref().unwrap_or("Select..."))
+                            .show_ui(ui, |ui| {
+                                for source in &sources {
+                                    ui.selectable_value(
+                                        &mut app.copy_config_source,
+                                        Some(source.clone()),
+                                        source,
+                                    );
+                                }
+                            });
+                    });
+                }
+
+                if let Some(source) = &app.copy_config_source {
+                    ui.add_space(5.0);
+                    match (source_mode, target_mode) {
+                        (None, _) => {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(220, 20, 60),
+                                format!("'{}' has no proxy.conf to copy", source),
+                            );
+                        }
+                        (Some(from), Some(to)) if from != to => {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(255, 165, 0),
+                                format!(
+                                    "⚠ This switches '{}' from {} to {}",
+                                    role,
+                                    to.display_name(),
+                                    from.display_name()
+                                ),
+                            );
+                        }
+                        (Some(from), _) => {
+                            ui.label(format!("Mode: {}", from.display_name()));
+                        }
+                    }
+                }
+
+                ui.add_space(5.0);
+                ui.checkbox(
+                    &mut app.copy_config_restart,
+                    "Restart gateway VM after copying (if running)",
+                );
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        app.copy_config_target = None;
+                    }
+                    if ui
+                        .add_enabled(source_mode.is_some(), egui::Button::new("📋 Copy"))
+                        .clicked()
+                    {
+                        app.copy_gateway_config();
+                    }
+                });
+            });
+    }
+
     fn show_config_editor(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui, role: &str) {
         egui::Window::new(format!("🔧 Edit Gateway Config: {}", role))
             .collapsible(false)
@@ -262,6 +1348,9 @@ impl DashboardView {
                         GatewayMode::OpenVpn => Self::show_openvpn_editor(app, ui),
                     });
 
+                ui.add_space(10.0);
+                Self::show_extra_env_editor(app, ui);
+
                 ui.add_space(10.0);
                 ui.separator();
 
@@ -283,10 +1372,59 @@ impl DashboardView {
                     if ui.button("💾 Save & Apply").clicked() {
                         app.save_role_config();
                     }
+                    if let Some(backup) = &app.config_editor.last_backup {
+                        let taken = backup.taken.format(timefmt::DATE_TIME_FORMAT);
+                        if ui
+                            .button("↩ Revert to last known good")
+                            .on_hover_text(format!(
+                                "Restore proxy.conf as it was before the save at {}, discarding the current file and any unsaved edits",
+                                taken
+                            ))
+                            .clicked()
+                        {
+                            app.revert_role_config();
+                        }
+                    }
                 });
             });
     }
 
+    fn show_extra_env_editor(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let rows = &mut app.config_editor.extra_env;
+        egui::CollapsingHeader::new(format!("Extra environment ({})", rows.len()))
+            .id_salt("config_extra_env")
+            .show(ui, |ui| {
+                ui.label(
+                    egui::RichText::new(
+                        "Written to proxy.conf as KEY=VALUE for custom gateway scripts. Keys start with X_.",
+                    )
+                    .small()
+                    .color(egui::Color32::GRAY),
+                );
+                let mut to_remove = None;
+                for (i, (key, value)) in rows.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(key)
+                                .hint_text("X_NAME")
+                                .desired_width(140.0),
+                        );
+                        ui.label("=");
+                        ui.add(egui::TextEdit::singleline(value).desired_width(220.0));
+                        if ui.small_button("✕").clicked() {
+                            to_remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = to_remove {
+                    rows.remove(i);
+                }
+                if ui.small_button("➕ Add variable").clicked() {
+                    rows.push((EXTRA_ENV_PREFIX.to_string(), String::new()));
+                }
+            });
+    }
+
     fn show_proxy_chain_editor(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
         ui.label("Proxy Chain Configuration:");
         ui.add_space(5.0);
@@ -300,6 +1438,7 @@ impl DashboardView {
                 .show(ui, |ui| {
                     ui.horizontal(|ui| {
                         ui.label(format!("Hop {}", i + 1));
+                        super::copy_url_button(ui, hop, i);
                         if hop_count > 1 {
                             ui.with_layout(
                                 egui::Layout::right_to_left(egui::Align::Center),
@@ -315,6 +1454,37 @@ impl DashboardView {
                     ui.horizontal(|ui| {
                         ui.radio_value(&mut hop.proxy_type, ProxyType::Socks5, "SOCKS5");
                         ui.radio_value(&mut hop.proxy_type, ProxyType::Http, "HTTP");
+                        if ui
+                            .small_button("🔍 Auto-detect")
+                            .on_hover_text("Probe host:port for SOCKS5 or HTTP")
+                            .clicked()
+                        {
+                            hop.auto_detect_type(&app.libvirt);
+                        }
+                        if hop.proxy_type == ProxyType::Socks5
+                            && ui
+                                .small_button("📡 Test UDP")
+                                .on_hover_text(
+                                    "Ask the proxy for a UDP relay (SOCKS5 UDP ASSOCIATE)",
+                                )
+                                .clicked()
+                        {
+                            hop.test_udp(&app.libvirt);
+                        }
+                        super::udp_badge(ui, hop);
+                        if hop.pinging {
+                            ui.spinner();
+                        } else if ui
+                            .small_button("📶 Ping")
+                            .on_hover_text("Send one ICMP echo to the host")
+                            .clicked()
+                        {
+                            hop.ping(&app.libvirt, &app.async_tx, HopList::ConfigEditor, i);
+                        }
+                        super::ping_badge(ui, hop);
+                        if let (Some(false), Some(message)) = (hop.test_status, &hop.test_message) {
+                            ui.colored_label(egui::Color32::from_rgb(220, 20, 60), message);
+                        }
                     });
 
                     ui.horizontal(|ui| {
@@ -322,6 +1492,7 @@ impl DashboardView {
                         ui.add(egui::TextEdit::singleline(&mut hop.host).desired_width(150.0));
                         ui.label("Port:");
                         ui.add(egui::TextEdit::singleline(&mut hop.port).desired_width(60.0));
+                        super::port_error(ui, hop);
                     });
 
                     ui.horizontal(|ui| {
@@ -334,6 +1505,43 @@ impl DashboardView {
                                 .desired_width(100.0),
                         );
                     });
+
+                    egui::CollapsingHeader::new("Details")
+                        .id_salt(format!("hop_details_{}", i))
+                        .show(ui, |ui| {
+                            egui::Grid::new(format!("hop_details_grid_{}", i))
+                                .num_columns(2)
+                                .show(ui, |ui| {
+                                    ui.label("Label:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut hop.label)
+                                            .desired_width(200.0),
+                                    );
+                                    ui.end_row();
+
+                                    ui.label("Provider:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut hop.provider)
+                                            .desired_width(200.0),
+                                    );
+                                    ui.end_row();
+
+                                    ui.label("Country:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut hop.country)
+                                            .desired_width(200.0),
+                                    );
+                                    ui.end_row();
+
+                                    ui.label("Notes:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut hop.notes)
+                                            .hint_text("e.g. expires 2026-12-01")
+                                            .desired_width(200.0),
+                                    );
+                                    ui.end_row();
+                                });
+                        });
                 });
             ui.add_space(3.0);
         }
@@ -342,9 +1550,28 @@ impl DashboardView {
             app.config_editor.proxy_hops.remove(idx);
         }
 
-        if app.config_editor.proxy_hops.len() < 8 && ui.button("➕ Add Proxy Hop").clicked() {
-            app.config_editor.proxy_hops.push(ProxyHopEntry::default());
-        }
+        ui.horizontal(|ui| {
+            if app.config_editor.proxy_hops.len() < MAX_PROXY_HOPS
+                && ui.button("➕ Add Proxy Hop").clicked()
+            {
+                app.config_editor.proxy_hops.push(ProxyHopEntry::default());
+            }
+            if let Some((level, message)) =
+                super::import_env_button(ui, &mut app.config_editor.proxy_hops)
+            {
+                app.set_status(level, message);
+            }
+            if let Some((level, message)) =
+                super::import_csv_button(ui, &mut app.config_editor.proxy_hops)
+            {
+                app.set_status(level, message);
+            }
+            if let Some((level, message)) =
+                super::optimize_order_button(ui, &mut app.config_editor.proxy_hops)
+            {
+                app.set_status(level, message);
+            }
+        });
     }
 
     fn show_wireguard_editor(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
@@ -464,6 +1691,11 @@ impl DashboardView {
             &mut app.config_editor.wireguard_config.route_all_traffic,
             "Route all traffic",
         );
+        ui.checkbox(
+            &mut app.config_editor.wireguard_config.kill_switch,
+            "Kill switch",
+        )
+        .on_hover_text(super::KILL_SWITCH_HINT);
     }
 
     fn show_openvpn_editor(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
@@ -566,6 +1798,60 @@ impl DashboardView {
                                 );
                             }
                         });
+
+                    if parsed.needs_passphrase() {
+                        ui.add_space(5.0);
+                        ui.colored_label(
+                            egui::Color32::from_rgb(255, 165, 0),
+                            "⚠ The private key is passphrase-protected. OpenVPN in the gateway \
+                             will wait for the passphrase at boot and never connect.",
+                        );
+                        ui.horizontal(|ui| {
+                            ui.label("Passphrase:");
+                            ui.add(
+                                egui::TextEdit::singleline(
+                                    &mut app.config_editor.openvpn_config.askpass_passphrase,
+                                )
+                                .password(true)
+                                .desired_width(160.0),
+                            );
+                            let ready = !app
+                                .config_editor
+                                .openvpn_config
+                                .askpass_passphrase
+                                .is_empty();
+                            if ui
+                                .add_enabled(ready, egui::Button::new("💾 Save askpass file"))
+                                .on_hover_text(
+                                    "Store the passphrase in the role directory (owner-only) \
+                                     and add an askpass line to the config",
+                                )
+                                .clicked()
+                            {
+                                let passphrase = std::mem::take(
+                                    &mut app.config_editor.openvpn_config.askpass_passphrase,
+                                );
+                                match add_openvpn_askpass(&config_path, &passphrase) {
+                                    Ok(path) => app.set_status(
+                                        StatusLevel::Success,
+                                        format!("Saved key passphrase to {}", path.display()),
+                                    ),
+                                    Err(e) => app.set_status(
+                                        StatusLevel::Error,
+                                        format!("Failed to save askpass file: {}", e),
+                                    ),
+                                }
+                            }
+                        });
+                    } else if parsed.has_encrypted_key {
+                        ui.label(
+                            egui::RichText::new(
+                                "🔑 Encrypted key; unlocked at boot with the askpass file",
+                            )
+                            .small()
+                            .color(egui::Color32::GRAY),
+                        );
+                    }
                 }
             }
         }
@@ -601,5 +1887,15 @@ impl DashboardView {
             &mut app.config_editor.openvpn_config.route_all_traffic,
             "Route all traffic",
         );
+        ui.checkbox(
+            &mut app.config_editor.openvpn_config.kill_switch,
+            "Kill switch",
+        )
+        .on_hover_text(super::KILL_SWITCH_HINT);
+        super::openvpn_overrides(
+            ui,
+            &mut app.config_editor.openvpn_config,
+            "editor_ovpn_proto",
+        );
     }
 }
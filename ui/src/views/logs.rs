@@ -2,6 +2,7 @@
 
 use crate::app::{ProxyVmWizardApp, StatusLevel};
 use eframe::egui;
+use proxy_vm_core::timefmt;
 
 pub struct LogsView;
 
@@ -41,7 +42,7 @@ impl LogsView {
 
                     ui.horizontal(|ui| {
                         ui.label(
-                            egui::RichText::new(entry.timestamp.format("%H:%M:%S").to_string())
+                            egui::RichText::new(timefmt::clock(&entry.timestamp))
                                 .color(egui::Color32::GRAY)
                                 .monospace(),
                         );
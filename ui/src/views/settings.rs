@@ -2,11 +2,78 @@
 
 use crate::app::{ProxyVmWizardApp, StatusLevel};
 use eframe::egui;
+use proxy_vm_core::{
+    file_log::{DEFAULT_KEPT_LOG_FILES, DEFAULT_MAX_LOG_BYTES, LOG_FILE_NAME},
+    timefmt, LogLevel, NamingSection, ShareDriver, DEFAULT_LIBVIRT_URI, DEFAULT_ROLE_SUBNET_POOL,
+    MIN_AUTO_REFRESH_SECS,
+};
 
 pub struct SettingsView;
 
 impl SettingsView {
+    fn show_import_confirmation(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let Some(src) = app.settings_view.pending_bundle_import.clone() else {
+            return;
+        };
+        egui::Window::new("📥 Import Bundle")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.label(format!("Import {}?", src.display()));
+                ui.add_space(5.0);
+                ui.label("This replaces your settings, templates and password, and merges");
+                ui.label("the bundled roles into the config root. Current config files are");
+                ui.label("kept next to the originals with a .pre-import suffix.");
+                ui.add_space(5.0);
+                ui.colored_label(
+                    egui::Color32::from_rgb(255, 165, 0),
+                    "⚠ The app must be restarted afterwards.",
+                );
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        app.settings_view.pending_bundle_import = None;
+                    }
+                    if ui.button("📥 Import").clicked() {
+                        app.import_bundle();
+                    }
+                });
+            });
+    }
+
+    fn show_network_removal_confirmation(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let Some(name) = app.settings_view.pending_network_removal.clone() else {
+            return;
+        };
+        egui::Window::new("🧹 Remove Network")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.label(format!("Stop and undefine network '{}'?", name));
+                ui.label("Any VM still attached to it loses that connection.");
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        app.settings_view.pending_network_removal = None;
+                    }
+                    if ui.button("🗑 Remove").clicked() {
+                        app.settings_view.pending_network_removal = None;
+                        app.remove_leftover_network(&name);
+                    }
+                });
+            });
+    }
+
     pub fn show(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        if app.settings_view.pending_bundle_import.is_some() {
+            Self::show_import_confirmation(app, ui);
+        }
+        if app.settings_view.pending_network_removal.is_some() {
+            Self::show_network_removal_confirmation(app, ui);
+        }
+
         ui.heading("⚙ Settings");
         ui.add_space(10.0);
 
@@ -69,6 +136,23 @@ impl SettingsView {
                         .num_columns(2)
                         .spacing([10.0, 8.0])
                         .show(ui, |ui| {
+                            ui.label("Connection URI:");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut app.settings_view.libvirt_uri)
+                                    .hint_text(DEFAULT_LIBVIRT_URI)
+                                    .desired_width(200.0),
+                            );
+                            ui.end_row();
+
+                            ui.label("virt-install timeout (s):");
+                            ui.add(
+                                egui::TextEdit::singleline(
+                                    &mut app.settings_view.virt_install_timeout,
+                                )
+                                .desired_width(80.0),
+                            );
+                            ui.end_row();
+
                             ui.label("LAN Network:");
                             ui.add(
                                 egui::TextEdit::singleline(&mut app.settings_view.lan_net)
@@ -76,13 +160,45 @@ impl SettingsView {
                                     .desired_width(200.0),
                             );
                             ui.end_row();
+
+                            ui.label("Role subnet range:");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut app.settings_view.role_subnet_pool)
+                                    .hint_text(DEFAULT_ROLE_SUBNET_POOL)
+                                    .desired_width(200.0),
+                            )
+                            .on_hover_text(
+                                "Private IPv4 range NAT role networks each get a free /24 from, \
+                                 skipping subnets already used by libvirt networks or host interfaces",
+                            );
+                            ui.end_row();
+
+                            ui.label("Proxy Share Driver:");
+                            egui::ComboBox::from_id_salt("share_driver")
+                                .selected_text(app.settings_view.share_driver.display_name())
+                                .show_ui(ui, |ui| {
+                                    for driver in [ShareDriver::NineP, ShareDriver::Virtiofs] {
+                                        ui.selectable_value(
+                                            &mut app.settings_view.share_driver,
+                                            driver,
+                                            driver.display_name(),
+                                        );
+                                    }
+                                });
+                            ui.end_row();
                         });
 
                     ui.add_space(5.0);
                     ui.label(
                         egui::RichText::new(
-                            "LAN Network: The libvirt network your pfSense/gateway connects to.\n\
-                         This network must already exist in libvirt.",
+                            "Connection URI: Where virsh and virt-install connect, e.g. qemu:///session\n\
+                         or qemu+ssh://user@host/system. It is tested before it is saved.\n\
+                         virt-install timeout: A VM creation still running after this long is\n\
+                         killed and rolled back. 0 waits forever.\n\
+                         LAN Network: The libvirt network your pfSense/gateway connects to.\n\
+                         This network must already exist in libvirt.\n\
+                         Proxy Share Driver: How the role directory reaches /proxy in new gateways.\n\
+                         virtiofs is faster but needs virtiofsd on the host and a matching fstab entry.",
                         )
                         .color(egui::Color32::GRAY)
                         .small(),
@@ -90,7 +206,8 @@ impl SettingsView {
 
                     ui.add_space(10.0);
                     if ui.button("🔌 Test Libvirt Connectivity").clicked() {
-                        match app.libvirt.check_libvirt_access() {
+                        let uri = app.settings_view.libvirt_uri.trim().to_string();
+                        match app.libvirt_for_uri(&uri).check_libvirt_access() {
                             Ok(_) => {
                                 app.set_status(
                                     StatusLevel::Success,
@@ -188,6 +305,296 @@ impl SettingsView {
                     );
                 });
 
+            ui.add_space(10.0);
+
+            // Dashboard section
+            egui::CollapsingHeader::new("📊 Dashboard")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("dashboard_grid")
+                        .num_columns(2)
+                        .spacing([10.0, 8.0])
+                        .show(ui, |ui| {
+                            ui.label("Auto-refresh (seconds):");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut app.settings_view.auto_refresh)
+                                    .desired_width(80.0),
+                            );
+                            ui.end_row();
+                        });
+
+                    ui.add_space(5.0);
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "Set to 0 to refresh only manually. Minimum {} seconds; every \
+                             refresh queries virsh once per VM.",
+                            MIN_AUTO_REFRESH_SECS
+                        ))
+                        .color(egui::Color32::GRAY)
+                        .small(),
+                    );
+                });
+
+            ui.add_space(10.0);
+
+            // Log file section
+            egui::CollapsingHeader::new("📝 Logging")
+                .default_open(false)
+                .show(ui, |ui| {
+                    egui::Grid::new("logging_grid")
+                        .num_columns(2)
+                        .spacing([10.0, 8.0])
+                        .show(ui, |ui| {
+                            ui.label("Log Directory:");
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut app.settings_view.log_dir)
+                                        .desired_width(400.0),
+                                );
+                                if ui.button("Browse...").clicked() {
+                                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                        app.settings_view.log_dir = path.display().to_string();
+                                    }
+                                }
+                            });
+                            ui.end_row();
+
+                            ui.label("Level:");
+                            egui::ComboBox::from_id_salt("log_level")
+                                .selected_text(app.settings_view.log_level.display_name())
+                                .show_ui(ui, |ui| {
+                                    for level in LogLevel::ALL {
+                                        ui.selectable_value(
+                                            &mut app.settings_view.log_level,
+                                            level,
+                                            level.display_name(),
+                                        );
+                                    }
+                                });
+                            ui.end_row();
+                        });
+
+                    ui.add_space(5.0);
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "Log entries are also written to {} in this directory, so they \
+                             survive a crash.\nIt is rotated at {} KiB, keeping {} old files. \
+                             Debug adds every virsh, virt-install and qemu-img command line.",
+                            LOG_FILE_NAME,
+                            DEFAULT_MAX_LOG_BYTES / 1024,
+                            DEFAULT_KEPT_LOG_FILES
+                        ))
+                        .color(egui::Color32::GRAY)
+                        .small(),
+                    );
+                });
+
+            ui.add_space(10.0);
+
+            // VM naming section
+            egui::CollapsingHeader::new("🏷 VM Names")
+                .default_open(false)
+                .show(ui, |ui| {
+                    egui::Grid::new("naming_grid")
+                        .num_columns(2)
+                        .spacing([10.0, 8.0])
+                        .show(ui, |ui| {
+                            let naming = &mut app.settings_view.naming;
+                            for (label, pattern) in [
+                                ("Gateway VM:", &mut naming.gateway),
+                                ("App VM:", &mut naming.app),
+                                ("Disposable VM:", &mut naming.disposable),
+                            ] {
+                                ui.label(label);
+                                ui.add(egui::TextEdit::singleline(pattern).desired_width(200.0));
+                                ui.end_row();
+                            }
+                        });
+
+                    ui.add_space(5.0);
+                    let preview = {
+                        let naming = &app.settings_view.naming;
+                        format!(
+                            "e.g. {}, {}, {}",
+                            naming.gateway_name("work"),
+                            naming.app_name("work", 1),
+                            naming.disposable_name("work", "20240101-120000")
+                        )
+                    };
+                    ui.label(egui::RichText::new(preview).small());
+                    ui.label(
+                        egui::RichText::new(
+                            "{role} is the role name, {n} the app VM number and {ts} the \
+                             disposable VM's start time. Existing VMs keep their names; VMs \
+                             that no longer match a pattern disappear from the dashboard.",
+                        )
+                        .color(egui::Color32::GRAY)
+                        .small(),
+                    );
+                    if ui.small_button("Reset to defaults").clicked() {
+                        app.settings_view.naming = NamingSection::default();
+                    }
+                });
+
+            ui.add_space(10.0);
+
+            // Leftover network cleanup
+            egui::CollapsingHeader::new("🧹 Leftover Networks")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.label(
+                        "Find <role>-inet networks whose role directory and VMs are gone, \
+                         e.g. after deleting a role by hand.",
+                    );
+                    ui.horizontal(|ui| {
+                        ui.add_enabled_ui(!app.settings_view.network_scan_busy, |ui| {
+                            if ui.button("🔍 Scan").clicked() {
+                                app.scan_leftover_networks();
+                            }
+                        });
+                        if app.settings_view.network_scan_busy {
+                            ui.spinner();
+                        }
+                    });
+
+                    let mut remove = None;
+                    match &app.settings_view.leftover_networks {
+                        Some(networks) if networks.is_empty() => {
+                            ui.label(
+                                egui::RichText::new("No leftover networks found")
+                                    .color(egui::Color32::GRAY),
+                            );
+                        }
+                        Some(networks) => {
+                            for net in networks {
+                                ui.horizontal(|ui| {
+                                    let state = if net.state.is_active() {
+                                        "active"
+                                    } else {
+                                        "inactive"
+                                    };
+                                    ui.label(format!("{} ({})", net.name, state));
+                                    if ui.small_button("🗑 Remove").clicked() {
+                                        remove = Some(net.name.clone());
+                                    }
+                                });
+                            }
+                        }
+                        None => {}
+                    }
+                    if remove.is_some() {
+                        app.settings_view.pending_network_removal = remove;
+                    }
+                });
+
+            ui.add_space(10.0);
+
+            // Backup section
+            egui::CollapsingHeader::new("📦 Backup & Migration")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.label(
+                        "Export config, templates, password data and all role directories \
+                         as one archive to move to another machine.",
+                    );
+                    ui.checkbox(
+                        &mut app.settings_view.bundle_include_images,
+                        "Include template base images (can be many GB)",
+                    );
+                    ui.horizontal(|ui| {
+                        ui.add_enabled_ui(!app.settings_view.bundle_busy, |ui| {
+                            if ui.button("📤 Export Bundle...").clicked() {
+                                if let Some(dest) = rfd::FileDialog::new()
+                                    .set_file_name("proxy-vm-wizard-bundle.tar.gz")
+                                    .save_file()
+                                {
+                                    app.export_bundle(dest);
+                                }
+                            }
+                            if ui.button("📥 Import Bundle...").clicked() {
+                                if let Some(src) = rfd::FileDialog::new()
+                                    .add_filter("Bundle", &["gz", "tgz"])
+                                    .pick_file()
+                                {
+                                    app.settings_view.pending_bundle_import = Some(src);
+                                }
+                            }
+                        });
+                        if app.settings_view.bundle_busy {
+                            ui.spinner();
+                        }
+                    });
+
+                    ui.add_space(5.0);
+                    ui.label(
+                        "Share just the VM defaults as a small TOML file, e.g. to give a \
+                         team the same baseline. No secrets or role paths are included.",
+                    );
+                    ui.checkbox(
+                        &mut app.settings_view.defaults_include_libvirt,
+                        "Include libvirt settings (images directory, LAN network, URI)",
+                    );
+                    ui.horizontal(|ui| {
+                        if ui.button("📤 Export Defaults...").clicked() {
+                            if let Some(dest) = rfd::FileDialog::new()
+                                .set_file_name("proxy-vm-wizard-defaults.toml")
+                                .save_file()
+                            {
+                                app.export_defaults(dest);
+                            }
+                        }
+                        if ui.button("📥 Import Defaults...").clicked() {
+                            if let Some(src) = rfd::FileDialog::new()
+                                .add_filter("TOML", &["toml"])
+                                .pick_file()
+                            {
+                                app.import_defaults(src);
+                            }
+                        }
+                    });
+
+                    if app.encryption.is_some() {
+                        ui.add_space(5.0);
+                        ui.label(
+                            "Settings and templates saved before a password was set may \
+                             still be plaintext on disk.",
+                        );
+                        if ui.button("🔒 Encrypt plaintext config").clicked()
+                            && !app.encrypt_plaintext_config()
+                        {
+                            app.set_status(StatusLevel::Info, "Config files are already encrypted");
+                        }
+                    }
+                });
+
+            ui.add_space(10.0);
+
+            // Diagnostics section
+            egui::CollapsingHeader::new("🩺 Diagnostics")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.label(
+                        "Collect settings, templates, role metadata and proxy.conf files, \
+                         libvirt VM and network lists, prerequisite checks and the log \
+                         into one archive to attach to a bug report.",
+                    );
+                    ui.label(
+                        "Proxy passwords are redacted and VPN configs are only listed by \
+                         name, but host names and addresses are kept.",
+                    );
+                    if ui.button("🩺 Generate Diagnostics...").clicked() {
+                        if let Some(dest) = rfd::FileDialog::new()
+                            .set_file_name(format!(
+                                "proxy-vm-wizard-diagnostics-{}.tar.gz",
+                                timefmt::now_stamp()
+                            ))
+                            .save_file()
+                        {
+                            app.export_diagnostics(dest);
+                        }
+                    }
+                });
+
             ui.add_space(20.0);
 
             // Error display
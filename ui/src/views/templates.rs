@@ -1,10 +1,12 @@
 //! Templates view - manage qcow2 templates
 
-use crate::app::{ProxyVmWizardApp, StatusLevel};
+use crate::app::{BootForm, ProxyVmWizardApp, StatusLevel};
 use eframe::egui;
-use proxy_vm_core::{RoleKind, Template};
-use std::fs;
-use std::path::PathBuf;
+use proxy_vm_core::{
+    format_size, recommended_resources, DiskCache, DiskIo, DiskTuning, InstallSource, RoleKind,
+    Template, VmInfo, DEFAULT_INSTALL_DISK_GB,
+};
+use std::path::{Path, PathBuf};
 
 pub struct TemplatesView;
 
@@ -16,8 +18,7 @@ impl TemplatesView {
         ui.horizontal(|ui| {
             if ui.button("➕ Add Template").clicked() {
                 // Discover existing qcow2 files in the images directory
-                app.templates_view.discovered_qcow2_files =
-                    Self::discover_qcow2_files(&app.global_config.libvirt.images_dir);
+                Self::refresh_discovered_files(app);
                 // Cache registered paths to avoid rebuilding every frame
                 app.templates_view.registered_paths_cache = app
                     .template_registry
@@ -32,6 +33,34 @@ impl TemplatesView {
                 app.templates_view.selected_existing_file = None;
                 app.templates_view.edit_template_id = None;
             }
+            if ui
+                .button("📂 Relocate images dir")
+                .on_hover_text("Update template paths after moving the images directory")
+                .clicked()
+            {
+                app.start_relocate_templates();
+            }
+            if ui
+                .button("#️⃣ Compute checksums")
+                .on_hover_text("SHA-256 every template image; unchanged images aren't hashed again")
+                .clicked()
+            {
+                let paths = app
+                    .template_registry
+                    .list()
+                    .iter()
+                    .filter(|t| t.exists())
+                    .map(|t| t.path.clone())
+                    .collect();
+                app.compute_template_checksums(paths);
+            }
+            if ui
+                .button("🩹 Check disk chains")
+                .on_hover_text("Find VMs whose disk points at a backing image that was moved")
+                .clicked()
+            {
+                app.check_backing_chains();
+            }
         });
 
         ui.add_space(10.0);
@@ -74,66 +103,179 @@ impl TemplatesView {
         if app.templates_view.pending_template_delete.is_some() {
             Self::show_delete_confirmation(app, ui);
         }
+
+        if app.templates_view.show_relocate_dialog {
+            Self::show_relocate_dialog(app, ui);
+        }
+
+        if app.templates_view.show_backing_dialog {
+            Self::show_backing_dialog(app, ui);
+        }
     }
 
-    /// Discover all qcow2 files in the images directory
-    fn discover_qcow2_files(images_dir: &PathBuf) -> Vec<PathBuf> {
-        let mut files = Vec::new();
-
-        // First try direct read (works if user has permissions)
-        if let Ok(entries) = fs::read_dir(images_dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(ext) = path.extension() {
-                        if ext.to_string_lossy().to_lowercase() == "qcow2" {
-                            files.push(path);
-                        }
-                    }
+    fn show_backing_dialog(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let mut open = true;
+        let mut rebase = None;
+        egui::Window::new("🩹 Broken Disk Chains")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.label("These VM disks point at a backing image that no longer exists.");
+                ui.label(
+                    egui::RichText::new(
+                        "Remapping only rewrites the path stored in the overlay. The new \
+                         image must be the same one, moved or copied; any other image \
+                         corrupts the VM's disk.",
+                    )
+                    .small(),
+                );
+                ui.add_space(10.0);
+
+                for entry in &app.templates_view.broken_backings {
+                    ui.group(|ui| {
+                        ui.label(egui::RichText::new(&entry.vm).strong());
+                        egui::Grid::new(("backing_grid", &entry.overlay))
+                            .num_columns(2)
+                            .spacing([10.0, 4.0])
+                            .show(ui, |ui| {
+                                ui.label("Disk:");
+                                ui.monospace(entry.overlay.display().to_string());
+                                ui.end_row();
+
+                                ui.label("Missing:");
+                                ui.colored_label(
+                                    egui::Color32::from_rgb(220, 20, 60),
+                                    entry.missing.display().to_string(),
+                                );
+                                ui.end_row();
+
+                                ui.label("New backing:");
+                                ui.horizontal(|ui| {
+                                    let target = app
+                                        .templates_view
+                                        .rebase_targets
+                                        .entry(entry.overlay.clone())
+                                        .or_default();
+                                    ui.add(
+                                        egui::TextEdit::singleline(target)
+                                            .hint_text("/path/to/moved-image.qcow2")
+                                            .desired_width(300.0),
+                                    );
+                                    if ui.button("Browse...").clicked() {
+                                        if let Some(path) = rfd::FileDialog::new()
+                                            .add_filter("QCOW2 Images", &["qcow2"])
+                                            .pick_file()
+                                        {
+                                            *target = path.display().to_string();
+                                        }
+                                    }
+                                    if ui.button("Remap").clicked() {
+                                        rebase = Some(entry.overlay.clone());
+                                    }
+                                });
+                                ui.end_row();
+                            });
+                    });
+                    ui.add_space(5.0);
                 }
-            }
-        }
 
-        // If no files found, try using 'ls' command (for permission-restricted directories)
-        if files.is_empty() {
-            if let Ok(output) = std::process::Command::new("ls")
-                .arg("-1")
-                .arg(images_dir)
-                .output()
-            {
-                if output.status.success() {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    for line in stdout.lines() {
-                        if line.to_lowercase().ends_with(".qcow2") {
-                            files.push(images_dir.join(line));
-                        }
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Close").clicked() {
+                        app.templates_view.show_backing_dialog = false;
                     }
-                }
-            }
+                    if ui.button("🔄 Check again").clicked() {
+                        app.check_backing_chains();
+                    }
+                });
+            });
+        if !open {
+            app.templates_view.show_backing_dialog = false;
+        }
+        if let Some(overlay) = rebase {
+            app.rebase_backing(&overlay);
         }
+    }
 
-        // If still no files, try with pkexec (will prompt for password)
-        if files.is_empty() {
-            if let Ok(output) = std::process::Command::new("pkexec")
-                .args(["ls", "-1"])
-                .arg(images_dir)
-                .output()
-            {
-                if output.status.success() {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    for line in stdout.lines() {
-                        if line.to_lowercase().ends_with(".qcow2") {
-                            files.push(images_dir.join(line));
+    fn show_relocate_dialog(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
+        egui::Window::new("📂 Relocate Images Directory")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.label("Rewrite template paths after moving their images to a new directory.");
+                ui.add_space(10.0);
+
+                egui::Grid::new("relocate_grid")
+                    .num_columns(3)
+                    .spacing([10.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.label("Old directory:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut app.templates_view.relocate_old_base)
+                                .desired_width(300.0),
+                        );
+                        ui.label("");
+                        ui.end_row();
+
+                        ui.label("New directory:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut app.templates_view.relocate_new_base)
+                                .desired_width(300.0),
+                        );
+                        if ui.button("Browse...").clicked() {
+                            if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                app.templates_view.relocate_new_base = path.display().to_string();
+                            }
                         }
-                    }
+                        ui.end_row();
+                    });
+
+                ui.add_space(5.0);
+                ui.label(
+                    egui::RichText::new(
+                        "Only templates stored under the old directory are changed. \
+                         Image files are not moved.",
+                    )
+                    .small(),
+                );
+
+                if let Some(ref error) = app.templates_view.relocate_error {
+                    ui.add_space(5.0);
+                    ui.colored_label(egui::Color32::from_rgb(220, 20, 60), error);
                 }
-            }
-        }
 
-        // Sort by filename
-        files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        app.templates_view.show_relocate_dialog = false;
+                    }
+                    if ui.button("Relocate").clicked() {
+                        app.relocate_templates();
+                    }
+                });
+            });
+    }
 
-        files
+    /// Discover all qcow2 files in the images directory
+    fn refresh_discovered_files(app: &mut ProxyVmWizardApp) {
+        let images_dir = app.global_config.libvirt.images_dir.clone();
+        match app.libvirt.discover_qcow2_files(&images_dir) {
+            Ok(scan) => {
+                app.templates_view.discovered_qcow2_files = scan.files;
+                app.templates_view.discovered_invalid_names = scan.invalid_names;
+            }
+            Err(e) => {
+                app.templates_view.discovered_qcow2_files.clear();
+                app.templates_view.discovered_invalid_names.clear();
+                app.set_status(
+                    StatusLevel::Error,
+                    format!("Failed to scan {}: {}", images_dir.display(), e),
+                );
+            }
+        }
     }
 
     fn show_selection_dialog(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
@@ -167,6 +309,18 @@ impl TemplatesView {
                     ));
                     ui.add_space(5.0);
 
+                    if !app.templates_view.discovered_invalid_names.is_empty() {
+                        ui.colored_label(
+                            egui::Color32::from_rgb(255, 165, 0),
+                            "⚠ These images have names that aren't valid UTF-8 and can't be \
+                             used until they are renamed:",
+                        );
+                        for path in &app.templates_view.discovered_invalid_names {
+                            ui.label(format!("  • {}", path.display()));
+                        }
+                        ui.add_space(5.0);
+                    }
+
                     if app.templates_view.discovered_qcow2_files.is_empty() {
                         ui.colored_label(
                             egui::Color32::from_rgb(150, 150, 150),
@@ -305,9 +459,19 @@ impl TemplatesView {
                                     };
 
                                     app.templates_view.form_role_kind = RoleKind::ProxyGateway;
-                                    app.templates_view.form_ram_mb = "1024".to_string();
+                                    app.templates_view.form_ram_mb = recommended_resources(
+                                        &app.templates_view.form_os_variant,
+                                        RoleKind::ProxyGateway,
+                                    )
+                                    .0
+                                    .to_string();
+                                    app.templates_view.form_disk_tuning = DiskTuning::default();
+                                    app.templates_view.form_boot = BootForm::default();
+                                    app.templates_view.form_install = InstallSource::default();
+                                    app.templates_view.form_install_disk_gb = String::new();
                                     app.templates_view.form_notes = String::new();
                                     app.templates_view.form_error = None;
+                                    app.templates_view.form_duplicate_of = None;
                                 }
                             }
                         });
@@ -344,10 +508,76 @@ impl TemplatesView {
                             app.templates_view.form_os_variant = "debian12".to_string();
                             app.templates_view.form_role_kind = RoleKind::ProxyGateway;
                             app.templates_view.form_ram_mb = "1024".to_string();
+                                    app.templates_view.form_disk_tuning = DiskTuning::default();
+                                    app.templates_view.form_boot = BootForm::default();
+                                    app.templates_view.form_install = InstallSource::default();
+                                    app.templates_view.form_install_disk_gb = String::new();
                             app.templates_view.form_notes = String::new();
                             app.templates_view.form_error = None;
+                            app.templates_view.form_duplicate_of = None;
+                        }
+                    }
+                });
+
+                ui.add_space(10.0);
+
+                // Section 3: Download from a URL
+                ui.group(|ui| {
+                    ui.label(egui::RichText::new("🌐 Download from URL").strong());
+                    ui.label("Fetch a cloud image into the images directory.");
+                    ui.add_space(5.0);
+
+                    let downloading = app.templates_view.download_progress.is_some();
+                    ui.add_enabled_ui(!downloading, |ui| {
+                        egui::Grid::new("template_url_form")
+                            .num_columns(2)
+                            .spacing([10.0, 6.0])
+                            .show(ui, |ui| {
+                                ui.label("URL:");
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut app.templates_view.url_form_url)
+                                        .hint_text("https://cloud.debian.org/.../debian-12-generic-amd64.qcow2")
+                                        .desired_width(350.0),
+                                );
+                                ui.end_row();
+
+                                ui.label("SHA-256:");
+                                ui.add(
+                                    egui::TextEdit::singleline(
+                                        &mut app.templates_view.url_form_sha256,
+                                    )
+                                    .hint_text("Optional, recommended")
+                                    .desired_width(350.0),
+                                );
+                                ui.end_row();
+                            });
+
+                        if ui.button("⬇ Download").clicked() {
+                            app.start_template_download();
+                        }
+                    });
+
+                    if let Some((downloaded, total)) = app.templates_view.download_progress {
+                        let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+                        match total {
+                            Some(total) if total > 0 => {
+                                ui.add(
+                                    egui::ProgressBar::new(downloaded as f32 / total as f32)
+                                        .text(format!("{:.1} / {:.1} MB", mb(downloaded), mb(total))),
+                                );
+                            }
+                            _ => {
+                                ui.horizontal(|ui| {
+                                    ui.spinner();
+                                    ui.label(format!("{:.1} MB downloaded", mb(downloaded)));
+                                });
+                            }
                         }
                     }
+
+                    if let Some(ref error) = app.templates_view.url_form_error {
+                        ui.colored_label(egui::Color32::from_rgb(220, 20, 60), error);
+                    }
                 });
 
                 ui.add_space(10.0);
@@ -364,14 +594,17 @@ impl TemplatesView {
         let template_path = app.templates_view.pending_template_delete_path.clone();
 
         if let (Some(id), Some(path)) = (template_id, template_path) {
-            // Get VMs using this image from the cached disk_to_vm_map
+            let pinned = app.template_registry.get(&id).is_some_and(|t| t.pinned);
+
+            // VMs using this image were fetched when the dialog opened
             // (Don't call virsh every frame - that freezes the UI!)
-            let vms_using_image = app
+            let (running, stopped): (Vec<&VmInfo>, Vec<&VmInfo>) = app
                 .templates_view
-                .disk_to_vm_map
-                .get(&path)
-                .cloned()
-                .unwrap_or_default();
+                .delete_image_users
+                .iter()
+                .partition(|vm| vm.state.is_running());
+            let running: Vec<String> = running.into_iter().map(|vm| vm.name.clone()).collect();
+            let stopped: Vec<String> = stopped.into_iter().map(|vm| vm.name.clone()).collect();
 
             egui::Window::new("⚠ Confirm Delete")
                 .collapsible(false)
@@ -381,36 +614,81 @@ impl TemplatesView {
                     ui.label("Are you sure you want to delete this template?");
                     ui.add_space(10.0);
 
+                    if pinned {
+                        ui.group(|ui| {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(255, 165, 0),
+                                "🔒 This template is pinned. Unpin it to delete it."
+                            );
+                            if ui.button("🔓 Unpin").clicked() {
+                                Self::set_pinned(app, &id, false);
+                            }
+                        });
+                        ui.add_space(10.0);
+                    }
+
                     ui.group(|ui| {
                         ui.label(egui::RichText::new("This will permanently delete:").strong());
                         ui.add_space(5.0);
                         ui.label("📋 Template from registry");
 
                         ui.add_space(5.0);
-                        ui.checkbox(
-                            &mut app.templates_view.delete_image_file,
-                            format!("🗑 Also delete image file: {}", path.file_name().unwrap_or_default().to_string_lossy())
+                        let users_known = app.templates_view.delete_image_users_error.is_none();
+                        ui.add_enabled(
+                            users_known,
+                            egui::Checkbox::new(
+                                &mut app.templates_view.delete_image_file,
+                                format!("🗑 Also delete image file: {}", path.file_name().unwrap_or_default().to_string_lossy())
+                            ),
                         );
+                        if let Some(e) = &app.templates_view.delete_image_users_error {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(255, 165, 0),
+                                format!("⚠ Could not check which VMs use this image, so it is kept: {}", e)
+                            );
+                        }
 
                         if app.templates_view.delete_image_file {
                             ui.label(egui::RichText::new(format!("   Path: {}", path.display())).small());
 
-                            // Show warning if VMs are using this image
-                            if !vms_using_image.is_empty() {
+                            // Running VMs would lose their disk (or its backing file) mid-flight
+                            if !running.is_empty() {
+                                ui.add_space(5.0);
+                                ui.colored_label(
+                                    egui::Color32::from_rgb(220, 20, 60),
+                                    format!("⛔ {} running VM(s) use this image:", running.len())
+                                );
+                                for vm_name in &running {
+                                    ui.colored_label(
+                                        egui::Color32::from_rgb(220, 20, 60),
+                                        format!("   • 🟢 {} (running)", vm_name)
+                                    );
+                                }
+                                ui.colored_label(
+                                    egui::Color32::from_rgb(220, 20, 60),
+                                    "   Deleting the file will crash them. Stop them first."
+                                );
+                                ui.checkbox(
+                                    &mut app.templates_view.force_delete_in_use,
+                                    "Force: delete the file anyway"
+                                );
+                            }
+
+                            if !stopped.is_empty() {
                                 ui.add_space(5.0);
                                 ui.colored_label(
                                     egui::Color32::from_rgb(255, 165, 0),
-                                    format!("⚠ Warning: {} VM(s) use this image:", vms_using_image.len())
+                                    format!("⚠ Warning: {} stopped VM(s) use this image:", stopped.len())
                                 );
-                                for vm_name in &vms_using_image {
+                                for vm_name in &stopped {
                                     ui.colored_label(
                                         egui::Color32::from_rgb(255, 165, 0),
-                                        format!("   • {}", vm_name)
+                                        format!("   • 🔴 {} (stopped)", vm_name)
                                     );
                                 }
                                 ui.colored_label(
                                     egui::Color32::from_rgb(255, 165, 0),
-                                    "   These VMs may stop working!"
+                                    "   These VMs will no longer start!"
                                 );
                             }
                         }
@@ -435,7 +713,18 @@ impl TemplatesView {
                             "🗑 Delete Template Only"
                         };
 
-                        if ui.button(egui::RichText::new(button_text).color(egui::Color32::from_rgb(220, 20, 60))).clicked() {
+                        let in_use = app.templates_view.delete_image_file
+                            && !running.is_empty()
+                            && !app.templates_view.force_delete_in_use;
+                        let delete_button = ui.add_enabled(
+                            !pinned && !in_use,
+                            egui::Button::new(egui::RichText::new(button_text).color(egui::Color32::from_rgb(220, 20, 60))),
+                        );
+                        if pinned {
+                            delete_button.on_disabled_hover_text("Unpin the template first");
+                        } else if in_use {
+                            delete_button.on_disabled_hover_text("Running VMs use this image");
+                        } else if delete_button.clicked() {
                             // First remove from registry
                             if let Err(e) = app.template_registry.remove(&id) {
                                 app.set_status(StatusLevel::Error, format!("Failed to remove from registry: {}", e));
@@ -445,7 +734,7 @@ impl TemplatesView {
 
                                 if app.templates_view.delete_image_file {
                                     // Delete the actual file using pkexec if needed
-                                    let delete_result = if path.starts_with("/var/lib") {
+                                    let delete_result = if proxy_vm_core::needs_privilege(&path) {
                                         app.libvirt.delete_overlay_disk(&path)
                                     } else {
                                         std::fs::remove_file(&path).map_err(proxy_vm_core::Error::Io)
@@ -478,6 +767,63 @@ impl TemplatesView {
         }
     }
 
+    /// Checksum of a template image: a progress bar while it is hashed, then the hex
+    fn show_checksum(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui, path: &Path) {
+        if let Some(&(hashed, total)) = app.templates_view.hashing.get(path) {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                let fraction = if total > 0 {
+                    hashed as f32 / total as f32
+                } else {
+                    1.0
+                };
+                ui.add(
+                    egui::ProgressBar::new(fraction)
+                        .desired_width(200.0)
+                        .text(format!("{} / {}", format_size(hashed), format_size(total))),
+                );
+            });
+        } else if let Some(sha256) = app.templates_view.checksums.get(path) {
+            let sha256 = sha256.to_string();
+            ui.horizontal(|ui| {
+                ui.code(&sha256);
+                if ui.small_button("📋").on_hover_text("Copy").clicked() {
+                    ui.ctx().copy_text(sha256);
+                }
+            });
+        } else if ui
+            .small_button("#️⃣ Compute")
+            .on_hover_text("Hash the image in the background; large images take a while")
+            .clicked()
+        {
+            app.compute_template_checksums(vec![path.to_path_buf()]);
+        }
+    }
+
+    fn set_pinned(app: &mut ProxyVmWizardApp, id: &str, pinned: bool) {
+        let result = app
+            .template_registry
+            .set_pinned(id, pinned)
+            .and_then(|_| app.save_template_registry());
+        match result {
+            Ok(()) => {
+                let label = app
+                    .template_registry
+                    .get(id)
+                    .map(|t| t.label.clone())
+                    .unwrap_or_default();
+                let verb = if pinned { "pinned" } else { "unpinned" };
+                app.set_status(StatusLevel::Info, format!("Template '{}' {}", label, verb));
+            }
+            Err(e) => {
+                app.set_status(
+                    StatusLevel::Error,
+                    format!("Failed to save registry: {}", e),
+                );
+            }
+        }
+    }
+
     fn show_template_card(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui, template: &Template) {
         let exists = template.exists();
         let border_color = if exists {
@@ -494,23 +840,54 @@ impl TemplatesView {
             .show(ui, |ui| {
                 ui.horizontal(|ui| {
                     ui.heading(&template.label);
+                    if template.pinned {
+                        ui.label(egui::RichText::new("🔒").size(16.0))
+                            .on_hover_text("Pinned: unpin before removing");
+                    }
 
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         // Delete button - now shows confirmation
                         if ui.small_button("🗑 Remove").clicked() {
-                            // Fetch disk-to-VM mapping for the delete confirmation dialog
-                            app.templates_view.disk_to_vm_map =
-                                app.libvirt.get_disk_to_vm_map().unwrap_or_default();
+                            // Fetch VM usage (including overlays) and state once for the dialog
+                            let users = app.libvirt.get_image_users(&template.path);
+                            // Without knowing the users, the file must not be offered for deletion
+                            app.templates_view.delete_image_file = users.is_ok();
+                            app.templates_view.delete_image_users_error =
+                                users.as_ref().err().map(|e| e.to_string());
+                            app.templates_view.delete_image_users = users.unwrap_or_default();
+                            app.templates_view.force_delete_in_use = false;
                             app.templates_view.pending_template_delete = Some(template.id.clone());
                             app.templates_view.pending_template_delete_path =
                                 Some(template.path.clone());
-                            app.templates_view.delete_image_file = true; // Default to checked
+                        }
+
+                        let (pin_label, pin_hover) = if template.pinned {
+                            ("🔓 Unpin", "Allow this template to be removed")
+                        } else {
+                            (
+                                "📌 Pin",
+                                "Require unpinning before this template can be removed",
+                            )
+                        };
+                        if ui
+                            .small_button(pin_label)
+                            .on_hover_text(pin_hover)
+                            .clicked()
+                        {
+                            Self::set_pinned(app, &template.id, !template.pinned);
+                        }
+
+                        if ui
+                            .small_button("🧙 Create role")
+                            .on_hover_text("Start the wizard with this template pre-selected")
+                            .clicked()
+                        {
+                            app.start_create_role_wizard(Some(template));
                         }
 
                         if ui.small_button("✏ Edit").clicked() {
                             // Discover existing qcow2 files for edit mode too
-                            app.templates_view.discovered_qcow2_files =
-                                Self::discover_qcow2_files(&app.global_config.libvirt.images_dir);
+                            Self::refresh_discovered_files(app);
                             // Cache registered paths to avoid rebuilding every frame
                             app.templates_view.registered_paths_cache = app
                                 .template_registry
@@ -530,9 +907,15 @@ impl TemplatesView {
                             app.templates_view.form_os_variant = template.os_variant.clone();
                             app.templates_view.form_role_kind = template.role_kind;
                             app.templates_view.form_ram_mb = template.default_ram_mb.to_string();
+                            app.templates_view.form_disk_tuning = template.disk_tuning;
+                            app.templates_view.form_boot = BootForm::from_options(&template.boot);
+                            app.templates_view.form_install = template.install;
+                            app.templates_view.form_install_disk_gb =
+                                template.install_disk_gb.to_string();
                             app.templates_view.form_notes =
                                 template.notes.clone().unwrap_or_default();
                             app.templates_view.form_error = None;
+                            app.templates_view.form_duplicate_of = None;
                         }
                     });
                 });
@@ -563,15 +946,66 @@ impl TemplatesView {
                         ui.label(template.role_kind.display_name());
                         ui.end_row();
 
+                        if template.install.is_fresh_install() {
+                            ui.label("Install:");
+                            ui.label(format!(
+                                "{}, {} GB blank disk",
+                                template.install.display_name(),
+                                template.install_disk_gb
+                            ));
+                            ui.end_row();
+                        }
+
                         ui.label("Default RAM:");
                         ui.label(format!("{} MB", template.default_ram_mb));
                         ui.end_row();
 
+                        if exists {
+                            ui.label("SHA-256:");
+                            Self::show_checksum(app, ui, &template.path);
+                            ui.end_row();
+                        }
+
                         if let Some(ref notes) = template.notes {
                             ui.label("Notes:");
                             ui.label(notes);
                             ui.end_row();
                         }
+
+                        ui.label("Used by:");
+                        ui.horizontal_wrapped(|ui| {
+                            match app.templates_view.template_usage.get(&template.id) {
+                                Some(Ok(usage)) if usage.is_empty() => {
+                                    ui.label("Nothing");
+                                }
+                                Some(Ok(usage)) => {
+                                    ui.label(usage.join(", "));
+                                }
+                                Some(Err(e)) => {
+                                    ui.colored_label(
+                                        egui::Color32::from_rgb(255, 165, 0),
+                                        "⚠ Usage unknown",
+                                    )
+                                    .on_hover_text(e);
+                                }
+                                None => {}
+                            }
+                            // Reading backing chains runs qemu-img per VM, so only on request
+                            if app
+                                .templates_view
+                                .usage_checks_in_flight
+                                .contains(&template.id)
+                            {
+                                ui.spinner();
+                            } else if ui
+                                .small_button("🔍 Check")
+                                .on_hover_text("List roles and VMs that depend on this template")
+                                .clicked()
+                            {
+                                app.check_template_usage(&template.id);
+                            }
+                        });
+                        ui.end_row();
                     });
             });
     }
@@ -602,22 +1036,77 @@ impl TemplatesView {
 
                         ui.label("Path:");
                         ui.horizontal(|ui| {
-                            ui.add(
-                                egui::TextEdit::singleline(&mut app.templates_view.form_path)
-                                    .hint_text("/var/lib/libvirt/images/template.qcow2")
-                                    .desired_width(300.0),
-                            );
+                            let edited = ui
+                                .add(
+                                    egui::TextEdit::singleline(&mut app.templates_view.form_path)
+                                        .hint_text("/var/lib/libvirt/images/template.qcow2")
+                                        .desired_width(300.0),
+                                )
+                                .changed();
+                            if edited {
+                                app.templates_view.form_duplicate_of = None;
+                            }
                             if ui.button("Browse...").clicked() {
-                                if let Some(path) = rfd::FileDialog::new()
-                                    .add_filter("QCOW2 Image", &["qcow2"])
-                                    .pick_file()
-                                {
+                                let dialog = if app.templates_view.form_install.is_fresh_install() {
+                                    rfd::FileDialog::new().add_filter("Installer ISO", &["iso"])
+                                } else {
+                                    rfd::FileDialog::new().add_filter("QCOW2 Image", &["qcow2"])
+                                };
+                                if let Some(path) = dialog.pick_file() {
                                     app.templates_view.form_path = path.display().to_string();
+                                    app.templates_view.form_duplicate_of = None;
                                 }
                             }
                         });
                         ui.end_row();
 
+                        ui.label("Install Source:");
+                        ui.vertical(|ui| {
+                            egui::ComboBox::from_id_salt("install_source_select")
+                                .selected_text(app.templates_view.form_install.display_name())
+                                .show_ui(ui, |ui| {
+                                    for source in [
+                                        InstallSource::Import,
+                                        InstallSource::Cdrom,
+                                        InstallSource::Location,
+                                    ] {
+                                        ui.selectable_value(
+                                            &mut app.templates_view.form_install,
+                                            source,
+                                            source.display_name(),
+                                        );
+                                    }
+                                });
+                            if app.templates_view.form_install.is_fresh_install() {
+                                ui.horizontal(|ui| {
+                                    ui.label("Blank disk (GB):");
+                                    ui.add(
+                                        egui::TextEdit::singleline(
+                                            &mut app.templates_view.form_install_disk_gb,
+                                        )
+                                        .hint_text(DEFAULT_INSTALL_DISK_GB.to_string())
+                                        .desired_width(60.0),
+                                    );
+                                });
+                                ui.label(
+                                    egui::RichText::new(
+                                        "Gateways only: each gets an empty disk and boots the\n\
+                                         installer. Finish the install from the VM console, then\n\
+                                         mount /proxy in the guest (see docs/TEMPLATES.md).",
+                                    )
+                                    .color(egui::Color32::GRAY)
+                                    .small(),
+                                );
+                            }
+                        });
+                        ui.end_row();
+
+                        // Follow the recommendation until the user types their own RAM
+                        let recommended_before = recommended_resources(
+                            &app.templates_view.form_os_variant,
+                            app.templates_view.form_role_kind,
+                        );
+
                         ui.label("OS Variant:");
                         egui::ComboBox::from_id_salt("os_variant_select")
                             .selected_text(&app.templates_view.form_os_variant)
@@ -665,13 +1154,133 @@ impl TemplatesView {
                         });
                         ui.end_row();
 
+                        let (ram_mb, vcpus) = recommended_resources(
+                            &app.templates_view.form_os_variant,
+                            app.templates_view.form_role_kind,
+                        );
+                        let form_ram = app.templates_view.form_ram_mb.trim();
+                        if (ram_mb, vcpus) != recommended_before
+                            && (form_ram.is_empty() || form_ram == recommended_before.0.to_string())
+                        {
+                            app.templates_view.form_ram_mb = ram_mb.to_string();
+                        }
+
                         ui.label("Default RAM (MB):");
-                        ui.add(
-                            egui::TextEdit::singleline(&mut app.templates_view.form_ram_mb)
-                                .desired_width(80.0),
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut app.templates_view.form_ram_mb)
+                                    .hint_text(ram_mb.to_string())
+                                    .desired_width(80.0),
+                            );
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "recommended: {} MB, {} vCPU{}",
+                                    ram_mb,
+                                    vcpus,
+                                    if vcpus == 1 { "" } else { "s" }
+                                ))
+                                .small()
+                                .color(egui::Color32::GRAY),
+                            );
+                        });
+                        ui.end_row();
+
+                        ui.label("Disk Cache:");
+                        let tuning = &mut app.templates_view.form_disk_tuning;
+                        egui::ComboBox::from_id_salt("disk_cache_select")
+                            .selected_text(tuning.cache.map_or("default", |c| c.as_str()))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut tuning.cache, None, "default");
+                                for cache in [
+                                    DiskCache::None,
+                                    DiskCache::Writeback,
+                                    DiskCache::Writethrough,
+                                    DiskCache::Directsync,
+                                    DiskCache::Unsafe,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut tuning.cache,
+                                        Some(cache),
+                                        cache.as_str(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Disk I/O:");
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_salt("disk_io_select")
+                                .selected_text(tuning.io.map_or("default", |io| io.as_str()))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut tuning.io, None, "default");
+                                    for io in [DiskIo::Native, DiskIo::Threads, DiskIo::IoUring] {
+                                        ui.selectable_value(&mut tuning.io, Some(io), io.as_str());
+                                    }
+                                });
+                            ui.checkbox(&mut tuning.discard_unmap, "discard=unmap")
+                                .on_hover_text(
+                                    "Return space the guest frees to the host; also sets \
+                                     detect_zeroes=unmap. See docs/TEMPLATES.md.",
+                                );
+                        });
+                        ui.end_row();
+
+                        ui.label("");
+                        ui.label(
+                            egui::RichText::new(
+                                "cache=none bypasses the host page cache: faster and safer for the VM's own\n\
+                                 flushes, but any guest that doesn't flush can lose recent writes on a host crash.\n\
+                                 'unsafe' ignores flushes entirely - only for throwaway VMs.",
+                            )
+                            .color(egui::Color32::GRAY)
+                            .small(),
                         );
                         ui.end_row();
 
+                        ui.label("Boot:");
+                        egui::CollapsingHeader::new("Kernel & console options")
+                            .id_salt("template_boot_options")
+                            .show(ui, |ui| {
+                                let boot = &mut app.templates_view.form_boot;
+                                ui.checkbox(&mut boot.serial_console, "Serial console (ttyS0)");
+                                egui::Grid::new("template_boot_grid")
+                                    .num_columns(2)
+                                    .show(ui, |ui| {
+                                        ui.label("Kernel:");
+                                        ui.add(
+                                            egui::TextEdit::singleline(&mut boot.kernel)
+                                                .hint_text("(optional, host path for direct boot)")
+                                                .desired_width(250.0),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label("Initrd:");
+                                        ui.add(
+                                            egui::TextEdit::singleline(&mut boot.initrd)
+                                                .hint_text("(optional)")
+                                                .desired_width(250.0),
+                                        );
+                                        ui.end_row();
+
+                                        ui.label("Kernel args:");
+                                        ui.add(
+                                            egui::TextEdit::singleline(&mut boot.kernel_args)
+                                                .hint_text("e.g. console=ttyS0 root=/dev/vda1")
+                                                .desired_width(250.0),
+                                        );
+                                        ui.end_row();
+                                    });
+                                ui.label(
+                                    egui::RichText::new(
+                                        "Kernel args only apply with direct kernel boot; an imported\n\
+                                         disk otherwise boots through its own bootloader.",
+                                    )
+                                    .color(egui::Color32::GRAY)
+                                    .small(),
+                                );
+                            });
+                        ui.end_row();
+
                         ui.label("Notes:");
                         ui.add(
                             egui::TextEdit::multiline(&mut app.templates_view.form_notes)
@@ -687,20 +1296,53 @@ impl TemplatesView {
                     ui.colored_label(egui::Color32::from_rgb(220, 20, 60), error);
                 }
 
+                let duplicate = app
+                    .templates_view
+                    .form_duplicate_of
+                    .as_ref()
+                    .and_then(|id| app.template_registry.get(id))
+                    .map(|t| t.label.clone());
+                if let Some(ref label) = duplicate {
+                    ui.add_space(5.0);
+                    ui.colored_label(
+                        egui::Color32::from_rgb(255, 165, 0),
+                        format!(
+                            "⚠ This image is already registered as '{}'. A second entry shows up twice in the template lists.",
+                            label
+                        ),
+                    );
+                }
+
                 ui.add_space(10.0);
                 ui.horizontal(|ui| {
                     if ui.button("Cancel").clicked() {
                         app.templates_view.show_add_dialog = false;
                     }
 
-                    if ui.button("Save").clicked() {
-                        Self::save_template(app);
+                    if let Some(label) = duplicate {
+                        if ui.button(format!("♻ Use '{}'", label)).clicked() {
+                            app.templates_view.show_add_dialog = false;
+                            app.templates_view.form_duplicate_of = None;
+                            app.set_status(
+                                StatusLevel::Info,
+                                format!("Kept existing template '{}'", label),
+                            );
+                        }
+                        if ui.button("Save anyway").clicked() {
+                            Self::save_template(app, true);
+                        }
+                    } else if ui.button("Save").clicked() {
+                        Self::save_template(app, false);
                     }
                 });
             });
     }
 
-    fn save_template(app: &mut ProxyVmWizardApp) {
+    /// Validate the form and store the template. Unless `allow_duplicate` is set,
+    /// an image that is already registered stops here so the user can reuse it.
+    fn save_template(app: &mut ProxyVmWizardApp, allow_duplicate: bool) {
+        app.templates_view.form_duplicate_of = None;
+
         // Validate
         if app.templates_view.form_label.is_empty() {
             app.templates_view.form_error = Some("Label is required".to_string());
@@ -719,6 +1361,42 @@ impl TemplatesView {
             }
         };
 
+        if let Err(e) = app.templates_view.form_disk_tuning.validate() {
+            app.templates_view.form_error = Some(e);
+            return;
+        }
+        let boot = app.templates_view.form_boot.to_options();
+        if let Err(e) = boot.validate() {
+            app.templates_view.form_error = Some(e);
+            return;
+        }
+        for path in [&boot.kernel, &boot.initrd].into_iter().flatten() {
+            if !path.exists() {
+                app.templates_view.form_error = Some(format!("File not found: {}", path.display()));
+                return;
+            }
+        }
+
+        let install = app.templates_view.form_install;
+        let install_disk_gb = match app.templates_view.form_install_disk_gb.trim() {
+            "" => DEFAULT_INSTALL_DISK_GB,
+            value => match value.parse() {
+                Ok(gb) if gb > 0 => gb,
+                _ => {
+                    app.templates_view.form_error =
+                        Some("Blank disk size must be a whole number of GB".to_string());
+                    return;
+                }
+            },
+        };
+        if install.is_fresh_install() && app.templates_view.form_role_kind == RoleKind::App {
+            app.templates_view.form_error = Some(
+                "Templates that install from an ISO can only be used for gateways; choose Proxy/Gateway or Generic"
+                    .to_string(),
+            );
+            return;
+        }
+
         let mut path = PathBuf::from(&app.templates_view.form_path);
 
         // Check if file exists
@@ -778,8 +1456,27 @@ impl TemplatesView {
             } else {
                 Some(app.templates_view.form_notes.clone())
             },
+            disk_tuning: app.templates_view.form_disk_tuning,
+            boot,
+            install,
+            install_disk_gb,
+            // Editing keeps the pin; it's toggled from the card
+            pinned: app
+                .templates_view
+                .edit_template_id
+                .as_deref()
+                .and_then(|id| app.template_registry.get(id))
+                .is_some_and(|t| t.pinned),
         };
 
+        if !allow_duplicate {
+            if let Some(existing) = app.template_registry.find_duplicate(&template) {
+                app.templates_view.form_duplicate_of = Some(existing.id.clone());
+                app.templates_view.form_error = None;
+                return;
+            }
+        }
+
         let result = if app.templates_view.edit_template_id.is_some() {
             app.template_registry.update(template)
         } else {
@@ -1,9 +1,11 @@
 //! Wizard view - create/edit roles
 
-use crate::app::{ProxyHopEntry, ProxyVmWizardApp, WizardMode, WizardStep};
+use crate::app::{HopList, ProxyHopEntry, ProxyVmWizardApp, WizardMode, WizardStep};
 use crate::views::View;
 use eframe::egui;
-use proxy_vm_core::{GatewayMode, ProxyType};
+use proxy_vm_core::{
+    ChainNodeKind, GatewayMode, ProxyConfigBuilder, ProxyType, RoleNetworkMode, MAX_PROXY_HOPS,
+};
 
 pub struct WizardView;
 
@@ -66,18 +68,15 @@ impl WizardView {
             match app.wizard.step {
                 WizardStep::Execution => {
                     // During/after execution, show appropriate buttons
-                    if app.wizard.execution_error.is_some() {
-                        // Error occurred - offer to clean up and go back or retry
-                        if ui.button("🗑 Clean Up & Cancel").clicked() {
-                            app.cleanup_wizard_resources();
+                    if app.wizard.execution.is_failed() {
+                        // Error occurred - anything created was already rolled back
+                        if ui.button("Cancel").clicked() {
                             app.reset_wizard();
                             app.navigate_to(View::Dashboard);
                         }
                         if ui.button("← Back to Edit").clicked() {
-                            app.cleanup_wizard_resources();
                             app.wizard.step = WizardStep::Confirmation;
-                            app.wizard.execution_error = None;
-                            app.wizard.execution_messages.clear();
+                            app.wizard.execution.clear();
                         }
                     } else if app.wizard.is_executing {
                         // Currently executing - can cancel
@@ -93,7 +92,6 @@ impl WizardView {
                 _ => {
                     // Normal wizard steps
                     if ui.button("Cancel").clicked() {
-                        app.cleanup_wizard_resources();
                         app.reset_wizard();
                         app.navigate_to(View::Dashboard);
                     }
@@ -251,6 +249,125 @@ impl WizardView {
                         });
                 }
                 ui.end_row();
+
+                ui.label("IPv6 Subnet:");
+                let response = ui
+                    .add(
+                        egui::TextEdit::singleline(&mut app.wizard.ipv6_subnet)
+                            .hint_text("(optional) e.g., fd00:10::/64")
+                            .desired_width(200.0),
+                    )
+                    .on_hover_text(
+                        "Adds DHCPv6 to the internal network. Leave empty for IPv4-only.",
+                    );
+                if response.changed() {
+                    app.wizard.ipv6_error = None;
+                }
+                ui.end_row();
+
+                if let Some(ref error) = app.wizard.ipv6_error {
+                    ui.label("");
+                    ui.colored_label(egui::Color32::from_rgb(220, 20, 60), error);
+                    ui.end_row();
+                }
+
+                ui.label("Network Mode:");
+                ui.horizontal(|ui| {
+                    let previous = app.wizard.network_mode.clone();
+                    egui::ComboBox::from_id_salt("network_mode")
+                        .selected_text(app.wizard.network_mode.label())
+                        .show_ui(ui, |ui| {
+                            // Keep a typed bridge name when switching away and back
+                            let bridge = match &previous {
+                                RoleNetworkMode::Bridge(name) => name.clone(),
+                                _ => String::new(),
+                            };
+                            for mode in [
+                                RoleNetworkMode::Isolated,
+                                RoleNetworkMode::Bridge(bridge),
+                                RoleNetworkMode::Nat,
+                            ] {
+                                let label = mode.label();
+                                ui.selectable_value(&mut app.wizard.network_mode, mode, label);
+                            }
+                        });
+                    if let RoleNetworkMode::Bridge(ref mut name) = app.wizard.network_mode {
+                        ui.add(
+                            egui::TextEdit::singleline(name)
+                                .hint_text("e.g., br0")
+                                .desired_width(100.0),
+                        );
+                    }
+                    if app.wizard.network_mode != previous {
+                        app.wizard.network_mode_error = None;
+                    }
+                });
+                ui.end_row();
+
+                if let Some(ref error) = app.wizard.network_mode_error {
+                    ui.label("");
+                    ui.colored_label(egui::Color32::from_rgb(220, 20, 60), error);
+                    ui.end_row();
+                } else if !app.wizard.network_mode.is_isolated() {
+                    ui.label("");
+                    ui.colored_label(
+                        egui::Color32::from_rgb(255, 165, 0),
+                        "⚠ App VMs on this network can reach the host's networks without going through the gateway",
+                    );
+                    ui.end_row();
+                }
+
+                ui.label("Gateway CPUs:");
+                let response = ui
+                    .add(
+                        egui::TextEdit::singleline(&mut app.wizard.cpuset)
+                            .hint_text("(optional) e.g., 2-3")
+                            .desired_width(200.0),
+                    )
+                    .on_hover_text(
+                        "Pin the gateway to these host CPUs, e.g. 2-3 or 0,4. Leave empty to let it run anywhere.",
+                    );
+                if response.changed() {
+                    app.wizard.cpuset_error = None;
+                }
+                ui.end_row();
+
+                if let Some(ref error) = app.wizard.cpuset_error {
+                    ui.label("");
+                    ui.colored_label(egui::Color32::from_rgb(220, 20, 60), error);
+                    ui.end_row();
+                }
+
+                ui.label("Extra gateway NICs:");
+                let response = ui
+                    .add(
+                        egui::TextEdit::singleline(&mut app.wizard.extra_networks)
+                            .hint_text("(optional) e.g., mgmt-net")
+                            .desired_width(200.0),
+                    )
+                    .on_hover_text(
+                        "Comma-separated libvirt networks attached after the LAN and role NICs, \
+                         in this order. The LAN NIC stays the gateway's uplink.",
+                    );
+                if response.changed() {
+                    app.wizard.extra_networks_error = None;
+                }
+                ui.end_row();
+
+                if let Some(ref error) = app.wizard.extra_networks_error {
+                    ui.label("");
+                    ui.colored_label(egui::Color32::from_rgb(220, 20, 60), error);
+                    ui.end_row();
+                }
+
+                ui.label("Gateway crash:");
+                ui.checkbox(&mut app.wizard.restart_gw_on_crash, "Restart automatically")
+                    .on_hover_text(
+                        "Have libvirt boot the gateway again if its guest crashes or its kernel \
+                         panics, so app VMs don't silently lose their connection. Off keeps libvirt's default of \
+                         leaving it stopped.",
+                    );
+                ui.end_row();
             });
 
         // Show computed names
@@ -258,7 +375,10 @@ impl WizardView {
             ui.add_space(20.0);
             ui.label("Computed resource names:");
             let role = proxy_vm_core::normalize_role_name(&app.wizard.role_name);
-            ui.code(format!("Gateway VM: {}-gw", role));
+            ui.code(format!(
+                "Gateway VM: {}",
+                app.global_config.naming.gateway_name(&role)
+            ));
             ui.code(format!("Internal network: {}-inet", role));
             ui.code(format!(
                 "Config directory: {}/{}",
@@ -352,6 +472,7 @@ impl WizardView {
                 .show(ui, |ui| {
                     ui.horizontal(|ui| {
                         ui.label(format!("Hop {}", i + 1));
+                        super::copy_url_button(ui, hop, i);
 
                         if hop_count > 1 {
                             ui.with_layout(
@@ -373,6 +494,34 @@ impl WizardView {
                             ui.horizontal(|ui| {
                                 ui.radio_value(&mut hop.proxy_type, ProxyType::Socks5, "SOCKS5");
                                 ui.radio_value(&mut hop.proxy_type, ProxyType::Http, "HTTP");
+                                if ui
+                                    .small_button("🔍 Auto-detect")
+                                    .on_hover_text("Probe host:port for SOCKS5 or HTTP")
+                                    .clicked()
+                                {
+                                    hop.auto_detect_type(&app.libvirt);
+                                }
+                                if hop.proxy_type == ProxyType::Socks5
+                                    && ui
+                                        .small_button("📡 Test UDP")
+                                        .on_hover_text(
+                                            "Ask the proxy for a UDP relay (SOCKS5 UDP ASSOCIATE)",
+                                        )
+                                        .clicked()
+                                {
+                                    hop.test_udp(&app.libvirt);
+                                }
+                                super::udp_badge(ui, hop);
+                                if hop.pinging {
+                                    ui.spinner();
+                                } else if ui
+                                    .small_button("📶 Ping")
+                                    .on_hover_text("Send one ICMP echo to the host")
+                                    .clicked()
+                                {
+                                    hop.ping(&app.libvirt, &app.async_tx, HopList::Wizard, i);
+                                }
+                                super::ping_badge(ui, hop);
                             });
                             ui.end_row();
 
@@ -385,11 +534,14 @@ impl WizardView {
                             ui.end_row();
 
                             ui.label("Port:");
-                            ui.add(
-                                egui::TextEdit::singleline(&mut hop.port)
-                                    .hint_text("1080")
-                                    .desired_width(80.0),
-                            );
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut hop.port)
+                                        .hint_text("1080")
+                                        .desired_width(80.0),
+                                );
+                                super::port_error(ui, hop);
+                            });
                             ui.end_row();
 
                             ui.label("Username:");
@@ -418,13 +570,52 @@ impl WizardView {
                             ui.end_row();
                         });
 
+                    egui::CollapsingHeader::new("Details")
+                        .id_salt(format!("hop_details_{}", i))
+                        .show(ui, |ui| {
+                            egui::Grid::new(format!("hop_details_grid_{}", i))
+                                .num_columns(2)
+                                .spacing([10.0, 6.0])
+                                .show(ui, |ui| {
+                                    ui.label("Provider:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut hop.provider)
+                                            .hint_text("(optional)")
+                                            .desired_width(150.0),
+                                    );
+                                    ui.end_row();
+
+                                    ui.label("Country:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut hop.country)
+                                            .hint_text("(optional)")
+                                            .desired_width(150.0),
+                                    );
+                                    ui.end_row();
+
+                                    ui.label("Notes:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut hop.notes)
+                                            .hint_text("(optional, e.g., expiry date)")
+                                            .desired_width(150.0),
+                                    );
+                                    ui.end_row();
+                                });
+                        });
+
                     // Test status display
                     ui.horizontal(|ui| {
-                        if let Some(status) = hop.test_status {
+                        if hop.testing {
+                            ui.spinner();
+                            ui.label("Testing...");
+                        } else if let Some(status) = hop.test_status {
                             if status {
                                 ui.colored_label(
                                     egui::Color32::from_rgb(34, 139, 34),
-                                    "✓ Connected",
+                                    format!(
+                                        "✓ {}",
+                                        hop.test_message.as_deref().unwrap_or("Connected")
+                                    ),
                                 );
                             } else {
                                 ui.colored_label(
@@ -447,15 +638,48 @@ impl WizardView {
         }
 
         // Add hop button
-        if app.wizard.proxy_hops.len() < 8 && ui.button("➕ Add Proxy Hop").clicked() {
-            app.wizard.proxy_hops.push(ProxyHopEntry::default());
-        }
+        ui.horizontal(|ui| {
+            if app.wizard.proxy_hops.len() < MAX_PROXY_HOPS
+                && ui.button("➕ Add Proxy Hop").clicked()
+            {
+                app.wizard.proxy_hops.push(ProxyHopEntry::default());
+            }
+            if let Some((level, message)) = super::import_env_button(ui, &mut app.wizard.proxy_hops)
+            {
+                app.set_status(level, message);
+            }
+            if let Some((level, message)) = super::import_csv_button(ui, &mut app.wizard.proxy_hops)
+            {
+                app.set_status(level, message);
+            }
+            if let Some((level, message)) =
+                super::optimize_order_button(ui, &mut app.wizard.proxy_hops)
+            {
+                app.set_status(level, message);
+            }
+        });
 
         // Handle test button clicks (separate to avoid borrow issues)
         ui.add_space(10.0);
         ui.horizontal(|ui| {
-            for i in 0..app.wizard.proxy_hops.len() {
-                if ui.small_button(format!("Test Hop {}", i + 1)).clicked() {
+            let hop_count = app.wizard.proxy_hops.len();
+            if hop_count > 1
+                && ui
+                    .small_button("Test All Hops")
+                    .on_hover_text("Connect to every hop at once")
+                    .clicked()
+            {
+                app.test_all_proxy_hops();
+            }
+            for i in 0..hop_count {
+                let idle = !app.wizard.proxy_hops[i].testing;
+                if ui
+                    .add_enabled(
+                        idle,
+                        egui::Button::new(format!("Test Hop {}", i + 1)).small(),
+                    )
+                    .clicked()
+                {
                     app.test_proxy_connection(i);
                 }
             }
@@ -507,6 +731,11 @@ impl WizardView {
                 ui.label("Route all traffic:");
                 ui.checkbox(&mut app.wizard.wireguard_config.route_all_traffic, "");
                 ui.end_row();
+
+                ui.label("Kill switch:");
+                ui.checkbox(&mut app.wizard.wireguard_config.kill_switch, "")
+                    .on_hover_text(super::KILL_SWITCH_HINT);
+                ui.end_row();
             });
     }
 
@@ -554,14 +783,33 @@ impl WizardView {
                             .pick_file()
                         {
                             app.wizard.openvpn_config.auth_filename = path.display().to_string();
+                            app.wizard.openvpn_config.check_auth_file();
                         }
                     }
                 });
                 ui.end_row();
 
+                if let Some(ref warning) = app.wizard.openvpn_config.auth_warning {
+                    ui.label("");
+                    ui.colored_label(
+                        egui::Color32::from_rgb(255, 165, 0),
+                        format!("⚠ {}", warning),
+                    );
+                    ui.end_row();
+                }
+
                 ui.label("Route all traffic:");
                 ui.checkbox(&mut app.wizard.openvpn_config.route_all_traffic, "");
                 ui.end_row();
+
+                ui.label("Kill switch:");
+                ui.checkbox(&mut app.wizard.openvpn_config.kill_switch, "")
+                    .on_hover_text(super::KILL_SWITCH_HINT);
+                ui.end_row();
+
+                ui.label("Override:");
+                super::openvpn_overrides(ui, &mut app.wizard.openvpn_config, "wizard_ovpn_proto");
+                ui.end_row();
             });
     }
 
@@ -582,13 +830,43 @@ impl WizardView {
                     app.global_config.cfg.root.display(),
                     role
                 ));
-                ui.label(format!("🌐 Network: {}-inet", role));
+                let mode = match &app.wizard.network_mode {
+                    RoleNetworkMode::Bridge(bridge) => format!("bridged to {}", bridge),
+                    mode => mode.label().to_lowercase(),
+                };
+                match app.wizard_ipv6_subnet() {
+                    Ok(Some(subnet)) => {
+                        ui.label(format!(
+                            "🌐 Network: {}-inet ({}, IPv6 {})",
+                            role, mode, subnet
+                        ));
+                    }
+                    _ => {
+                        ui.label(format!("🌐 Network: {}-inet ({})", role, mode));
+                    }
+                }
                 ui.label(format!(
                     "💾 Overlay disk: {}/{}-gw.qcow2",
                     app.global_config.libvirt.images_dir.display(),
                     role
                 ));
-                ui.label(format!("🖥 Gateway VM: {}-gw", role));
+                let gw_name = app.global_config.naming.gateway_name(&role);
+                match app.wizard.cpuset.trim() {
+                    "" => ui.label(format!("🖥 Gateway VM: {}", gw_name)),
+                    cpuset => ui.label(format!(
+                        "🖥 Gateway VM: {} (pinned to CPUs {})",
+                        gw_name, cpuset
+                    )),
+                };
+                if !app.wizard.extra_networks.trim().is_empty() {
+                    ui.label(format!(
+                        "🔌 Extra gateway NICs: {}",
+                        app.wizard.extra_networks.trim()
+                    ));
+                }
+                if app.wizard.restart_gw_on_crash {
+                    ui.label("♻ Gateway restarts automatically if it crashes");
+                }
 
                 if let Some(ref id) = app.wizard.selected_gw_template_id {
                     if let Some(template) = app.template_registry.get(id) {
@@ -609,21 +887,67 @@ impl WizardView {
                     GatewayMode::ProxyChain => {
                         ui.label(format!("Proxy hops: {}", app.wizard.proxy_hops.len()));
                     }
-                    GatewayMode::WireGuard => {
-                        ui.label(format!(
-                            "WireGuard config: /proxy/{}",
-                            app.wizard.wireguard_config.config_filename
-                        ));
-                    }
-                    GatewayMode::OpenVpn => {
-                        ui.label(format!(
-                            "OpenVPN config: /proxy/{}",
-                            app.wizard.openvpn_config.config_filename
-                        ));
+                    GatewayMode::WireGuard | GatewayMode::OpenVpn => {}
+                }
+
+                let files = app.role_file_preview();
+                if !files.is_empty() {
+                    ui.add_space(5.0);
+                    ui.label("Files:");
+                    for file in &files {
+                        match file.source {
+                            Some(ref source) => ui.label(format!(
+                                "📄 {}: {} → {}",
+                                file.label,
+                                source.display(),
+                                file.dest.display()
+                            )),
+                            None if file.dest.is_file() => ui.label(format!(
+                                "📄 {}: already in the role directory at {}",
+                                file.label,
+                                file.dest.display()
+                            )),
+                            None => ui.colored_label(
+                                egui::Color32::from_rgb(255, 165, 0),
+                                format!(
+                                    "⚠ {}: not found; expected at {}",
+                                    file.label,
+                                    file.dest.display()
+                                ),
+                            ),
+                        };
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "    seen by the gateway as {}",
+                                file.guest_path
+                            ))
+                            .color(egui::Color32::GRAY)
+                            .small(),
+                        );
                     }
                 }
             });
 
+        ui.add_space(10.0);
+        ui.label("Traffic path:");
+        egui::Frame::group(ui.style())
+            .inner_margin(10.0)
+            .show(ui, |ui| {
+                Self::show_chain_flow(app, ui);
+            });
+
+        if let Ok(config) = app.build_proxy_config() {
+            ui.add_space(10.0);
+            ui.label("What apply-proxy.sh will do in the gateway:");
+            egui::Frame::group(ui.style())
+                .inner_margin(10.0)
+                .show(ui, |ui| {
+                    for line in ProxyConfigBuilder::describe_apply_plan(&config) {
+                        ui.label(format!("• {}", line));
+                    }
+                });
+        }
+
         ui.add_space(10.0);
         ui.checkbox(
             &mut app.wizard.create_app_vm,
@@ -631,6 +955,73 @@ impl WizardView {
         );
     }
 
+    /// Render "You → hop → ... → Internet" so the hop order can be checked before building
+    fn show_chain_flow(app: &ProxyVmWizardApp, ui: &mut egui::Ui) {
+        let config = match app.build_proxy_config() {
+            Ok(config) => config,
+            Err(e) => {
+                ui.colored_label(egui::Color32::from_rgb(220, 20, 60), e);
+                return;
+            }
+        };
+        let nodes = config.describe_chain();
+
+        ui.horizontal_wrapped(|ui| {
+            for (i, node) in nodes.iter().enumerate() {
+                if i > 0 {
+                    ui.label("→");
+                }
+
+                let mut text = match node.kind {
+                    ChainNodeKind::Client => format!("🖥 {}", node.label),
+                    ChainNodeKind::Proxy => format!("🔀 {}", node.label),
+                    ChainNodeKind::Vpn => format!("🔒 {}", node.label),
+                    ChainNodeKind::Internet => format!("🌍 {}", node.label),
+                };
+                if let Some(status) = node
+                    .hop_position
+                    .and_then(|pos| app.wizard.proxy_hops.get(pos))
+                    .and_then(|hop| hop.test_status)
+                {
+                    text.push_str(if status { " ✓" } else { " ✗" });
+                }
+
+                let mut rich = egui::RichText::new(text);
+                if node.is_exit {
+                    rich = rich.strong().color(egui::Color32::from_rgb(34, 139, 34));
+                }
+
+                let response = egui::Frame::none()
+                    .stroke(egui::Stroke::new(1.0, egui::Color32::GRAY))
+                    .rounding(4.0)
+                    .inner_margin(4.0)
+                    .show(ui, |ui| ui.label(rich))
+                    .inner;
+
+                let mut hover = node.detail.clone().unwrap_or_default();
+                if node.is_exit {
+                    if !hover.is_empty() {
+                        hover.push('\n');
+                    }
+                    hover.push_str("Exit hop - sites see this address");
+                }
+                if !hover.is_empty() {
+                    response.on_hover_text(hover);
+                }
+            }
+        });
+
+        if let Some(exit) = config.exit_hop() {
+            if ui
+                .small_button("🔗 Copy exit URL")
+                .on_hover_text("Copy the exit hop as a single proxy URL, e.g. for curl")
+                .clicked()
+            {
+                ui.ctx().copy_text(exit.to_url());
+            }
+        }
+    }
+
     fn show_step_execution(app: &mut ProxyVmWizardApp, ui: &mut egui::Ui) {
         ui.heading("Step 4: Execution");
         ui.add_space(10.0);
@@ -642,27 +1033,6 @@ impl WizardView {
 
         ui.add_space(10.0);
 
-        for (i, msg) in app.wizard.execution_messages.iter().enumerate() {
-            let is_current = i == app.wizard.execution_step.saturating_sub(1);
-            let is_done = i < app.wizard.execution_step;
-
-            let color = if is_done {
-                egui::Color32::from_rgb(34, 139, 34)
-            } else if is_current && app.wizard.is_executing {
-                egui::Color32::from_rgb(100, 149, 237)
-            } else {
-                egui::Color32::WHITE
-            };
-
-            ui.colored_label(color, msg);
-        }
-
-        if let Some(ref error) = app.wizard.execution_error.clone() {
-            ui.add_space(10.0);
-            ui.colored_label(
-                egui::Color32::from_rgb(220, 20, 60),
-                format!("❌ Error: {}", error),
-            );
-        }
+        super::step_list(ui, &app.wizard.execution, app.wizard.is_executing);
     }
 }